@@ -0,0 +1,95 @@
+//! Compares sending whole trades over a channel into a report with sending only the fields the
+//! report aggregates, as the channels-input strategy does. Runs on trades in memory, so it
+//! measures the channel and aggregation without the database:
+//!
+//! ```text
+//! cargo bench --bench channel_payload
+//! ```
+
+use chrono::{DateTime, Duration, TimeZone};
+use chrono_tz::Europe::Copenhagen;
+use criterion::{criterion_group, criterion_main, Criterion};
+use rust_decimal::Decimal;
+use tokio::runtime::Runtime;
+use tokio::sync::mpsc;
+use trading_results_rs::report::TradeAggregateInput;
+use trading_results_rs::{Area, CounterPart, Currency, Report, Trade, TradeSide, TradeType};
+
+const TRADES: i64 = 100_000;
+
+fn trades() -> Vec<Trade> {
+    let start = DateTime::parse_from_rfc3339("2024-05-01T00:00:00+02:00").unwrap();
+    (0..TRADES)
+        .map(|id| {
+            let delivery_start = start + Duration::hours(id % 24);
+            let (trade_side, quantity) = match id % 2 {
+                0 => (TradeSide::Buy, Decimal::from(id % 50 + 1)),
+                _ => (TradeSide::Sell, -Decimal::from(id % 50 + 1)),
+            };
+            Trade {
+                id,
+                area: [Area::DK1, Area::DK2, Area::GB][(id % 3) as usize],
+                counter_part: CounterPart::NORDPOOL,
+                currency: [Currency::Eur, Currency::Eur, Currency::Gbp][(id % 3) as usize],
+                delivery_start,
+                delivery_end: delivery_start + Duration::hours(1),
+                price: Some(Decimal::new(id % 10_000 + 1_000, 2)),
+                quantity_mwh: quantity,
+                trade_side,
+                trade_type: TradeType::Intraday,
+            }
+        })
+        .collect()
+}
+
+fn channel_payload_benchmark(c: &mut Criterion) {
+    let runtime = Runtime::new().unwrap();
+    let from = Copenhagen.with_ymd_and_hms(2024, 5, 1, 0, 0, 0).unwrap();
+    let to = Copenhagen.with_ymd_and_hms(2024, 5, 2, 0, 0, 0).unwrap();
+
+    let mut group = c.benchmark_group("channel_payload");
+    group.sample_size(20);
+    group.bench_function("trade", |b| {
+        b.to_async(&runtime).iter_batched(
+            trades,
+            |trades| async move {
+                let (tx, mut rx) = mpsc::channel(100);
+                tokio::spawn(async move {
+                    for trade in trades {
+                        tx.send(trade).await.unwrap();
+                    }
+                });
+                let mut report = Report::empty(&from, &to).unwrap();
+                while let Some(trade) = rx.recv().await {
+                    report.add_trade(&trade).unwrap();
+                }
+                report
+            },
+            criterion::BatchSize::LargeInput,
+        )
+    });
+    group.bench_function("aggregate_input", |b| {
+        b.to_async(&runtime).iter_batched(
+            trades,
+            |trades| async move {
+                let (tx, mut rx) = mpsc::channel(100);
+                tokio::spawn(async move {
+                    for trade in trades {
+                        let input = TradeAggregateInput::from_trade(&trade).unwrap();
+                        tx.send(input).await.unwrap();
+                    }
+                });
+                let mut report = Report::empty(&from, &to).unwrap();
+                while let Some(input) = rx.recv().await {
+                    report.add_input(&input);
+                }
+                report
+            },
+            criterion::BatchSize::LargeInput,
+        )
+    });
+    group.finish();
+}
+
+criterion_group!(benches, channel_payload_benchmark);
+criterion_main!(benches);
@@ -0,0 +1,61 @@
+//! Compares looking up the contract length of trades with dividing their duration out for each of
+//! them, over 10M trades of mostly hourly and quarter-hourly products:
+//!
+//! ```text
+//! cargo bench --bench contract_length
+//! ```
+
+use chrono::{DateTime, Duration, FixedOffset};
+use criterion::{black_box, criterion_group, criterion_main, Criterion};
+use rust_decimal::prelude::FromPrimitive;
+use rust_decimal::Decimal;
+use trading_results_rs::report::contract_length;
+
+const ROWS: usize = 10_000_000;
+
+/// Distinct periods, cycled through for the rows
+fn periods() -> Vec<(DateTime<FixedOffset>, DateTime<FixedOffset>)> {
+    let start = DateTime::parse_from_rfc3339("2024-05-01T00:00:00+02:00").unwrap();
+    (0..10_000)
+        .map(|index| {
+            let delivery_start = start + Duration::minutes(15 * index);
+            let minutes = [60, 15, 30, 60, 240][index as usize % 5];
+            (delivery_start, delivery_start + Duration::minutes(minutes))
+        })
+        .collect()
+}
+
+/// `contract_length` without the precomputed lengths
+fn divided_contract_length(
+    delivery_start: &DateTime<FixedOffset>,
+    delivery_end: &DateTime<FixedOffset>,
+) -> Decimal {
+    let seconds = Decimal::from_i64((*delivery_end - *delivery_start).num_seconds()).unwrap();
+    seconds / Decimal::from_str_exact("3600.0").unwrap()
+}
+
+fn contract_length_benchmark(c: &mut Criterion) {
+    let periods = periods();
+    let rows = || periods.iter().cycle().take(ROWS);
+
+    let mut group = c.benchmark_group("contract_length");
+    group.sample_size(10);
+    group.bench_function("divided", |b| {
+        b.iter(|| {
+            rows()
+                .map(|(start, end)| divided_contract_length(black_box(start), black_box(end)))
+                .sum::<Decimal>()
+        })
+    });
+    group.bench_function("precomputed", |b| {
+        b.iter(|| {
+            rows()
+                .map(|(start, end)| contract_length(black_box(start), black_box(end)).unwrap())
+                .sum::<Decimal>()
+        })
+    });
+    group.finish();
+}
+
+criterion_group!(benches, contract_length_benchmark);
+criterion_main!(benches);
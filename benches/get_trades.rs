@@ -0,0 +1,70 @@
+//! Compares querying the three trade tables one after the other with querying them
+//! concurrently, as `get_trades` does, on a month and on a year of deliveries:
+//!
+//! ```text
+//! DATABASE_URL=postgres://... cargo bench --bench get_trades
+//! ```
+
+use chrono::TimeZone;
+use chrono_tz::Europe::Copenhagen;
+use criterion::{criterion_group, criterion_main, BenchmarkId, Criterion};
+use tokio::runtime::Runtime;
+use trading_results_rs::bounds::WindowFilter;
+use trading_results_rs::db::{get_table_trades, get_trades, init_db_pool, TradeTable};
+use trading_results_rs::timing::StrategyTimings;
+use trading_results_rs::units::QuantityUnits;
+use trading_results_rs::Decoding;
+
+fn get_trades_benchmark(c: &mut Criterion) {
+    dotenvy::dotenv().ok();
+    let database_url = std::env::var("DATABASE_URL").expect("DATABASE_URL to be set");
+    let runtime = Runtime::new().unwrap();
+    let pool = runtime.block_on(init_db_pool(&database_url)).unwrap();
+    let units = QuantityUnits::default();
+
+    let from = Copenhagen.with_ymd_and_hms(2024, 1, 1, 0, 0, 0).unwrap();
+    let ranges = [
+        (
+            "month",
+            Copenhagen.with_ymd_and_hms(2024, 2, 1, 0, 0, 0).unwrap(),
+        ),
+        (
+            "year",
+            Copenhagen.with_ymd_and_hms(2025, 1, 1, 0, 0, 0).unwrap(),
+        ),
+    ];
+
+    let mut group = c.benchmark_group("get_trades");
+    group.sample_size(10);
+    for (name, to) in ranges.iter() {
+        group.bench_with_input(BenchmarkId::new("sequential", name), to, |b, to| {
+            b.to_async(&runtime).iter(|| async {
+                let mut trades = Vec::new();
+                for table in TradeTable::ALL {
+                    trades.extend(get_table_trades(&pool, table, &from, to).await.unwrap());
+                }
+                trades
+            })
+        });
+        group.bench_with_input(BenchmarkId::new("concurrent", name), to, |b, to| {
+            b.to_async(&runtime).iter(|| async {
+                let mut timings = StrategyTimings::new("bench");
+                get_trades(
+                    &pool,
+                    &from,
+                    to,
+                    WindowFilter::DeliveryStart,
+                    &units,
+                    Decoding::Strict,
+                    &mut timings,
+                )
+                .await
+                .unwrap()
+            })
+        });
+    }
+    group.finish();
+}
+
+criterion_group!(benches, get_trades_benchmark);
+criterion_main!(benches);
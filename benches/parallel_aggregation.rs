@@ -0,0 +1,65 @@
+//! Compares aggregating trades in memory on one thread, as `Report::new` does, with aggregating
+//! chunks of them on the rayon thread pool and merging the partial reports:
+//!
+//! ```text
+//! cargo bench --bench parallel_aggregation
+//! ```
+
+use chrono::{DateTime, Duration, TimeZone};
+use chrono_tz::Europe::Copenhagen;
+use criterion::{criterion_group, criterion_main, Criterion};
+use rust_decimal::Decimal;
+use trading_results_rs::{Area, CounterPart, Currency, Report, Trade, TradeSide, TradeType};
+
+const TRADES: i64 = 2_000_000;
+
+fn trades() -> Vec<Trade> {
+    let start = DateTime::parse_from_rfc3339("2024-05-01T00:00:00+02:00").unwrap();
+    (0..TRADES)
+        .map(|id| {
+            let delivery_start = start + Duration::hours(id % 24);
+            let (trade_side, quantity) = match id % 2 {
+                0 => (TradeSide::Buy, Decimal::from(id % 50 + 1)),
+                _ => (TradeSide::Sell, -Decimal::from(id % 50 + 1)),
+            };
+            Trade {
+                id,
+                area: [Area::DK1, Area::DK2, Area::GB][(id % 3) as usize],
+                counter_part: CounterPart::NORDPOOL,
+                currency: [Currency::Eur, Currency::Eur, Currency::Gbp][(id % 3) as usize],
+                delivery_start,
+                delivery_end: delivery_start + Duration::hours(1),
+                price: Some(Decimal::new(id % 10_000 + 1_000, 2)),
+                quantity_mwh: quantity,
+                trade_side,
+                trade_type: TradeType::Intraday,
+            }
+        })
+        .collect()
+}
+
+fn parallel_aggregation_benchmark(c: &mut Criterion) {
+    let from = Copenhagen.with_ymd_and_hms(2024, 5, 1, 0, 0, 0).unwrap();
+    let to = Copenhagen.with_ymd_and_hms(2024, 5, 2, 0, 0, 0).unwrap();
+
+    let mut group = c.benchmark_group("parallel_aggregation");
+    group.sample_size(10);
+    group.bench_function("single_thread", |b| {
+        b.iter_batched(
+            trades,
+            |trades| Report::new(&from, &to, trades).unwrap(),
+            criterion::BatchSize::LargeInput,
+        )
+    });
+    group.bench_function("rayon", |b| {
+        b.iter_batched(
+            trades,
+            |trades| Report::new_parallel(&from, &to, trades).unwrap(),
+            criterion::BatchSize::LargeInput,
+        )
+    });
+    group.finish();
+}
+
+criterion_group!(benches, parallel_aggregation_benchmark);
+criterion_main!(benches);
@@ -0,0 +1,120 @@
+-- Synthetic trades for the `demo` command, delivered hourly from 2024-05-01 to 2024-05-08 in
+-- Copenhagen. The tables are laid out like the trade tables, with quantities in MW and signed,
+-- prices in the currency of the trade, times as seconds since the epoch and decimals as text,
+-- as SQLite has types for neither.
+CREATE TABLE intraday_trades (
+    id INTEGER PRIMARY KEY,
+    area TEXT NOT NULL,
+    counter_part TEXT NOT NULL,
+    currency TEXT NOT NULL,
+    delivery_start INTEGER NOT NULL,
+    delivery_end INTEGER NOT NULL,
+    price TEXT,
+    quantity_mwh TEXT NOT NULL,
+    trade_side TEXT NOT NULL,
+    trade_type TEXT NOT NULL
+);
+
+CREATE TABLE auction_trades (
+    id INTEGER PRIMARY KEY,
+    area TEXT NOT NULL,
+    counter_part TEXT NOT NULL,
+    currency TEXT NOT NULL,
+    delivery_start INTEGER NOT NULL,
+    delivery_end INTEGER NOT NULL,
+    price TEXT,
+    quantity_mwh TEXT NOT NULL,
+    trade_side TEXT NOT NULL,
+    trade_type TEXT NOT NULL
+);
+
+CREATE TABLE imbalance_trades (
+    id INTEGER PRIMARY KEY,
+    area TEXT NOT NULL,
+    counter_part TEXT NOT NULL,
+    currency TEXT NOT NULL,
+    delivery_start INTEGER NOT NULL,
+    delivery_end INTEGER NOT NULL,
+    price TEXT,
+    quantity_mwh TEXT NOT NULL,
+    trade_side TEXT NOT NULL,
+    trade_type TEXT NOT NULL
+);
+
+-- Bought in the auctions of each hour, with prices peaking in the day
+INSERT INTO auction_trades (area, counter_part, currency, delivery_start, delivery_end, price, quantity_mwh, trade_side, trade_type)
+WITH RECURSIVE
+    hours(hour, start) AS (
+        SELECT 0, CAST(strftime('%s', '2024-04-30 22:00:00') AS INTEGER)
+        UNION ALL
+        SELECT hour + 1, start + 3600 FROM hours WHERE hour < 167
+    ),
+    areas(number, area, counter_part, currency, trade_type) AS (
+        VALUES
+            (0, 'DK1', 'nordpool', 'EUR', 'auction_eur_dah_h'),
+            (1, 'DK2', 'nordpool', 'EUR', 'auction_eur_dah_h'),
+            (2, 'SE3', 'nordpool', 'EUR', 'auction_eur_dah_h'),
+            (3, 'GB', 'epex', 'GBP', 'auction_gb_dah_h')
+    )
+SELECT
+    area,
+    counter_part,
+    currency,
+    start,
+    start + 3600,
+    printf('%.2f', 30 + 25 * ((hour % 24) BETWEEN 6 AND 20) + (hour * 37 + number * 11) % 23 * 0.5),
+    printf('%d', 5 + (hour * 7 + number * 3) % 10),
+    'buy',
+    trade_type
+FROM hours, areas
+ORDER BY start, number;
+
+-- Sold back, and some more bought, intraday, at prices around those of the auction
+INSERT INTO intraday_trades (area, counter_part, currency, delivery_start, delivery_end, price, quantity_mwh, trade_side, trade_type)
+WITH RECURSIVE
+    hours(hour, start) AS (
+        SELECT 0, CAST(strftime('%s', '2024-04-30 22:00:00') AS INTEGER)
+        UNION ALL
+        SELECT hour + 1, start + 3600 FROM hours WHERE hour < 167
+    ),
+    areas(number, area, counter_part, currency) AS (
+        VALUES
+            (0, 'DK1', 'nordpool', 'EUR'),
+            (1, 'DK2', 'epex', 'EUR'),
+            (2, 'SE3', 'nordpool', 'EUR'),
+            (3, 'GB', 'epex', 'GBP')
+    )
+SELECT
+    area,
+    counter_part,
+    currency,
+    start,
+    start + 3600,
+    printf('%.2f', 28 + 30 * ((hour % 24) BETWEEN 6 AND 20) + (hour * 29 + number * 7) % 31 * 0.5),
+    CASE WHEN (hour + number) % 3 = 0 THEN printf('%d', 1 + hour % 4) ELSE printf('%d', -(2 + (hour + number) % 5)) END,
+    CASE WHEN (hour + number) % 3 = 0 THEN 'buy' ELSE 'sell' END,
+    'intraday'
+FROM hours, areas
+ORDER BY start, number;
+
+-- Settled imbalances of the Danish areas
+INSERT INTO imbalance_trades (area, counter_part, currency, delivery_start, delivery_end, price, quantity_mwh, trade_side, trade_type)
+WITH RECURSIVE
+    hours(hour, start) AS (
+        SELECT 0, CAST(strftime('%s', '2024-04-30 22:00:00') AS INTEGER)
+        UNION ALL
+        SELECT hour + 1, start + 3600 FROM hours WHERE hour < 167
+    ),
+    areas(number, area) AS (VALUES (0, 'DK1'), (1, 'DK2'))
+SELECT
+    area,
+    'esett',
+    'EUR',
+    start,
+    start + 3600,
+    printf('%.2f', 20 + (hour * 41 + number * 13) % 60),
+    CASE WHEN (hour * 5 + number) % 4 = 0 THEN '1.5' ELSE '-0.5' END,
+    CASE WHEN (hour * 5 + number) % 4 = 0 THEN 'buy' ELSE 'sell' END,
+    'imbalance'
+FROM hours, areas
+ORDER BY start, number;
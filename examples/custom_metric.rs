@@ -0,0 +1,75 @@
+//! A metric the report doesn't have, the volume weighted average price per area, as an
+//! `Aggregation` fed by the same scan as any other. Runs on a few trades in memory with
+//! `cargo run --example custom_metric`, use `TradeSource::stream` for the trade tables.
+
+use std::collections::BTreeMap;
+
+use anyhow::Result;
+use chrono::DateTime;
+use rust_decimal::Decimal;
+use trading_results_rs::report::contract_length;
+use trading_results_rs::{
+    aggregate_shared, trade_stream, Aggregation, Area, CounterPart, Currency, Trade, TradeSide,
+    TradeType,
+};
+
+#[derive(Default)]
+struct VolumeWeightedPrice {
+    /// MWh and price times MWh per area
+    areas: BTreeMap<Area, (Decimal, Decimal)>,
+}
+
+impl Aggregation for VolumeWeightedPrice {
+    fn add_trade(&mut self, trade: &Trade) -> Result<()> {
+        // Like the report, trades without a price are left out
+        let Some(price) = trade.price else {
+            return Ok(());
+        };
+        let mwh =
+            trade.quantity_mwh.abs() * contract_length(&trade.delivery_start, &trade.delivery_end)?;
+        let (volume, weighted) = self.areas.entry(trade.area).or_default();
+        *volume += mwh;
+        *weighted += mwh * price;
+        Ok(())
+    }
+}
+
+fn trade(id: i64, area: Area, side: TradeSide, quantity: i64, price: i64) -> Trade {
+    let quantity = Decimal::from(quantity);
+    Trade {
+        id,
+        area,
+        counter_part: CounterPart::NORDPOOL,
+        currency: Currency::Eur,
+        delivery_start: DateTime::parse_from_rfc3339("2024-05-01T12:00:00+02:00").unwrap(),
+        delivery_end: DateTime::parse_from_rfc3339("2024-05-01T13:00:00+02:00").unwrap(),
+        price: Some(Decimal::from(price)),
+        quantity_mwh: match side {
+            TradeSide::Buy => quantity,
+            TradeSide::Sell => -quantity,
+        },
+        trade_side: side,
+        trade_type: TradeType::Intraday,
+    }
+}
+
+#[tokio::main]
+async fn main() -> Result<()> {
+    let trades = vec![
+        trade(1, Area::DK1, TradeSide::Buy, 10, 40),
+        trade(2, Area::DK1, TradeSide::Sell, 30, 60),
+        trade(3, Area::DK2, TradeSide::Buy, 5, 45),
+    ];
+
+    let aggregations =
+        aggregate_shared(trade_stream(trades), vec![VolumeWeightedPrice::default()]).await?;
+    for (area, (volume, weighted)) in aggregations[0].areas.iter() {
+        println!(
+            "{}: {} MWh at {}",
+            area,
+            volume,
+            (weighted / volume).round_dp(2)
+        );
+    }
+    Ok(())
+}
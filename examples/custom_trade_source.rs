@@ -0,0 +1,42 @@
+//! Builds a report from trades in a CSV file rather than the trade tables, e.g. an export of
+//! another trading system. The columns are the fields of `Trade`:
+//!
+//! ```text
+//! id,area,counter_part,currency,delivery_start,delivery_end,price,quantity_mwh,trade_side,trade_type
+//! 1,DK1,tradition,EUR,2024-05-01T12:00:00+02:00,2024-05-01T13:00:00+02:00,40,10,Buy,Intraday
+//! ```
+//!
+//! Run with `cargo run --example custom_trade_source -- trades.csv`.
+
+use std::env;
+
+use anyhow::{anyhow, Context, Result};
+use chrono_tz::Europe::Copenhagen;
+use trading_results_rs::bounds::{parse_delivery_bound, Bound};
+use trading_results_rs::{export, trade_stream, ReportBuilder, Trade};
+
+#[tokio::main]
+async fn main() -> Result<()> {
+    let path = env::args()
+        .nth(1)
+        .ok_or(anyhow!("Usage: custom_trade_source <trades.csv>"))?;
+    let trades = csv::Reader::from_path(&path)
+        .with_context(|| format!("Could not read {}", path))?
+        .deserialize()
+        .collect::<Result<Vec<Trade>, _>>()?;
+
+    // The window covers every trade of the file
+    let (Some(first), Some(last)) = (
+        trades.iter().map(|trade| trade.delivery_start).min(),
+        trades.iter().map(|trade| trade.delivery_end).max(),
+    ) else {
+        return Err(anyhow!("No trades in {}", path));
+    };
+    let from = parse_delivery_bound(&first.to_rfc3339(), Copenhagen, Bound::StartOfDay)?;
+    let to = parse_delivery_bound(&last.to_rfc3339(), Copenhagen, Bound::EndOfDay)?;
+
+    let report = ReportBuilder::new(&from, &to)
+        .build_from_stream(trade_stream(trades))
+        .await?;
+    export::write_breakdown_csv(&report, None)
+}
@@ -0,0 +1,39 @@
+//! Builds a report from the trade tables and prints it as JSON and CSV, as a service embedding
+//! the aggregation would. Run with `cargo run --example embed_as_library -- 2024-05-01 2024-06-01`
+//! and `DATABASE_URL` set.
+
+use std::env;
+
+use anyhow::{anyhow, Result};
+use chrono_tz::Europe::Copenhagen;
+use trading_results_rs::bounds::{parse_delivery_bound, Bound};
+use trading_results_rs::{
+    export, AreaSelection, MarketSelection, Metric, QuantityUnits, ReportBuilder, TradeSource,
+};
+
+#[tokio::main]
+async fn main() -> Result<()> {
+    let args: Vec<String> = env::args().skip(1).collect();
+    let [from, to] = [0, 1].map(|index| args.get(index));
+    let (Some(from), Some(to)) = (from, to) else {
+        return Err(anyhow!("Usage: embed_as_library <from> <to>"));
+    };
+    let from = parse_delivery_bound(from, Copenhagen, Bound::StartOfDay)?;
+    let to = parse_delivery_bound(to, Copenhagen, Bound::StartOfDay)?;
+
+    let source = TradeSource::connect(&env::var("DATABASE_URL")?, QuantityUnits::default()).await?;
+    let report = ReportBuilder::new(&from, &to)
+        .build_from_source(&source)
+        .await?;
+
+    // Cash flows of different currencies aren't summed
+    for (currency, gross_profit) in report.metric_by_currency(
+        Metric::GrossProfit,
+        MarketSelection::All,
+        AreaSelection::All,
+    ) {
+        println!("Gross profit: {} {}", currency, gross_profit);
+    }
+    println!("{}", report.to_json()?);
+    export::write_breakdown_csv(&report, None)
+}
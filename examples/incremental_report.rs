@@ -0,0 +1,59 @@
+//! A report kept up to date by a long-running service as batches of trades arrive, instead of
+//! rebuilding it from all trades for every batch. Runs on a few trades in memory with
+//! `cargo run --example incremental_report`.
+
+use anyhow::Result;
+use chrono::{DateTime, TimeZone};
+use chrono_tz::Europe::Copenhagen;
+use rust_decimal::Decimal;
+use trading_results_rs::{
+    Area, AreaSelection, CounterPart, Currency, MarketSelection, Metric, Report, Trade, TradeSide,
+    TradeType,
+};
+
+fn trade(id: i64, side: TradeSide, quantity: i64, price: i64) -> Trade {
+    let quantity = Decimal::from(quantity);
+    Trade {
+        id,
+        area: Area::DK1,
+        counter_part: CounterPart::NORDPOOL,
+        currency: Currency::Eur,
+        delivery_start: DateTime::parse_from_rfc3339("2024-05-01T12:00:00+02:00").unwrap(),
+        delivery_end: DateTime::parse_from_rfc3339("2024-05-01T13:00:00+02:00").unwrap(),
+        price: Some(Decimal::from(price)),
+        quantity_mwh: match side {
+            TradeSide::Buy => quantity,
+            TradeSide::Sell => -quantity,
+        },
+        trade_side: side,
+        trade_type: TradeType::Intraday,
+    }
+}
+
+fn main() -> Result<()> {
+    let from = Copenhagen.with_ymd_and_hms(2024, 5, 1, 0, 0, 0).unwrap();
+    let to = Copenhagen.with_ymd_and_hms(2024, 5, 2, 0, 0, 0).unwrap();
+    let mut report = Report::empty(&from, &to)?;
+
+    // E.g. the trades of each poll of an exchange API
+    let batches = vec![
+        vec![trade(1, TradeSide::Buy, 10, 40)],
+        vec![
+            trade(2, TradeSide::Sell, 30, 60),
+            trade(3, TradeSide::Buy, 20, 45),
+        ],
+    ];
+    for batch in batches {
+        report.add_trades(&batch)?;
+        println!(
+            "{} trades added, gross profit {}",
+            batch.len(),
+            report.try_metric(
+                Metric::GrossProfit,
+                MarketSelection::All,
+                AreaSelection::All
+            )?
+        );
+    }
+    Ok(())
+}
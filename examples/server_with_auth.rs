@@ -0,0 +1,147 @@
+//! Serves the key metrics over HTTP to callers with a bearer token, each token mapped to one of
+//! the roles of the config, so e.g. operations only see volumes:
+//!
+//! ```text
+//! REPORT_API_TOKENS="s3cret=operations,t0ken=finance" cargo run --example server_with_auth
+//! curl -H "Authorization: Bearer s3cret" "localhost:8080/key-metrics?from=2024-05-01&to=2024-06-01"
+//! ```
+//!
+//! It's a bare HTTP/1.1 responder to show the embedding, not a production server.
+
+use std::collections::HashMap;
+use std::env;
+use std::sync::Arc;
+
+use anyhow::{anyhow, bail, Result};
+use tokio::io::{AsyncReadExt, AsyncWriteExt};
+use tokio::net::{TcpListener, TcpStream};
+use trading_results_rs::bounds::parse_delivery_day;
+use trading_results_rs::config::Config;
+use trading_results_rs::permissions::RolePermissions;
+use trading_results_rs::TradeSource;
+
+struct Server {
+    config: Config,
+    source: TradeSource,
+    /// Role of each token
+    tokens: HashMap<String, String>,
+}
+
+/// Compares in constant time, so a token can't be guessed from how fast it's rejected
+fn tokens_equal(given: &str, expected: &str) -> bool {
+    given.len() == expected.len()
+        && given
+            .bytes()
+            .zip(expected.bytes())
+            .fold(0, |difference, (a, b)| difference | (a ^ b))
+            == 0
+}
+
+impl Server {
+    fn role(&self, request: &str) -> Option<&str> {
+        let token = request.lines().find_map(|line| {
+            let (name, value) = line.split_once(':')?;
+            let value = value.trim();
+            (name.eq_ignore_ascii_case("authorization"))
+                .then(|| value.strip_prefix("Bearer "))
+                .flatten()
+        })?;
+        self.tokens
+            .iter()
+            .find(|(expected, _)| tokens_equal(token, expected))
+            .map(|(_, role)| role.as_str())
+    }
+
+    async fn key_metrics(&self, role: &str, target: &str) -> Result<String> {
+        let Some(query) = target.strip_prefix("/key-metrics?") else {
+            bail!("Unknown path {}", target);
+        };
+        let params: HashMap<&str, &str> = query
+            .split('&')
+            .filter_map(|param| param.split_once('='))
+            .collect();
+        let bound = |name: &str| {
+            let value = params.get(name).ok_or(anyhow!("Missing {}", name))?;
+            parse_delivery_day(value)
+        };
+        let (from, to) = (bound("from")?, bound("to")?);
+        self.config.guardrails.check_window(&from, &to)?;
+
+        let report = self
+            .config
+            .report_builder(self.source.pool(), &from, &to)
+            .await?
+            .build_from_source(&self.source)
+            .await?;
+        let permissions = RolePermissions::for_role(&self.config.roles, Some(role))?;
+        Ok(permissions
+            .key_metrics(&report, self.config.sign_convention)
+            .to_string())
+    }
+
+    async fn handle(&self, mut stream: TcpStream) -> Result<()> {
+        let mut buffer = vec![0; 8192];
+        let read = stream.read(&mut buffer).await?;
+        let request = String::from_utf8_lossy(&buffer[..read]);
+        let target = request
+            .lines()
+            .next()
+            .and_then(|line| line.strip_prefix("GET "))
+            .and_then(|line| line.split_whitespace().next())
+            .unwrap_or_default();
+
+        let (status, body) = match self.role(&request) {
+            None => (
+                "401 Unauthorized",
+                "{\"error\":\"unauthorized\"}".to_string(),
+            ),
+            Some(role) => match self.key_metrics(role, target).await {
+                Ok(body) => ("200 OK", body),
+                Err(err) => (
+                    "400 Bad Request",
+                    serde_json::json!({ "error": err.to_string() }).to_string(),
+                ),
+            },
+        };
+        let response = format!(
+            "HTTP/1.1 {}\r\ncontent-type: application/json\r\ncontent-length: {}\r\nconnection: close\r\n\r\n{}",
+            status,
+            body.len(),
+            body
+        );
+        stream.write_all(response.as_bytes()).await?;
+        Ok(())
+    }
+}
+
+#[tokio::main]
+async fn main() -> Result<()> {
+    let config = Config::load(None)?;
+    let tokens = env::var("REPORT_API_TOKENS")?
+        .split(',')
+        .map(|pair| {
+            pair.split_once('=')
+                .map(|(token, role)| (token.to_string(), role.to_string()))
+                .ok_or(anyhow!("Expected token=role, not {}", pair))
+        })
+        .collect::<Result<_>>()?;
+    let source =
+        TradeSource::connect(&env::var("DATABASE_URL")?, config.quantity_units.clone()).await?;
+    let server = Arc::new(Server {
+        config,
+        source,
+        tokens,
+    });
+
+    let listener = TcpListener::bind("127.0.0.1:8080").await?;
+    println!("Listening on {}", listener.local_addr()?);
+    loop {
+        let (stream, _) = listener.accept().await?;
+        let server = Arc::clone(&server);
+        tokio::spawn(async move {
+            if let Err(err) = server.handle(stream).await {
+                eprintln!("Request failed: {:#}", err);
+            }
+        });
+    }
+}
@@ -0,0 +1,131 @@
+//! Arbitrary sequences of trades through the report and the aggregations sharing its scan. Timestamps
+//! range over everything chrono represents, including offsets down to the second and delivery ending
+//! before it starts, and decimals over their full range and scale. Errors are fine, panics and
+//! overflows are what's looked for.
+
+#![no_main]
+
+use arbitrary::Arbitrary;
+use chrono::{DateTime, FixedOffset, TimeZone};
+use chrono_tz::Europe::Copenhagen;
+use libfuzzer_sys::fuzz_target;
+use rust_decimal::Decimal;
+use strum::IntoEnumIterator;
+use trading_results_rs::concentration::Concentration;
+use trading_results_rs::netting::Netting;
+use trading_results_rs::{
+    Aggregation, Area, AreaSelection, CounterPart, Currency, MarketSelection, Metric, ReportBuilder, Trade,
+    TradeSide, TradeType,
+};
+
+const TRADE_TYPES: [TradeType; 10] = [
+    TradeType::Intraday,
+    TradeType::Imbalance,
+    TradeType::AuctionGbDahH,
+    TradeType::AuctionGbDahHh,
+    TradeType::AuctionGbId1Hh,
+    TradeType::AuctionGbId2Hh,
+    TradeType::AuctionEurDahH,
+    TradeType::AuctionEurId1H,
+    TradeType::AuctionEurId2H,
+    TradeType::AuctionEurId3H,
+];
+
+#[derive(Debug, Arbitrary)]
+struct FuzzDecimal {
+    lo: u32,
+    mid: u32,
+    hi: u32,
+    negative: bool,
+    scale: u8,
+}
+
+impl FuzzDecimal {
+    fn decimal(&self) -> Decimal {
+        Decimal::from_parts(
+            self.lo,
+            self.mid,
+            self.hi,
+            self.negative,
+            u32::from(self.scale % 29),
+        )
+    }
+}
+
+#[derive(Debug, Arbitrary)]
+struct FuzzTimestamp {
+    seconds: i64,
+    offset_seconds: i32,
+}
+
+impl FuzzTimestamp {
+    fn timestamp(&self) -> Option<DateTime<FixedOffset>> {
+        FixedOffset::east_opt(self.offset_seconds)?
+            .timestamp_opt(self.seconds, 0)
+            .single()
+    }
+}
+
+#[derive(Debug, Arbitrary)]
+struct FuzzTrade {
+    area: u8,
+    counter_part: String,
+    gbp: bool,
+    delivery_start: FuzzTimestamp,
+    delivery_end: FuzzTimestamp,
+    price: Option<FuzzDecimal>,
+    quantity: FuzzDecimal,
+    sell: bool,
+    trade_type: u8,
+}
+
+impl FuzzTrade {
+    fn trade(&self, id: i64) -> Option<Trade> {
+        Some(Trade {
+            id,
+            area: Area::KNOWN[usize::from(self.area) % Area::KNOWN.len()],
+            counter_part: self.counter_part.parse().unwrap_or(CounterPart::NORDPOOL),
+            currency: if self.gbp {
+                Currency::Gbp
+            } else {
+                Currency::Eur
+            },
+            delivery_start: self.delivery_start.timestamp()?,
+            delivery_end: self.delivery_end.timestamp()?,
+            price: self.price.as_ref().map(FuzzDecimal::decimal),
+            quantity_mwh: self.quantity.decimal(),
+            trade_side: if self.sell {
+                TradeSide::Sell
+            } else {
+                TradeSide::Buy
+            },
+            trade_type: TRADE_TYPES[usize::from(self.trade_type) % TRADE_TYPES.len()],
+        })
+    }
+}
+
+fuzz_target!(|trades: Vec<FuzzTrade>| {
+    let from = Copenhagen.with_ymd_and_hms(2024, 5, 1, 0, 0, 0).unwrap();
+    let to = Copenhagen.with_ymd_and_hms(2024, 6, 1, 0, 0, 0).unwrap();
+    let Ok(mut report) = ReportBuilder::new(&from, &to).empty() else {
+        return;
+    };
+    let mut concentration = Concentration::default();
+    let mut netting = Netting::default();
+
+    for (id, trade) in trades.iter().enumerate() {
+        let Some(trade) = trade.trade(id as i64) else {
+            continue;
+        };
+        let _ = Aggregation::add_trade(&mut report, &trade);
+        let _ = Aggregation::add_trade(&mut concentration, &trade);
+        let _ = Aggregation::add_trade(&mut netting, &trade);
+    }
+
+    for metric in Metric::iter() {
+        report.metric(metric, MarketSelection::All, AreaSelection::All);
+    }
+    let _ = report.to_json();
+    concentration.rows();
+    netting.records();
+});
@@ -0,0 +1,13 @@
+//! Arbitrary bytes as an exchange file. Unknown areas, counter parts, sides and trade types,
+//! unparseable timestamps and out of range decimals must end up as problems of their row, never
+//! as a panic.
+
+#![no_main]
+
+use libfuzzer_sys::fuzz_target;
+use trading_results_rs::ingest;
+use trading_results_rs::QuantityUnits;
+
+fuzz_target!(|data: &[u8]| {
+    let _ = ingest::validate(data, &QuantityUnits::default());
+});
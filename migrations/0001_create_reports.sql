@@ -0,0 +1,12 @@
+-- Reports saved with `Report::save`, checked by `schema::check_schema`
+CREATE TABLE reports (
+    id bigserial PRIMARY KEY,
+    delivery_from timestamptz NOT NULL,
+    delivery_to timestamptz NOT NULL,
+    timezone text NOT NULL,
+    generated_at timestamptz NOT NULL DEFAULT now(),
+    code_version text NOT NULL,
+    report jsonb NOT NULL
+);
+
+CREATE INDEX reports_window_idx ON reports (delivery_from, delivery_to, generated_at DESC);
@@ -0,0 +1,240 @@
+use std::collections::{BTreeMap, HashMap};
+use std::str::FromStr;
+
+use anyhow::{anyhow, bail, Result};
+use chrono::{DateTime, Datelike, Months, NaiveDate, TimeZone};
+use chrono_tz::Tz;
+use rust_decimal::Decimal;
+use serde::Deserialize;
+
+use crate::report::{Metric, Report, SignConvention};
+use crate::trade::{Area, AreaSelection, Market, MarketSelection};
+
+/// A monthly lump sum not tied to trades, e.g. an exchange membership or a broker fee,
+/// configured as e.g.
+///
+/// ```toml
+/// [[fixed_costs]]
+/// name = "epex-membership"
+/// monthly_amount = 5000
+/// allocation = { volume = { markets = ["auction"] } }
+///
+/// [[fixed_costs]]
+/// name = "broker"
+/// monthly_amount = 1200
+/// allocation = { shares = { DK1 = 0.5, DK2 = 0.5 } }
+/// ```
+#[derive(Debug, Deserialize, Clone)]
+pub struct FixedCost {
+    pub name: String,
+    pub monthly_amount: Decimal,
+    pub allocation: Allocation,
+}
+
+#[derive(Debug, Deserialize, Clone)]
+#[serde(rename_all = "snake_case")]
+pub enum Allocation {
+    /// By each area's share of the traded volume in the markets, all markets if none are given
+    Volume {
+        #[serde(default)]
+        markets: Vec<String>,
+    },
+    /// By fixed shares per area, which are relative to their sum
+    Shares(HashMap<Area, Decimal>),
+}
+
+/// The result of an area after its share of the fixed costs
+#[derive(Debug, PartialEq)]
+pub struct NetResult {
+    pub area: Area,
+    pub gross_profit: Decimal,
+    pub allocated_costs: Decimal,
+    pub net_result: Decimal,
+}
+
+/// How many months of fixed costs fall in a window, counting partial months by their share of
+/// time covered, so a window of mid-January to mid-February is a month.
+fn months_in_window(delivery_from: &DateTime<Tz>, delivery_to: &DateTime<Tz>) -> Result<Decimal> {
+    let timezone = delivery_from.timezone();
+    let mut month_start = NaiveDate::from_ymd_opt(delivery_from.year(), delivery_from.month(), 1)
+        .ok_or(anyhow!("Invalid month of {}", delivery_from))?;
+    let mut months = Decimal::ZERO;
+
+    loop {
+        let next_month = month_start + Months::new(1);
+        let local_midnight = |date: NaiveDate| {
+            timezone
+                .from_local_datetime(&date.and_hms_opt(0, 0, 0).unwrap_or_default())
+                .earliest()
+                .ok_or(anyhow!("No local midnight on {}", date))
+        };
+        let start = local_midnight(month_start)?;
+        if start >= *delivery_to {
+            return Ok(months);
+        }
+        let end = local_midnight(next_month)?;
+        let covered = (end.min(*delivery_to) - start.max(*delivery_from)).num_seconds();
+        let length = (end - start).num_seconds();
+        months += Decimal::from(covered) / Decimal::from(length);
+        month_start = next_month;
+    }
+}
+
+impl FixedCost {
+    /// The cost allocated to each area over the window of the report
+    fn allocate(&self, report: &Report) -> Result<BTreeMap<Area, Decimal>> {
+        let weights: BTreeMap<Area, Decimal> = match &self.allocation {
+            Allocation::Volume { markets } => {
+                let markets = markets
+                    .iter()
+                    .map(|market| {
+                        Market::from_str(market)
+                            .map_err(|_| anyhow!("Unknown market in fixed cost: {}", market))
+                    })
+                    .collect::<Result<Vec<_>>>()?;
+                let mut volumes = BTreeMap::new();
+                for row in report.breakdown() {
+                    if markets.is_empty() || markets.contains(&row.market) {
+                        *volumes.entry(row.area).or_insert(Decimal::ZERO) += row.mw;
+                    }
+                }
+                volumes
+            }
+            Allocation::Shares(shares) => {
+                shares.iter().map(|(&area, &share)| (area, share)).collect()
+            }
+        };
+
+        let total: Decimal = weights.values().sum();
+        if total <= Decimal::ZERO {
+            bail!("Nothing to allocate fixed cost {} by", self.name);
+        }
+        let amount =
+            self.monthly_amount * months_in_window(report.delivery_from(), report.delivery_to())?;
+
+        Ok(weights
+            .into_iter()
+            .map(|(area, weight)| (area, amount * weight / total))
+            .collect())
+    }
+}
+
+/// The gross profit of each area in the report, less its share of the fixed costs
+pub fn net_results(report: &Report, costs: &[FixedCost]) -> Result<Vec<NetResult>> {
+    let mut allocated: BTreeMap<Area, Decimal> = report
+        .breakdown()
+        .into_iter()
+        .map(|row| (row.area, Decimal::ZERO))
+        .collect();
+    for cost in costs {
+        for (area, amount) in cost.allocate(report)? {
+            *allocated.entry(area).or_insert(Decimal::ZERO) += amount;
+        }
+    }
+
+    allocated
+        .into_iter()
+        .map(|(area, allocated_costs)| {
+            // An area settled in several currencies has no single gross profit to net costs from
+            let gross_profit = report.try_metric(
+                Metric::GrossProfit,
+                MarketSelection::All,
+                AreaSelection::Specific(area),
+            )?;
+            let allocated_costs = allocated_costs.round_dp(2);
+            Ok(NetResult {
+                area,
+                gross_profit,
+                allocated_costs,
+                net_result: gross_profit - allocated_costs,
+            })
+        })
+        .collect()
+}
+
+/// Rows of net results for a sink, with the allocated costs signed as costs
+pub fn records(results: &[NetResult], signs: SignConvention) -> Vec<Vec<String>> {
+    results
+        .iter()
+        .map(|result| {
+            vec![
+                result.area.to_string(),
+                result.gross_profit.to_string(),
+                signs
+                    .apply(Metric::Costs, result.allocated_costs)
+                    .to_string(),
+                result.net_result.to_string(),
+            ]
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::trade::{Trade, TradeType};
+    use chrono_tz::Europe::Copenhagen;
+    use rust_decimal_macros::dec;
+
+    #[test]
+    fn costs_are_prorated_to_the_window_and_allocated_by_volume() {
+        // Half of May
+        let from = Copenhagen.with_ymd_and_hms(2024, 5, 1, 0, 0, 0).unwrap();
+        let to = Copenhagen.with_ymd_and_hms(2024, 5, 16, 12, 0, 0).unwrap();
+        let trades = vec![
+            Trade::test()
+                .area(Area::DK1)
+                .mwh(30)
+                .price(10)
+                .hour("2024-05-01T12:00+02:00")
+                .build(),
+            Trade::test()
+                .area(Area::DK2)
+                .sell()
+                .mwh(10)
+                .price(10)
+                .hour("2024-05-01T12:00+02:00")
+                .build(),
+            // Not counted, as the cost is only allocated by intraday volume
+            Trade::test()
+                .area(Area::DK2)
+                .trade_type(TradeType::AuctionEurDahH)
+                .mwh(100)
+                .price(10)
+                .hour("2024-05-01T12:00+02:00")
+                .build(),
+        ];
+        let report = Report::new(&from, &to, trades).unwrap();
+        let costs: Vec<FixedCost> = toml::from_str::<HashMap<String, Vec<FixedCost>>>(
+            r#"
+            [[fixed_costs]]
+            name = "membership"
+            monthly_amount = 800
+            allocation = { volume = { markets = ["intraday"] } }
+            "#,
+        )
+        .unwrap()
+        .remove("fixed_costs")
+        .unwrap();
+
+        let results = net_results(&report, &costs).unwrap();
+
+        assert_eq!(
+            results,
+            vec![
+                NetResult {
+                    area: Area::DK1,
+                    gross_profit: dec!(-300),
+                    allocated_costs: dec!(300),
+                    net_result: dec!(-600),
+                },
+                NetResult {
+                    area: Area::DK2,
+                    gross_profit: dec!(-900),
+                    allocated_costs: dec!(100),
+                    net_result: dec!(-1000),
+                },
+            ]
+        );
+    }
+}
@@ -0,0 +1,247 @@
+use std::collections::BTreeMap;
+
+use anyhow::{anyhow, bail, Result};
+use chrono::{DateTime, Datelike, Days, FixedOffset, NaiveDate, TimeZone, Weekday};
+use chrono_tz::Tz;
+use rust_decimal::Decimal;
+use serde::Deserialize;
+
+use crate::trade::{Area, Trade, TradeSide};
+
+/// A block product the long-term hedges are made in, as local delivery hours, e.g.
+///
+/// ```toml
+/// [[blocks]]
+/// name = "base"
+///
+/// [[blocks]]
+/// name = "peak"
+/// start_hour = 8
+/// end_hour = 20
+/// weekdays_only = true
+///
+/// [[blocks]]
+/// name = "evening_flex"
+/// areas = ["GB"]
+/// timezone = "Europe/London"
+/// start_hour = 16
+/// end_hour = 19
+/// ```
+#[derive(Debug, Deserialize, Clone)]
+pub struct BlockDefinition {
+    pub name: String,
+    /// Areas the block is traded in, all areas if empty
+    #[serde(default)]
+    pub areas: Vec<Area>,
+    /// Timezone the hours are local to
+    #[serde(default = "default_timezone")]
+    pub timezone: String,
+    /// First local hour of each day in the block
+    #[serde(default)]
+    pub start_hour: u32,
+    /// Local hour each day of the block ends at, 24 for midnight
+    #[serde(default = "default_end_hour")]
+    pub end_hour: u32,
+    /// Whether Saturdays and Sundays are left out of the block
+    #[serde(default)]
+    pub weekdays_only: bool,
+}
+
+fn default_timezone() -> String {
+    "Europe/Copenhagen".to_string()
+}
+
+fn default_end_hour() -> u32 {
+    24
+}
+
+struct Block {
+    name: String,
+    areas: Vec<Area>,
+    timezone: Tz,
+    start_hour: u32,
+    end_hour: u32,
+    weekdays_only: bool,
+}
+
+impl Block {
+    fn from_definition(definition: &BlockDefinition) -> Result<Self> {
+        if definition.start_hour >= definition.end_hour || definition.end_hour > 24 {
+            bail!(
+                "Block {} has to start before it ends within a day, not {} - {}",
+                definition.name,
+                definition.start_hour,
+                definition.end_hour
+            );
+        }
+        Ok(Self {
+            name: definition.name.clone(),
+            areas: definition.areas.clone(),
+            timezone: definition
+                .timezone
+                .parse()
+                .map_err(|_| anyhow!("Unknown timezone in block: {}", definition.timezone))?,
+            start_hour: definition.start_hour,
+            end_hour: definition.end_hour,
+            weekdays_only: definition.weekdays_only,
+        })
+    }
+
+    fn local_hour(&self, date: NaiveDate, hour: u32) -> Result<DateTime<Tz>> {
+        let (date, hour) = match hour {
+            24 => (date + Days::new(1), 0),
+            hour => (date, hour),
+        };
+        date.and_hms_opt(hour, 0, 0)
+            .and_then(|local| self.timezone.from_local_datetime(&local).earliest())
+            .ok_or(anyhow!(
+                "No local hour {} on {} in {}",
+                hour,
+                date,
+                self.timezone
+            ))
+    }
+
+    /// Hours of the delivery period in the block, across DST changes
+    fn overlap_hours(
+        &self,
+        delivery_start: &DateTime<FixedOffset>,
+        delivery_end: &DateTime<FixedOffset>,
+    ) -> Result<Decimal> {
+        let mut seconds = 0;
+        let mut date = delivery_start.with_timezone(&self.timezone).date_naive();
+        let last = delivery_end.with_timezone(&self.timezone).date_naive();
+
+        while date <= last {
+            let weekend = matches!(date.weekday(), Weekday::Sat | Weekday::Sun);
+            if !(self.weekdays_only && weekend) {
+                let start = self.local_hour(date, self.start_hour)?;
+                let end = self.local_hour(date, self.end_hour)?;
+                let overlap = end.fixed_offset().min(*delivery_end)
+                    - start.fixed_offset().max(*delivery_start);
+                seconds += overlap.num_seconds().max(0);
+            }
+            date = date + Days::new(1);
+        }
+
+        Ok(Decimal::from(seconds) / Decimal::from(3600))
+    }
+}
+
+/// Traded energy per area and block. Blocks can overlap, e.g. peak is part of base, so each
+/// block is mapped independently. Trades without a price count, like in the forward curve, as
+/// they're part of the position either way.
+pub struct BlockVolumes {
+    blocks: Vec<Block>,
+    volumes: BTreeMap<(Area, usize, TradeSide), Decimal>,
+}
+
+impl BlockVolumes {
+    pub fn new(definitions: &[BlockDefinition]) -> Result<Self> {
+        if definitions.is_empty() {
+            bail!("No blocks configured");
+        }
+        Ok(Self {
+            blocks: definitions
+                .iter()
+                .map(Block::from_definition)
+                .collect::<Result<_>>()?,
+            volumes: BTreeMap::new(),
+        })
+    }
+
+    pub fn add_trade(&mut self, trade: &Trade) -> Result<()> {
+        let side = if trade.quantity_mwh < Decimal::ZERO {
+            TradeSide::Sell
+        } else {
+            TradeSide::Buy
+        };
+        for (index, block) in self.blocks.iter().enumerate() {
+            if !block.areas.is_empty() && !block.areas.contains(&trade.area) {
+                continue;
+            }
+            let hours = block.overlap_hours(&trade.delivery_start, &trade.delivery_end)?;
+            if hours.is_zero() {
+                continue;
+            }
+            *self
+                .volumes
+                .entry((trade.area, index, side))
+                .or_insert(Decimal::ZERO) += trade.quantity_mwh.abs() * hours;
+        }
+        Ok(())
+    }
+
+    /// Rows of bought, sold and net energy per area and block, in the order the blocks are
+    /// configured
+    pub fn records(&self) -> Vec<Vec<String>> {
+        let mut rows: BTreeMap<(Area, usize), (Decimal, Decimal)> = BTreeMap::new();
+        for (&(area, index, side), &mwh) in self.volumes.iter() {
+            let row = rows.entry((area, index)).or_default();
+            match side {
+                TradeSide::Buy => row.0 += mwh,
+                TradeSide::Sell => row.1 += mwh,
+            }
+        }
+
+        rows.into_iter()
+            .map(|((area, index), (bought, sold))| {
+                vec![
+                    area.to_string(),
+                    self.blocks[index].name.clone(),
+                    bought.round_dp(1).to_string(),
+                    sold.round_dp(1).to_string(),
+                    (bought - sold).round_dp(1).to_string(),
+                ]
+            })
+            .collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn volume_is_mapped_onto_the_hours_of_each_block() {
+        let definitions: Vec<BlockDefinition> =
+            toml::from_str::<std::collections::HashMap<String, Vec<BlockDefinition>>>(
+                r#"
+            [[blocks]]
+            name = "base"
+
+            [[blocks]]
+            name = "peak"
+            start_hour = 8
+            end_hour = 20
+            weekdays_only = true
+            "#,
+            )
+            .unwrap()
+            .remove("blocks")
+            .unwrap();
+        let mut volumes = BlockVolumes::new(&definitions).unwrap();
+
+        // Friday 19:00 - Saturday 09:00, of which only Friday 19:00 - 20:00 is peak
+        let mut trade = Trade::test().mwh(10).hour("2024-05-03T19:00+02:00").build();
+        trade.delivery_end = DateTime::parse_from_rfc3339("2024-05-04T09:00:00+02:00").unwrap();
+        volumes.add_trade(&trade).unwrap();
+        volumes
+            .add_trade(
+                &Trade::test()
+                    .sell()
+                    .mwh(4)
+                    .hour("2024-05-03T12:00+02:00")
+                    .build(),
+            )
+            .unwrap();
+
+        assert_eq!(
+            volumes.records(),
+            vec![
+                vec!["DK1", "base", "140", "4", "136"],
+                vec!["DK1", "peak", "10", "4", "6"],
+            ]
+        );
+    }
+}
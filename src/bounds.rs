@@ -0,0 +1,326 @@
+use anyhow::{anyhow, bail, Result};
+use chrono::{
+    DateTime, Datelike, Duration, LocalResult, NaiveDate, NaiveDateTime, NaiveTime, Offset,
+    TimeZone,
+};
+use chrono_tz::{Europe::Copenhagen, Tz};
+use serde::Deserialize;
+
+/// Which end of a delivery window a bound is, deciding how local times are resolved
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Bound {
+    /// The bound starts at the given time, or at midnight when only a day is given
+    StartOfDay,
+    /// The bound ends at the given time, or at the following midnight when only a day is given,
+    /// so the day is included in the window
+    EndOfDay,
+}
+
+/// Which trades a delivery window selects from the trade tables, set in the config:
+///
+/// ```toml
+/// window_filter = "overlap"
+/// ```
+#[derive(Debug, Deserialize, Default, Clone, Copy, PartialEq)]
+#[serde(rename_all = "snake_case")]
+pub enum WindowFilter {
+    /// Trades whose delivery starts in the window, which the indexes and partitions on
+    /// `delivery_start` serve directly
+    #[default]
+    DeliveryStart,
+    /// Trades delivered at any time in the window, including long contracts that started
+    /// before it. Partitions can only be pruned by the end of the window.
+    Overlap,
+}
+
+/// Local times given for a bound, most specific first
+const LOCAL_FORMATS: [&str; 3] = ["%Y-%m-%dT%H:%M:%S", "%Y-%m-%dT%H:%M", "%Y-%m-%d %H:%M"];
+
+/// Parses a delivery bound given as a day, `2024-03-31`, a local time, `2024-03-31T02:30`, or a
+/// time with an offset, `2024-03-31T02:30:00+01:00`, in the timezone. Local times that don't
+/// exist, in the hour skipped when clocks spring forward, resolve to when the clocks jumped.
+/// Local times that exist twice, when clocks fall back, resolve to the first of them for the
+/// start of a window and the second for its end, so the window covers the whole hour.
+pub fn parse_delivery_bound(input: &str, timezone: Tz, bound: Bound) -> Result<DateTime<Tz>> {
+    let input = input.trim();
+    if let Ok(time) = DateTime::parse_from_rfc3339(input) {
+        return Ok(time.with_timezone(&timezone));
+    }
+    if let Some(local) = LOCAL_FORMATS
+        .iter()
+        .find_map(|format| NaiveDateTime::parse_from_str(input, format).ok())
+    {
+        return resolve_local(local, timezone, bound);
+    }
+    let Ok(day) = NaiveDate::parse_from_str(input, "%Y-%m-%d") else {
+        bail!(
+            "Expected a day as YYYY-MM-DD or a time as YYYY-MM-DDTHH:MM, not {}",
+            input
+        );
+    };
+    let midnight = match bound {
+        Bound::StartOfDay => day,
+        Bound::EndOfDay => day
+            .succ_opt()
+            .ok_or_else(|| anyhow!("No day after {}", day))?,
+    };
+    resolve_local(midnight.and_time(NaiveTime::MIN), timezone, bound)
+}
+
+/// Parses either end of a delivery window as given to the commands with `--from` and `--to`, and
+/// to the server: local to Copenhagen, with a day given for either end starting at its midnight,
+/// so a window ends before the day given for its end.
+pub fn parse_delivery_day(input: &str) -> Result<DateTime<Tz>> {
+    parse_delivery_bound(input, Copenhagen, Bound::StartOfDay)
+}
+
+/// The instant of a local time, see `parse_delivery_bound` for how DST changes are resolved
+fn resolve_local(local: NaiveDateTime, timezone: Tz, bound: Bound) -> Result<DateTime<Tz>> {
+    match timezone.from_local_datetime(&local) {
+        LocalResult::Single(time) => Ok(time),
+        LocalResult::Ambiguous(first, second) => Ok(match bound {
+            Bound::StartOfDay => first,
+            Bound::EndOfDay => second,
+        }),
+        // The first local minute after the gap is when the clocks jumped
+        LocalResult::None => (1..=24 * 60)
+            .map(|minutes| local + Duration::minutes(minutes))
+            .find_map(|later| timezone.from_local_datetime(&later).earliest())
+            .ok_or_else(|| anyhow!("No local time {} in {}", local, timezone)),
+    }
+}
+
+/// Describes a delivery window for people reading an output without its context, e.g.
+/// `October 2024, Europe/Copenhagen, 745 hours incl. DST transition on Oct 27`. Windows of whole
+/// local days are named by their month or year when they cover exactly one, otherwise by their
+/// first and last day.
+pub fn describe_window(from: &DateTime<Tz>, to: &DateTime<Tz>) -> String {
+    let timezone = from.timezone();
+    let to = to.with_timezone(&timezone);
+    let period = match (is_midnight(from), is_midnight(&to)) {
+        (true, true) => describe_days(from.date_naive(), to.date_naive()),
+        _ => format!(
+            "{} - {}",
+            from.format("%Y-%m-%dT%H:%M"),
+            to.format("%Y-%m-%dT%H:%M")
+        ),
+    };
+
+    let minutes = (to - *from).num_minutes();
+    let mut description = format!(
+        "{}, {}, {}",
+        period,
+        timezone.name(),
+        plural(minutes / 60, "hour")
+    );
+    if minutes % 60 != 0 {
+        description += &format!(" {}", plural(minutes % 60, "minute"));
+    }
+
+    let transitions = dst_transitions(from, &to);
+    if let Some((last, first)) = transitions.split_last() {
+        let day = |time: &DateTime<Tz>| time.format("%b %-d").to_string();
+        description += &match first {
+            [] => format!(" incl. DST transition on {}", day(last)),
+            _ => format!(
+                " incl. DST transitions on {} and {}",
+                first.iter().map(day).collect::<Vec<_>>().join(", "),
+                day(last)
+            ),
+        };
+    }
+    description
+}
+
+/// Labels the local hour of a time, e.g. `02`. The hour repeated when the clocks fall back is
+/// labelled `02A` the first time and `02B` the second, telling them apart by their offset so
+/// their volumes are neither merged nor dropped, and sorting them in the order they occur.
+pub fn local_hour_label(time: &DateTime<Tz>) -> String {
+    let hour = time.format("%H");
+    match time.timezone().from_local_datetime(&time.naive_local()) {
+        LocalResult::Ambiguous(first, _) if first.offset().fix() == time.offset().fix() => {
+            format!("{}A", hour)
+        }
+        LocalResult::Ambiguous(..) => format!("{}B", hour),
+        _ => hour.to_string(),
+    }
+}
+
+/// The local hour a time is in as `2024-10-27T02:00+01:00`, with the hour labelled by
+/// `local_hour_label`, e.g. `2024-10-27T02B:00+01:00`
+pub fn format_local_hour(time: &DateTime<Tz>) -> String {
+    format!(
+        "{}T{}:00{}",
+        time.format("%Y-%m-%d"),
+        local_hour_label(time),
+        time.format("%:z")
+    )
+}
+
+/// A local time to the minute as `2024-10-27T02:15+01:00`, with the hour labelled by
+/// `local_hour_label`
+pub fn format_local_minute(time: &DateTime<Tz>) -> String {
+    format!(
+        "{}T{}:{}",
+        time.format("%Y-%m-%d"),
+        local_hour_label(time),
+        time.format("%M%:z")
+    )
+}
+
+fn is_midnight(time: &DateTime<Tz>) -> bool {
+    time.time() == NaiveTime::MIN
+}
+
+fn plural(count: i64, unit: &str) -> String {
+    match count {
+        1 => format!("1 {}", unit),
+        _ => format!("{} {}s", count, unit),
+    }
+}
+
+/// Names the local days from `first` up to, but excluding, `end`
+fn describe_days(first: NaiveDate, end: NaiveDate) -> String {
+    let last = end.pred_opt().unwrap_or(first);
+    let next_month = |day: NaiveDate| {
+        let (year, month) = match day.month() {
+            12 => (day.year() + 1, 1),
+            month => (day.year(), month + 1),
+        };
+        NaiveDate::from_ymd_opt(year, month, 1)
+    };
+
+    if first.ordinal() == 1 && NaiveDate::from_yo_opt(first.year() + 1, 1) == Some(end) {
+        first.format("%Y").to_string()
+    } else if first.day() == 1 && next_month(first) == Some(end) {
+        first.format("%B %Y").to_string()
+    } else if first == last {
+        first.format("%-d %B %Y").to_string()
+    } else {
+        format!("{} - {}", first.format("%Y-%m-%d"), last.format("%Y-%m-%d"))
+    }
+}
+
+/// Instants within the window at which the offset of its timezone changes. The offsets change
+/// on whole hours in the timezones we trade in, so the window is stepped through hourly.
+fn dst_transitions(from: &DateTime<Tz>, to: &DateTime<Tz>) -> Vec<DateTime<Tz>> {
+    let mut transitions = Vec::new();
+    let mut time = *from;
+    while time < *to {
+        let next = (time + Duration::hours(1)).min(*to);
+        if next.offset().fix() != time.offset().fix() {
+            transitions.push(next);
+        }
+        time = next;
+    }
+    transitions
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use chrono_tz::Europe::Copenhagen;
+
+    #[test]
+    fn local_times_around_dst_changes_resolve_without_panicking() {
+        let bound = |input, bound| {
+            parse_delivery_bound(input, Copenhagen, bound)
+                .unwrap()
+                .to_rfc3339()
+        };
+        assert_eq!(
+            bound("2024-03-31", Bound::StartOfDay),
+            "2024-03-31T00:00:00+01:00"
+        );
+        assert_eq!(
+            bound("2024-03-31", Bound::EndOfDay),
+            "2024-04-01T00:00:00+02:00"
+        );
+        // Skipped when the clocks spring forward from 02:00 to 03:00
+        assert_eq!(
+            bound("2024-03-31T02:30", Bound::StartOfDay),
+            "2024-03-31T03:00:00+02:00"
+        );
+        // Happens twice when the clocks fall back from 03:00 to 02:00
+        assert_eq!(
+            bound("2024-10-27T02:30", Bound::StartOfDay),
+            "2024-10-27T02:30:00+02:00"
+        );
+        assert_eq!(
+            bound("2024-10-27T02:30", Bound::EndOfDay),
+            "2024-10-27T02:30:00+01:00"
+        );
+        assert_eq!(
+            bound("2024-10-27T01:30:00Z", Bound::EndOfDay),
+            "2024-10-27T02:30:00+01:00"
+        );
+
+        assert!(parse_delivery_bound("2024-02-30", Copenhagen, Bound::StartOfDay).is_err());
+        assert!(parse_delivery_bound("31-03-2024", Copenhagen, Bound::StartOfDay).is_err());
+    }
+
+    #[test]
+    fn the_repeated_hour_is_labelled_by_its_occurrence() {
+        let hour = |utc: &str| {
+            let time = DateTime::parse_from_rfc3339(utc)
+                .unwrap()
+                .with_timezone(&Copenhagen);
+            format_local_hour(&time)
+        };
+        let hours: Vec<String> = [
+            "2024-10-26T23:00:00Z",
+            "2024-10-27T00:00:00Z",
+            "2024-10-27T01:00:00Z",
+            "2024-10-27T02:00:00Z",
+        ]
+        .into_iter()
+        .map(hour)
+        .collect();
+        assert_eq!(
+            hours,
+            [
+                "2024-10-27T01:00+02:00",
+                "2024-10-27T02A:00+02:00",
+                "2024-10-27T02B:00+01:00",
+                "2024-10-27T03:00+01:00"
+            ]
+        );
+        // Labels sort in the order the hours occur
+        assert!(hours.windows(2).all(|pair| pair[0] < pair[1]));
+
+        let quarter = DateTime::parse_from_rfc3339("2024-10-27T01:15:00Z")
+            .unwrap()
+            .with_timezone(&Copenhagen);
+        assert_eq!(format_local_minute(&quarter), "2024-10-27T02B:15+01:00");
+    }
+
+    #[test]
+    fn windows_are_described_by_their_period_length_and_dst_transitions() {
+        let window = |from, to| {
+            describe_window(
+                &parse_delivery_bound(from, Copenhagen, Bound::StartOfDay).unwrap(),
+                &parse_delivery_bound(to, Copenhagen, Bound::StartOfDay).unwrap(),
+            )
+        };
+        assert_eq!(
+            window("2024-10-01", "2024-11-01"),
+            "October 2024, Europe/Copenhagen, 745 hours incl. DST transition on Oct 27"
+        );
+        assert_eq!(
+            window("2024-01-01", "2025-01-01"),
+            "2024, Europe/Copenhagen, 8784 hours incl. DST transitions on Mar 31 and Oct 27"
+        );
+        assert_eq!(
+            window("2024-03-31", "2024-04-01"),
+            "31 March 2024, Europe/Copenhagen, 23 hours incl. DST transition on Mar 31"
+        );
+        assert_eq!(
+            window("2024-05-01", "2024-05-15"),
+            "2024-05-01 - 2024-05-14, Europe/Copenhagen, 336 hours"
+        );
+        assert_eq!(
+            window("2024-05-01T06:00", "2024-05-01T09:30"),
+            "2024-05-01T06:00 - 2024-05-01T09:30, Europe/Copenhagen, 3 hours 30 minutes"
+        );
+    }
+}
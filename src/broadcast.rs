@@ -0,0 +1,225 @@
+use std::collections::{BTreeMap, HashMap};
+
+use anyhow::{anyhow, Context, Result};
+use redis::AsyncCommands;
+use rust_decimal::Decimal;
+use serde::{Deserialize, Serialize};
+use tokio::sync::mpsc;
+
+use crate::report::{Metric, Report};
+use crate::secrets::Secrets;
+use crate::trade::{Area, AreaSelection, Currency, Market, MarketSelection};
+
+/// Metrics published per area and market. Price statistics are left out, as they'd sort all
+/// prices of a cell on every update.
+const BROADCAST_METRICS: [Metric; 7] = [
+    Metric::MwBought,
+    Metric::MwSold,
+    Metric::Revenue,
+    Metric::Costs,
+    Metric::GrossProfit,
+    Metric::NetPosition,
+    Metric::NetCashFlow,
+];
+
+/// Where watch mode publishes metric updates, e.g.
+///
+/// ```toml
+/// [broadcast]
+/// url_secret = "REDIS_URL"
+/// channel_prefix = "metrics"
+/// ```
+#[derive(Debug, Deserialize, Clone)]
+#[serde(default)]
+pub struct BroadcastConfig {
+    /// Secret holding the URL of the Redis server, e.g. `redis://localhost:6379`
+    pub url_secret: String,
+    /// Updates of an area and market go to `<prefix>:<area>:<market>`, e.g. `metrics:DK1:intraday`
+    pub channel_prefix: String,
+}
+
+impl Default for BroadcastConfig {
+    fn default() -> Self {
+        Self {
+            url_secret: "REDIS_URL".to_string(),
+            channel_prefix: "metrics".to_string(),
+        }
+    }
+}
+
+/// The metrics of an area and market after a sync, published as JSON. The trades of an area
+/// settled in several currencies are published as an update per currency, as their cash flows
+/// aren't summed.
+#[derive(Debug, Serialize, PartialEq)]
+pub struct MetricUpdate {
+    pub area: Area,
+    pub currency: Currency,
+    pub market: Market,
+    /// Values by metric name, e.g. `gross_profit`
+    pub metrics: BTreeMap<String, Decimal>,
+}
+
+impl MetricUpdate {
+    pub fn channel(&self, prefix: &str) -> String {
+        format!("{}:{}:{}", prefix, self.area, self.market)
+    }
+}
+
+/// The metrics last published per area, currency and market, so only cells that changed are
+/// published again
+#[derive(Debug, Default)]
+pub struct MetricChanges {
+    published: HashMap<(Area, Currency, Market), BTreeMap<String, Decimal>>,
+}
+
+impl MetricChanges {
+    /// Updates of the areas, currencies and markets whose metrics changed since the last call
+    pub fn updates(&mut self, report: &Report) -> Vec<MetricUpdate> {
+        let mut cells: Vec<(Area, Currency, Market)> = report
+            .breakdown()
+            .iter()
+            .map(|row| (row.area, row.currency, row.market))
+            .collect();
+        cells.sort();
+        cells.dedup();
+
+        cells
+            .into_iter()
+            .filter_map(|(area, currency, market)| {
+                let report = report.in_currency(currency);
+                let metrics: BTreeMap<String, Decimal> = BROADCAST_METRICS
+                    .into_iter()
+                    .map(|metric| {
+                        let value = report.metric(
+                            metric,
+                            MarketSelection::Specific(market),
+                            AreaSelection::Specific(area),
+                        );
+                        (metric.to_string(), value)
+                    })
+                    .collect();
+                if self.published.get(&(area, currency, market)) == Some(&metrics) {
+                    return None;
+                }
+                self.published
+                    .insert((area, currency, market), metrics.clone());
+                Some(MetricUpdate {
+                    area,
+                    currency,
+                    market,
+                    metrics,
+                })
+            })
+            .collect()
+    }
+}
+
+/// Publishes the metrics that changed to Redis pub/sub channels per area and market, so any
+/// number of dashboards, alerts and bots can subscribe to them. Publishing runs on a task of its
+/// own, so a slow or unavailable Redis doesn't hold up the syncs; updates that fail to publish
+/// are reported and dropped, as the next update of the cell supersedes them.
+pub struct Broadcaster {
+    changes: MetricChanges,
+    channel_prefix: String,
+    updates: mpsc::UnboundedSender<(String, String)>,
+}
+
+impl Broadcaster {
+    pub async fn connect(config: &BroadcastConfig, secrets: &Secrets) -> Result<Self> {
+        let url = secrets.get(&config.url_secret).await?;
+        let mut connection = redis::Client::open(url)?
+            .get_multiplexed_async_connection()
+            .await
+            .context("Could not connect to Redis to broadcast metrics")?;
+
+        let (updates, mut receiver) = mpsc::unbounded_channel::<(String, String)>();
+        tokio::spawn(async move {
+            while let Some((channel, payload)) = receiver.recv().await {
+                let published: redis::RedisResult<()> = connection.publish(&channel, payload).await;
+                if let Err(err) = published {
+                    eprintln!("Warning: could not publish metrics to {}: {}", channel, err);
+                }
+            }
+        });
+
+        Ok(Self {
+            changes: MetricChanges::default(),
+            channel_prefix: config.channel_prefix.clone(),
+            updates,
+        })
+    }
+
+    /// Queues the metrics of the areas and markets that changed since the last call
+    pub fn publish(&mut self, report: &Report) -> Result<()> {
+        for update in self.changes.updates(report) {
+            let channel = update.channel(&self.channel_prefix);
+            self.updates
+                .send((channel, serde_json::to_string(&update)?))
+                .map_err(|_| anyhow!("Metric broadcast has stopped"))?;
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::trade::Trade;
+    use chrono::TimeZone;
+    use chrono_tz::Europe::Copenhagen;
+    use rust_decimal_macros::dec;
+
+    #[test]
+    fn only_cells_that_changed_are_published_again() {
+        let from = Copenhagen.with_ymd_and_hms(2024, 5, 1, 0, 0, 0).unwrap();
+        let to = Copenhagen.with_ymd_and_hms(2024, 5, 2, 0, 0, 0).unwrap();
+        let mut report = Report::empty(&from, &to).unwrap();
+        let mut changes = MetricChanges::default();
+        for trade in [
+            Trade::test().area(Area::DK1).mwh(10).price(40).build(),
+            Trade::test().area(Area::DK2).mwh(5).price(40).build(),
+        ] {
+            report.add_trade(&trade).unwrap();
+        }
+
+        let updates = changes.updates(&report);
+        assert_eq!(updates.len(), 2);
+        assert_eq!(updates[0].channel("metrics"), "metrics:DK1:intraday");
+        assert_eq!(updates[0].metrics["gross_profit"], dec!(-400));
+
+        report
+            .add_trade(
+                &Trade::test()
+                    .area(Area::DK2)
+                    .sell()
+                    .mwh(5)
+                    .price(50)
+                    .build(),
+            )
+            .unwrap();
+        let updates = changes.updates(&report);
+        assert_eq!(updates.len(), 1);
+        assert_eq!(updates[0].area, Area::DK2);
+        assert_eq!(updates[0].metrics["net_position"], dec!(0));
+        assert!(changes.updates(&report).is_empty());
+
+        // Cash flows of another currency in the cell are published apart rather than summed
+        report
+            .add_trade(
+                &Trade::test()
+                    .area(Area::DK1)
+                    .currency(Currency::Gbp)
+                    .mwh(1)
+                    .price(30)
+                    .build(),
+            )
+            .unwrap();
+        let updates = changes.updates(&report);
+        assert_eq!(updates.len(), 1);
+        assert_eq!(
+            (updates[0].area, updates[0].currency),
+            (Area::DK1, Currency::Gbp)
+        );
+        assert_eq!(updates[0].metrics["gross_profit"], dec!(-30));
+    }
+}
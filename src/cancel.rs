@@ -0,0 +1,105 @@
+use std::fmt;
+use std::future::Future;
+use std::io;
+
+use anyhow::Result;
+use futures::{stream, StreamExt};
+pub use tokio_util::sync::CancellationToken;
+
+use crate::db::TradeStream;
+
+/// Exit code of a command stopped by cancelling it, as by SIGINT in a shell
+pub const EXIT_CANCELLED: i32 = 130;
+
+/// The error of an operation stopped through its `CancellationToken`
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Cancelled;
+
+impl fmt::Display for Cancelled {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str("Cancelled")
+    }
+}
+
+impl std::error::Error for Cancelled {}
+
+/// Fails with `Cancelled` once the token is cancelled, to check between the steps of an operation
+pub fn check(token: &CancellationToken) -> Result<()> {
+    if token.is_cancelled() {
+        return Err(Cancelled.into());
+    }
+    Ok(())
+}
+
+/// Runs the operation unless the token is cancelled first, dropping it at the await point it's
+/// waiting on
+pub async fn or_cancelled<T>(
+    token: &CancellationToken,
+    operation: impl Future<Output = Result<T>>,
+) -> Result<T> {
+    tokio::select! {
+        biased;
+        _ = token.cancelled() => Err(Cancelled.into()),
+        result = operation => result,
+    }
+}
+
+/// Ends the trades with an error once the token is cancelled, so whatever aggregates them fails
+/// with `Cancelled` rather than finishing on part of the trades. The error is an interrupted
+/// `sqlx::Error::Io` around `Cancelled`, which `is_cancelled` recognizes.
+pub fn until_cancelled(trades: TradeStream<'_>, token: CancellationToken) -> TradeStream<'_> {
+    let ended = token.clone();
+    let cancellation =
+        stream::once(async move { ended.is_cancelled() }).filter_map(|cancelled| async move {
+            cancelled.then(|| {
+                Err(sqlx::Error::Io(io::Error::new(
+                    io::ErrorKind::Interrupted,
+                    Cancelled,
+                )))
+            })
+        });
+    Box::pin(
+        trades
+            .take_until(token.cancelled_owned())
+            .chain(cancellation),
+    )
+}
+
+/// Whether the error was caused by cancelling the operation
+pub fn is_cancelled(error: &anyhow::Error) -> bool {
+    error.chain().any(|cause| {
+        cause.is::<Cancelled>()
+            || cause
+                .downcast_ref::<io::Error>()
+                .and_then(io::Error::get_ref)
+                .is_some_and(|inner| inner.is::<Cancelled>())
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::db::trade_stream;
+    use crate::report::Report;
+    use crate::trade::Trade;
+    use chrono::TimeZone;
+    use chrono_tz::Europe::Copenhagen;
+
+    #[tokio::test]
+    async fn cancelled_streams_fail_the_report_instead_of_finishing_it() {
+        let from = Copenhagen.with_ymd_and_hms(2024, 5, 1, 0, 0, 0).unwrap();
+        let to = Copenhagen.with_ymd_and_hms(2024, 5, 2, 0, 0, 0).unwrap();
+        let trades = || trade_stream(vec![Trade::test().mwh(10).price(40).build()]);
+
+        let token = CancellationToken::new();
+        let report = Report::new_from_stream(&from, &to, until_cancelled(trades(), token.clone()));
+        assert!(report.await.is_ok());
+
+        token.cancel();
+        let error = Report::new_from_stream(&from, &to, until_cancelled(trades(), token))
+            .await
+            .unwrap_err();
+        assert!(is_cancelled(&error));
+        assert!(!is_cancelled(&anyhow::anyhow!("Connection refused")));
+    }
+}
@@ -0,0 +1,124 @@
+use anyhow::{bail, Result};
+use chrono::DateTime;
+use chrono_tz::Tz;
+use rust_decimal::Decimal;
+use sqlx::PgPool;
+
+use crate::db::TradeTable;
+use crate::schema::CLEARED_QUANTITY_COLUMN;
+use crate::trade::TradeType;
+use crate::units::{QuantityUnit, QuantityUnits};
+
+/// Submitted and cleared energy of an auction product. Orders can be partially filled, in which
+/// case `cleared_quantity_mwh` holds the filled part of `quantity_mwh`. Rows without it, like
+/// the ones stored before fills were recorded, are taken to have cleared fully.
+#[derive(Debug, PartialEq)]
+pub struct ProductClearing {
+    pub trade_type: TradeType,
+    pub submitted_mwh: Decimal,
+    pub cleared_mwh: Decimal,
+}
+
+impl ProductClearing {
+    /// Share of the submitted volume that cleared, `None` without submitted volume
+    pub fn clearing_ratio(&self) -> Option<Decimal> {
+        if self.submitted_mwh.is_zero() {
+            return None;
+        }
+        Some((self.cleared_mwh / self.submitted_mwh).round_dp(4))
+    }
+
+    pub fn record(&self) -> Vec<String> {
+        vec![
+            self.trade_type.to_string(),
+            self.submitted_mwh.round_dp(1).to_string(),
+            self.cleared_mwh.round_dp(1).to_string(),
+            self.clearing_ratio()
+                .map_or(String::new(), |ratio| ratio.to_string()),
+        ]
+    }
+}
+
+/// Submitted and cleared energy per auction product over the delivery window. Volumes are
+/// energy like in reports, i.e. quantities in MW are multiplied with the contract length.
+pub async fn clearing_by_product(
+    pool: &PgPool,
+    delivery_from: &DateTime<Tz>,
+    delivery_to: &DateTime<Tz>,
+    units: &QuantityUnits,
+) -> Result<Vec<ProductClearing>> {
+    let has_fills: bool = sqlx::query_scalar(
+        "
+    SELECT EXISTS (
+        SELECT 1 FROM information_schema.columns
+        WHERE table_schema = current_schema() AND table_name = $1 AND column_name = $2
+    )",
+    )
+    .bind(TradeTable::Auction.table_name())
+    .bind(CLEARED_QUANTITY_COLUMN)
+    .fetch_one(pool)
+    .await?;
+    if !has_fills {
+        bail!(
+            "{} has no {} column to tell cleared from submitted volume",
+            TradeTable::Auction.table_name(),
+            CLEARED_QUANTITY_COLUMN
+        );
+    }
+
+    // Quantities already in MWh are energy, MW have to be multiplied with the contract length
+    let hours = match units.unit(TradeTable::Auction) {
+        QuantityUnit::Mw => "EXTRACT(EPOCH FROM delivery_end - delivery_start)::numeric / 3600",
+        QuantityUnit::Mwh => "1",
+    };
+    let query = format!(
+        "
+    SELECT trade_type,
+        SUM(ABS(quantity_mwh) * {0}) AS submitted_mwh,
+        SUM(ABS(COALESCE({1}, quantity_mwh)) * {0}) AS cleared_mwh
+    FROM {2}
+    WHERE delivery_start >= $1 AND delivery_start < $2
+    GROUP BY trade_type
+    ORDER BY trade_type",
+        hours,
+        CLEARED_QUANTITY_COLUMN,
+        TradeTable::Auction.table_name()
+    );
+
+    let rows: Vec<(String, Decimal, Decimal)> = sqlx::query_as(&query)
+        .bind(delivery_from)
+        .bind(delivery_to)
+        .fetch_all(pool)
+        .await?;
+
+    rows.into_iter()
+        .map(|(trade_type, submitted_mwh, cleared_mwh)| {
+            Ok(ProductClearing {
+                trade_type: TradeType::try_from(trade_type)?,
+                submitted_mwh,
+                cleared_mwh,
+            })
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use rust_decimal_macros::dec;
+
+    #[test]
+    fn clearing_ratio_is_the_cleared_share_of_submitted_volume() {
+        let product = |submitted_mwh, cleared_mwh| ProductClearing {
+            trade_type: TradeType::AuctionGbDahHh,
+            submitted_mwh,
+            cleared_mwh,
+        };
+
+        assert_eq!(
+            product(dec!(40), dec!(30)).record(),
+            vec!["auction_gb_dah_hh", "40", "30", "0.75"]
+        );
+        assert_eq!(product(dec!(0), dec!(0)).clearing_ratio(), None);
+    }
+}
@@ -0,0 +1,545 @@
+use std::path::PathBuf;
+
+use anyhow::{bail, Result};
+use chrono::{DateTime, FixedOffset};
+use chrono_tz::Tz;
+use clap::{Args, Parser, Subcommand, ValueEnum};
+use rust_decimal::Decimal;
+use trading_results_rs::bounds::parse_delivery_day;
+use trading_results_rs::hierarchy::AreaHierarchy;
+use trading_results_rs::report::ReportInput;
+use trading_results_rs::search::{SearchCursor, TradeSearch};
+use trading_results_rs::trade::{
+    Area, AreaSelection, CounterPart, CounterPartSelection, Market, MarketSelection, TradeSide,
+};
+
+/// Aggregates trades into reports, pipelines and snapshots. Options go before the command,
+/// e.g. `--from 2024-05-01 --to 2024-06-01 report`.
+#[derive(Debug, Parser)]
+pub struct Cli {
+    /// First delivery day of the window, or a local time like 2024-05-01T06:00, in Copenhagen
+    #[arg(long, default_value = "2024-01-01", value_parser = parse_delivery_day)]
+    pub from: DateTime<Tz>,
+    /// Delivery day or local time the window ends at, exclusive, in Copenhagen
+    #[arg(long, default_value = "2024-11-01", value_parser = parse_delivery_day)]
+    pub to: DateTime<Tz>,
+    /// Area the key metrics are shown for, can be given several times. All areas if not given
+    #[arg(long)]
+    pub area: Vec<Area>,
+    /// Node of `area_hierarchy` in the config the key metrics are shown for, e.g. a country
+    #[arg(long, conflicts_with = "area")]
+    pub region: Option<String>,
+    /// Market the key metrics are shown for, can be given several times. All markets if not given
+    #[arg(long)]
+    pub market: Vec<Market>,
+    /// Counter part whose trades the reports of the strategies aggregate, can be given several
+    /// times or separated by commas. All counter parts if not given
+    #[arg(long, value_delimiter = ',')]
+    pub counter_part: Vec<CounterPart>,
+    /// Strategy creating the report, can be given several times. All of them if not given
+    #[arg(long, value_enum)]
+    pub strategy: Vec<Strategy>,
+    /// Format the reports of the strategies are printed in
+    #[arg(long, value_enum, default_value_t = OutputFormat::Text)]
+    pub output: OutputFormat,
+    /// Sink under `report_sinks` in the config the reports are delivered to as well, can be given
+    /// several times
+    #[arg(long)]
+    pub sink: Vec<String>,
+    /// Saves the report of each strategy to the reports table, see `Report::save`
+    #[arg(long)]
+    pub save: bool,
+    /// CSV file the average profit and volume per hour of the week and area are written to,
+    /// aggregated in the same pass as the report of the stream strategy
+    #[arg(long)]
+    pub seasonality: Option<PathBuf>,
+    /// Fails on warnings, e.g. skipped trades
+    #[arg(long)]
+    pub strict: bool,
+    /// Config profile to merge into the config, `CONFIG_PROFILE` if not given
+    #[arg(long)]
+    pub profile: Option<String>,
+    /// Without a command the report is created with each strategy, like with `report`
+    #[command(subcommand)]
+    pub command: Option<Command>,
+}
+
+#[derive(Debug, Subcommand)]
+pub enum Command {
+    /// Creates the report with each strategy, or manages the report definitions saved in the
+    /// database
+    Report {
+        #[command(subcommand)]
+        saved: Option<SavedReportCommand>,
+    },
+    /// Writes the breakdown of the report as CSV
+    Export {
+        /// File the CSV is written to, stdout if not given. With --parquet, the directory the
+        /// Parquet files are written to
+        path: Option<PathBuf>,
+        /// Writes report.parquet instead of CSV
+        #[cfg(feature = "parquet")]
+        #[arg(long, requires = "path")]
+        parquet: bool,
+        /// Writes the trades to trades.parquet as well
+        #[cfg(feature = "parquet")]
+        #[arg(long, requires = "parquet")]
+        trades: bool,
+    },
+    /// Aggregates a file of trades into a report without the database, in the columns of
+    /// `csv_mapping` in the config
+    Ingest {
+        /// The file, or `-` for stdin with --ndjson
+        path: String,
+        /// Reads JSON lines with the fields of `json_mapping` in the config
+        #[arg(long)]
+        ndjson: bool,
+    },
+    /// Checks every row of an exchange file, reporting the invalid ones. Without a file, scans
+    /// the trade tables for bad data in the window
+    Validate { path: Option<PathBuf> },
+    /// Lists, inspects or cancels the reports a running `serve` is building
+    Jobs {
+        /// Address of the report server
+        #[arg(long, default_value = "http://127.0.0.1:8080")]
+        server: String,
+        #[command(subcommand)]
+        command: JobsCommand,
+    },
+    /// Creates the report from synthetic trades delivered in the first week of May 2024, bundled
+    /// in an in-memory SQLite database, so reports, exports and the API run without a database
+    #[cfg(feature = "sqlite")]
+    Demo {
+        /// Directory the breakdown CSV and the report as JSON are written to as well
+        #[arg(long)]
+        out: Option<PathBuf>,
+        /// Serves report metrics of the demo trades on the address instead, e.g. 127.0.0.1:8080
+        #[cfg(feature = "server")]
+        #[arg(long)]
+        serve: Option<String>,
+    },
+    /// Checks a file against its detached signature
+    Verify {
+        path: PathBuf,
+        /// The signature, `<path>.sig` if not given
+        signature: Option<PathBuf>,
+    },
+    /// Lists the counter parts of the config with their kind, currency, fees and VAT treatment
+    CounterParts,
+    /// Migrates the records of the snapshot log to the current schema version
+    MigrateSnapshot,
+    /// Checks the columns and values of the trade tables, and that the reports table exists
+    CheckSchema,
+    /// Replays the trades of the window in delivery order into the report
+    Replay {
+        /// Speed relative to delivery time, e.g. 3600 replays an hour per second. As fast as the
+        /// trades are fetched if not given
+        speed: Option<f64>,
+    },
+    /// Runs pipelines of the config, which share a single scan of the trades
+    Pipeline {
+        #[arg(required = true)]
+        names: Vec<String>,
+        /// Aggregates the delivery days in parallel, e.g. for backfills
+        #[arg(long, conflicts_with = "by_partition")]
+        by_day: bool,
+        /// Scans the partitions of the trade tables one by one, e.g. for backfills
+        #[arg(long)]
+        by_partition: bool,
+    },
+    /// Traces a cell of the report, e.g. `explain DK1 intraday sell`, back to its trades
+    Explain {
+        area: Area,
+        market: Market,
+        side: TradeSide,
+    },
+    /// Prints the key metrics of the report as JSON
+    KeyMetrics {
+        /// Role under `roles` in the config restricting which metrics are shown
+        role: Option<String>,
+    },
+    /// Prints a report per delivery day as a line of JSON, as soon as its day is complete
+    DailyReports,
+    /// Appends a snapshot of the report to the snapshot log
+    Snapshot,
+    /// Prints the report as JSON, taking the start of the window from finalized snapshots
+    StitchedReport,
+    /// Compares the submitted and cleared volumes of each auction product
+    Clearing,
+    /// Volumes per area and block of the config
+    Blocks,
+    /// Volumes and cash flows per fiscal month of the config, area, market and side
+    FiscalMonths,
+    /// How much of the auction volume of each area and hour was traded out intraday
+    Netting,
+    /// What the imbalances cost at the settlement prices against the day-ahead prices
+    ImbalanceCost,
+    /// Compares our totals per counter part and delivery day to the end of day summaries of
+    /// the exchanges, taking the window from the days of the summaries
+    Reconcile {
+        #[arg(required = true)]
+        paths: Vec<PathBuf>,
+    },
+    /// Price quantiles per area, market and side
+    PriceQuantiles,
+    /// Volume and cash flow shares of the counter parts of each market
+    Concentration,
+    /// Cash flows per counter part with the VAT of their invoices, for finance
+    Vat,
+    /// Results of the areas settled in a foreign currency at the locked hedge rates, with the
+    /// effect of the currency moves since apart, for treasury
+    FxHedge {
+        /// CSV file of the spot rates
+        spot_rates: PathBuf,
+    },
+    /// Gross profit per area with the fixed costs of the config allocated
+    NetResults,
+    /// Writes the forward curve of the config, over a window of its own
+    ForwardCurve,
+    /// Searches the trades of all tables, streaming the matches as CSV
+    SearchTrades {
+        #[command(flatten)]
+        search: SearchArgs,
+    },
+    /// Runs the jobs of `scheduler` in the config until stopped
+    Schedule,
+    /// Keeps a report of the window up to date from the trade events of the Kafka topic
+    #[cfg(feature = "kafka")]
+    Consume,
+    /// Serves report metrics over HTTP
+    #[cfg(feature = "server")]
+    Serve {
+        #[arg(default_value = "127.0.0.1:8080")]
+        address: String,
+    },
+    /// Keeps a report of the window up to date as trades are inserted
+    Watch {
+        /// Seconds between polls, in case notifications are missed
+        #[arg(default_value_t = 60)]
+        poll_seconds: u64,
+    },
+}
+
+/// Criteria of `search-trades`, all optional, with ranges inclusive
+#[derive(Debug, Args)]
+pub struct SearchArgs {
+    #[arg(long)]
+    pub price_min: Option<Decimal>,
+    #[arg(long)]
+    pub price_max: Option<Decimal>,
+    /// In MW, after normalizing the quantities of the trades
+    #[arg(long)]
+    pub quantity_min: Option<Decimal>,
+    #[arg(long)]
+    pub quantity_max: Option<Decimal>,
+    /// Counter parts to match any of, e.g. `epex,nordpool`
+    #[arg(long, value_delimiter = ',')]
+    pub counter_part: Vec<CounterPart>,
+    /// Matches trades whose delivery period overlaps the window, in RFC 3339
+    #[arg(long)]
+    pub delivery_from: Option<DateTime<FixedOffset>>,
+    #[arg(long)]
+    pub delivery_to: Option<DateTime<FixedOffset>>,
+    /// Trades fetched per page, 1000 if not given
+    #[arg(long, value_parser = clap::value_parser!(i64).range(1..))]
+    pub page_size: Option<i64>,
+    /// Continues after a trade from an earlier search, `<table>:<id>`
+    #[arg(long)]
+    pub after: Option<SearchCursor>,
+}
+
+impl SearchArgs {
+    pub fn search(&self) -> (TradeSearch, Option<SearchCursor>) {
+        let defaults = TradeSearch::default();
+        let search = TradeSearch {
+            price_min: self.price_min,
+            price_max: self.price_max,
+            quantity_min: self.quantity_min,
+            quantity_max: self.quantity_max,
+            counter_parts: self.counter_part.clone(),
+            delivery_from: self.delivery_from,
+            delivery_to: self.delivery_to,
+            page_size: self.page_size.unwrap_or(defaults.page_size),
+        };
+        (search, self.after)
+    }
+}
+
+/// Report definitions saved in the database, see `saved::parse_definition`
+#[derive(Debug, Subcommand)]
+pub enum SavedReportCommand {
+    /// Saves the definition under the name, e.g. `save gb-monthly "gross_profit by area"`
+    Save {
+        name: String,
+        definition: String,
+    },
+    /// Runs the saved reports, sharing a single scan of the trades
+    Run {
+        #[arg(required = true)]
+        names: Vec<String>,
+    },
+    List,
+}
+
+/// Jobs of a running report server, see `jobs::JobRegistry`
+#[derive(Debug, Subcommand)]
+pub enum JobsCommand {
+    /// Lists each running job with its window, strategy, rows so far and elapsed time
+    List,
+    /// Prints a running job as JSON
+    Inspect { id: u64 },
+    /// Cancels a running job, which stops at its next trade
+    Cancel { id: u64 },
+}
+
+/// How the trades are fetched and aggregated into a report
+#[derive(Debug, Clone, Copy, PartialEq, ValueEnum)]
+pub enum Strategy {
+    /// Fetches all trades into a vector first
+    Vec,
+    /// Like vec, aggregating chunks of the trades in parallel
+    VecParallel,
+    /// Like vec, with only the columns the report needs
+    SimpleTrade,
+    /// Aggregates the trades as they're streamed
+    Stream,
+    /// Fetches each table in a task of its own, sending the trades over a channel
+    Channels,
+    /// Like channels, aggregating the trades as they arrive over the channel
+    ChannelsStream,
+    /// Like channels stream, sending only the fields the report needs over the channel
+    ChannelsInput,
+    /// Sums the trades in the database, fetching a row per area, trade type and side
+    Pushdown,
+}
+
+impl Strategy {
+    /// What the report of the strategy is built from, which the config has to suit, see
+    /// `ReportBuilder::check`
+    pub fn input(&self) -> ReportInput {
+        match self {
+            Strategy::SimpleTrade => ReportInput::TradesForReport,
+            Strategy::ChannelsInput => ReportInput::AggregateInputs,
+            Strategy::Pushdown => ReportInput::AggregatedRows,
+            Strategy::Vec
+            | Strategy::VecParallel
+            | Strategy::Stream
+            | Strategy::Channels
+            | Strategy::ChannelsStream => ReportInput::Trades,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, ValueEnum)]
+pub enum OutputFormat {
+    /// The key metrics and timings of each strategy
+    Text,
+    /// The full report of each strategy as a line of JSON, with progress on stderr
+    Json,
+}
+
+impl Cli {
+    /// The delivery window, which has to end after it starts
+    pub fn delivery_window(&self) -> Result<(DateTime<Tz>, DateTime<Tz>)> {
+        if self.from >= self.to {
+            bail!(
+                "Delivery window has to end after it starts, not {} - {}",
+                self.from.date_naive(),
+                self.to.date_naive()
+            );
+        }
+        Ok((self.from, self.to))
+    }
+
+    pub fn area_selection(&self, hierarchy: &AreaHierarchy) -> Result<AreaSelection> {
+        if let Some(region) = &self.region {
+            return hierarchy.selection(region);
+        }
+        Ok(match self.area.as_slice() {
+            [] => AreaSelection::All,
+            [area] => AreaSelection::Specific(*area),
+            areas => AreaSelection::multiple(areas.iter().copied()),
+        })
+    }
+
+    pub fn market_selection(&self) -> MarketSelection {
+        match self.market.as_slice() {
+            [] => MarketSelection::All,
+            [market] => MarketSelection::Specific(*market),
+            markets => MarketSelection::multiple(markets.iter().copied()),
+        }
+    }
+
+    pub fn counter_part_selection(&self) -> CounterPartSelection {
+        match self.counter_part.as_slice() {
+            [] => CounterPartSelection::All,
+            [counter_part] => CounterPartSelection::Specific(counter_part.clone()),
+            counter_parts => CounterPartSelection::multiple(counter_parts.iter().cloned()),
+        }
+    }
+
+    /// Prints progress, on stderr when stdout is for JSON
+    pub fn progress(&self, message: &str) {
+        match self.output {
+            OutputFormat::Text => println!("{}", message),
+            OutputFormat::Json => eprintln!("{}", message),
+        }
+    }
+
+    /// Whether the report should be created with the strategy
+    pub fn runs(&self, strategy: Strategy) -> bool {
+        self.strategy.is_empty() || self.strategy.contains(&strategy)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn invalid_delivery_windows_are_errors() {
+        let cli = Cli::try_parse_from(["trading-results-rs", "--from", "2024-03-31"]).unwrap();
+        assert_eq!(cli.from.to_rfc3339(), "2024-03-31T00:00:00+01:00");
+        assert_eq!(cli.to.to_rfc3339(), "2024-11-01T00:00:00+01:00");
+
+        assert!(Cli::try_parse_from(["trading-results-rs", "--from", "2024-02-30"]).is_err());
+        assert!(Cli::try_parse_from(["trading-results-rs", "--to", "01-11-2024"]).is_err());
+        let reversed = Cli::try_parse_from([
+            "trading-results-rs",
+            "--from",
+            "2024-06-01",
+            "--to",
+            "2024-05-01",
+        ])
+        .unwrap();
+        assert!(reversed.delivery_window().is_err());
+
+        let cli = Cli::try_parse_from([
+            "trading-results-rs",
+            "--area",
+            "DK2",
+            "--area",
+            "DK1",
+            "--market",
+            "intraday",
+            "--counter-part",
+            "nordpool,epex",
+        ])
+        .unwrap();
+        assert_eq!(
+            cli.area_selection(&AreaHierarchy::default()).unwrap(),
+            AreaSelection::multiple([Area::DK1, Area::DK2])
+        );
+        assert_eq!(
+            cli.market_selection(),
+            MarketSelection::Specific(Market::Intraday)
+        );
+        assert_eq!(
+            cli.counter_part_selection(),
+            CounterPartSelection::multiple([CounterPart::EPEX, CounterPart::NORDPOOL])
+        );
+    }
+
+    #[test]
+    fn commands_parse_into_subcommands() {
+        let parse = |args: &[&str]| {
+            Cli::try_parse_from(["trading-results-rs"].iter().chain(args)).map(|cli| cli.command)
+        };
+
+        assert!(parse(&[]).unwrap().is_none());
+        assert!(matches!(
+            parse(&["report", "run", "gb-monthly", "dk-daily"]).unwrap(),
+            Some(Command::Report {
+                saved: Some(SavedReportCommand::Run { names })
+            }) if names == ["gb-monthly", "dk-daily"]
+        ));
+        assert!(parse(&["report", "run"]).is_err());
+        assert!(matches!(
+            parse(&["--strict", "ingest", "-", "--ndjson"]).unwrap(),
+            Some(Command::Ingest { path, ndjson: true }) if path == "-"
+        ));
+        assert!(matches!(
+            parse(&["jobs", "--server", "http://reports:8080", "cancel", "7"]).unwrap(),
+            Some(Command::Jobs {
+                server,
+                command: JobsCommand::Cancel { id: 7 }
+            }) if server == "http://reports:8080"
+        ));
+        assert!(parse(&["jobs", "inspect"]).is_err());
+        #[cfg(feature = "sqlite")]
+        assert!(matches!(
+            parse(&["demo", "--out", "demo"]).unwrap(),
+            Some(Command::Demo { out: Some(out), .. }) if out.to_str() == Some("demo")
+        ));
+        assert!(matches!(
+            parse(&["pipeline", "daily", "weekly", "--by-day"]).unwrap(),
+            Some(Command::Pipeline { names, by_day: true, by_partition: false })
+                if names == ["daily", "weekly"]
+        ));
+        assert!(matches!(
+            parse(&["explain", "DK1", "intraday", "sell"]).unwrap(),
+            Some(Command::Explain {
+                area: Area::DK1,
+                market: Market::Intraday,
+                side: TradeSide::Sell
+            })
+        ));
+        assert!(matches!(
+            parse(&["watch"]).unwrap(),
+            Some(Command::Watch { poll_seconds: 60 })
+        ));
+    }
+
+    #[test]
+    fn typos_and_bad_arguments_are_errors() {
+        let parse = |args: &[&str]| Cli::try_parse_from(["trading-results-rs"].iter().chain(args));
+
+        assert!(parse(&["pipline", "daily"]).is_err());
+        assert!(parse(&["key_metrics"]).is_err());
+        assert!(parse(&["pipeline"]).is_err());
+        assert!(parse(&["pipeline", "daily", "--by-dya"]).is_err());
+        assert!(parse(&["pipeline", "daily", "--by-day", "--by-partition"]).is_err());
+        assert!(parse(&["explain", "DK1", "intraday"]).is_err());
+        assert!(parse(&["explain", "DK9", "intraday", "sell"]).is_err());
+        assert!(parse(&["replay", "fast"]).is_err());
+        assert!(parse(&["watch", "-5"]).is_err());
+        assert!(parse(&["reconcile"]).is_err());
+        assert!(parse(&["netting", "--by-day"]).is_err());
+        assert!(parse(&["search-trades", "--price-min"]).is_err());
+        assert!(parse(&["search-trades", "--page-size", "0"]).is_err());
+        assert!(parse(&["search-trades", "--after", "auction"]).is_err());
+    }
+
+    #[test]
+    fn search_flags_are_parsed_into_criteria_and_cursor() {
+        let cli = Cli::try_parse_from([
+            "trading-results-rs",
+            "search-trades",
+            "--price-min",
+            "10.5",
+            "--counter-part",
+            "epex,nordpool",
+            "--after",
+            "auction:1234",
+        ])
+        .unwrap();
+        let Some(Command::SearchTrades { search }) = cli.command else {
+            panic!("Not a search: {:?}", cli.command);
+        };
+
+        let (search, cursor) = search.search();
+        assert_eq!(search.price_min, Some(Decimal::new(105, 1)));
+        assert_eq!(
+            search.counter_parts,
+            vec![CounterPart::EPEX, CounterPart::NORDPOOL]
+        );
+        assert_eq!(search.page_size, TradeSearch::default().page_size);
+        assert_eq!(
+            cursor,
+            Some(SearchCursor {
+                table: trading_results_rs::db::TradeTable::Auction,
+                id: 1234
+            })
+        );
+    }
+}
@@ -0,0 +1,167 @@
+use std::fs::File;
+use std::path::Path;
+use std::sync::Arc;
+
+use anyhow::{Context, Result};
+use arrow::array::{ArrayRef, Decimal128Array, Int64Array, StringArray, TimestampMicrosecondArray};
+use arrow::datatypes::{DataType, Field, Schema, TimeUnit};
+use arrow::record_batch::RecordBatch;
+use parquet::arrow::ArrowWriter;
+use rust_decimal::Decimal;
+
+use crate::report::Report;
+use crate::trade::Trade;
+
+/// Digits after the decimal point of the decimal columns, which Spark and DuckDB read as
+/// `decimal(38, 10)`
+const DECIMAL_SCALE: u32 = 10;
+const DECIMAL_PRECISION: u8 = 38;
+
+fn decimal_type() -> DataType {
+    DataType::Decimal128(DECIMAL_PRECISION, DECIMAL_SCALE as i8)
+}
+
+/// The value as a mantissa at the scale of the decimal columns
+fn mantissa(value: Decimal) -> i128 {
+    let mut value = value.round_dp(DECIMAL_SCALE);
+    value.rescale(DECIMAL_SCALE);
+    value.mantissa()
+}
+
+fn decimal_array(values: impl Iterator<Item = Option<Decimal>>) -> Result<ArrayRef> {
+    Ok(Arc::new(
+        values
+            .map(|value| value.map(mantissa))
+            .collect::<Decimal128Array>()
+            .with_precision_and_scale(DECIMAL_PRECISION, DECIMAL_SCALE as i8)?,
+    ))
+}
+
+fn string_array(values: impl Iterator<Item = String>) -> ArrayRef {
+    Arc::new(values.map(Some).collect::<StringArray>())
+}
+
+/// The breakdown of the report, a row per area, currency, market and side, as a record batch
+pub fn report_batch(report: &Report) -> Result<RecordBatch> {
+    let rows = report.breakdown();
+    let schema = Schema::new(vec![
+        Field::new("area", DataType::Utf8, false),
+        Field::new("currency", DataType::Utf8, false),
+        Field::new("market", DataType::Utf8, false),
+        Field::new("side", DataType::Utf8, false),
+        Field::new("mw", decimal_type(), false),
+        Field::new("cash_flow", decimal_type(), false),
+    ]);
+    Ok(RecordBatch::try_new(
+        Arc::new(schema),
+        vec![
+            string_array(rows.iter().map(|row| row.area.to_string())),
+            string_array(rows.iter().map(|row| row.currency.to_string())),
+            string_array(rows.iter().map(|row| row.market.to_string())),
+            string_array(rows.iter().map(|row| row.side.to_string())),
+            decimal_array(rows.iter().map(|row| Some(row.mw)))?,
+            decimal_array(rows.iter().map(|row| Some(row.cash_flow)))?,
+        ],
+    )?)
+}
+
+/// The trades as a record batch, with delivery times in UTC
+pub fn trades_batch(trades: &[Trade]) -> Result<RecordBatch> {
+    let timestamp_type = DataType::Timestamp(TimeUnit::Microsecond, Some("UTC".into()));
+    let schema = Schema::new(vec![
+        Field::new("id", DataType::Int64, false),
+        Field::new("area", DataType::Utf8, false),
+        Field::new("counter_part", DataType::Utf8, false),
+        Field::new("currency", DataType::Utf8, false),
+        Field::new("delivery_start", timestamp_type.clone(), false),
+        Field::new("delivery_end", timestamp_type, false),
+        Field::new("price", decimal_type(), true),
+        Field::new("quantity_mwh", decimal_type(), false),
+        Field::new("trade_side", DataType::Utf8, false),
+        Field::new("trade_type", DataType::Utf8, false),
+    ]);
+    let timestamps = |times: Vec<i64>| -> ArrayRef {
+        Arc::new(TimestampMicrosecondArray::from(times).with_timezone("UTC"))
+    };
+    Ok(RecordBatch::try_new(
+        Arc::new(schema),
+        vec![
+            Arc::new(Int64Array::from_iter_values(
+                trades.iter().map(|trade| trade.id),
+            )),
+            string_array(trades.iter().map(|trade| trade.area.to_string())),
+            string_array(trades.iter().map(|trade| trade.counter_part.to_string())),
+            string_array(trades.iter().map(|trade| trade.currency.to_string())),
+            timestamps(
+                trades
+                    .iter()
+                    .map(|trade| trade.delivery_start.timestamp_micros())
+                    .collect(),
+            ),
+            timestamps(
+                trades
+                    .iter()
+                    .map(|trade| trade.delivery_end.timestamp_micros())
+                    .collect(),
+            ),
+            decimal_array(trades.iter().map(|trade| trade.price))?,
+            decimal_array(trades.iter().map(|trade| Some(trade.quantity_mwh)))?,
+            string_array(trades.iter().map(|trade| trade.trade_side.to_string())),
+            string_array(trades.iter().map(|trade| trade.trade_type.to_string())),
+        ],
+    )?)
+}
+
+/// Writes the batch as a Parquet file
+pub fn write_parquet(path: &Path, batch: &RecordBatch) -> Result<()> {
+    let file =
+        File::create(path).with_context(|| format!("Could not create {}", path.display()))?;
+    let mut writer = ArrowWriter::try_new(file, batch.schema(), None)?;
+    writer.write(batch)?;
+    writer.close()?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use arrow::array::Array;
+    use chrono::TimeZone;
+    use parquet::arrow::arrow_reader::ParquetRecordBatchReaderBuilder;
+
+    #[test]
+    fn trades_and_reports_round_trip_through_parquet() {
+        let from = chrono_tz::Europe::Copenhagen
+            .with_ymd_and_hms(2024, 5, 1, 0, 0, 0)
+            .unwrap();
+        let to = from + chrono::Duration::days(1);
+        let trades = || {
+            vec![
+                Trade::test().mwh(10).price(40).build(),
+                Trade::test().sell().mwh(4).no_price().build(),
+            ]
+        };
+        let report = Report::new(&from, &to, trades()).unwrap();
+        assert_eq!(report_batch(&report).unwrap().num_rows(), 1);
+
+        let path = std::env::temp_dir().join("columnar_test.parquet");
+        write_parquet(&path, &trades_batch(&trades()).unwrap()).unwrap();
+        let batches: Vec<RecordBatch> =
+            ParquetRecordBatchReaderBuilder::try_new(File::open(&path).unwrap())
+                .unwrap()
+                .build()
+                .unwrap()
+                .collect::<Result<_, _>>()
+                .unwrap();
+        std::fs::remove_file(&path).unwrap();
+
+        let prices = batches[0]
+            .column_by_name("price")
+            .unwrap()
+            .as_any()
+            .downcast_ref::<Decimal128Array>()
+            .unwrap();
+        assert_eq!(prices.value_as_string(0), "40.0000000000");
+        assert!(prices.is_null(1));
+    }
+}
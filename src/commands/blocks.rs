@@ -0,0 +1,25 @@
+//! Volumes per area and block of the config
+use anyhow::Result;
+use futures::TryStreamExt;
+use sqlx::PgPool;
+use trading_results_rs::blocks::BlockVolumes;
+use trading_results_rs::db::get_trades_stream_prefetched;
+use trading_results_rs::pipeline::SinkDefinition;
+
+use super::Context;
+
+pub async fn run(ctx: &Context<'_>, pool: &PgPool) -> Result<()> {
+    let mut volumes = BlockVolumes::new(&ctx.config.blocks)?;
+    let mut trades = get_trades_stream_prefetched(
+        pool,
+        &ctx.delivery_from,
+        &ctx.delivery_to,
+        ctx.units(),
+        ctx.decoding(),
+    );
+    while let Some(trade) = trades.try_next().await? {
+        volumes.add_trade(&trade)?;
+    }
+    let header = ["area", "block", "mwh_bought", "mwh_sold", "net_mwh"].map(String::from);
+    SinkDefinition::Stdout.write(&header, volumes.records().into_iter())
+}
@@ -0,0 +1,13 @@
+//! Checks the schema of the reports table
+use anyhow::{Context, Result};
+use sqlx::PgPool;
+use trading_results_rs::schema;
+
+/// Fails the command, exiting with 1, so scripts can tell
+pub async fn run(pool: &PgPool) -> Result<()> {
+    schema::check_reports_schema(pool).await.context(
+        "Schema check failed, the reports table is only needed to save reports with --save",
+    )?;
+    println!("Schema check passed");
+    Ok(())
+}
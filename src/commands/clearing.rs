@@ -0,0 +1,21 @@
+//! Compares the submitted and cleared volumes of each auction product
+use anyhow::Result;
+use sqlx::PgPool;
+use trading_results_rs::clearing;
+use trading_results_rs::pipeline::SinkDefinition;
+
+use super::Context;
+
+pub async fn run(ctx: &Context<'_>, pool: &PgPool) -> Result<()> {
+    let products =
+        clearing::clearing_by_product(pool, &ctx.delivery_from, &ctx.delivery_to, ctx.units())
+            .await?;
+    let header = [
+        "trade_type",
+        "submitted_mwh",
+        "cleared_mwh",
+        "clearing_ratio",
+    ]
+    .map(String::from);
+    SinkDefinition::Stdout.write(&header, products.iter().map(|product| product.record()))
+}
@@ -0,0 +1,37 @@
+//! Volume and cash flow shares of the counter parts of each market
+use anyhow::Result;
+use futures::TryStreamExt;
+use sqlx::PgPool;
+use trading_results_rs::concentration::Concentration;
+use trading_results_rs::db::get_trades_stream_prefetched;
+use trading_results_rs::pipeline::SinkDefinition;
+use trading_results_rs::warnings::Warnings;
+
+use super::Context;
+
+pub async fn run(ctx: &Context<'_>, pool: &PgPool, warnings: &mut Warnings) -> Result<()> {
+    let mut concentration = Concentration::default();
+    let mut trades = get_trades_stream_prefetched(
+        pool,
+        &ctx.delivery_from,
+        &ctx.delivery_to,
+        ctx.units(),
+        ctx.decoding(),
+    );
+    while let Some(trade) = trades.try_next().await? {
+        concentration.add_trade(&trade)?;
+    }
+    warnings.skipped_trades(concentration.skipped_trades(), "the concentration report");
+    let header = [
+        "market",
+        "counter_part",
+        "mwh",
+        "volume_share",
+        "cash_flow",
+        "cash_flow_share",
+        "volume_hhi",
+        "cash_flow_hhi",
+    ]
+    .map(String::from);
+    SinkDefinition::Stdout.write(&header, concentration.rows().iter().map(|row| row.record()))
+}
@@ -0,0 +1,28 @@
+//! Keeps a report of the window up to date from the trade events of the Kafka topic
+use anyhow::Result;
+use trading_results_rs::ingest;
+use trading_results_rs::report::Report;
+
+use super::{cancel_on_ctrl_c, gross_profit_by_currency, Context};
+
+/// Prints the gross profit as events arrive, until stopped
+pub async fn run(ctx: &Context<'_>) -> Result<()> {
+    let config = ctx.config;
+    let consumer = ingest::kafka::TradeConsumer::subscribe(
+        &config.kafka,
+        config.json_mapping.clone(),
+        ctx.units().clone(),
+    )?;
+    let mut report = Report::empty(&ctx.delivery_from, &ctx.delivery_to)?;
+    consumer
+        .consume(
+            &mut report,
+            &ctx.delivery_from,
+            &ctx.delivery_to,
+            &cancel_on_ctrl_c(),
+            |report| {
+                println!("Gross profit {}", gross_profit_by_currency(report));
+            },
+        )
+        .await
+}
@@ -0,0 +1,20 @@
+//! Lists the counter parts of the config
+use anyhow::Result;
+use trading_results_rs::counterparts::CounterPartRegistry;
+use trading_results_rs::pipeline::SinkDefinition;
+
+use super::Context;
+
+/// Prints the counter parts with their kind, currency, fees and VAT treatment as CSV
+pub fn run(ctx: &Context<'_>) -> Result<()> {
+    let registry = CounterPartRegistry::new(&ctx.config.counter_parts);
+    let header = [
+        "counter_part",
+        "kind",
+        "currency",
+        "fee_schedule",
+        "vat_treatment",
+    ]
+    .map(String::from);
+    SinkDefinition::Stdout.write(&header, registry.records().into_iter())
+}
@@ -0,0 +1,30 @@
+//! Prints a report per delivery day of the window
+use std::pin::pin;
+
+use anyhow::Result;
+use futures::TryStreamExt;
+use sqlx::PgPool;
+use trading_results_rs::db::get_trades_stream_ordered;
+use trading_results_rs::fanout;
+use trading_results_rs::warnings::Warnings;
+
+use super::Context;
+
+/// A report per delivery day as a line of JSON, each printed as soon as its day is complete in
+/// the trades ordered by delivery
+pub async fn run(ctx: &Context<'_>, pool: &PgPool, warnings: &mut Warnings) -> Result<()> {
+    let (delivery_from, delivery_to) = (&ctx.delivery_from, &ctx.delivery_to);
+    let trades = get_trades_stream_ordered(
+        pool,
+        delivery_from,
+        delivery_to,
+        ctx.units(),
+        ctx.decoding(),
+    );
+    let mut days = pin!(fanout::reports_by_day(trades, delivery_from, delivery_to)?);
+    while let Some(day) = days.try_next().await? {
+        warnings.skipped_trades(day.skipped_trades(), "the daily reports");
+        println!("{}", day.to_json()?);
+    }
+    Ok(())
+}
@@ -0,0 +1,95 @@
+//! Builds a report of the bundled demo trades, without a database
+use std::path::Path;
+
+use anyhow::Result;
+#[cfg(feature = "parquet")]
+use trading_results_rs::columnar;
+#[cfg(feature = "server")]
+use trading_results_rs::server;
+use trading_results_rs::warnings::Warnings;
+use trading_results_rs::{export, sqlite};
+
+use super::{apply_missing_prices, Context};
+
+/// Prints the key metrics of the demo trades and writes the exports into `out` if given, or
+/// serves them on `serve` instead
+pub async fn run(
+    out: Option<&Path>,
+    #[cfg(feature = "server")] serve: Option<&str>,
+    ctx: &Context<'_>,
+    warnings: &mut Warnings,
+) -> Result<()> {
+    let config = ctx.config;
+    let source = sqlite::demo_source().await?.with_decoding(ctx.decoding());
+    #[cfg(feature = "server")]
+    if let Some(address) = serve {
+        server::ReportServer::new(source, config)
+            .serve(address)
+            .await?;
+        return Ok(());
+    }
+    let report = ctx
+        .report_builder(None)
+        .await?
+        .build_from_source(&source)
+        .await?;
+    let report = apply_missing_prices(report, config.missing_prices, warnings)?;
+    if let Some(directory) = out {
+        std::fs::create_dir_all(directory)?;
+        export::write_breakdown_csv(&report, Some(&directory.join("breakdown.csv")))?;
+        std::fs::write(directory.join("report.json"), report.to_json()?)?;
+        #[cfg(feature = "parquet")]
+        columnar::write_parquet(
+            &directory.join("report.parquet"),
+            &columnar::report_batch(&report)?,
+        )?;
+        println!("Wrote the exports to {}", directory.display());
+    }
+    report.print_key_metrics(
+        config.sign_convention,
+        ctx.cli.market_selection(),
+        ctx.area_selection.clone(),
+    );
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use std::process;
+
+    use super::*;
+    use crate::commands::TestContext;
+
+    /// The currencies of the totals of the report.json of the demo built with the config
+    async fn currencies(context: TestContext) -> Result<Vec<String>> {
+        let out = std::env::temp_dir().join(format!("demo-{}", process::id()));
+        let mut warnings = Warnings::default();
+        run(
+            Some(&out),
+            #[cfg(feature = "server")]
+            None,
+            &context.context(),
+            &mut warnings,
+        )
+        .await?;
+        let report: serde_json::Value =
+            serde_json::from_str(&std::fs::read_to_string(out.join("report.json"))?)?;
+        std::fs::remove_dir_all(&out)?;
+        Ok(report["totals_by_currency"]
+            .as_object()
+            .unwrap()
+            .keys()
+            .cloned()
+            .collect())
+    }
+
+    #[tokio::test]
+    async fn the_demo_is_built_as_configured() -> Result<()> {
+        assert_eq!(
+            currencies(TestContext::new(Default::default())).await?,
+            ["EUR", "GBP"]
+        );
+        assert_eq!(currencies(TestContext::converting_gbp()).await?, ["EUR"]);
+        Ok(())
+    }
+}
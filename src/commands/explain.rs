@@ -0,0 +1,76 @@
+//! Lists the trades making up a cell of the report
+use anyhow::Result;
+use trading_results_rs::db::{TradeSource, TradeTable};
+use trading_results_rs::pipeline::SinkDefinition;
+use trading_results_rs::report::Report;
+use trading_results_rs::trade::{Area, Market, TradeSide};
+
+use super::Context;
+
+/// Prints the id, volume and cash flow each trade contributes to the cell as CSV
+pub async fn run(
+    area: Area,
+    market: Market,
+    side: TradeSide,
+    ctx: &Context<'_>,
+    source: &TradeSource,
+) -> Result<()> {
+    let mut report = explained_report(ctx, source).await?;
+    let table = TradeTable::from(market);
+    let header = ["table", "id", "mw", "cash_flow"].map(String::from);
+    SinkDefinition::Stdout.write(
+        &header,
+        report
+            .explain(area, market, side)?
+            .iter()
+            .map(|contribution| {
+                vec![
+                    table.to_string(),
+                    contribution.id.to_string(),
+                    contribution.mw.to_string(),
+                    contribution.cash_flow.round_dp(2).to_string(),
+                ]
+            }),
+    )
+}
+
+/// The report of the window retaining what each trade contributed, built like the reports of
+/// the other commands, so the contributions of a cell sum to the cell of their reports
+async fn explained_report(ctx: &Context<'_>, source: &TradeSource) -> Result<Report> {
+    ctx.report_builder(source.pool())
+        .await?
+        .retain_trades(ctx.config.retention.memory_budget())
+        .build_from_source(source)
+        .await
+}
+
+#[cfg(all(test, feature = "sqlite"))]
+mod tests {
+    use rust_decimal_macros::dec;
+    use trading_results_rs::sqlite;
+
+    use super::*;
+    use crate::commands::TestContext;
+
+    #[tokio::test]
+    async fn trades_are_explained_as_configured() -> Result<()> {
+        let source = sqlite::demo_source().await?;
+        let explain = |context: TestContext| {
+            let source = source.clone();
+            async move {
+                explained_report(&context.context(), &source)
+                    .await?
+                    .explain(Area::GB, Market::Auction, TradeSide::Buy)
+            }
+        };
+        let by_default = explain(TestContext::new(Default::default())).await?;
+        let converted = explain(TestContext::converting_gbp()).await?;
+        assert!(!by_default.is_empty());
+        assert_eq!(by_default.len(), converted.len());
+        for (by_default, converted) in by_default.iter().zip(converted.iter()) {
+            assert_eq!(converted.id, by_default.id);
+            assert_eq!(converted.cash_flow, by_default.cash_flow * dec!(1.17));
+        }
+        Ok(())
+    }
+}
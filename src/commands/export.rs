@@ -0,0 +1,99 @@
+//! Exports the report of the window as CSV, or as Parquet with `--parquet`
+use std::path::Path;
+
+use anyhow::Result;
+#[cfg(feature = "parquet")]
+use futures::TryStreamExt;
+use sqlx::PgPool;
+#[cfg(feature = "parquet")]
+use trading_results_rs::columnar;
+#[cfg(feature = "parquet")]
+use trading_results_rs::db::TradeSource;
+use trading_results_rs::export;
+#[cfg(feature = "parquet")]
+use trading_results_rs::report::Report;
+use trading_results_rs::timing::StrategyTimings;
+#[cfg(feature = "parquet")]
+use trading_results_rs::trade::Trade;
+use trading_results_rs::warnings::Warnings;
+
+use super::report::create_report_stream;
+use super::{apply_missing_prices, Context};
+
+/// Writes the breakdown of the report as CSV to the path, or stdout if not given
+pub async fn run(
+    path: Option<&Path>,
+    ctx: &Context<'_>,
+    pool: &PgPool,
+    warnings: &mut Warnings,
+) -> Result<()> {
+    let mut timings = StrategyTimings::new("stream");
+    let builder = ctx.report_builder(Some(pool)).await?;
+    let report =
+        create_report_stream(pool, &builder, ctx.units(), ctx.decoding(), &mut timings).await?;
+    let report = apply_missing_prices(report, ctx.config.missing_prices, warnings)?;
+    export::write_breakdown_csv(&report, path)
+}
+
+/// Writes report.parquet, and trades.parquet with `with_trades`, into the directory
+#[cfg(feature = "parquet")]
+pub async fn run_parquet(
+    directory: &Path,
+    with_trades: bool,
+    ctx: &Context<'_>,
+    source: &TradeSource,
+    warnings: &mut Warnings,
+) -> Result<()> {
+    let (report, trades) = parquet_report(ctx, source).await?;
+    let report = apply_missing_prices(report, ctx.config.missing_prices, warnings)?;
+    columnar::write_parquet(
+        &directory.join("report.parquet"),
+        &columnar::report_batch(&report)?,
+    )?;
+    if with_trades {
+        columnar::write_parquet(
+            &directory.join("trades.parquet"),
+            &columnar::trades_batch(&trades)?,
+        )?;
+    }
+    Ok(())
+}
+
+/// The report of the window and the trades fetched for it, as they are in the source
+#[cfg(feature = "parquet")]
+async fn parquet_report(ctx: &Context<'_>, source: &TradeSource) -> Result<(Report, Vec<Trade>)> {
+    let builder = ctx.report_builder(source.pool()).await?;
+    let trades: Vec<Trade> = builder.trades(source).try_collect().await?;
+    let report = builder.build(&trades)?;
+    Ok((report, trades))
+}
+
+#[cfg(all(test, feature = "parquet", feature = "sqlite"))]
+mod tests {
+    use std::collections::BTreeSet;
+
+    use trading_results_rs::sqlite;
+    use trading_results_rs::trade::{Area, AreaSelection, Currency};
+
+    use super::*;
+    use crate::commands::TestContext;
+
+    #[tokio::test]
+    async fn parquet_reports_are_built_as_configured() -> Result<()> {
+        let source = sqlite::demo_source().await?;
+        let gb = AreaSelection::Specific(Area::GB);
+        let context = TestContext::new(Default::default());
+        let (report, _) = parquet_report(&context.context(), &source).await?;
+        assert_eq!(
+            report.currencies(gb.clone()),
+            BTreeSet::from([Currency::Gbp])
+        );
+
+        let context = TestContext::converting_gbp();
+        let (report, trades) = parquet_report(&context.context(), &source).await?;
+        assert_eq!(report.currencies(gb), BTreeSet::from([Currency::Eur]));
+        // The trades are written as they are in the source
+        assert!(trades.iter().any(|trade| trade.currency == Currency::Gbp));
+        Ok(())
+    }
+}
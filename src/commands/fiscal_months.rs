@@ -0,0 +1,37 @@
+//! Volumes and cash flows per fiscal month of the config, area, market and side
+use anyhow::Result;
+use futures::TryStreamExt;
+use sqlx::PgPool;
+use trading_results_rs::db::get_trades_stream_prefetched;
+use trading_results_rs::fiscal::FiscalMonths;
+use trading_results_rs::pipeline::SinkDefinition;
+use trading_results_rs::warnings::Warnings;
+
+use super::Context;
+
+pub async fn run(ctx: &Context<'_>, pool: &PgPool, warnings: &mut Warnings) -> Result<()> {
+    let mut months = FiscalMonths::new(&ctx.config.fiscal)?;
+    let mut trades = get_trades_stream_prefetched(
+        pool,
+        &ctx.delivery_from,
+        &ctx.delivery_to,
+        ctx.units(),
+        ctx.decoding(),
+    );
+    while let Some(trade) = trades.try_next().await? {
+        months.add_trade(&trade)?;
+    }
+    warnings.skipped_trades(months.skipped_trades(), "the fiscal months");
+    let header = [
+        "fiscal_month",
+        "month_from",
+        "month_to",
+        "area",
+        "market",
+        "side",
+        "mw",
+        "cash_flow",
+    ]
+    .map(String::from);
+    SinkDefinition::Stdout.write(&header, months.records().into_iter())
+}
@@ -0,0 +1,23 @@
+//! Writes the forward curve of the config, over a window of its own
+use anyhow::Result;
+use chrono::Local;
+use chrono_tz::Europe::Copenhagen;
+use futures::TryStreamExt;
+use sqlx::PgPool;
+use trading_results_rs::db::get_trades_stream_prefetched;
+use trading_results_rs::position::ForwardCurve;
+
+use super::Context;
+
+pub async fn run(ctx: &Context<'_>, pool: &PgPool) -> Result<()> {
+    let curve_config = ctx.config.forward_curve.clone().unwrap_or_default();
+    let mut curve = ForwardCurve::new(&curve_config, Local::now().with_timezone(&Copenhagen))?;
+    let (curve_from, curve_to) = (*curve.delivery_from(), *curve.delivery_to());
+    ctx.config.guardrails.check_window(&curve_from, &curve_to)?;
+    let mut trades =
+        get_trades_stream_prefetched(pool, &curve_from, &curve_to, ctx.units(), ctx.decoding());
+    while let Some(trade) = trades.try_next().await? {
+        curve.add_trade(&trade)?;
+    }
+    curve.write(Local::now().fixed_offset())
+}
@@ -0,0 +1,54 @@
+//! Results of the areas settled in a foreign currency at the locked hedge rates, with the effect
+//! of the currency moves since apart, for treasury
+use std::path::Path;
+
+use anyhow::{anyhow, Result};
+use futures::TryStreamExt;
+use sqlx::PgPool;
+use trading_results_rs::db::get_trades_stream_prefetched;
+use trading_results_rs::hedging::{self, HedgedPnl};
+use trading_results_rs::pipeline::SinkDefinition;
+use trading_results_rs::warnings::Warnings;
+
+use super::Context;
+
+/// Prints the hedged results per month against the spot rates of the CSV file
+pub async fn run(
+    spot_rates: &Path,
+    ctx: &Context<'_>,
+    pool: &PgPool,
+    warnings: &mut Warnings,
+) -> Result<()> {
+    let hedge = ctx
+        .config
+        .fx_hedge
+        .as_ref()
+        .ok_or(anyhow!("fx_hedge is not configured"))?;
+    let spot_rates = hedging::read_spot_rates_file(spot_rates)?;
+    let mut pnl = HedgedPnl::new(hedge, spot_rates)?;
+    let mut trades = get_trades_stream_prefetched(
+        pool,
+        &ctx.delivery_from,
+        &ctx.delivery_to,
+        ctx.units(),
+        ctx.decoding(),
+    );
+    while let Some(trade) = trades.try_next().await? {
+        pnl.add_trade(&trade)?;
+    }
+    warnings.skipped_trades(pnl.skipped_trades(), "the hedged results");
+    // Amounts are in the settlement currency and then the reporting currency
+    let (currency, reporting) = (
+        hedge.currency.to_lowercase(),
+        hedge.reporting_currency.to_lowercase(),
+    );
+    let header = [
+        "month".to_string(),
+        format!("pnl_{}", currency),
+        "hedge_rate".to_string(),
+        format!("hedged_pnl_{}", reporting),
+        format!("spot_pnl_{}", reporting),
+        format!("fx_effect_{}", reporting),
+    ];
+    SinkDefinition::Stdout.write(&header, pnl.rows()?.iter().map(|row| row.record()))
+}
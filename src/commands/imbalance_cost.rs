@@ -0,0 +1,53 @@
+//! What the imbalances cost at the settlement prices against the day-ahead prices
+use anyhow::{anyhow, Result};
+use futures::TryStreamExt;
+use sqlx::PgPool;
+use trading_results_rs::db::get_imbalance_trades_stream;
+use trading_results_rs::imbalance::ImbalanceCost;
+use trading_results_rs::pipeline::SinkDefinition;
+use trading_results_rs::prices::SpotPrices;
+
+use super::Context;
+
+pub async fn run(ctx: &Context<'_>, pool: &PgPool) -> Result<()> {
+    let (config, delivery_from, delivery_to) = (ctx.config, &ctx.delivery_from, &ctx.delivery_to);
+    let imbalance_config = config
+        .imbalance_cost
+        .as_ref()
+        .ok_or(anyhow!("imbalance_cost is not configured"))?;
+    let spot_config = config
+        .spot_prices
+        .as_ref()
+        .ok_or(anyhow!("spot_prices is not configured"))?;
+    let imbalance_prices = SpotPrices::load_table(
+        pool,
+        &imbalance_config.prices_table,
+        delivery_from,
+        delivery_to,
+    )
+    .await?;
+    let spot_prices =
+        SpotPrices::load(spot_config, pool, ctx.secrets, delivery_from, delivery_to).await?;
+    let mut cost = ImbalanceCost::new(&imbalance_prices, &spot_prices, delivery_from.timezone());
+    let mut trades = get_imbalance_trades_stream(
+        pool,
+        delivery_from,
+        delivery_to,
+        ctx.units(),
+        ctx.decoding(),
+    );
+    while let Some(trade) = trades.try_next().await? {
+        cost.add_trade(&trade);
+    }
+    let header = [
+        "area",
+        "delivery_day",
+        "imbalance_mwh",
+        "imbalance_cost",
+        "spot_cost",
+        "cost_vs_spot",
+        "unpriced_trades",
+    ]
+    .map(String::from);
+    SinkDefinition::Stdout.write(&header, cost.rows().iter().map(|row| row.record()))
+}
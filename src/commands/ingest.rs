@@ -0,0 +1,46 @@
+//! Aggregates a file of trades into a report without the database
+use std::io;
+use std::path::Path;
+
+use anyhow::Result;
+use trading_results_rs::ingest;
+use trading_results_rs::warnings::Warnings;
+
+use super::{apply_missing_prices, Context};
+
+/// Reads the trades of the file, or of stdin for `-` with `--ndjson`, and prints the key metrics
+/// of their report
+pub fn run(path: &str, ndjson: bool, ctx: &Context<'_>, warnings: &mut Warnings) -> Result<()> {
+    let (config, units) = (ctx.config, ctx.units());
+    // Trade dumps that never hit the database, read from stdin for `-` with --ndjson
+    let report = match (ndjson, path) {
+        (true, "-") => ingest::ndjson::read_report(
+            io::stdin().lock(),
+            &config.json_mapping,
+            units,
+            &ctx.delivery_from,
+            &ctx.delivery_to,
+        )?,
+        (true, _) => ingest::ndjson::read_report_file(
+            Path::new(path),
+            &config.json_mapping,
+            units,
+            &ctx.delivery_from,
+            &ctx.delivery_to,
+        )?,
+        (false, _) => ingest::csv::read_report_file(
+            Path::new(path),
+            &config.csv_mapping,
+            units,
+            &ctx.delivery_from,
+            &ctx.delivery_to,
+        )?,
+    };
+    let report = apply_missing_prices(report, config.missing_prices, warnings)?;
+    report.print_key_metrics(
+        config.sign_convention,
+        ctx.cli.market_selection(),
+        ctx.area_selection.clone(),
+    );
+    Ok(())
+}
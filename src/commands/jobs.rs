@@ -0,0 +1,31 @@
+//! Lists, inspects or cancels the jobs of a report server elsewhere
+use anyhow::Result;
+use trading_results_rs::jobs::JobsClient;
+use trading_results_rs::pipeline::SinkDefinition;
+
+use crate::cli::JobsCommand;
+
+/// Runs the command against the server, so on-call can stop a job without a restart
+pub async fn run(server: &str, command: &JobsCommand) -> Result<()> {
+    let client = JobsClient::new(server);
+    let job = match command {
+        JobsCommand::List => {
+            let header = [
+                "id",
+                "strategy",
+                "window",
+                "rows",
+                "elapsed_seconds",
+                "cancelled",
+            ]
+            .map(String::from);
+            let running = client.list().await?;
+            SinkDefinition::Stdout.write(&header, running.iter().map(|job| job.record()))?;
+            return Ok(());
+        }
+        JobsCommand::Inspect { id } => client.inspect(*id).await?,
+        JobsCommand::Cancel { id } => client.cancel(*id).await?,
+    };
+    println!("{}", serde_json::to_string_pretty(&job)?);
+    Ok(())
+}
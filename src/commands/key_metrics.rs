@@ -0,0 +1,30 @@
+//! Prints the key metrics of the report as JSON
+use anyhow::Result;
+use sqlx::PgPool;
+use trading_results_rs::permissions::RolePermissions;
+use trading_results_rs::timing::StrategyTimings;
+use trading_results_rs::warnings::Warnings;
+
+use super::report::create_report_stream;
+use super::{apply_missing_prices, Context};
+
+/// Prints the metrics the role, if given, may read
+pub async fn run(
+    role: Option<&str>,
+    ctx: &Context<'_>,
+    pool: &PgPool,
+    warnings: &mut Warnings,
+) -> Result<()> {
+    let config = ctx.config;
+    let permissions = RolePermissions::for_role(&config.roles, role)?;
+    let mut timings = StrategyTimings::new("stream");
+    let builder = ctx.report_builder(Some(pool)).await?;
+    let report =
+        create_report_stream(pool, &builder, ctx.units(), ctx.decoding(), &mut timings).await?;
+    let report = apply_missing_prices(report, config.missing_prices, warnings)?;
+    println!(
+        "{}",
+        serde_json::to_string_pretty(&permissions.key_metrics(&report, config.sign_convention))?
+    );
+    Ok(())
+}
@@ -0,0 +1,18 @@
+//! Migrates the snapshot log to the current schema version
+use anyhow::Result;
+use trading_results_rs::snapshot::{self, SnapshotLog};
+
+use super::Context;
+
+pub fn run(ctx: &Context<'_>) -> Result<()> {
+    let snapshots = &ctx.config.snapshots;
+    let log = SnapshotLog::new(&snapshots.path, snapshots.full_every)?;
+    let migrated = log.migrate()?;
+    println!(
+        "Migrated {} snapshot records in {} to schema version {}",
+        migrated,
+        snapshots.path.display(),
+        snapshot::SNAPSHOT_SCHEMA_VERSION
+    );
+    Ok(())
+}
@@ -0,0 +1,211 @@
+//! The commands of the CLI, each in a module of its own with a `run` function taking the
+//! arguments of the command and the `Context` shared by all of them. Commands creating reports
+//! build them with `Context::report_builder`, so they agree with each other and the server.
+use anyhow::Result;
+use chrono::DateTime;
+use chrono_tz::Tz;
+use rust_decimal::Decimal;
+use sqlx::PgPool;
+use trading_results_rs::cancel::CancellationToken;
+use trading_results_rs::config::Config;
+use trading_results_rs::db::TradeSource;
+use trading_results_rs::report::{MissingPricePolicy, Report, ReportBuilder};
+use trading_results_rs::secrets::Secrets;
+use trading_results_rs::trade::{AreaSelection, Decoding, MarketSelection};
+use trading_results_rs::units::QuantityUnits;
+use trading_results_rs::warnings::Warnings;
+use trading_results_rs::Metric;
+
+use crate::cli::Cli;
+
+pub mod blocks;
+pub mod check_schema;
+pub mod clearing;
+pub mod concentration;
+#[cfg(feature = "kafka")]
+pub mod consume;
+pub mod counter_parts;
+pub mod daily_reports;
+#[cfg(feature = "sqlite")]
+pub mod demo;
+pub mod explain;
+pub mod export;
+pub mod fiscal_months;
+pub mod forward_curve;
+pub mod fx_hedge;
+pub mod imbalance_cost;
+pub mod ingest;
+pub mod jobs;
+pub mod key_metrics;
+pub mod migrate_snapshot;
+pub mod net_results;
+pub mod netting;
+pub mod pipeline;
+pub mod price_quantiles;
+pub mod reconcile;
+pub mod replay;
+pub mod report;
+pub mod schedule;
+pub mod search_trades;
+#[cfg(feature = "server")]
+pub mod serve;
+pub mod snapshot;
+pub mod stitched_report;
+pub mod validate;
+pub mod vat;
+pub mod verify;
+pub mod watch;
+
+/// What every command runs with: the options given before the command, the config and the
+/// delivery window they select
+pub struct Context<'a> {
+    pub cli: &'a Cli,
+    pub config: &'a Config,
+    pub secrets: &'a Secrets,
+    pub delivery_from: DateTime<Tz>,
+    pub delivery_to: DateTime<Tz>,
+    /// The areas of `--area` or `--region` the key metrics are shown for
+    pub area_selection: AreaSelection,
+}
+
+impl Context<'_> {
+    pub fn units(&self) -> &QuantityUnits {
+        &self.config.quantity_units
+    }
+
+    pub fn decoding(&self) -> Decoding {
+        self.config.decoding
+    }
+
+    /// The trades of the database, decoded as configured
+    pub fn source(&self, pool: &PgPool) -> TradeSource {
+        TradeSource::new(pool.clone(), self.units().clone()).with_decoding(self.decoding())
+    }
+
+    /// The builder of the reports of the window, see `report_builder_over`
+    pub async fn report_builder(&self, pool: Option<&PgPool>) -> Result<ReportBuilder> {
+        self.report_builder_over(pool, &self.delivery_from, &self.delivery_to)
+            .await
+    }
+
+    /// The builder every command builds its reports with, as configured, see
+    /// `Config::report_builder`, and of the trades of the counter parts of `--counter-part`
+    pub async fn report_builder_over(
+        &self,
+        pool: Option<&PgPool>,
+        delivery_from: &DateTime<Tz>,
+        delivery_to: &DateTime<Tz>,
+    ) -> Result<ReportBuilder> {
+        Ok(self
+            .config
+            .report_builder(pool, delivery_from, delivery_to)
+            .await?
+            .counter_parts(self.cli.counter_part_selection()))
+    }
+}
+
+/// Applies the policy for trades without a price to a built report, warning about the trades
+pub fn apply_missing_prices(
+    report: Report,
+    policy: MissingPricePolicy,
+    warnings: &mut Warnings,
+) -> Result<Report> {
+    let report = report.with_missing_prices(policy)?;
+    match policy {
+        MissingPricePolicy::CountVolume if report.skipped_trades() > 0 => warnings.add(format!(
+            "{} trades without a price only count towards the volumes of the report",
+            report.skipped_trades()
+        )),
+        _ => warnings.skipped_trades(report.skipped_trades(), "the report"),
+    }
+    Ok(report)
+}
+
+/// A token cancelled by Ctrl-C, for the commands that run until stopped so they stop
+/// cooperatively. Other commands keep the default of being terminated.
+pub fn cancel_on_ctrl_c() -> CancellationToken {
+    let token = CancellationToken::new();
+    let cancel = token.clone();
+    tokio::spawn(async move {
+        if tokio::signal::ctrl_c().await.is_ok() {
+            cancel.cancel();
+        }
+    });
+    token
+}
+
+/// The running gross profit of a report for the commands printing it as trades arrive, per
+/// currency, e.g. `EUR 500.00, GBP 400.00`, as cash flows of different currencies aren't summed
+pub fn gross_profit_by_currency(report: &Report) -> String {
+    let amounts = report.metric_by_currency(
+        Metric::GrossProfit,
+        MarketSelection::All,
+        AreaSelection::All,
+    );
+    if amounts.is_empty() {
+        return Decimal::ZERO.to_string();
+    }
+    amounts
+        .iter()
+        .map(|(currency, amount)| format!("{} {}", currency, amount))
+        .collect::<Vec<_>>()
+        .join(", ")
+}
+
+/// The options, config and secrets of a `Context` over the week of the demo trades, see
+/// `sqlite::demo_source`
+#[cfg(all(test, feature = "sqlite"))]
+pub struct TestContext {
+    cli: Cli,
+    config: Config,
+    secrets: Secrets,
+}
+
+#[cfg(all(test, feature = "sqlite"))]
+impl TestContext {
+    pub fn new(config: Config) -> Self {
+        use clap::Parser;
+
+        let args = [
+            "trading-results-rs",
+            "--from",
+            "2024-05-01",
+            "--to",
+            "2024-05-08",
+        ];
+        Self {
+            cli: Cli::try_parse_from(args).unwrap(),
+            secrets: Secrets::new(config.secrets.clone()),
+            config,
+        }
+    }
+
+    /// A config converting the cash flows of the GB trades from GBP to the default EUR, for
+    /// tests telling reports built as configured from those built by default
+    pub fn converting_gbp() -> Self {
+        use rust_decimal_macros::dec;
+        use std::collections::BTreeMap;
+        use trading_results_rs::fx::FxConfig;
+        use trading_results_rs::trade::Currency;
+
+        Self::new(Config {
+            fx: Some(FxConfig {
+                fixed_rates: BTreeMap::from([(Currency::Gbp, dec!(1.17))]),
+                ..FxConfig::default()
+            }),
+            ..Config::default()
+        })
+    }
+
+    pub fn context(&self) -> Context<'_> {
+        let (delivery_from, delivery_to) = self.cli.delivery_window().unwrap();
+        Context {
+            cli: &self.cli,
+            config: &self.config,
+            secrets: &self.secrets,
+            delivery_from,
+            delivery_to,
+            area_selection: AreaSelection::All,
+        }
+    }
+}
@@ -0,0 +1,25 @@
+//! Gross profit per area with the fixed costs of the config allocated
+use anyhow::Result;
+use sqlx::PgPool;
+use trading_results_rs::allocation;
+use trading_results_rs::pipeline::SinkDefinition;
+use trading_results_rs::timing::StrategyTimings;
+use trading_results_rs::warnings::Warnings;
+
+use super::report::create_report_stream;
+use super::{apply_missing_prices, Context};
+
+pub async fn run(ctx: &Context<'_>, pool: &PgPool, warnings: &mut Warnings) -> Result<()> {
+    let config = ctx.config;
+    let mut timings = StrategyTimings::new("stream");
+    let builder = ctx.report_builder(Some(pool)).await?;
+    let report =
+        create_report_stream(pool, &builder, ctx.units(), ctx.decoding(), &mut timings).await?;
+    let report = apply_missing_prices(report, config.missing_prices, warnings)?;
+    let results = allocation::net_results(&report, &config.fixed_costs)?;
+    let header = ["area", "gross_profit", "allocated_costs", "net_result"].map(String::from);
+    SinkDefinition::Stdout.write(
+        &header,
+        allocation::records(&results, config.sign_convention).into_iter(),
+    )
+}
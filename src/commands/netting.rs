@@ -0,0 +1,34 @@
+//! How much of the auction volume of each area and hour was traded out intraday
+use anyhow::Result;
+use futures::TryStreamExt;
+use sqlx::PgPool;
+use trading_results_rs::db::get_trades_stream_prefetched;
+use trading_results_rs::netting::Netting;
+use trading_results_rs::pipeline::SinkDefinition;
+
+use super::Context;
+
+pub async fn run(ctx: &Context<'_>, pool: &PgPool) -> Result<()> {
+    let mut netting = Netting::default();
+    let mut trades = get_trades_stream_prefetched(
+        pool,
+        &ctx.delivery_from,
+        &ctx.delivery_to,
+        ctx.units(),
+        ctx.decoding(),
+    );
+    while let Some(trade) = trades.try_next().await? {
+        netting.add_trade(&trade)?;
+    }
+    let header = [
+        "area",
+        "delivery_hour",
+        "auction_mwh",
+        "intraday_mwh",
+        "net_mwh",
+        "traded_out_mwh",
+        "traded_out_share",
+    ]
+    .map(String::from);
+    SinkDefinition::Stdout.write(&header, netting.records().into_iter())
+}
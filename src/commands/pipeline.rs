@@ -0,0 +1,120 @@
+//! Runs pipelines of the config, or saved in the database, over the trades of the window
+use std::path::PathBuf;
+
+use anyhow::{Context as _, Result};
+use chrono::DateTime;
+use chrono_tz::Tz;
+use sqlx::PgPool;
+use trading_results_rs::config::Config;
+use trading_results_rs::db::{get_trades_stream_by_partition, TradeSource};
+use trading_results_rs::fanout::{aggregate_by_day, aggregate_shared};
+use trading_results_rs::pipeline::Pipeline;
+use trading_results_rs::saved::SavedReports;
+use trading_results_rs::signing::{self, Signer};
+use trading_results_rs::warnings::Warnings;
+
+use super::Context;
+
+/// Runs the pipelines of the config under the names and signs the files they write
+pub async fn run(
+    names: &[String],
+    scan: Scan,
+    ctx: &Context<'_>,
+    pool: &PgPool,
+    warnings: &mut Warnings,
+) -> Result<()> {
+    let pipelines = names
+        .iter()
+        .map(|name| ctx.config.pipeline(name))
+        .collect::<Result<Vec<_>>>()?;
+    let written = run_pipelines(
+        &ctx.source(pool),
+        ctx.delivery_from,
+        ctx.delivery_to,
+        pipelines,
+        scan,
+        warnings,
+    )
+    .await?;
+    let signer = Signer::from_config(ctx.config.signing.as_ref(), ctx.secrets).await?;
+    signing::sign_files(signer.as_ref(), &written)
+}
+
+/// Builds the pipelines of the reports saved in the database under the names
+pub async fn saved_pipelines(
+    pool: &PgPool,
+    config: &Config,
+    names: &[String],
+) -> Result<Vec<Pipeline>> {
+    let saved = SavedReports::new(pool);
+    let mut pipelines = Vec::new();
+    for name in names {
+        pipelines.push(config.build_pipeline(saved.definition(name).await?)?);
+    }
+    Ok(pipelines)
+}
+
+/// How pipelines scan the trades of the window
+pub enum Scan {
+    /// A single range scan of each table
+    Range,
+    /// Each partition of the tables on its own
+    ByPartition,
+    /// Each delivery day in parallel, merged in delivery order
+    ByDay,
+}
+
+/// Days aggregated at a time with `Scan::ByDay`, each streaming the three tables, which keeps
+/// the connections in use within the pool
+const PARALLEL_DAYS: usize = 4;
+
+pub async fn run_pipelines(
+    source: &TradeSource,
+    delivery_from: DateTime<Tz>,
+    delivery_to: DateTime<Tz>,
+    pipelines: Vec<Pipeline>,
+    scan: Scan,
+    warnings: &mut Warnings,
+) -> Result<Vec<PathBuf>> {
+    let pipelines = match scan {
+        Scan::Range => {
+            aggregate_shared(source.stream(&delivery_from, &delivery_to), pipelines).await?
+        }
+        Scan::ByPartition => {
+            let pool = source
+                .pool()
+                .context("Partitions can only be scanned in the tables of Postgres")?;
+            let trades = get_trades_stream_by_partition(
+                pool,
+                &delivery_from,
+                &delivery_to,
+                source.units(),
+                source.decoding(),
+            )
+            .await?;
+            aggregate_shared(trades, pipelines).await?
+        }
+        Scan::ByDay => {
+            aggregate_by_day(
+                source,
+                &delivery_from,
+                &delivery_to,
+                pipelines,
+                PARALLEL_DAYS,
+            )
+            .await?
+        }
+    };
+    let mut written = Vec::new();
+    for pipeline in pipelines {
+        pipeline.write()?;
+        written.extend(pipeline.output_paths());
+        warnings.skipped_trades(
+            pipeline.skipped_trades(),
+            &format!("pipeline {}", pipeline.name()),
+        );
+        println!("Pipeline {} done", pipeline.name());
+    }
+
+    Ok(written)
+}
@@ -0,0 +1,27 @@
+//! Price quantiles per area, market and side
+use anyhow::Result;
+use futures::TryStreamExt;
+use sqlx::PgPool;
+use trading_results_rs::db::get_trades_stream_prefetched;
+use trading_results_rs::pipeline::SinkDefinition;
+use trading_results_rs::quantiles::PriceQuantiles;
+use trading_results_rs::warnings::Warnings;
+
+use super::Context;
+
+pub async fn run(ctx: &Context<'_>, pool: &PgPool, warnings: &mut Warnings) -> Result<()> {
+    let mut quantiles = PriceQuantiles::default();
+    let mut trades = get_trades_stream_prefetched(
+        pool,
+        &ctx.delivery_from,
+        &ctx.delivery_to,
+        ctx.units(),
+        ctx.decoding(),
+    );
+    while let Some(trade) = trades.try_next().await? {
+        quantiles.add_trade(&trade)?;
+    }
+    warnings.skipped_trades(quantiles.skipped_trades(), "the price quantiles");
+    let header = ["area", "market", "side", "trades", "p10", "p50", "p90"].map(String::from);
+    SinkDefinition::Stdout.write(&header, quantiles.records().into_iter())
+}
@@ -0,0 +1,72 @@
+//! Compares our totals per counter part and delivery day to the end of day summaries of the
+//! exchanges
+use std::path::PathBuf;
+
+use anyhow::Result;
+use futures::TryStreamExt;
+use sqlx::PgPool;
+use trading_results_rs::db::get_trades_stream_prefetched;
+use trading_results_rs::pipeline::SinkDefinition;
+use trading_results_rs::reconciliation::{self, Reconciliation};
+use trading_results_rs::warnings::Warnings;
+
+use super::Context;
+
+/// Reconciles the trades of the days of the summary files, warning about each mismatch and
+/// alerting the webhook of the config about them
+pub async fn run(
+    paths: &[PathBuf],
+    ctx: &Context<'_>,
+    pool: &PgPool,
+    warnings: &mut Warnings,
+) -> Result<()> {
+    let config = ctx.config;
+    let mut totals = reconciliation::ExchangeTotals::new();
+    for path in paths.iter() {
+        reconciliation::read_summary_file(path, &mut totals)?;
+    }
+    let mut reconciliation = Reconciliation::new(&config.reconciliation, totals)?;
+    let (summary_from, summary_to) = reconciliation.delivery_window()?;
+    let mut trades = get_trades_stream_prefetched(
+        pool,
+        &summary_from,
+        &summary_to,
+        ctx.units(),
+        ctx.decoding(),
+    );
+    while let Some(trade) = trades.try_next().await? {
+        reconciliation.add_trade(&trade)?;
+    }
+
+    let rows = reconciliation.rows();
+    let header = [
+        "counter_part",
+        "delivery_day",
+        "exchange_mwh",
+        "our_mwh",
+        "exchange_turnover",
+        "our_turnover",
+        "status",
+    ]
+    .map(String::from);
+    SinkDefinition::Stdout.write(&header, rows.iter().map(|row| row.record()))?;
+
+    let mismatches: Vec<_> = rows.iter().filter(|row| row.mismatch).collect();
+    for row in mismatches.iter() {
+        warnings.add(format!(
+            "{} on {} reports {} MWh and {} turnover, we have {} MWh and {}",
+            row.counter_part,
+            row.delivery_day,
+            row.exchange.volume_mwh,
+            row.exchange.turnover,
+            row.ours.volume_mwh.round_dp(3),
+            row.ours.turnover.round_dp(2)
+        ));
+    }
+    if let Some(url) = config.reconciliation.alert_webhook.as_deref() {
+        if !mismatches.is_empty() {
+            reconciliation::alert(url, &mismatches).await?;
+        }
+    }
+    Ok(())
+}
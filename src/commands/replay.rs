@@ -0,0 +1,62 @@
+//! Replays the trades of the window in the order they were traded
+use anyhow::Result;
+use sqlx::PgPool;
+use trading_results_rs::bounds;
+use trading_results_rs::replay::Replay;
+use trading_results_rs::report::Report;
+use trading_results_rs::warnings::Warnings;
+
+use super::{apply_missing_prices, gross_profit_by_currency, Context};
+
+/// Prints the gross profit as of each delivery hour as trades are replayed at `speed`, and the
+/// key metrics of the report at the end
+pub async fn run(
+    speed: Option<f64>,
+    ctx: &Context<'_>,
+    pool: &PgPool,
+    warnings: &mut Warnings,
+) -> Result<()> {
+    let config = ctx.config;
+    let report = replay_trades(pool, ctx, speed).await?;
+    let report = apply_missing_prices(report, config.missing_prices, warnings)?;
+    for area in report.other_areas() {
+        warnings.add(format!(
+            "Trades of unknown area {} are aggregated under it",
+            area
+        ));
+    }
+    report.print_key_metrics(
+        config.sign_convention,
+        ctx.cli.market_selection(),
+        ctx.area_selection.clone(),
+    );
+    Ok(())
+}
+
+async fn replay_trades(pool: &PgPool, ctx: &Context<'_>, speed: Option<f64>) -> Result<Report> {
+    let replay = Replay::new(speed)?;
+    let timezone = ctx.delivery_from.timezone();
+    let mut current_hour = None;
+
+    replay
+        .run(
+            pool,
+            &ctx.delivery_from,
+            &ctx.delivery_to,
+            ctx.units(),
+            ctx.decoding(),
+            |trade, report| {
+                let hour =
+                    bounds::format_local_hour(&trade.delivery_start.with_timezone(&timezone));
+                if current_hour.as_ref() != Some(&hour) {
+                    println!(
+                        "{}: gross profit {}",
+                        hour,
+                        gross_profit_by_currency(report)
+                    );
+                    current_hour = Some(hour);
+                }
+            },
+        )
+        .await
+}
@@ -0,0 +1,610 @@
+//! Creates the report of the window with each strategy run, or runs the reports saved in the
+//! database
+use std::env;
+use std::time::Instant;
+
+use anyhow::{anyhow, Context as _, Result};
+use clap::ValueEnum;
+use futures::{StreamExt, TryStreamExt};
+use sqlx::PgPool;
+use tokio::sync::mpsc;
+use tokio::task::{self, JoinSet};
+use tokio_stream::wrappers::ReceiverStream;
+use tracing::instrument;
+use trading_results_rs::db::{
+    get_aggregated_report_rows, get_table_trades_stream, get_trades, get_trades_for_report,
+    TradeSource, TradeStream, TradeTable,
+};
+use trading_results_rs::monitoring;
+use trading_results_rs::pipeline::SinkDefinition;
+use trading_results_rs::report::{
+    MissingPricePolicy, Report, ReportBuilder, SignConvention, TradeAggregateInput,
+};
+use trading_results_rs::saved::SavedReports;
+use trading_results_rs::schema;
+use trading_results_rs::seasonality::Seasonality;
+use trading_results_rs::signing::{self, Signer};
+use trading_results_rs::sinks::{ConfiguredSink, RenderedReport, ReportSink};
+use trading_results_rs::timing::{Phase, RunMetadata, StrategyTimings};
+use trading_results_rs::trade::{AreaSelection, Decoding, Trade};
+use trading_results_rs::units::QuantityUnits;
+use trading_results_rs::warnings::Warnings;
+
+use super::pipeline::{run_pipelines, saved_pipelines, Scan};
+use super::{apply_missing_prices, Context};
+use crate::cli::{Cli, OutputFormat, SavedReportCommand, Strategy};
+
+/// Saves, runs or lists the reports saved in the database
+pub async fn run_saved(
+    command: &SavedReportCommand,
+    ctx: &Context<'_>,
+    pool: &PgPool,
+    warnings: &mut Warnings,
+) -> Result<()> {
+    let saved = SavedReports::new(pool);
+    match command {
+        SavedReportCommand::Save { name, definition } => {
+            saved.save(name, definition).await?;
+            println!("Saved report {}", name);
+        }
+        SavedReportCommand::Run { names } => {
+            let pipelines = saved_pipelines(pool, ctx.config, names).await?;
+            let written = run_pipelines(
+                &ctx.source(pool),
+                ctx.delivery_from,
+                ctx.delivery_to,
+                pipelines,
+                Scan::Range,
+                warnings,
+            )
+            .await?;
+            let signer = Signer::from_config(ctx.config.signing.as_ref(), ctx.secrets).await?;
+            signing::sign_files(signer.as_ref(), &written)?;
+        }
+        SavedReportCommand::List => {
+            let header = ["name", "definition"].map(String::from);
+            let rows = saved.list().await?;
+            SinkDefinition::Stdout.write(
+                &header,
+                rows.into_iter()
+                    .map(|(name, definition)| vec![name, definition]),
+            )?;
+        }
+    }
+    Ok(())
+}
+
+/// Creates the report with each strategy of `--strategy`, delivering each to the sinks and
+/// exporting it, and pushes the metrics of the run to the monitoring gateway if configured
+pub async fn run(
+    ctx: &Context<'_>,
+    pool: &PgPool,
+    mut run_metadata: RunMetadata,
+    warnings: &mut Warnings,
+) -> Result<()> {
+    let (cli, config) = (ctx.cli, ctx.config);
+    let (units, decoding) = (ctx.units(), ctx.decoding());
+    // Set VERBOSE to get the phase breakdown of all strategies as JSON after the run
+    let verbose = env::var("VERBOSE").is_ok_and(|value| !value.is_empty() && value != "0");
+
+    let mut sinks = Vec::new();
+    for name in cli.sink.iter() {
+        let definition = config
+            .report_sinks
+            .get(name)
+            .ok_or(anyhow!("No sink {} under report_sinks in the config", name))?;
+        sinks.push(definition.connect(pool, ctx.secrets).await?);
+    }
+    if cli.save {
+        schema::check_reports_schema(pool).await?;
+    }
+    let saving = cli.save.then(|| pool.clone());
+
+    let builder = ctx.report_builder(Some(pool)).await?;
+    // Fails before any report is created if a strategy can't apply the config
+    for strategy in Strategy::value_variants() {
+        if let Some(name) = strategy.to_possible_value().filter(|_| cli.runs(*strategy)) {
+            builder.check(strategy.input()).with_context(|| {
+                format!(
+                    "Can't create the report with the {} strategy",
+                    name.get_name()
+                )
+            })?;
+        }
+    }
+
+    let export = |report, timings: &mut StrategyTimings, warnings: &mut Warnings| {
+        export_report(
+            report,
+            config.sign_convention,
+            config.missing_prices,
+            ctx.area_selection.clone(),
+            cli,
+            timings,
+            warnings,
+        )
+    };
+
+    if cli.runs(Strategy::Vec) {
+        cli.progress("Create report, standard");
+        let mut timings = StrategyTimings::new("vec");
+        let report = create_report(pool, &builder, units, decoding, &mut timings).await?;
+        deliver(&sinks, saving.as_ref(), &report).await?;
+        export(report, &mut timings, warnings)?;
+        run_metadata.add(timings);
+    }
+
+    if cli.runs(Strategy::VecParallel) {
+        cli.progress("Create report, aggregated in parallel");
+        let mut timings = StrategyTimings::new("vec_parallel");
+        let report = create_report_parallel(pool, &builder, units, decoding, &mut timings).await?;
+        deliver(&sinks, saving.as_ref(), &report).await?;
+        export(report, &mut timings, warnings)?;
+        run_metadata.add(timings);
+    }
+
+    if cli.runs(Strategy::SimpleTrade) {
+        cli.progress("Create report, simple trade structure (TradeForReport)");
+        let mut timings = StrategyTimings::new("simple_trade");
+        let report =
+            create_report_from_simple_trade(pool, &builder, units, decoding, &mut timings).await?;
+        deliver(&sinks, saving.as_ref(), &report).await?;
+        export(report, &mut timings, warnings)?;
+        run_metadata.add(timings);
+    }
+
+    if cli.seasonality.is_some() && !cli.runs(Strategy::Stream) {
+        return Err(anyhow!(
+            "Seasonality is aggregated alongside the stream strategy, which isn't run"
+        ));
+    }
+
+    if cli.runs(Strategy::Stream) {
+        cli.progress("Create report, stream");
+        let mut timings = StrategyTimings::new("stream");
+        let report = match cli.seasonality.clone() {
+            None => create_report_stream(pool, &builder, units, decoding, &mut timings).await?,
+            Some(path) => {
+                let (report, seasonality) = create_report_and_seasonality_stream(
+                    pool,
+                    &builder,
+                    units,
+                    decoding,
+                    &mut timings,
+                )
+                .await?;
+                warnings.skipped_trades(seasonality.skipped_trades(), "the seasonality");
+                let header = [
+                    "area",
+                    "hour_of_week",
+                    "weekday",
+                    "hour",
+                    "occurrences",
+                    "avg_pnl",
+                    "avg_mwh",
+                ]
+                .map(String::from);
+                SinkDefinition::Csv { path }.write(&header, seasonality.records().into_iter())?;
+                report
+            }
+        };
+        deliver(&sinks, saving.as_ref(), &report).await?;
+        export(report, &mut timings, warnings)?;
+        run_metadata.add(timings);
+    }
+
+    if cli.runs(Strategy::Pushdown) {
+        cli.progress("Create report, aggregated in the database");
+        let mut timings = StrategyTimings::new("pushdown");
+        let report = create_report_pushdown(pool, &builder, units, decoding, &mut timings).await?;
+        deliver(&sinks, saving.as_ref(), &report).await?;
+        export(report, &mut timings, warnings)?;
+        run_metadata.add(timings);
+    }
+
+    if cli.runs(Strategy::ChannelsStream) {
+        cli.progress("Create report, channels -> Stream<Trade> -> Report");
+        let mut timings = StrategyTimings::new("channels_stream");
+        let report =
+            create_report_channels_stream(pool, &builder, units, decoding, &mut timings).await?;
+        deliver(&sinks, saving.as_ref(), &report).await?;
+        export(report, &mut timings, warnings)?;
+        run_metadata.add(timings);
+    }
+
+    if cli.runs(Strategy::ChannelsInput) {
+        cli.progress("Create report, channels -> TradeAggregateInput -> Report");
+        let mut timings = StrategyTimings::new("channels_input");
+        let report =
+            create_report_channels_input(pool, &builder, units, decoding, &mut timings).await?;
+        deliver(&sinks, saving.as_ref(), &report).await?;
+        export(report, &mut timings, warnings)?;
+        run_metadata.add(timings);
+    }
+
+    if cli.runs(Strategy::Channels) {
+        cli.progress("Create report, channels -> Vec<Trace> -> Report::new(trades)");
+        let mut timings = StrategyTimings::new("channels");
+        let report = create_report_channels(pool, &builder, units, decoding, &mut timings).await?;
+        deliver(&sinks, saving.as_ref(), &report).await?;
+        export(report, &mut timings, warnings)?;
+        run_metadata.add(timings);
+    }
+
+    if verbose {
+        println!("{}", run_metadata.to_json()?);
+    }
+
+    if let Some(gateway) = &config.monitoring {
+        if let Err(err) = monitoring::push(gateway).await {
+            warnings.add(format!("{:#}", err));
+        }
+    }
+
+    cli.progress("Done :)");
+    Ok(())
+}
+
+/// Delivers the report to the sinks given with `--sink`, and saves it with `--save`
+async fn deliver(sinks: &[ConfiguredSink], saving: Option<&PgPool>, report: &Report) -> Result<()> {
+    if let Some(pool) = saving {
+        let id = report.save(pool).await?;
+        tracing::info!(id, "Saved the report");
+    }
+    if sinks.is_empty() {
+        return Ok(());
+    }
+    let rendered = RenderedReport::new(report)?;
+    for sink in sinks {
+        sink.write(&rendered).await?;
+    }
+    Ok(())
+}
+
+fn export_report(
+    report: Report,
+    signs: SignConvention,
+    missing_prices: MissingPricePolicy,
+    area: AreaSelection,
+    cli: &Cli,
+    timings: &mut StrategyTimings,
+    warnings: &mut Warnings,
+) -> Result<()> {
+    let report = apply_missing_prices(report, missing_prices, warnings)?;
+    match cli.output {
+        OutputFormat::Text => {
+            let now = Instant::now();
+            report.print_key_metrics(signs, cli.market_selection(), area);
+            timings.record(Phase::Export, now.elapsed());
+        }
+        // A line per strategy, leaving stdout to the reports
+        OutputFormat::Json => println!("{}", report.to_json()?),
+    }
+    Ok(())
+}
+
+#[instrument(skip_all, fields(from = %builder.delivery_from(), to = %builder.delivery_to()))]
+async fn create_report(
+    pool: &PgPool,
+    builder: &ReportBuilder,
+    units: &QuantityUnits,
+    decoding: Decoding,
+    timings: &mut StrategyTimings,
+) -> Result<Report> {
+    let trades = get_trades(
+        pool,
+        &builder.fetch_from(),
+        builder.delivery_to(),
+        builder.fetch_filter(),
+        units,
+        decoding,
+        timings,
+    )
+    .await?;
+
+    let now = Instant::now();
+    // In an async-sense, this is a compute heavy task, so we spawn it in a blocking thread
+    let builder = builder.clone();
+    let report = task::spawn_blocking(move || builder.build(&trades)).await??;
+    timings.record(Phase::Aggregation, now.elapsed());
+
+    Ok(report)
+}
+
+#[instrument(skip_all, fields(from = %builder.delivery_from(), to = %builder.delivery_to()))]
+async fn create_report_parallel(
+    pool: &PgPool,
+    builder: &ReportBuilder,
+    units: &QuantityUnits,
+    decoding: Decoding,
+    timings: &mut StrategyTimings,
+) -> Result<Report> {
+    let trades = get_trades(
+        pool,
+        &builder.fetch_from(),
+        builder.delivery_to(),
+        builder.fetch_filter(),
+        units,
+        decoding,
+        timings,
+    )
+    .await?;
+
+    let now = Instant::now();
+    // Rayon blocks the calling thread until all chunks are aggregated
+    let builder = builder.clone();
+    let report = task::spawn_blocking(move || builder.build_parallel(&trades)).await??;
+    timings.record(Phase::Aggregation, now.elapsed());
+
+    Ok(report)
+}
+
+#[instrument(skip_all, fields(from = %builder.delivery_from(), to = %builder.delivery_to()))]
+async fn create_report_from_simple_trade(
+    pool: &PgPool,
+    builder: &ReportBuilder,
+    units: &QuantityUnits,
+    decoding: Decoding,
+    timings: &mut StrategyTimings,
+) -> Result<Report> {
+    let trades_for_report = get_trades_for_report(
+        pool,
+        &builder.fetch_from(),
+        builder.delivery_to(),
+        builder.fetch_filter(),
+        units,
+        decoding,
+        timings,
+    )
+    .await?;
+
+    let now = Instant::now();
+    // In an async-sense, this is a compute heavy task, so we spawn it in a blocking thread
+    let builder = builder.clone();
+    let report =
+        task::spawn_blocking(move || builder.build_from_trades_for_report(trades_for_report))
+            .await??;
+    timings.record(Phase::Aggregation, now.elapsed());
+
+    Ok(report)
+}
+
+#[instrument(skip_all, fields(from = %builder.delivery_from(), to = %builder.delivery_to()))]
+pub async fn create_report_stream(
+    pool: &PgPool,
+    builder: &ReportBuilder,
+    units: &QuantityUnits,
+    decoding: Decoding,
+    timings: &mut StrategyTimings,
+) -> Result<Report> {
+    let source = TradeSource::new(pool.clone(), units.clone()).with_decoding(decoding);
+    let trades_stream = builder.trades(&source);
+
+    let now = Instant::now();
+    let report = builder.build_from_stream(trades_stream).await?;
+    timings.record(Phase::Stream, now.elapsed());
+
+    Ok(report)
+}
+
+#[instrument(skip_all, fields(from = %builder.delivery_from(), to = %builder.delivery_to()))]
+async fn create_report_pushdown(
+    pool: &PgPool,
+    builder: &ReportBuilder,
+    units: &QuantityUnits,
+    decoding: Decoding,
+    timings: &mut StrategyTimings,
+) -> Result<Report> {
+    let rows = get_aggregated_report_rows(pool, builder, units, decoding, timings).await?;
+
+    let now = Instant::now();
+    let report = builder.build_from_aggregated_rows(rows)?;
+    timings.record(Phase::Aggregation, now.elapsed());
+
+    Ok(report)
+}
+
+/// Like `create_report_stream`, aggregating the seasonality of the trades as the builder counts
+/// them from the same stream
+#[instrument(skip_all, fields(from = %builder.delivery_from(), to = %builder.delivery_to()))]
+async fn create_report_and_seasonality_stream(
+    pool: &PgPool,
+    builder: &ReportBuilder,
+    units: &QuantityUnits,
+    decoding: Decoding,
+    timings: &mut StrategyTimings,
+) -> Result<(Report, Seasonality)> {
+    let source = TradeSource::new(pool.clone(), units.clone()).with_decoding(decoding);
+    let mut trades_stream = builder.trades(&source);
+
+    let now = Instant::now();
+    let mut report = builder.empty()?;
+    let mut seasonality = Seasonality::new(builder.delivery_from(), builder.delivery_to())?;
+    while let Some(trade) = trades_stream.try_next().await? {
+        if let Some(trade) = builder.prepare(&trade)? {
+            report.add_trade(&trade)?;
+            seasonality.add_trade(&trade)?;
+        }
+    }
+    timings.record(Phase::Stream, now.elapsed());
+
+    Ok((report, seasonality))
+}
+
+/// Sends the trades of a table over the channel until they run out or the receiver is gone,
+/// e.g. because the report failed, failing with the error of the stream if there is one
+async fn send_trades(mut trades: TradeStream<'_>, tx: mpsc::Sender<Trade>) -> Result<()> {
+    while let Some(trade) = trades.try_next().await? {
+        if tx.send(trade).await.is_err() {
+            break;
+        }
+    }
+    Ok(())
+}
+
+/// Waits for the tasks sending the trades of the tables, failing with the first error, or
+/// panic, of any of them. The channel closes when a task fails, so its receiver can't tell.
+async fn join_senders(mut tasks: JoinSet<Result<()>>) -> Result<()> {
+    while let Some(sent) = tasks.join_next().await {
+        sent??;
+    }
+    Ok(())
+}
+
+/// Spawns a task per table sending its trades over the channel, see `send_trades`
+fn spawn_senders(
+    pool: &PgPool,
+    builder: &ReportBuilder,
+    units: &QuantityUnits,
+    decoding: Decoding,
+    tx: mpsc::Sender<Trade>,
+) -> JoinSet<Result<()>> {
+    let (delivery_from, delivery_to) = (builder.fetch_from(), *builder.delivery_to());
+    let filter = builder.fetch_filter();
+    let mut tasks = JoinSet::new();
+    for table in TradeTable::ALL {
+        // The pool is a handle to the shared connections, so each task takes a clone of its own
+        let (tx, pool, units) = (tx.clone(), pool.clone(), units.clone());
+        tasks.spawn(async move {
+            let trades = get_table_trades_stream(
+                &pool,
+                table,
+                &delivery_from,
+                &delivery_to,
+                filter,
+                &units,
+                decoding,
+            );
+            send_trades(trades, tx).await
+        });
+    }
+    tasks
+}
+
+/// Fetches each table in a task of its own like `create_report_channels`, but streams the
+/// receiving end of the channel into the report, so no trades are collected in between
+#[instrument(skip_all, fields(from = %builder.delivery_from(), to = %builder.delivery_to()))]
+async fn create_report_channels_stream(
+    pool: &PgPool,
+    builder: &ReportBuilder,
+    units: &QuantityUnits,
+    decoding: Decoding,
+    timings: &mut StrategyTimings,
+) -> Result<Report> {
+    let (tx, rx) = mpsc::channel(100);
+    let senders = spawn_senders(pool, builder, units, decoding, tx);
+
+    let now = Instant::now();
+    // The stream ends once the tasks are done and have dropped their senders
+    let trades: TradeStream<'static> = Box::pin(ReceiverStream::new(rx).map(Ok));
+    let report = builder.build_from_stream(trades).await?;
+    join_senders(senders).await?;
+    timings.record(Phase::Stream, now.elapsed());
+
+    Ok(report)
+}
+
+/// Like `create_report_channels_stream`, but the tasks map their trades to the fields the report
+/// aggregates before sending them, so a fraction of each trade crosses the channel
+#[instrument(skip_all, fields(from = %builder.delivery_from(), to = %builder.delivery_to()))]
+async fn create_report_channels_input(
+    pool: &PgPool,
+    builder: &ReportBuilder,
+    units: &QuantityUnits,
+    decoding: Decoding,
+    timings: &mut StrategyTimings,
+) -> Result<Report> {
+    let (tx, mut rx) = mpsc::channel::<Result<TradeAggregateInput>>(100);
+    let (delivery_from, delivery_to) = (builder.fetch_from(), *builder.delivery_to());
+    let filter = builder.fetch_filter();
+
+    for table in TradeTable::ALL {
+        let (tx, pool, units, builder) = (tx.clone(), pool.clone(), units.clone(), builder.clone());
+        tokio::spawn(async move {
+            let mut trades = get_table_trades_stream(
+                &pool,
+                table,
+                &delivery_from,
+                &delivery_to,
+                filter,
+                &units,
+                decoding,
+            );
+            while let Some(trade) = trades.next().await {
+                // Trades the builder doesn't count aren't sent at all
+                let Some(input) = trade
+                    .map_err(anyhow::Error::from)
+                    .and_then(|trade| builder.input(&trade))
+                    .transpose()
+                else {
+                    continue;
+                };
+                let failed = input.is_err();
+                if tx.send(input).await.is_err() || failed {
+                    return;
+                }
+            }
+        });
+    }
+    drop(tx);
+
+    let now = Instant::now();
+    let mut report = builder.empty()?;
+    while let Some(input) = rx.recv().await {
+        report.add_input(&input?);
+    }
+    timings.record(Phase::Stream, now.elapsed());
+
+    Ok(report)
+}
+
+#[instrument(skip_all, fields(from = %builder.delivery_from(), to = %builder.delivery_to()))]
+async fn create_report_channels(
+    pool: &PgPool,
+    builder: &ReportBuilder,
+    units: &QuantityUnits,
+    decoding: Decoding,
+    timings: &mut StrategyTimings,
+) -> Result<Report> {
+    // This is pretty slow as we have to get all trades (send them over the channels as well)
+    // and then collect them into a vector. `create_report_channels_stream` aggregates them as
+    // they arrive instead, and `create_report_channels_input` only sends what the report needs.
+    let (tx, mut rx) = mpsc::channel(100);
+    let senders = spawn_senders(pool, builder, units, decoding, tx);
+
+    let now = Instant::now();
+    // The receiver returns `None` once all the tasks are done and have dropped their senders
+    let mut trades: Vec<Trade> = Vec::new();
+    while let Some(trade) = rx.recv().await {
+        trades.push(trade);
+    }
+    join_senders(senders).await?;
+    timings.record(Phase::Collect, now.elapsed());
+
+    let now = Instant::now();
+    let report = builder.build(&trades)?;
+    timings.record(Phase::Aggregation, now.elapsed());
+
+    Ok(report)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test]
+    async fn failing_senders_fail_the_report() {
+        let (tx, mut rx) = mpsc::channel(1);
+        let mut senders = JoinSet::new();
+        let failing: TradeStream<'static> =
+            Box::pin(futures::stream::iter([Err(sqlx::Error::RowNotFound)]));
+        senders.spawn(send_trades(failing, tx));
+        // The channel closes as if the trades had run out
+        assert!(rx.recv().await.is_none());
+        assert!(join_senders(senders).await.is_err());
+
+        let mut panicking = JoinSet::new();
+        panicking.spawn(async { panic!("Failed to decode a trade") });
+        assert!(join_senders(panicking).await.is_err());
+    }
+}
@@ -0,0 +1,139 @@
+//! Runs the jobs of `scheduler` in the config until stopped
+use std::time::Instant;
+
+use anyhow::{anyhow, Result};
+use chrono::{DateTime, Days, NaiveTime, TimeZone};
+use chrono_tz::{Europe::Copenhagen, Tz};
+use sqlx::PgPool;
+use trading_results_rs::config::Config;
+use trading_results_rs::db::TradeSource;
+use trading_results_rs::monitoring;
+use trading_results_rs::pipeline::Pipeline;
+use trading_results_rs::scheduler::{JobAction, JobRunner, ScheduledJob, Scheduler};
+use trading_results_rs::signing::{self, Signer};
+use trading_results_rs::snapshot::{ReportSnapshot, SnapshotLog};
+use trading_results_rs::timing::{Phase, StrategyTimings};
+use trading_results_rs::warnings::Warnings;
+
+use super::pipeline::{run_pipelines, saved_pipelines, Scan};
+use super::report::create_report_stream;
+use super::{apply_missing_prices, cancel_on_ctrl_c, Context};
+
+pub async fn run(ctx: &Context<'_>, pool: &PgPool) -> Result<()> {
+    let signer = Signer::from_config(ctx.config.signing.as_ref(), ctx.secrets).await?;
+    let runner = ScheduledRunner {
+        pool,
+        config: ctx.config,
+        signer: signer.as_ref(),
+    };
+    Scheduler::load(ctx.config.scheduler.clone())?
+        .run(&runner, &cancel_on_ctrl_c())
+        .await
+}
+
+/// Runs scheduled jobs over the delivery day before the day they're scheduled on
+struct ScheduledRunner<'a> {
+    pool: &'a PgPool,
+    config: &'a Config,
+    signer: Option<&'a Signer>,
+}
+
+impl JobRunner for ScheduledRunner<'_> {
+    async fn run(&self, job: &ScheduledJob, scheduled_at: DateTime<Tz>) -> Result<()> {
+        let result = self.run_job(job, scheduled_at).await;
+        monitoring::record_scheduled_run(&job.name, result.is_ok());
+        if let Some(gateway) = &self.config.monitoring {
+            // The run is done either way, so a gateway that's down doesn't fail it
+            if let Err(err) = monitoring::push(gateway).await {
+                eprintln!("Warning: {:#}", err);
+            }
+        }
+        result
+    }
+}
+
+impl ScheduledRunner<'_> {
+    async fn run_job(&self, job: &ScheduledJob, scheduled_at: DateTime<Tz>) -> Result<()> {
+        let delivery_to = Copenhagen
+            .from_local_datetime(&scheduled_at.date_naive().and_time(NaiveTime::MIN))
+            .earliest()
+            .ok_or(anyhow!(
+                "No local midnight on {}",
+                scheduled_at.date_naive()
+            ))?;
+        let delivery_from = Copenhagen
+            .from_local_datetime(
+                &(delivery_to.date_naive() - Days::new(1)).and_time(NaiveTime::MIN),
+            )
+            .earliest()
+            .ok_or(anyhow!("No local midnight before {}", delivery_to))?;
+        // A scheduled job keeps running, so its warnings are only logged
+        let mut warnings = Warnings::default();
+
+        match &job.action {
+            JobAction::Snapshot => {
+                let snapshots = &self.config.snapshots;
+                let log = SnapshotLog::new(&snapshots.path, snapshots.full_every)?;
+                let mut timings = StrategyTimings::new("stream");
+                let builder = self
+                    .config
+                    .report_builder(Some(self.pool), &delivery_from, &delivery_to)
+                    .await?;
+                let report = create_report_stream(
+                    self.pool,
+                    &builder,
+                    &self.config.quantity_units,
+                    self.config.decoding,
+                    &mut timings,
+                )
+                .await?;
+                timings.finish();
+                let report =
+                    apply_missing_prices(report, self.config.missing_prices, &mut warnings)?;
+                log.append(&ReportSnapshot::from_report(&report))?;
+                signing::sign_files(self.signer, std::slice::from_ref(&snapshots.path))?;
+            }
+            JobAction::Pipelines(names) => {
+                let pipelines = names
+                    .iter()
+                    .map(|name| self.config.pipeline(name))
+                    .collect::<Result<Vec<_>>>()?;
+                self.run_pipelines(delivery_from, delivery_to, pipelines, &mut warnings)
+                    .await?;
+            }
+            JobAction::SavedReports(names) => {
+                let pipelines = saved_pipelines(self.pool, self.config, names).await?;
+                self.run_pipelines(delivery_from, delivery_to, pipelines, &mut warnings)
+                    .await?;
+            }
+        }
+        warnings.print();
+
+        Ok(())
+    }
+
+    async fn run_pipelines(
+        &self,
+        delivery_from: DateTime<Tz>,
+        delivery_to: DateTime<Tz>,
+        pipelines: Vec<Pipeline>,
+        warnings: &mut Warnings,
+    ) -> Result<()> {
+        let mut timings = StrategyTimings::new("pipelines");
+        let now = Instant::now();
+        let source = TradeSource::new(self.pool.clone(), self.config.quantity_units.clone())
+            .with_decoding(self.config.decoding);
+        let written = run_pipelines(
+            &source,
+            delivery_from,
+            delivery_to,
+            pipelines,
+            Scan::Range,
+            warnings,
+        )
+        .await?;
+        timings.record(Phase::Stream, now.elapsed());
+        timings.finish();
+        signing::sign_files(self.signer, &written)
+    }
+}
@@ -0,0 +1,14 @@
+//! Searches the trades of all tables, streaming the matches as CSV
+use anyhow::Result;
+use sqlx::PgPool;
+
+use super::Context;
+use crate::cli::SearchArgs;
+
+/// Matching trades are streamed as CSV, continuing after `--after <table>:<id>` if given
+pub async fn run(search: &SearchArgs, ctx: &Context<'_>, pool: &PgPool) -> Result<()> {
+    let (search, cursor) = search.search();
+    search
+        .write_csv(pool, cursor, ctx.units(), std::io::stdout())
+        .await
+}
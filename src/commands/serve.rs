@@ -0,0 +1,43 @@
+//! Serves report metrics over HTTP
+use anyhow::Result;
+use sqlx::PgPool;
+use trading_results_rs::server::ReportServer;
+
+use super::Context;
+
+/// Serves the reports of the trades of the database on the address until stopped
+pub async fn run(address: &str, ctx: &Context<'_>, pool: &PgPool) -> Result<()> {
+    ReportServer::new(ctx.source(pool), ctx.config)
+        .serve(address)
+        .await
+}
+
+#[cfg(all(test, feature = "sqlite"))]
+mod tests {
+    use chrono::DateTime;
+    use clap::Parser;
+    use trading_results_rs::config::Config;
+    use trading_results_rs::sqlite;
+
+    use super::*;
+    use crate::cli::Cli;
+
+    #[tokio::test]
+    async fn the_server_reads_windows_like_the_cli() -> Result<()> {
+        let (from, to) = ("2024-05-01", "2024-05-31");
+        let cli = Cli::try_parse_from(["trading-results-rs", "--from", from, "--to", to])?;
+        let (delivery_from, delivery_to) = cli.delivery_window()?;
+
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await?;
+        let address = listener.local_addr()?;
+        let router = ReportServer::new(sqlite::demo_source().await?, &Config::default()).router();
+        tokio::spawn(async move { axum::serve(listener, router).await });
+        let url = format!("http://{}/report?from={}&to={}", address, from, to);
+        let response: serde_json::Value =
+            reqwest::get(url).await?.error_for_status()?.json().await?;
+        let bound = |name: &str| DateTime::parse_from_rfc3339(response[name].as_str().unwrap());
+        assert_eq!(bound("delivery_from")?, delivery_from.fixed_offset());
+        assert_eq!(bound("delivery_to")?, delivery_to.fixed_offset());
+        Ok(())
+    }
+}
@@ -0,0 +1,33 @@
+//! Stores a snapshot of the report in the snapshot log
+use anyhow::Result;
+use sqlx::PgPool;
+use trading_results_rs::signing::{self, Signer};
+use trading_results_rs::snapshot::{ReportSnapshot, SnapshotLog};
+use trading_results_rs::timing::StrategyTimings;
+use trading_results_rs::warnings::Warnings;
+
+use super::report::create_report_stream;
+use super::{apply_missing_prices, Context};
+
+/// Appends the snapshot of the report to the log and signs the log if configured
+pub async fn run(ctx: &Context<'_>, pool: &PgPool, warnings: &mut Warnings) -> Result<()> {
+    let config = ctx.config;
+    let log = SnapshotLog::new(&config.snapshots.path, config.snapshots.full_every)?;
+    let mut timings = StrategyTimings::new("stream");
+    let builder = ctx.report_builder(Some(pool)).await?;
+    let report =
+        create_report_stream(pool, &builder, ctx.units(), ctx.decoding(), &mut timings).await?;
+    let report = apply_missing_prices(report, config.missing_prices, warnings)?;
+    log.append(&ReportSnapshot::from_report(&report))?;
+    let signer = Signer::from_config(config.signing.as_ref(), ctx.secrets).await?;
+    signing::sign_files(
+        signer.as_ref(),
+        std::slice::from_ref(&config.snapshots.path),
+    )?;
+    println!(
+        "Stored snapshot of {} cells in {}",
+        report.breakdown().len(),
+        config.snapshots.path.display()
+    );
+    Ok(())
+}
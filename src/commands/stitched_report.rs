@@ -0,0 +1,52 @@
+//! Prints the report of the window stitched from finalized snapshots and the trades of the rest
+use anyhow::Result;
+use sqlx::PgPool;
+use trading_results_rs::bounds;
+use trading_results_rs::signing;
+use trading_results_rs::snapshot::SnapshotLog;
+use trading_results_rs::stitch::StitchPlan;
+use trading_results_rs::timing::StrategyTimings;
+use trading_results_rs::warnings::Warnings;
+
+use super::report::create_report_stream;
+use super::Context;
+
+/// Takes the start of the window from finalized snapshots and only aggregates the trades of the
+/// rest, e.g. the last days of a month to date
+pub async fn run(ctx: &Context<'_>, pool: &PgPool, warnings: &mut Warnings) -> Result<()> {
+    let config = ctx.config;
+    let snapshots = &config.snapshots;
+    if let Some(public_key) = config
+        .signing
+        .as_ref()
+        .and_then(|signing| signing.public_key.as_deref())
+    {
+        signing::verify_file(
+            public_key,
+            &snapshots.path,
+            &signing::signature_path(&snapshots.path),
+        )?;
+    }
+    let log = SnapshotLog::new(&snapshots.path, snapshots.full_every)?;
+    let plan = StitchPlan::new(
+        log.read_all()?,
+        &ctx.delivery_from,
+        &ctx.delivery_to,
+        chrono::Duration::hours(snapshots.finalized_after_hours.into()),
+    );
+    // On stderr, leaving stdout to the report
+    eprintln!(
+        "Stitching {} finalized snapshots, aggregating {}",
+        plan.finalized.len(),
+        bounds::describe_window(&plan.live_from, &ctx.delivery_to)
+    );
+    let mut timings = StrategyTimings::new("stream");
+    let builder = ctx
+        .report_builder_over(Some(pool), &plan.live_from, &ctx.delivery_to)
+        .await?;
+    let live =
+        create_report_stream(pool, &builder, ctx.units(), ctx.decoding(), &mut timings).await?;
+    warnings.skipped_trades(live.skipped_trades(), "the preliminary part of the report");
+    println!("{}", plan.stitch(live)?.to_json()?);
+    Ok(())
+}
@@ -0,0 +1,42 @@
+//! Checks exchange files and the trade tables for bad data
+use std::path::Path;
+
+use anyhow::{anyhow, Result};
+use sqlx::PgPool;
+use trading_results_rs::{ingest, validate};
+
+use super::Context;
+
+/// Checks every row of an exchange file, failing if any are invalid
+pub fn run_file(path: &Path, ctx: &Context<'_>) -> Result<()> {
+    let summary = ingest::validate_file(path, ctx.units())?;
+    summary.print();
+    if !summary.is_valid() {
+        return Err(anyhow!(
+            "{} invalid rows in {}",
+            summary.errors.len(),
+            path.display()
+        ));
+    }
+    Ok(())
+}
+
+/// Scans the trade tables for bad data in the window, failing if there is any
+pub async fn run(ctx: &Context<'_>, pool: &PgPool) -> Result<()> {
+    let summary = validate::check_tables(
+        pool,
+        &ctx.delivery_from,
+        &ctx.delivery_to,
+        ctx.units(),
+        ctx.decoding(),
+    )
+    .await?;
+    println!("{}", serde_json::to_string_pretty(&summary)?);
+    if !summary.is_clean() {
+        return Err(anyhow!(
+            "{} issues in the trade tables, see the summary",
+            summary.issues()
+        ));
+    }
+    Ok(())
+}
@@ -0,0 +1,40 @@
+//! Cash flows per counter part with the VAT of their invoices, for finance
+use anyhow::Result;
+use futures::TryStreamExt;
+use sqlx::PgPool;
+use trading_results_rs::counterparts::CounterPartRegistry;
+use trading_results_rs::db::get_trades_stream_prefetched;
+use trading_results_rs::pipeline::SinkDefinition;
+use trading_results_rs::vat::VatLedger;
+use trading_results_rs::warnings::Warnings;
+
+use super::Context;
+
+pub async fn run(ctx: &Context<'_>, pool: &PgPool, warnings: &mut Warnings) -> Result<()> {
+    let registry = CounterPartRegistry::new(&ctx.config.counter_parts);
+    let mut ledger = VatLedger::new(&registry, &ctx.config.vat);
+    let mut trades = get_trades_stream_prefetched(
+        pool,
+        &ctx.delivery_from,
+        &ctx.delivery_to,
+        ctx.units(),
+        ctx.decoding(),
+    );
+    while let Some(trade) = trades.try_next().await? {
+        ledger.add_trade(&trade)?;
+    }
+    warnings.skipped_trades(ledger.skipped_trades(), "the VAT ledger");
+    let header = [
+        "counter_part",
+        "currency",
+        "area",
+        "side",
+        "vat_treatment",
+        "vat_rate",
+        "net",
+        "vat",
+        "gross",
+    ]
+    .map(String::from);
+    SinkDefinition::Stdout.write(&header, ledger.rows()?.iter().map(|row| row.record()))
+}
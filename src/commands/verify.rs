@@ -0,0 +1,26 @@
+//! Checks a file against its detached signature
+use std::path::Path;
+
+use anyhow::{anyhow, Result};
+use trading_results_rs::signing::{self, Signer};
+
+use super::Context;
+
+/// Verifies the file with the configured public key, or that of the signing key, against the
+/// signature, `<path>.sig` if not given
+pub async fn run(path: &Path, signature: Option<&Path>, ctx: &Context<'_>) -> Result<()> {
+    let signature = signature
+        .map(Path::to_path_buf)
+        .unwrap_or_else(|| signing::signature_path(path));
+    let signing = ctx.config.signing.as_ref();
+    let public_key = match signing.and_then(|signing| signing.public_key.clone()) {
+        Some(public_key) => public_key,
+        None => Signer::from_config(signing, ctx.secrets)
+            .await?
+            .ok_or(anyhow!("Signing is not configured"))?
+            .public_key(),
+    };
+    signing::verify_file(&public_key, path, &signature)?;
+    println!("{} matches its signature", path.display());
+    Ok(())
+}
@@ -0,0 +1,49 @@
+//! Keeps a report of the window up to date as trades are inserted
+use std::time::Duration;
+
+use anyhow::Result;
+use chrono::Local;
+use chrono_tz::Europe::Copenhagen;
+use sqlx::PgPool;
+use trading_results_rs::broadcast::Broadcaster;
+use trading_results_rs::position::ForwardCurve;
+use trading_results_rs::watch;
+
+use super::{cancel_on_ctrl_c, gross_profit_by_currency, Context};
+
+/// Polls for new trades every `poll_seconds`, printing the gross profit and publishing the
+/// metrics to the broadcast of the config, until stopped
+pub async fn run(poll_seconds: u64, ctx: &Context<'_>, pool: &PgPool) -> Result<()> {
+    let config = ctx.config;
+    let poll_interval = Duration::from_secs(poll_seconds);
+    let forward_curve = config
+        .forward_curve
+        .as_ref()
+        .map(|curve| ForwardCurve::new(curve, Local::now().with_timezone(&Copenhagen)))
+        .transpose()?;
+    let mut broadcaster = match config.broadcast.as_ref() {
+        Some(broadcast) => Some(Broadcaster::connect(broadcast, ctx.secrets).await?),
+        None => None,
+    };
+    let source = ctx.source(pool).with_cancellation(cancel_on_ctrl_c());
+    watch::watch(
+        &source,
+        &ctx.delivery_from,
+        &ctx.delivery_to,
+        poll_interval,
+        forward_curve,
+        |report, added| {
+            println!(
+                "{} new trades, gross profit {}",
+                added,
+                gross_profit_by_currency(report)
+            );
+            if let Some(broadcaster) = broadcaster.as_mut() {
+                if let Err(err) = broadcaster.publish(report) {
+                    eprintln!("Warning: {:#}", err);
+                }
+            }
+        },
+    )
+    .await
+}
@@ -0,0 +1,183 @@
+use std::collections::BTreeMap;
+
+use anyhow::Result;
+use rust_decimal::Decimal;
+
+use crate::report::contract_length;
+use crate::trade::{CounterPart, Market, Trade};
+
+/// Volume and gross cash flow traded with a counter part, regardless of side
+#[derive(Debug, Default, Clone, Copy, PartialEq)]
+pub struct Exposure {
+    pub mwh: Decimal,
+    pub cash_flow: Decimal,
+}
+
+/// A counter part's share of the volume and gross cash flow traded in a market, along with the
+/// concentration of the market
+#[derive(Debug, PartialEq)]
+pub struct ConcentrationRow {
+    pub market: Market,
+    pub counter_part: CounterPart,
+    pub exposure: Exposure,
+    pub volume_share: Decimal,
+    pub cash_flow_share: Decimal,
+    pub volume_hhi: Decimal,
+    pub cash_flow_hhi: Decimal,
+}
+
+impl ConcentrationRow {
+    pub fn record(&self) -> Vec<String> {
+        vec![
+            self.market.to_string(),
+            self.counter_part.to_string(),
+            self.exposure.mwh.round_dp(1).to_string(),
+            self.volume_share.to_string(),
+            self.exposure.cash_flow.round_dp(2).to_string(),
+            self.cash_flow_share.to_string(),
+            self.volume_hhi.to_string(),
+            self.cash_flow_hhi.to_string(),
+        ]
+    }
+}
+
+/// Exposure per counter part and market. Trades are counted like in `Report`, so trades without
+/// a price are skipped and volumes are energy.
+#[derive(Debug, Default)]
+pub struct Concentration {
+    exposures: BTreeMap<(Market, CounterPart), Exposure>,
+    skipped_trades: usize,
+}
+
+/// Share of the part in the total, zero without a total
+fn share(part: Decimal, total: Decimal) -> Decimal {
+    if total.is_zero() {
+        return Decimal::ZERO;
+    }
+    part / total
+}
+
+/// Herfindahl-Hirschman index of the shares, i.e. the sum of the squared shares in percent.
+/// It ranges from 10000 when everything is traded with a single counter part down towards zero.
+fn hhi(shares: impl Iterator<Item = Decimal>) -> Decimal {
+    let hundred = Decimal::ONE_HUNDRED;
+    shares
+        .map(|share| (share * hundred) * (share * hundred))
+        .sum::<Decimal>()
+        .round_dp(0)
+}
+
+impl Concentration {
+    pub fn add_trade(&mut self, trade: &Trade) -> Result<()> {
+        let Some(price) = trade.price else {
+            self.skipped_trades += 1;
+            return Ok(());
+        };
+        let mwh =
+            trade.quantity_mwh.abs() * contract_length(&trade.delivery_start, &trade.delivery_end)?;
+        let exposure = self
+            .exposures
+            .entry((Market::from(trade.trade_type), trade.counter_part.clone()))
+            .or_default();
+        exposure.mwh += mwh;
+        exposure.cash_flow += mwh * price.abs();
+        Ok(())
+    }
+
+    pub fn skipped_trades(&self) -> usize {
+        self.skipped_trades
+    }
+
+    /// A row per market and counter part, with shares of the market totals
+    pub fn rows(&self) -> Vec<ConcentrationRow> {
+        let mut totals: BTreeMap<Market, Exposure> = BTreeMap::new();
+        for ((market, _), exposure) in self.exposures.iter() {
+            let total = totals.entry(*market).or_default();
+            total.mwh += exposure.mwh;
+            total.cash_flow += exposure.cash_flow;
+        }
+
+        let mut rows: Vec<ConcentrationRow> = self
+            .exposures
+            .iter()
+            .map(|((market, counter_part), &exposure)| ConcentrationRow {
+                market: *market,
+                counter_part: counter_part.clone(),
+                exposure,
+                volume_share: share(exposure.mwh, totals[market].mwh),
+                cash_flow_share: share(exposure.cash_flow, totals[market].cash_flow),
+                volume_hhi: Decimal::ZERO,
+                cash_flow_hhi: Decimal::ZERO,
+            })
+            .collect();
+
+        let indices: BTreeMap<Market, (Decimal, Decimal)> = totals
+            .keys()
+            .map(|&market| {
+                let in_market = || rows.iter().filter(move |row| row.market == market);
+                (
+                    market,
+                    (
+                        hhi(in_market().map(|row| row.volume_share)),
+                        hhi(in_market().map(|row| row.cash_flow_share)),
+                    ),
+                )
+            })
+            .collect();
+        for row in rows.iter_mut() {
+            (row.volume_hhi, row.cash_flow_hhi) = indices[&row.market];
+            row.volume_share = row.volume_share.round_dp(4);
+            row.cash_flow_share = row.cash_flow_share.round_dp(4);
+        }
+        rows
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::trade::TradeType;
+    use rust_decimal_macros::dec;
+
+    #[test]
+    fn shares_and_concentration_are_per_market() {
+        let mut concentration = Concentration::default();
+        let trades = [
+            Trade::test()
+                .counter_part(CounterPart::NORDPOOL)
+                .mwh(30)
+                .price(10)
+                .build(),
+            Trade::test()
+                .counter_part(CounterPart::EPEX)
+                .sell()
+                .mwh(10)
+                .price(30)
+                .build(),
+            Trade::test()
+                .counter_part(CounterPart::EPEX)
+                .trade_type(TradeType::AuctionEurDahH)
+                .mwh(5)
+                .price(10)
+                .build(),
+            Trade::test().no_price().build(),
+        ];
+        for trade in trades.iter() {
+            concentration.add_trade(trade).unwrap();
+        }
+
+        let rows = concentration.rows();
+
+        assert_eq!(concentration.skipped_trades(), 1);
+        // The auction is only traded with a single counter part
+        assert_eq!(rows[0].market, Market::Auction);
+        assert_eq!(rows[0].volume_share, dec!(1));
+        assert_eq!(rows[0].volume_hhi, dec!(10000));
+        // 75% / 25% of the intraday volume, but half of its cash flow, ordered after epex
+        assert_eq!(rows[2].counter_part, CounterPart::NORDPOOL);
+        assert_eq!(rows[2].volume_share, dec!(0.75));
+        assert_eq!(rows[2].cash_flow_share, dec!(0.5));
+        assert_eq!(rows[2].volume_hhi, dec!(6250));
+        assert_eq!(rows[2].cash_flow_hhi, dec!(5000));
+    }
+}
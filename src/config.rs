@@ -0,0 +1,378 @@
+use std::collections::HashMap;
+use std::env;
+use std::fs;
+use std::path::PathBuf;
+use std::sync::Arc;
+
+use anyhow::{anyhow, bail, Context, Result};
+use chrono::{DateTime, Duration};
+use chrono_tz::Tz;
+use serde::Deserialize;
+use sqlx::PgPool;
+
+use crate::allocation::FixedCost;
+use crate::blocks::BlockDefinition;
+use crate::bounds::WindowFilter;
+use crate::broadcast::BroadcastConfig;
+use crate::counterparts::CounterPartInfo;
+use crate::fees::{FeeRule, FeeSchedule};
+use crate::fiscal::FiscalConfig;
+use crate::fx::{FxConfig, FxRates};
+use crate::hedging::FxHedgeConfig;
+use crate::hierarchy::AreaHierarchy;
+use crate::imbalance::ImbalanceCostConfig;
+use crate::ingest::csv::CsvMapping;
+#[cfg(feature = "kafka")]
+use crate::ingest::kafka::KafkaConfig;
+use crate::ingest::ndjson::JsonMapping;
+use crate::monitoring::MonitoringConfig;
+use crate::permissions::RolePermissions;
+use crate::pipeline::{Pipeline, PipelineDefinition};
+use crate::position::ForwardCurveConfig;
+use crate::prices::{SpotPriceConfig, SpotPrices};
+use crate::reconciliation::ReconciliationConfig;
+use crate::report::{BoundaryPolicy, MissingPricePolicy, ReportBuilder, SignConvention};
+use crate::scheduler::SchedulerConfig;
+use crate::secrets::{Secrets, SecretsConfig};
+use crate::settlement::IspSchedule;
+use crate::signing::SigningConfig;
+use crate::sinks::ReportSinkDefinition;
+use crate::trade::Decoding;
+use crate::units::QuantityUnits;
+use crate::vat::VatConfig;
+
+const DEFAULT_CONFIG_PATH: &str = "config.toml";
+
+/// Profiles override the config with the table of the same name under `profiles`, e.g.
+///
+/// ```toml
+/// [profiles.staging]
+/// database_url_secret = "STAGING_DATABASE_URL"
+/// guardrails = { max_window_days = 31 }
+/// ```
+///
+/// Tables are merged key by key, anything else, like the list of pipelines, is replaced.
+const PROFILES_KEY: &str = "profiles";
+
+#[derive(Debug, Default, Deserialize, Clone)]
+pub struct Config {
+    #[serde(default)]
+    pub pipelines: Vec<PipelineDefinition>,
+    #[serde(default)]
+    pub snapshots: SnapshotConfig,
+    #[serde(default)]
+    pub imbalance: IspSchedule,
+    #[serde(default)]
+    pub roles: HashMap<String, RolePermissions>,
+    #[serde(default)]
+    pub quantity_units: QuantityUnits,
+    #[serde(default)]
+    pub scheduler: SchedulerConfig,
+    #[serde(default)]
+    pub secrets: SecretsConfig,
+    #[serde(default)]
+    pub sign_convention: SignConvention,
+    /// What reports do with trades without a price, skipping them by default
+    #[serde(default)]
+    pub missing_prices: MissingPricePolicy,
+    /// How trades delivered partly outside the window are counted, in full by default
+    #[serde(default)]
+    pub boundary_trades: BoundaryPolicy,
+    /// Which trades a window selects, those starting in it by default
+    #[serde(default)]
+    pub window_filter: WindowFilter,
+    #[serde(default)]
+    pub decoding: Decoding,
+    #[serde(default)]
+    pub fixed_costs: Vec<FixedCost>,
+    /// Fees charged per trade by market and counter part, deducted from the gross profit
+    #[serde(default)]
+    pub fees: Vec<FeeRule>,
+    #[serde(default)]
+    pub blocks: Vec<BlockDefinition>,
+    #[serde(default)]
+    pub fiscal: FiscalConfig,
+    #[serde(default)]
+    pub reconciliation: ReconciliationConfig,
+    /// Countries and regions key metrics can be shown for with `--region`
+    #[serde(default)]
+    pub area_hierarchy: AreaHierarchy,
+    /// Columns of the trade exports read with `ingest <file>`
+    #[serde(default)]
+    pub csv_mapping: CsvMapping,
+    /// Fields of the JSON trades read with `ingest <file> --ndjson` and `consume`
+    #[serde(default)]
+    pub json_mapping: JsonMapping,
+    /// Kept up to date by watch mode when configured
+    pub forward_curve: Option<ForwardCurveConfig>,
+    /// Publishes the metric updates of watch mode to Redis when configured
+    pub broadcast: Option<BroadcastConfig>,
+    /// Topic `consume` reads trade events from
+    #[cfg(feature = "kafka")]
+    #[serde(default)]
+    pub kafka: KafkaConfig,
+    /// Signs snapshot logs and pipeline exports when configured
+    pub signing: Option<SigningConfig>,
+    /// Secret holding the URL of the database to connect to, `DATABASE_URL` if not set
+    pub database_url_secret: Option<String>,
+    #[serde(default)]
+    pub guardrails: Guardrails,
+    #[serde(default)]
+    pub retention: RetentionConfig,
+    /// Metadata of brokers and other counter parts next to the built-in ones, by name
+    #[serde(default)]
+    pub counter_parts: HashMap<String, CounterPartInfo>,
+    /// VAT rates of the areas, for the VAT of the counter parts' invoices
+    #[serde(default)]
+    pub vat: VatConfig,
+    /// Destinations reports can be delivered to with `--sink`, by name
+    #[serde(default)]
+    pub report_sinks: HashMap<String, ReportSinkDefinition>,
+    /// Pushes the metrics of runs to a Prometheus push gateway when configured
+    pub monitoring: Option<MonitoringConfig>,
+    /// Hedge rates of the areas settled in a foreign currency, for `fx-hedge`
+    pub fx_hedge: Option<FxHedgeConfig>,
+    /// Converts the cash flows of streamed reports to a single currency when configured
+    pub fx: Option<FxConfig>,
+    /// Day-ahead prices streamed reports benchmark the trades against when configured
+    pub spot_prices: Option<SpotPriceConfig>,
+    /// Imbalance settlement prices, for `imbalance-cost`
+    pub imbalance_cost: Option<ImbalanceCostConfig>,
+}
+
+/// How much of the per-trade contributions retained for `explain` is kept in memory
+#[derive(Debug, Deserialize, Clone)]
+pub struct RetentionConfig {
+    /// Megabytes of contributions kept in memory before spilling to a temporary file
+    pub memory_budget_mb: usize,
+}
+
+impl Default for RetentionConfig {
+    fn default() -> Self {
+        Self {
+            memory_budget_mb: 256,
+        }
+    }
+}
+
+impl RetentionConfig {
+    pub fn memory_budget(&self) -> usize {
+        self.memory_budget_mb * 1024 * 1024
+    }
+}
+
+/// Limits protecting a database from accidentally heavy use, e.g. a prod-sized scan against
+/// staging
+#[derive(Debug, Default, Deserialize, Clone)]
+pub struct Guardrails {
+    /// Longest delivery window trades are fetched for
+    pub max_window_days: Option<i64>,
+}
+
+impl Guardrails {
+    pub fn check_window(
+        &self,
+        delivery_from: &DateTime<Tz>,
+        delivery_to: &DateTime<Tz>,
+    ) -> Result<()> {
+        if let Some(max_days) = self.max_window_days {
+            if *delivery_to - *delivery_from > Duration::days(max_days) {
+                bail!(
+                    "Delivery window {} - {} is longer than the {} days allowed by the guardrails",
+                    delivery_from,
+                    delivery_to,
+                    max_days
+                );
+            }
+        }
+        Ok(())
+    }
+}
+
+/// Merges the overrides into the table, recursing into tables present in both
+fn merge(table: &mut toml::Table, overrides: toml::Table) {
+    for (key, value) in overrides {
+        match (table.get_mut(&key), value) {
+            (Some(toml::Value::Table(existing)), toml::Value::Table(value)) => {
+                merge(existing, value)
+            }
+            (_, value) => {
+                table.insert(key, value);
+            }
+        }
+    }
+}
+
+#[derive(Debug, Deserialize, Clone)]
+#[serde(default)]
+pub struct SnapshotConfig {
+    pub path: PathBuf,
+    /// Store a full snapshot every this many snapshots, and deltas in between
+    pub full_every: usize,
+    /// Snapshots taken at least this many hours after the end of their window are final, and
+    /// stand in for the trades of their window in stitched reports
+    pub finalized_after_hours: u32,
+}
+
+impl Default for SnapshotConfig {
+    fn default() -> Self {
+        Self {
+            path: PathBuf::from("snapshots.jsonl"),
+            full_every: 24,
+            finalized_after_hours: 48,
+        }
+    }
+}
+
+impl Config {
+    /// Loads the config from `CONFIG_PATH`, or `config.toml` if it's not set, with the profile
+    /// applied if one is given or set in `CONFIG_PROFILE`.
+    /// A missing default config file gives the default config, a missing explicit one is an error.
+    pub fn load(profile: Option<&str>) -> Result<Self> {
+        let profile = match profile {
+            Some(profile) => Some(profile.to_string()),
+            None => env::var("CONFIG_PROFILE").ok(),
+        };
+        let path = match env::var("CONFIG_PATH") {
+            Ok(path) => PathBuf::from(path),
+            Err(_) => {
+                let path = PathBuf::from(DEFAULT_CONFIG_PATH);
+                if !path.exists() {
+                    if let Some(profile) = profile {
+                        bail!("No config file to take profile {} from", profile);
+                    }
+                    return Ok(Config::default());
+                }
+                path
+            }
+        };
+
+        let content = fs::read_to_string(&path)
+            .with_context(|| format!("Could not read config file {}", path.display()))?;
+        Self::parse(&content, profile.as_deref())
+            .with_context(|| format!("Could not parse config file {}", path.display()))
+    }
+
+    fn parse(content: &str, profile: Option<&str>) -> Result<Self> {
+        let mut table: toml::Table = toml::from_str(content)?;
+        let profiles = match table.remove(PROFILES_KEY) {
+            Some(toml::Value::Table(profiles)) => profiles,
+            Some(_) => bail!("{} has to be a table of profiles", PROFILES_KEY),
+            None => toml::Table::new(),
+        };
+        if let Some(profile) = profile {
+            match profiles.get(profile) {
+                Some(toml::Value::Table(overrides)) => merge(&mut table, overrides.clone()),
+                _ => bail!(
+                    "No profile named {}, the profiles are: {}",
+                    profile,
+                    profiles.keys().cloned().collect::<Vec<_>>().join(", ")
+                ),
+            }
+        }
+        Ok(table.try_into()?)
+    }
+
+    /// Builds the pipeline with the given name
+    pub fn pipeline(&self, name: &str) -> Result<Pipeline> {
+        let definition = self
+            .pipelines
+            .iter()
+            .find(|definition| definition.name == name)
+            .ok_or(anyhow!("No pipeline named {} in config", name))?;
+        self.build_pipeline(definition.clone())
+    }
+
+    /// Builds a pipeline defined elsewhere, e.g. a saved report, with the settlement periods and
+    /// sign convention of the config
+    pub fn build_pipeline(&self, definition: PipelineDefinition) -> Result<Pipeline> {
+        Pipeline::from_definition(definition, &self.imbalance, self.sign_convention)
+    }
+
+    /// The builder of the reports of the window, counting, converting, charging and benchmarking
+    /// the trades as configured. Every command and route builds its reports with it, so their
+    /// reports agree. FX rates and spot prices configured as tables are read from the database,
+    /// which reports of trades from elsewhere, e.g. the demo, don't have.
+    pub async fn report_builder(
+        &self,
+        pool: Option<&PgPool>,
+        delivery_from: &DateTime<Tz>,
+        delivery_to: &DateTime<Tz>,
+    ) -> Result<ReportBuilder> {
+        let database = |table: &str| {
+            pool.ok_or(anyhow!(
+                "{} is read from the database, which the trades of the report aren't",
+                table
+            ))
+        };
+        let mut builder = ReportBuilder::new(delivery_from, delivery_to)
+            .boundary_trades(self.boundary_trades)
+            .window_filter(self.window_filter);
+        if let Some(fx) = &self.fx {
+            let rates = match &fx.rates_table {
+                Some(table) => FxRates::load(fx, database(table)?).await?,
+                None => FxRates::from_config(fx)?,
+            };
+            builder = builder.fx(rates);
+        }
+        if !self.fees.is_empty() {
+            builder = builder.fees(FeeSchedule::new(&self.fees)?);
+        }
+        if let Some(spot) = &self.spot_prices {
+            let secrets = Secrets::new(self.secrets.clone());
+            let mut prices = match &spot.table {
+                Some(table) => {
+                    SpotPrices::load(spot, database(table)?, &secrets, delivery_from, delivery_to)
+                        .await?
+                }
+                None => SpotPrices::load_entsoe(spot, &secrets, delivery_from, delivery_to).await?,
+            };
+            // Benchmarked in the currency the trades are converted to
+            if let Some(rates) = builder.fx_rates() {
+                prices = prices.converted(rates)?;
+            }
+            builder = builder.spot_prices(Arc::new(prices));
+        }
+        Ok(builder)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn profiles_override_the_config() {
+        let content = r#"
+            sign_convention = "trading"
+
+            [snapshots]
+            path = "snapshots.jsonl"
+            full_every = 12
+
+            [profiles.staging]
+            database_url_secret = "STAGING_DATABASE_URL"
+            snapshots = { path = "staging_snapshots.jsonl" }
+            guardrails = { max_window_days = 31 }
+        "#;
+
+        let config = Config::parse(content, None).unwrap();
+        assert!(config.database_url_secret.is_none());
+        assert!(config.guardrails.max_window_days.is_none());
+
+        let config = Config::parse(content, Some("staging")).unwrap();
+        assert_eq!(
+            config.database_url_secret.as_deref(),
+            Some("STAGING_DATABASE_URL")
+        );
+        assert_eq!(
+            config.snapshots.path,
+            PathBuf::from("staging_snapshots.jsonl")
+        );
+        assert_eq!(config.snapshots.full_every, 12);
+        assert_eq!(config.sign_convention, SignConvention::Trading);
+        assert_eq!(config.guardrails.max_window_days, Some(31));
+
+        assert!(Config::parse(content, Some("prod")).is_err());
+    }
+}
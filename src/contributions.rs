@@ -0,0 +1,171 @@
+use std::fs::{self, File};
+use std::io::{BufRead, BufReader, BufWriter, Write};
+use std::mem;
+use std::path::PathBuf;
+use std::process;
+use std::sync::atomic::{AtomicUsize, Ordering};
+
+use anyhow::{Context, Result};
+use rust_decimal::Decimal;
+use serde::{Deserialize, Serialize};
+
+use crate::trade::{Area, Market, TradeSide};
+
+/// What a trade added to a report cell
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Contribution {
+    pub id: i64,
+    pub area: Area,
+    pub market: Market,
+    pub side: TradeSide,
+    pub mw: Decimal,
+    pub cash_flow: Decimal,
+}
+
+impl Contribution {
+    fn is_in(&self, area: Area, market: Market, side: TradeSide) -> bool {
+        self.area == area && self.market == market && self.side == side
+    }
+}
+
+/// Distinguishes the spill files of logs in the same process
+static SPILL_FILES: AtomicUsize = AtomicUsize::new(0);
+
+#[derive(Debug)]
+struct Spill {
+    path: PathBuf,
+    writer: BufWriter<File>,
+}
+
+/// Contributions of all trades of a report, kept in memory up to a budget in bytes. Beyond
+/// the budget they're appended to a temporary file as JSON lines, which is removed again when
+/// the log is dropped.
+#[derive(Debug)]
+pub struct ContributionLog {
+    in_memory: Vec<Contribution>,
+    memory_budget: usize,
+    spill: Option<Spill>,
+}
+
+impl ContributionLog {
+    pub fn new(memory_budget: usize) -> Self {
+        Self {
+            in_memory: Vec::new(),
+            memory_budget,
+            spill: None,
+        }
+    }
+
+    pub fn push(&mut self, contribution: Contribution) -> Result<()> {
+        let in_budget =
+            (self.in_memory.len() + 1) * mem::size_of::<Contribution>() <= self.memory_budget;
+        if in_budget && self.spill.is_none() {
+            self.in_memory.push(contribution);
+            return Ok(());
+        }
+
+        let spill = match self.spill.as_mut() {
+            Some(spill) => spill,
+            None => {
+                let path = std::env::temp_dir().join(format!(
+                    "report-contributions-{}-{}.jsonl",
+                    process::id(),
+                    SPILL_FILES.fetch_add(1, Ordering::Relaxed)
+                ));
+                let file = File::create(&path)
+                    .with_context(|| format!("Could not create {}", path.display()))?;
+                self.spill.insert(Spill {
+                    path,
+                    writer: BufWriter::new(file),
+                })
+            }
+        };
+        serde_json::to_writer(&mut spill.writer, &contribution)?;
+        writeln!(spill.writer)?;
+        Ok(())
+    }
+
+    /// Whether contributions beyond the memory budget were written to a file
+    pub fn spilled(&self) -> bool {
+        self.spill.is_some()
+    }
+
+    /// The contributions to a cell in the order they were added, reading back spilled ones
+    pub fn for_cell(
+        &mut self,
+        area: Area,
+        market: Market,
+        side: TradeSide,
+    ) -> Result<Vec<Contribution>> {
+        let mut contributions: Vec<Contribution> = self
+            .in_memory
+            .iter()
+            .filter(|contribution| contribution.is_in(area, market, side))
+            .cloned()
+            .collect();
+
+        if let Some(spill) = self.spill.as_mut() {
+            spill.writer.flush()?;
+            let file = File::open(&spill.path)
+                .with_context(|| format!("Could not open {}", spill.path.display()))?;
+            for line in BufReader::new(file).lines() {
+                let contribution: Contribution = serde_json::from_str(&line?)?;
+                if contribution.is_in(area, market, side) {
+                    contributions.push(contribution);
+                }
+            }
+        }
+        Ok(contributions)
+    }
+}
+
+impl Drop for ContributionLog {
+    fn drop(&mut self) {
+        if let Some(spill) = self.spill.take() {
+            drop(spill.writer);
+            fs::remove_file(&spill.path).ok();
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn contribution(id: i64, side: TradeSide) -> Contribution {
+        Contribution {
+            id,
+            area: Area::DK1,
+            market: Market::Intraday,
+            side,
+            mw: Decimal::ONE,
+            cash_flow: Decimal::TEN,
+        }
+    }
+
+    #[test]
+    fn contributions_beyond_the_budget_are_spilled_and_read_back() {
+        let mut log = ContributionLog::new(2 * mem::size_of::<Contribution>());
+        for id in 0..5 {
+            let side = if id % 2 == 0 {
+                TradeSide::Buy
+            } else {
+                TradeSide::Sell
+            };
+            log.push(contribution(id, side)).unwrap();
+        }
+
+        assert!(log.spilled());
+        let ids: Vec<i64> = log
+            .for_cell(Area::DK1, Market::Intraday, TradeSide::Buy)
+            .unwrap()
+            .iter()
+            .map(|contribution| contribution.id)
+            .collect();
+        assert_eq!(ids, vec![0, 2, 4]);
+
+        let path = log.spill.as_ref().unwrap().path.clone();
+        drop(log);
+        assert!(!path.exists());
+    }
+}
@@ -0,0 +1,146 @@
+use std::collections::{BTreeMap, HashMap};
+
+use rust_decimal::Decimal;
+use serde::Deserialize;
+use strum_macros::Display;
+
+use crate::trade::CounterPart;
+use crate::vat::VatTreatment;
+
+#[derive(Debug, Deserialize, Display, Clone, Copy, PartialEq)]
+#[serde(rename_all = "lowercase")]
+#[strum(serialize_all = "lowercase")]
+pub enum CounterPartKind {
+    Exchange,
+    Broker,
+    /// Transmission system operators and the imbalance settlement they delegate to
+    Tso,
+}
+
+/// What we know about a counter part, configured as e.g.
+///
+/// ```toml
+/// [counter_parts.tradition]
+/// kind = "broker"
+/// currency = "EUR"
+/// fee_schedule = "https://example.com/fees.pdf"
+/// vat = "reverse_charge"
+/// ```
+#[derive(Debug, Deserialize, Clone, PartialEq)]
+pub struct CounterPartInfo {
+    pub kind: CounterPartKind,
+    /// Currency trades with the counter part are settled in
+    pub currency: String,
+    /// Link to the fees charged by the counter part
+    pub fee_schedule: Option<String>,
+    /// How VAT applies to invoices with the counter part, charged by default
+    #[serde(default)]
+    pub vat: VatTreatment,
+    /// VAT rate of the counter part's invoices, the rate of the area if not set
+    pub vat_rate: Option<Decimal>,
+}
+
+impl CounterPartInfo {
+    fn built_in(kind: CounterPartKind, currency: &str) -> Self {
+        Self {
+            kind,
+            currency: currency.to_string(),
+            fee_schedule: None,
+            vat: VatTreatment::default(),
+            vat_rate: None,
+        }
+    }
+}
+
+/// The counter parts with metadata, the built-in ones overridden and extended by the config
+#[derive(Debug, Clone)]
+pub struct CounterPartRegistry {
+    counter_parts: BTreeMap<CounterPart, CounterPartInfo>,
+}
+
+impl Default for CounterPartRegistry {
+    fn default() -> Self {
+        use CounterPartKind::*;
+        let counter_parts = [
+            (CounterPart::NORDPOOL, Exchange, "EUR"),
+            (CounterPart::EPEX, Exchange, "EUR"),
+            (CounterPart::SEMO, Exchange, "EUR"),
+            (CounterPart::ESETT, Tso, "EUR"),
+            (CounterPart::ELEXON, Tso, "GBP"),
+            (CounterPart::RTE, Tso, "EUR"),
+            (CounterPart::TENNET, Tso, "EUR"),
+            (CounterPart::AMPRION, Tso, "EUR"),
+        ]
+        .into_iter()
+        .map(|(counter_part, kind, currency)| {
+            (counter_part, CounterPartInfo::built_in(kind, currency))
+        })
+        .collect();
+        Self { counter_parts }
+    }
+}
+
+impl CounterPartRegistry {
+    pub fn new(configured: &HashMap<String, CounterPartInfo>) -> Self {
+        let mut registry = Self::default();
+        for (name, info) in configured {
+            registry
+                .counter_parts
+                .insert(CounterPart::from(name.clone()), info.clone());
+        }
+        registry
+    }
+
+    /// The metadata of the counter part, `None` when it's neither built in nor configured
+    pub fn get(&self, counter_part: &CounterPart) -> Option<&CounterPartInfo> {
+        self.counter_parts.get(counter_part)
+    }
+
+    /// Rows of name, kind, currency, fee schedule and VAT treatment, by name
+    pub fn records(&self) -> Vec<Vec<String>> {
+        self.counter_parts
+            .iter()
+            .map(|(counter_part, info)| {
+                vec![
+                    counter_part.to_string(),
+                    info.kind.to_string(),
+                    info.currency.clone(),
+                    info.fee_schedule.clone().unwrap_or_default(),
+                    info.vat.to_string(),
+                ]
+            })
+            .collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn brokers_are_registered_next_to_the_built_in_counter_parts() {
+        let configured: HashMap<String, HashMap<String, CounterPartInfo>> = toml::from_str(
+            r#"
+            [counter_parts.Tradition]
+            kind = "broker"
+            currency = "EUR"
+            fee_schedule = "https://example.com/fees.pdf"
+            "#,
+        )
+        .unwrap();
+        let registry = CounterPartRegistry::new(&configured["counter_parts"]);
+
+        let broker = CounterPart::from("tradition".to_string());
+        assert_eq!(registry.get(&broker).unwrap().kind, CounterPartKind::Broker);
+        assert_eq!(
+            registry.get(&CounterPart::ELEXON).unwrap().currency,
+            "GBP".to_string()
+        );
+        assert_eq!(registry.get(&CounterPart::from("icap".to_string())), None);
+
+        // Variant names serialized while counter parts were an enum decode to the same
+        let decoded: CounterPart = serde_json::from_str("\"Nordpool\"").unwrap();
+        assert_eq!(decoded, CounterPart::NORDPOOL);
+        assert_eq!(serde_json::to_string(&broker).unwrap(), "\"tradition\"");
+    }
+}
@@ -1,172 +1,775 @@
 use std::pin::Pin;
 
-use chrono::DateTime;
+use chrono::{DateTime, Utc};
 use chrono_tz::Tz;
-use futures::{Stream, StreamExt};
-use sqlx::{postgres::PgPoolOptions, Error, PgPool};
+use futures::{stream, Stream, StreamExt, TryStreamExt};
+use sqlx::{
+    postgres::{types::Oid, PgPoolOptions},
+    Error, PgPool,
+};
+use std::time::Instant;
+use tokio::sync::mpsc;
 
-use crate::trade::{Trade, TradeForReport};
-use anyhow::{Context, Result};
+use crate::bounds::WindowFilter;
+use crate::cancel::{or_cancelled, until_cancelled, CancellationToken};
+use crate::monitoring;
+use crate::report::{AggregatedRow, BoundaryPolicy, ReportBuilder};
+#[cfg(feature = "sqlite")]
+use crate::sqlite;
+use crate::timing::{Phase, StrategyTimings};
+use crate::trade::{
+    Area, CounterPart, Currency, Decoding, Market, Trade, TradeForReport, TradeSide, TradeType,
+};
+use crate::units::{QuantityConvention, QuantityUnit, QuantityUnits};
+use anyhow::{bail, Context, Result};
+
+pub use crate::trade::TradeTable;
+
+/// Trades as they're fetched from the database
+pub type TradeStream<'a> = Pin<Box<dyn Stream<Item = Result<Trade, Error>> + Send + 'a>>;
+
+/// A stream of trades from elsewhere than the trade tables, e.g. a file or another service, so
+/// reports and aggregations can be built from them like from fetched trades
+pub fn trade_stream<'a, I>(trades: I) -> TradeStream<'a>
+where
+    I: IntoIterator<Item = Trade>,
+    I::IntoIter: Send + 'a,
+{
+    Box::pin(stream::iter(trades.into_iter().map(Ok)))
+}
+
+/// Normalizes the quantity of a trade fetched from a table into signed MW
+fn normalize_trade(trade: &mut Trade, table: TradeTable, units: &QuantityUnits) -> Result<()> {
+    trade.quantity_mwh = units.normalize(
+        table,
+        units
+            .convention()
+            .signed(trade.quantity_mwh, trade.trade_side),
+        &trade.delivery_start,
+        &trade.delivery_end,
+    )?;
+    Ok(())
+}
+
+/// Checks the areas of trades fetched from a table and normalizes their quantities into MW
+pub(crate) fn normalize_trades(
+    trades: &mut [Trade],
+    table: TradeTable,
+    units: &QuantityUnits,
+    decoding: Decoding,
+) -> Result<()> {
+    for trade in trades.iter() {
+        decoding.check(trade.area)?;
+    }
+    if units.is_normalized(table) {
+        return Ok(());
+    }
+    trades
+        .iter_mut()
+        .try_for_each(|trade| normalize_trade(trade, table, units))
+}
+
+fn normalize_trades_for_report(
+    trades: &mut [TradeForReport],
+    table: TradeTable,
+    units: &QuantityUnits,
+    decoding: Decoding,
+) -> Result<()> {
+    for trade in trades.iter() {
+        decoding.check(trade.area)?;
+    }
+    if units.is_normalized(table) {
+        return Ok(());
+    }
+    for trade in trades.iter_mut() {
+        trade.quantity_mwh = units.normalize(
+            table,
+            units
+                .convention()
+                .signed(trade.quantity_mwh, trade.trade_side),
+            &trade.delivery_start,
+            &trade.delivery_end,
+        )?;
+    }
+    Ok(())
+}
+
+/// Checks the areas of a stream of trades and normalizes them into MW. Without a table, the
+/// table of each trade is derived from its trade type.
+fn normalize_stream<'a>(
+    trades: TradeStream<'a>,
+    table: Option<TradeTable>,
+    units: &'a QuantityUnits,
+    decoding: Decoding,
+) -> TradeStream<'a> {
+    let table_of =
+        move |trade: &Trade| table.unwrap_or(TradeTable::from(Market::from(trade.trade_type)));
+    let trades = trades.inspect(move |trade| match trade {
+        Ok(trade) => monitoring::record_trades_fetched(table_of(trade), 1),
+        Err(err) => monitoring::record_db_error(err),
+    });
+    let trades = Box::pin(trades.map(move |trade| {
+        let trade = trade?;
+        decoding
+            .check(trade.area)
+            .map_err(|err| Error::Decode(err.into()))?;
+        Ok(trade)
+    }));
+    if table.is_some_and(|table| units.is_normalized(table)) {
+        return trades;
+    }
+    Box::pin(trades.map(move |trade| {
+        let mut trade = trade?;
+        let table = table_of(&trade);
+        normalize_trade(&mut trade, table, units).map_err(|err| Error::Decode(err.into()))?;
+        Ok(trade)
+    }))
+}
 
 pub async fn init_db_pool(db_url: &str) -> Result<PgPool> {
     PgPoolOptions::new()
         .max_connections(5)
         .connect(db_url)
         .await
+        .inspect_err(monitoring::record_db_error)
         .context("Failed to create database pool")
 }
 
+/// The trade tables of a database, with the units their quantities are stored in and how
+/// strictly their values are decoded. Fetched trades always have their quantities in MW.
+#[derive(Debug, Clone)]
+pub struct TradeSource {
+    backend: Backend,
+    units: QuantityUnits,
+    decoding: Decoding,
+    cancellation: CancellationToken,
+}
+
+/// The database the trade tables of a source are in
+#[derive(Debug, Clone)]
+enum Backend {
+    Postgres(PgPool),
+    #[cfg(feature = "sqlite")]
+    Sqlite(sqlx::SqlitePool),
+}
+
+impl TradeSource {
+    pub fn new(pool: PgPool, units: QuantityUnits) -> Self {
+        Self::with_backend(Backend::Postgres(pool), units)
+    }
+
+    /// The trade tables of a SQLite database, e.g. the demo trades of `sqlite::demo_source`
+    #[cfg(feature = "sqlite")]
+    pub fn sqlite(pool: sqlx::SqlitePool, units: QuantityUnits) -> Self {
+        Self::with_backend(Backend::Sqlite(pool), units)
+    }
+
+    fn with_backend(backend: Backend, units: QuantityUnits) -> Self {
+        Self {
+            backend,
+            units,
+            decoding: Decoding::default(),
+            cancellation: CancellationToken::new(),
+        }
+    }
+
+    /// Decodes the trades fetched from the source as configured, strictly by default
+    pub fn with_decoding(mut self, decoding: Decoding) -> Self {
+        self.decoding = decoding;
+        self
+    }
+
+    /// Fails everything fetched from the source with `Cancelled` once the token is cancelled,
+    /// including reports built and days aggregated from it. Streams stop at their next trade
+    /// and release their connection, rather than being dropped by the embedder mid-query.
+    pub fn with_cancellation(mut self, token: CancellationToken) -> Self {
+        self.cancellation = token;
+        self
+    }
+
+    pub fn cancellation(&self) -> &CancellationToken {
+        &self.cancellation
+    }
+
+    pub async fn connect(database_url: &str, units: QuantityUnits) -> Result<Self> {
+        Ok(Self::new(init_db_pool(database_url).await?, units))
+    }
+
+    /// The pool of a Postgres source, `None` for a SQLite source
+    pub fn pool(&self) -> Option<&PgPool> {
+        match &self.backend {
+            Backend::Postgres(pool) => Some(pool),
+            #[cfg(feature = "sqlite")]
+            Backend::Sqlite(_) => None,
+        }
+    }
+
+    pub fn units(&self) -> &QuantityUnits {
+        &self.units
+    }
+
+    pub fn decoding(&self) -> Decoding {
+        self.decoding
+    }
+
+    /// Streams the trades delivered in the window, prefetching them ahead of the consumer
+    pub fn stream(
+        &self,
+        delivery_from: &DateTime<Tz>,
+        delivery_to: &DateTime<Tz>,
+    ) -> TradeStream<'static> {
+        self.stream_filtered(delivery_from, delivery_to, WindowFilter::DeliveryStart)
+    }
+
+    /// Like `stream`, selecting the trades of the window with the filter
+    pub fn stream_filtered(
+        &self,
+        delivery_from: &DateTime<Tz>,
+        delivery_to: &DateTime<Tz>,
+        filter: WindowFilter,
+    ) -> TradeStream<'static> {
+        let trades = match &self.backend {
+            Backend::Postgres(pool) => prefetch_trades(
+                pool,
+                delivery_from,
+                delivery_to,
+                &self.units,
+                self.decoding,
+                filter,
+            ),
+            #[cfg(feature = "sqlite")]
+            Backend::Sqlite(pool) => sqlite::get_trades_stream(
+                pool,
+                delivery_from,
+                delivery_to,
+                &self.units,
+                self.decoding,
+                filter,
+            ),
+        };
+        until_cancelled(trades, self.cancellation.clone())
+    }
+
+    /// Streams the trades delivered in the window ordered by delivery start
+    pub fn stream_ordered<'a>(
+        &'a self,
+        delivery_from: &'a DateTime<Tz>,
+        delivery_to: &'a DateTime<Tz>,
+    ) -> TradeStream<'a> {
+        let trades = match &self.backend {
+            Backend::Postgres(pool) => get_trades_stream_ordered(
+                pool,
+                delivery_from,
+                delivery_to,
+                &self.units,
+                self.decoding,
+            ),
+            // Trades of SQLite are streamed in delivery order already
+            #[cfg(feature = "sqlite")]
+            Backend::Sqlite(pool) => sqlite::get_trades_stream(
+                pool,
+                delivery_from,
+                delivery_to,
+                &self.units,
+                self.decoding,
+                WindowFilter::DeliveryStart,
+            ),
+        };
+        until_cancelled(trades, self.cancellation.clone())
+    }
+
+    /// Fetches the trades delivered in the window with ids above the given one, in id order
+    pub async fn trades_after_id(
+        &self,
+        table: TradeTable,
+        after_id: i64,
+        delivery_from: &DateTime<Tz>,
+        delivery_to: &DateTime<Tz>,
+    ) -> Result<Vec<Trade>> {
+        match &self.backend {
+            Backend::Postgres(pool) => {
+                let trades = get_trades_after_id(
+                    pool,
+                    table,
+                    after_id,
+                    delivery_from,
+                    delivery_to,
+                    &self.units,
+                    self.decoding,
+                );
+                or_cancelled(&self.cancellation, trades).await
+            }
+            #[cfg(feature = "sqlite")]
+            Backend::Sqlite(pool) => {
+                let trades = sqlite::get_trades_after_id(
+                    pool,
+                    table,
+                    after_id,
+                    delivery_from,
+                    delivery_to,
+                    &self.units,
+                    self.decoding,
+                );
+                or_cancelled(&self.cancellation, trades).await
+            }
+        }
+    }
+
+    /// Writes trades into a table of the source, see `insert_trades`
+    pub async fn insert_trades(
+        &self,
+        trades: &[Trade],
+        table: TradeTable,
+        on_conflict: OnConflict,
+    ) -> Result<u64> {
+        match &self.backend {
+            Backend::Postgres(pool) => {
+                insert_trades(pool, trades, table, &self.units, on_conflict).await
+            }
+            #[cfg(feature = "sqlite")]
+            Backend::Sqlite(_) => bail!("Trades can only be inserted into the tables of Postgres"),
+        }
+    }
+}
+
+/// Gets the trades of the three tables selected with the filter, querying them concurrently on
+/// the pool. The query timings of the tables overlap, so they don't add up to the time spent.
 pub async fn get_trades(
     pool: &PgPool,
     delivery_from: &DateTime<Tz>,
     delivery_to: &DateTime<Tz>,
+    filter: WindowFilter,
+    units: &QuantityUnits,
+    decoding: Decoding,
+    timings: &mut StrategyTimings,
 ) -> Result<Vec<Trade>> {
-    let mut trades = sqlx::query_as!(
-        Trade,
-        "
-    SELECT id, area, counter_part, delivery_start, delivery_end, price, quantity_mwh, trade_side, trade_type
+    let timed = |table| async move {
+        let now = Instant::now();
+        let trades = match filter {
+            WindowFilter::DeliveryStart => {
+                let mut trades = get_table_trades(pool, table, delivery_from, delivery_to).await?;
+                normalize_trades(&mut trades, table, units, decoding)?;
+                trades
+            }
+            // Normalized as they're streamed
+            WindowFilter::Overlap => {
+                get_table_trades_stream_overlapping(
+                    pool,
+                    table,
+                    delivery_from,
+                    delivery_to,
+                    units,
+                    decoding,
+                )
+                .try_collect()
+                .await?
+            }
+        };
+        Ok::<_, anyhow::Error>((table, trades, now.elapsed()))
+    };
+    let (intraday, auction, imbalance) = tokio::try_join!(
+        timed(TradeTable::Intraday),
+        timed(TradeTable::Auction),
+        timed(TradeTable::Imbalance),
+    )?;
+
+    let mut trades = Vec::new();
+    for (table, table_trades, elapsed) in [intraday, auction, imbalance] {
+        timings.record(Phase::Query(table), elapsed);
+        trades.extend(table_trades);
+    }
+    Ok(trades)
+}
+
+/// Gets the trades of a single table as stored, with quantities in the unit of the table
+pub async fn get_table_trades(
+    pool: &PgPool,
+    table: TradeTable,
+    delivery_from: &DateTime<Tz>,
+    delivery_to: &DateTime<Tz>,
+) -> Result<Vec<Trade>> {
+    let trades = match table {
+        TradeTable::Intraday => sqlx::query_as!(
+            Trade,
+            r#"
+    SELECT id::bigint AS "id!", area AS "area: Area", counter_part AS "counter_part: CounterPart",
+        currency AS "currency: Currency", delivery_start, delivery_end,
+        price, quantity_mwh, trade_side AS "trade_side: TradeSide", trade_type AS "trade_type: TradeType"
     FROM intraday_trades
-    WHERE delivery_start >= $1 AND delivery_start < $2",
-        delivery_from,
-        delivery_to,
-    )
+    WHERE delivery_start >= $1 AND delivery_start < $2"#,
+            delivery_from,
+            delivery_to,
+        )
         .fetch_all(pool)
-        .await?;
-
-    let auction_trades = sqlx::query_as!(
-        Trade,
-        "
-    SELECT id, area, counter_part, delivery_start, delivery_end, price, quantity_mwh, trade_side, trade_type
+        .await
+        .inspect_err(monitoring::record_db_error)?,
+        TradeTable::Auction => sqlx::query_as!(
+            Trade,
+            r#"
+    SELECT id::bigint AS "id!", area AS "area: Area", counter_part AS "counter_part: CounterPart",
+        currency AS "currency: Currency", delivery_start, delivery_end,
+        price, quantity_mwh, trade_side AS "trade_side: TradeSide", trade_type AS "trade_type: TradeType"
     FROM auction_trades
-    WHERE delivery_start >= $1 AND delivery_start < $2",
-        delivery_from,
-        delivery_to,
-    )
+    WHERE delivery_start >= $1 AND delivery_start < $2"#,
+            delivery_from,
+            delivery_to,
+        )
         .fetch_all(pool)
-        .await?;
-    trades.extend(auction_trades);
-
-    let imbalance_trades = sqlx::query_as!(
-        Trade,
-        "
-    SELECT id, area, counter_part, delivery_start, delivery_end, price, quantity_mwh, trade_side, trade_type
+        .await
+        .inspect_err(monitoring::record_db_error)?,
+        TradeTable::Imbalance => sqlx::query_as!(
+            Trade,
+            r#"
+    SELECT id::bigint AS "id!", area AS "area: Area", counter_part AS "counter_part: CounterPart",
+        currency AS "currency: Currency", delivery_start, delivery_end,
+        price, quantity_mwh, trade_side AS "trade_side: TradeSide", trade_type AS "trade_type: TradeType"
     FROM imbalance_trades
-    WHERE delivery_start >= $1 AND delivery_start < $2",
-        delivery_from,
-        delivery_to,
-    )
+    WHERE delivery_start >= $1 AND delivery_start < $2"#,
+            delivery_from,
+            delivery_to,
+        )
         .fetch_all(pool)
-        .await?;
-    trades.extend(imbalance_trades);
-
+        .await
+        .inspect_err(monitoring::record_db_error)?,
+    };
+    monitoring::record_trades_fetched(table, trades.len());
     Ok(trades)
 }
 
+/// Sums the trades of the three tables per area, currency, trade type and side in the database, which
+/// only ships a few rows however long the window is. Energy is the quantity times the contract
+/// length, or the quantity itself for tables in MWh, and the side follows the convention of the
+/// tables, like when trades are normalized. The trades of the window of the builder are selected
+/// with its filter and counted by its boundary policy, so the sums are those of the trades it
+/// would aggregate.
+pub async fn get_aggregated_report_rows(
+    pool: &PgPool,
+    builder: &ReportBuilder,
+    units: &QuantityUnits,
+    decoding: Decoding,
+    timings: &mut StrategyTimings,
+) -> Result<Vec<AggregatedRow>> {
+    let pro_rata = builder.boundary_policy() == BoundaryPolicy::ProRata;
+    let (fetch_from, delivery_to) = (builder.fetch_from(), *builder.delivery_to());
+    let selected = match builder.fetch_filter() {
+        WindowFilter::DeliveryStart => "delivery_start >= $1 AND delivery_start < $2",
+        WindowFilter::Overlap => "delivery_end > $1 AND delivery_start < $2",
+    };
+    let timed = |table: TradeTable| async move {
+        let energy = match units.unit(table) {
+            QuantityUnit::Mw => {
+                "ABS(quantity_mwh) * EXTRACT(EPOCH FROM delivery_end - delivery_start)::numeric / 3600"
+            }
+            QuantityUnit::Mwh => "ABS(quantity_mwh)",
+        };
+        // Like `BoundaryPolicy::apply`, scaled by the share of the delivery inside the window,
+        // with trades delivered before it and those without a delivery period left as they are
+        let (share, counted) = if pro_rata {
+            (
+                "CASE WHEN delivery_end > delivery_start
+            THEN EXTRACT(EPOCH FROM LEAST(delivery_end, $2) - GREATEST(delivery_start, $3))::numeric
+                / EXTRACT(EPOCH FROM delivery_end - delivery_start)::numeric
+            ELSE 1 END",
+                "AND (delivery_end > $3 OR delivery_end <= delivery_start)",
+            )
+        } else {
+            ("1", "")
+        };
+        let sold = match units.convention() {
+            QuantityConvention::Signed => "quantity_mwh < 0",
+            QuantityConvention::SideColumn => "trade_side = 'sell'",
+        };
+        let query = format!(
+            "
+    SELECT area, currency, trade_type, {sold} AS sold,
+        COALESCE(SUM({energy} * {share}) FILTER (WHERE price IS NOT NULL), 0) AS mwh,
+        COALESCE(SUM({energy} * {share} * price), 0) AS cash_flow,
+        COALESCE(SUM({energy} * {share}) FILTER (WHERE price IS NULL), 0) AS unpriced_mwh,
+        COUNT(price) AS priced_trades,
+        COUNT(*) - COUNT(price) AS skipped_trades
+    FROM {table}
+    WHERE {selected} {counted}
+    GROUP BY area, currency, trade_type, sold",
+            energy = energy,
+            share = share,
+            sold = sold,
+            table = table.table_name(),
+            selected = selected,
+            counted = counted
+        );
+        let now = Instant::now();
+        let mut query = sqlx::query_as(&query).bind(fetch_from).bind(delivery_to);
+        if pro_rata {
+            query = query.bind(*builder.delivery_from());
+        }
+        let rows: Vec<AggregatedRow> = query
+            .fetch_all(pool)
+            .await
+            .inspect_err(monitoring::record_db_error)?;
+        Ok::<_, anyhow::Error>((table, rows, now.elapsed()))
+    };
+    let (intraday, auction, imbalance) = tokio::try_join!(
+        timed(TradeTable::Intraday),
+        timed(TradeTable::Auction),
+        timed(TradeTable::Imbalance),
+    )?;
+
+    let mut rows = Vec::new();
+    for (table, table_rows, elapsed) in [intraday, auction, imbalance] {
+        timings.record(Phase::Query(table), elapsed);
+        for row in table_rows.iter() {
+            decoding.check(row.area)?;
+        }
+        rows.extend(table_rows);
+    }
+    Ok(rows)
+}
+
+/// Gets the trades of the three tables selected with the filter, with only the fields a report
+/// needs, table by table
 pub async fn get_trades_for_report(
     pool: &PgPool,
     delivery_from: &DateTime<Tz>,
     delivery_to: &DateTime<Tz>,
+    filter: WindowFilter,
+    units: &QuantityUnits,
+    decoding: Decoding,
+    timings: &mut StrategyTimings,
 ) -> Result<Vec<TradeForReport>> {
-    let mut trades = sqlx::query_as!(
-        TradeForReport,
-        "
-    SELECT area, delivery_start, delivery_end, price, quantity_mwh, trade_type
-    FROM intraday_trades
-    WHERE delivery_start >= $1 AND delivery_start < $2",
-        delivery_from,
-        delivery_to,
-    )
-    .fetch_all(pool)
-    .await?;
+    let mut trades = Vec::new();
+    for table in TradeTable::ALL {
+        let now = Instant::now();
+        let mut table_trades =
+            get_table_trades_for_report(pool, table, delivery_from, delivery_to, filter)
+                .await
+                .inspect_err(monitoring::record_db_error)?;
+        timings.record(Phase::Query(table), now.elapsed());
+        monitoring::record_trades_fetched(table, table_trades.len());
+        normalize_trades_for_report(&mut table_trades, table, units, decoding)?;
+        trades.extend(table_trades);
+    }
+    Ok(trades)
+}
 
-    let auction_trades = sqlx::query_as!(
-        TradeForReport,
-        "
-    SELECT area, delivery_start, delivery_end, price, quantity_mwh, trade_type
+/// Gets the trades of a table selected with the filter as stored, with only the fields a report
+/// needs
+async fn get_table_trades_for_report(
+    pool: &PgPool,
+    table: TradeTable,
+    delivery_from: &DateTime<Tz>,
+    delivery_to: &DateTime<Tz>,
+    filter: WindowFilter,
+) -> Result<Vec<TradeForReport>, Error> {
+    match (table, filter) {
+        (TradeTable::Intraday, WindowFilter::DeliveryStart) => {
+            sqlx::query_as!(
+                TradeForReport,
+                r#"
+    SELECT area AS "area: Area", currency AS "currency: Currency", delivery_start, delivery_end,
+        price, quantity_mwh,
+        trade_side AS "trade_side: TradeSide", trade_type AS "trade_type: TradeType"
+    FROM intraday_trades
+    WHERE delivery_start >= $1 AND delivery_start < $2"#,
+                delivery_from,
+                delivery_to,
+            )
+            .fetch_all(pool)
+            .await
+        }
+        (TradeTable::Intraday, WindowFilter::Overlap) => {
+            sqlx::query_as!(
+                TradeForReport,
+                r#"
+    SELECT area AS "area: Area", currency AS "currency: Currency", delivery_start, delivery_end,
+        price, quantity_mwh,
+        trade_side AS "trade_side: TradeSide", trade_type AS "trade_type: TradeType"
+    FROM intraday_trades
+    WHERE delivery_end > $1 AND delivery_start < $2"#,
+                delivery_from,
+                delivery_to,
+            )
+            .fetch_all(pool)
+            .await
+        }
+        (TradeTable::Auction, WindowFilter::DeliveryStart) => {
+            sqlx::query_as!(
+                TradeForReport,
+                r#"
+    SELECT area AS "area: Area", currency AS "currency: Currency", delivery_start, delivery_end,
+        price, quantity_mwh,
+        trade_side AS "trade_side: TradeSide", trade_type AS "trade_type: TradeType"
     FROM auction_trades
-    WHERE delivery_start >= $1 AND delivery_start < $2",
-        delivery_from,
-        delivery_to,
-    )
-    .fetch_all(pool)
-    .await?;
-    trades.extend(auction_trades);
-
-    let imbalance_trades = sqlx::query_as!(
-        TradeForReport,
-        "
-    SELECT area, delivery_start, delivery_end, price, quantity_mwh, trade_type
+    WHERE delivery_start >= $1 AND delivery_start < $2"#,
+                delivery_from,
+                delivery_to,
+            )
+            .fetch_all(pool)
+            .await
+        }
+        (TradeTable::Auction, WindowFilter::Overlap) => {
+            sqlx::query_as!(
+                TradeForReport,
+                r#"
+    SELECT area AS "area: Area", currency AS "currency: Currency", delivery_start, delivery_end,
+        price, quantity_mwh,
+        trade_side AS "trade_side: TradeSide", trade_type AS "trade_type: TradeType"
+    FROM auction_trades
+    WHERE delivery_end > $1 AND delivery_start < $2"#,
+                delivery_from,
+                delivery_to,
+            )
+            .fetch_all(pool)
+            .await
+        }
+        (TradeTable::Imbalance, WindowFilter::DeliveryStart) => {
+            sqlx::query_as!(
+                TradeForReport,
+                r#"
+    SELECT area AS "area: Area", currency AS "currency: Currency", delivery_start, delivery_end,
+        price, quantity_mwh,
+        trade_side AS "trade_side: TradeSide", trade_type AS "trade_type: TradeType"
     FROM imbalance_trades
-    WHERE delivery_start >= $1 AND delivery_start < $2",
-        delivery_from,
-        delivery_to,
-    )
-    .fetch_all(pool)
-    .await?;
-    trades.extend(imbalance_trades);
-
-    Ok(trades)
+    WHERE delivery_start >= $1 AND delivery_start < $2"#,
+                delivery_from,
+                delivery_to,
+            )
+            .fetch_all(pool)
+            .await
+        }
+        (TradeTable::Imbalance, WindowFilter::Overlap) => {
+            sqlx::query_as!(
+                TradeForReport,
+                r#"
+    SELECT area AS "area: Area", currency AS "currency: Currency", delivery_start, delivery_end,
+        price, quantity_mwh,
+        trade_side AS "trade_side: TradeSide", trade_type AS "trade_type: TradeType"
+    FROM imbalance_trades
+    WHERE delivery_end > $1 AND delivery_start < $2"#,
+                delivery_from,
+                delivery_to,
+            )
+            .fetch_all(pool)
+            .await
+        }
+    }
 }
 
 pub fn get_intraday_trades_stream<'a>(
     pool: &'a PgPool,
     delivery_from: &'a DateTime<Tz>,
     delivery_to: &'a DateTime<Tz>,
-) -> Pin<Box<dyn Stream<Item = Result<Trade, Error>> + Send + 'a>> {
-    sqlx::query_as!(
+    units: &'a QuantityUnits,
+    decoding: Decoding,
+) -> TradeStream<'a> {
+    let trades = sqlx::query_as!(
         Trade,
-        "
-    SELECT id, area, counter_part, delivery_start, delivery_end, price, quantity_mwh, trade_side, trade_type
+        r#"
+    SELECT id::bigint AS "id!", area AS "area: Area", counter_part AS "counter_part: CounterPart",
+        currency AS "currency: Currency", delivery_start, delivery_end,
+        price, quantity_mwh, trade_side AS "trade_side: TradeSide", trade_type AS "trade_type: TradeType"
     FROM intraday_trades
-    WHERE delivery_start >= $1 AND delivery_start < $2",
+    WHERE delivery_start >= $1 AND delivery_start < $2"#,
         delivery_from,
         delivery_to,
     )
-        .fetch(pool)
+        .fetch(pool);
+
+    normalize_stream(trades, Some(TradeTable::Intraday), units, decoding)
 }
 
 pub fn get_auction_trades_stream<'a>(
     pool: &'a PgPool,
     delivery_from: &'a DateTime<Tz>,
     delivery_to: &'a DateTime<Tz>,
-) -> Pin<Box<dyn Stream<Item = Result<Trade, Error>> + Send + 'a>> {
-    sqlx::query_as!(
+    units: &'a QuantityUnits,
+    decoding: Decoding,
+) -> TradeStream<'a> {
+    let trades = sqlx::query_as!(
         Trade,
-        "
-    SELECT id, area, counter_part, delivery_start, delivery_end, price, quantity_mwh, trade_side, trade_type
+        r#"
+    SELECT id::bigint AS "id!", area AS "area: Area", counter_part AS "counter_part: CounterPart",
+        currency AS "currency: Currency", delivery_start, delivery_end,
+        price, quantity_mwh, trade_side AS "trade_side: TradeSide", trade_type AS "trade_type: TradeType"
     FROM auction_trades
-    WHERE delivery_start >= $1 AND delivery_start < $2",
+    WHERE delivery_start >= $1 AND delivery_start < $2"#,
         delivery_from,
         delivery_to,
     )
-        .fetch(pool)
+        .fetch(pool);
+
+    normalize_stream(trades, Some(TradeTable::Auction), units, decoding)
 }
 
 pub fn get_imbalance_trades_stream<'a>(
     pool: &'a PgPool,
     delivery_from: &'a DateTime<Tz>,
     delivery_to: &'a DateTime<Tz>,
-) -> Pin<Box<dyn Stream<Item = Result<Trade, Error>> + Send + 'a>> {
-    sqlx::query_as!(
+    units: &'a QuantityUnits,
+    decoding: Decoding,
+) -> TradeStream<'a> {
+    let trades = sqlx::query_as!(
         Trade,
-        "
-    SELECT id, area, counter_part, delivery_start, delivery_end, price, quantity_mwh, trade_side, trade_type
+        r#"
+    SELECT id::bigint AS "id!", area AS "area: Area", counter_part AS "counter_part: CounterPart",
+        currency AS "currency: Currency", delivery_start, delivery_end,
+        price, quantity_mwh, trade_side AS "trade_side: TradeSide", trade_type AS "trade_type: TradeType"
     FROM imbalance_trades
-    WHERE delivery_start >= $1 AND delivery_start < $2",
+    WHERE delivery_start >= $1 AND delivery_start < $2"#,
         delivery_from,
         delivery_to,
     )
-        .fetch(pool)
+        .fetch(pool);
+
+    normalize_stream(trades, Some(TradeTable::Imbalance), units, decoding)
 }
 
+/// Streams the trades of a table selected with the filter
+pub fn get_table_trades_stream<'a>(
+    pool: &'a PgPool,
+    table: TradeTable,
+    delivery_from: &'a DateTime<Tz>,
+    delivery_to: &'a DateTime<Tz>,
+    filter: WindowFilter,
+    units: &'a QuantityUnits,
+    decoding: Decoding,
+) -> TradeStream<'a> {
+    match (table, filter) {
+        (TradeTable::Intraday, WindowFilter::DeliveryStart) => {
+            get_intraday_trades_stream(pool, delivery_from, delivery_to, units, decoding)
+        }
+        (TradeTable::Auction, WindowFilter::DeliveryStart) => {
+            get_auction_trades_stream(pool, delivery_from, delivery_to, units, decoding)
+        }
+        (TradeTable::Imbalance, WindowFilter::DeliveryStart) => {
+            get_imbalance_trades_stream(pool, delivery_from, delivery_to, units, decoding)
+        }
+        (table, WindowFilter::Overlap) => get_table_trades_stream_overlapping(
+            pool,
+            table,
+            delivery_from,
+            delivery_to,
+            units,
+            decoding,
+        ),
+    }
+}
+
+/// Streams the trades of all three tables, table by table. Like all queries here, the delivery
+/// bounds are bound as `timestamptz`, the type of `delivery_start`, so tables partitioned by it
+/// are pruned to the partitions overlapping the window.
 pub fn get_trades_stream<'a>(
     pool: &'a PgPool,
     delivery_from: &'a DateTime<Tz>,
     delivery_to: &'a DateTime<Tz>,
-) -> Pin<Box<dyn Stream<Item = Result<Trade, Error>> + Send + 'a>> {
-    let intraday_trades = get_intraday_trades_stream(pool, delivery_from, delivery_to);
-    let auction_trades = get_auction_trades_stream(pool, delivery_from, delivery_to);
-    let imbalance_trades = get_imbalance_trades_stream(pool, delivery_from, delivery_to);
+    units: &'a QuantityUnits,
+    decoding: Decoding,
+) -> TradeStream<'a> {
+    let intraday_trades =
+        get_intraday_trades_stream(pool, delivery_from, delivery_to, units, decoding);
+    let auction_trades =
+        get_auction_trades_stream(pool, delivery_from, delivery_to, units, decoding);
+    let imbalance_trades =
+        get_imbalance_trades_stream(pool, delivery_from, delivery_to, units, decoding);
 
     Box::pin(
         intraday_trades
@@ -174,3 +777,558 @@ pub fn get_trades_stream<'a>(
             .chain(imbalance_trades),
     )
 }
+
+/// Trades per batch sent ahead by `get_trades_stream_prefetched`
+const PREFETCH_BATCH_SIZE: usize = 1000;
+/// Batches fetched ahead of the consumer, bounding the trades held in memory
+const PREFETCH_BATCHES: usize = 4;
+
+/// Like `get_trades_stream`, but the trades are fetched and decoded on their own task, up to
+/// `PREFETCH_BATCHES` batches ahead of the consumer. Aggregating a batch then overlaps with
+/// fetching the next ones, instead of waiting on the database for every row, which roughly
+/// halves the time of a scan on high-latency connections.
+pub fn get_trades_stream_prefetched(
+    pool: &PgPool,
+    delivery_from: &DateTime<Tz>,
+    delivery_to: &DateTime<Tz>,
+    units: &QuantityUnits,
+    decoding: Decoding,
+) -> TradeStream<'static> {
+    prefetch_trades(
+        pool,
+        delivery_from,
+        delivery_to,
+        units,
+        decoding,
+        WindowFilter::DeliveryStart,
+    )
+}
+
+fn prefetch_trades(
+    pool: &PgPool,
+    delivery_from: &DateTime<Tz>,
+    delivery_to: &DateTime<Tz>,
+    units: &QuantityUnits,
+    decoding: Decoding,
+    filter: WindowFilter,
+) -> TradeStream<'static> {
+    let (tx, rx) = mpsc::channel::<Result<Vec<Trade>, Error>>(PREFETCH_BATCHES);
+    let (pool, delivery_from, delivery_to, units) =
+        (pool.clone(), *delivery_from, *delivery_to, units.clone());
+
+    let fetcher = tokio::spawn(async move {
+        let trades = match filter {
+            WindowFilter::DeliveryStart => {
+                get_trades_stream(&pool, &delivery_from, &delivery_to, &units, decoding)
+            }
+            WindowFilter::Overlap => {
+                get_trades_stream_overlapping(&pool, &delivery_from, &delivery_to, &units, decoding)
+            }
+        };
+        let mut batches = trades.try_chunks(PREFETCH_BATCH_SIZE);
+        loop {
+            // Stop fetching as soon as the consumer is gone, e.g. after failing on a trade or
+            // being cancelled, instead of once the next batch is ready
+            let batch = tokio::select! {
+                _ = tx.closed() => break,
+                batch = batches.next() => batch,
+            };
+            let Some(batch) = batch else {
+                break;
+            };
+            let batch = batch.map_err(|error| error.1);
+            let failed = batch.is_err();
+            // Stop when the consumer is gone, e.g. after failing on a trade
+            if tx.send(batch).await.is_err() || failed {
+                break;
+            }
+        }
+    });
+
+    // A closed channel is only the end of the trades if the fetcher didn't panic
+    let batches = stream::unfold(Some((rx, fetcher)), |state| async move {
+        let (mut rx, fetcher) = state?;
+        match rx.recv().await {
+            Some(batch) => Some((batch, Some((rx, fetcher)))),
+            None => match fetcher.await {
+                Ok(()) => None,
+                Err(_) => Some((Err(Error::WorkerCrashed), None)),
+            },
+        }
+    });
+    Box::pin(
+        batches
+            .map_ok(|batch| stream::iter(batch.into_iter().map(Ok)))
+            .try_flatten(),
+    )
+}
+
+/// Streams the trades of a table delivered at any time in the window
+fn get_table_trades_stream_overlapping<'a>(
+    pool: &'a PgPool,
+    table: TradeTable,
+    delivery_from: &'a DateTime<Tz>,
+    delivery_to: &'a DateTime<Tz>,
+    units: &'a QuantityUnits,
+    decoding: Decoding,
+) -> TradeStream<'a> {
+    let trades = match table {
+        TradeTable::Intraday => sqlx::query_as!(
+            Trade,
+            r#"
+    SELECT id::bigint AS "id!", area AS "area: Area", counter_part AS "counter_part: CounterPart",
+        currency AS "currency: Currency", delivery_start, delivery_end,
+        price, quantity_mwh, trade_side AS "trade_side: TradeSide", trade_type AS "trade_type: TradeType"
+    FROM intraday_trades
+    WHERE delivery_end > $1 AND delivery_start < $2"#,
+            delivery_from,
+            delivery_to,
+        )
+        .fetch(pool),
+        TradeTable::Auction => sqlx::query_as!(
+            Trade,
+            r#"
+    SELECT id::bigint AS "id!", area AS "area: Area", counter_part AS "counter_part: CounterPart",
+        currency AS "currency: Currency", delivery_start, delivery_end,
+        price, quantity_mwh, trade_side AS "trade_side: TradeSide", trade_type AS "trade_type: TradeType"
+    FROM auction_trades
+    WHERE delivery_end > $1 AND delivery_start < $2"#,
+            delivery_from,
+            delivery_to,
+        )
+        .fetch(pool),
+        TradeTable::Imbalance => sqlx::query_as!(
+            Trade,
+            r#"
+    SELECT id::bigint AS "id!", area AS "area: Area", counter_part AS "counter_part: CounterPart",
+        currency AS "currency: Currency", delivery_start, delivery_end,
+        price, quantity_mwh, trade_side AS "trade_side: TradeSide", trade_type AS "trade_type: TradeType"
+    FROM imbalance_trades
+    WHERE delivery_end > $1 AND delivery_start < $2"#,
+            delivery_from,
+            delivery_to,
+        )
+        .fetch(pool),
+    };
+    normalize_stream(trades, Some(table), units, decoding)
+}
+
+/// Streams the trades of all three tables delivered at any time in the window, table by table,
+/// see `WindowFilter::Overlap`
+pub fn get_trades_stream_overlapping<'a>(
+    pool: &'a PgPool,
+    delivery_from: &'a DateTime<Tz>,
+    delivery_to: &'a DateTime<Tz>,
+    units: &'a QuantityUnits,
+    decoding: Decoding,
+) -> TradeStream<'a> {
+    let tables = TradeTable::ALL.into_iter().map(move |table| {
+        get_table_trades_stream_overlapping(
+            pool,
+            table,
+            delivery_from,
+            delivery_to,
+            units,
+            decoding,
+        )
+    });
+    Box::pin(stream::iter(tables).flatten())
+}
+
+/// Streams the trades of a table ordered by `delivery_start` and id, which the indexes on
+/// `delivery_start` serve without sorting
+pub fn get_table_trades_stream_ordered<'a>(
+    pool: &'a PgPool,
+    table: TradeTable,
+    delivery_from: &'a DateTime<Tz>,
+    delivery_to: &'a DateTime<Tz>,
+    units: &'a QuantityUnits,
+    decoding: Decoding,
+) -> TradeStream<'a> {
+    let trades = match table {
+        TradeTable::Intraday => sqlx::query_as!(
+            Trade,
+            r#"
+    SELECT id::bigint AS "id!", area AS "area: Area", counter_part AS "counter_part: CounterPart",
+        currency AS "currency: Currency", delivery_start, delivery_end,
+        price, quantity_mwh, trade_side AS "trade_side: TradeSide", trade_type AS "trade_type: TradeType"
+    FROM intraday_trades
+    WHERE delivery_start >= $1 AND delivery_start < $2
+    ORDER BY delivery_start, id"#,
+            delivery_from,
+            delivery_to,
+        )
+        .fetch(pool),
+        TradeTable::Auction => sqlx::query_as!(
+            Trade,
+            r#"
+    SELECT id::bigint AS "id!", area AS "area: Area", counter_part AS "counter_part: CounterPart",
+        currency AS "currency: Currency", delivery_start, delivery_end,
+        price, quantity_mwh, trade_side AS "trade_side: TradeSide", trade_type AS "trade_type: TradeType"
+    FROM auction_trades
+    WHERE delivery_start >= $1 AND delivery_start < $2
+    ORDER BY delivery_start, id"#,
+            delivery_from,
+            delivery_to,
+        )
+        .fetch(pool),
+        TradeTable::Imbalance => sqlx::query_as!(
+            Trade,
+            r#"
+    SELECT id::bigint AS "id!", area AS "area: Area", counter_part AS "counter_part: CounterPart",
+        currency AS "currency: Currency", delivery_start, delivery_end,
+        price, quantity_mwh, trade_side AS "trade_side: TradeSide", trade_type AS "trade_type: TradeType"
+    FROM imbalance_trades
+    WHERE delivery_start >= $1 AND delivery_start < $2
+    ORDER BY delivery_start, id"#,
+            delivery_from,
+            delivery_to,
+        )
+        .fetch(pool),
+    };
+
+    normalize_stream(trades, Some(table), units, decoding)
+}
+
+/// Merges streams that are each ordered by `delivery_start` into one ordered stream, holding
+/// only the next trade of each. Trades delivered at the same time come in the order of their
+/// streams.
+pub fn merge_by_delivery_start<'a>(streams: Vec<TradeStream<'a>>) -> TradeStream<'a> {
+    let heads: Vec<Option<Trade>> = streams.iter().map(|_| None).collect();
+    let streams: Vec<Option<TradeStream<'a>>> = streams.into_iter().map(Some).collect();
+
+    Box::pin(stream::try_unfold(
+        (streams, heads),
+        |(mut streams, mut heads)| async move {
+            for (stream, head) in streams.iter_mut().zip(heads.iter_mut()) {
+                if let (Some(trades), None) = (stream.as_mut(), head.as_ref()) {
+                    *head = trades.try_next().await?;
+                    if head.is_none() {
+                        // Dropping an exhausted stream releases its connection right away
+                        *stream = None;
+                    }
+                }
+            }
+            let next = heads
+                .iter()
+                .enumerate()
+                .filter_map(|(index, head)| {
+                    head.as_ref().map(|trade| (trade.delivery_start, index))
+                })
+                .min();
+            Ok(next.map(|(_, index)| {
+                let trade = heads[index].take().expect("head was just compared");
+                (trade, (streams, heads))
+            }))
+        },
+    ))
+}
+
+/// Streams the trades of all three tables ordered by `delivery_start` (and table, id to make the
+/// order deterministic), merging an ordered scan of each table. Aggregations can then tell when
+/// a delivery period is complete, e.g. to finalize days early with `fanout::reports_by_day`.
+pub fn get_trades_stream_ordered<'a>(
+    pool: &'a PgPool,
+    delivery_from: &'a DateTime<Tz>,
+    delivery_to: &'a DateTime<Tz>,
+    units: &'a QuantityUnits,
+    decoding: Decoding,
+) -> TradeStream<'a> {
+    merge_by_delivery_start(
+        TradeTable::ALL
+            .into_iter()
+            .map(|table| {
+                get_table_trades_stream_ordered(
+                    pool,
+                    table,
+                    delivery_from,
+                    delivery_to,
+                    units,
+                    decoding,
+                )
+            })
+            .collect(),
+    )
+}
+
+/// Gets the trades of a table with an id above `after_id`, ordered by id. Ids are assigned
+/// increasingly, so the highest id seen works as a watermark for fetching only new trades, as
+/// long as the trades committed out of order below it are fetched again, see
+/// `watch::IncrementalSync`.
+pub async fn get_trades_after_id(
+    pool: &PgPool,
+    table: TradeTable,
+    after_id: i64,
+    delivery_from: &DateTime<Tz>,
+    delivery_to: &DateTime<Tz>,
+    units: &QuantityUnits,
+    decoding: Decoding,
+) -> Result<Vec<Trade>> {
+    let query = format!(
+        "
+    SELECT id::bigint AS id, area, counter_part, currency, delivery_start, delivery_end, price, quantity_mwh, trade_side, trade_type
+    FROM {}
+    WHERE id > $1 AND delivery_start >= $2 AND delivery_start < $3
+    ORDER BY id",
+        table.table_name()
+    );
+
+    let mut trades = sqlx::query_as(&query)
+        .bind(after_id)
+        .bind(delivery_from)
+        .bind(delivery_to)
+        .fetch_all(pool)
+        .await
+        .inspect_err(monitoring::record_db_error)?;
+    monitoring::record_trades_fetched(table, trades.len());
+    normalize_trades(&mut trades, table, units, decoding)?;
+
+    Ok(trades)
+}
+
+/// A partition of a trade table with its bounds on `delivery_start`, `None` where it's
+/// unbounded, as for `MINVALUE`, `MAXVALUE` and the default partition
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Partition {
+    pub name: String,
+    pub oid: Oid,
+    pub lower: Option<DateTime<Utc>>,
+    pub upper: Option<DateTime<Utc>>,
+}
+
+/// Partitions of a trade table, ordered by name, which for our monthly partitions of
+/// `delivery_start` is chronological. Empty if the table isn't partitioned. The bounds are
+/// deparsed against the parent table, so listing them doesn't lock the partitions.
+pub async fn get_partitions(pool: &PgPool, table: TradeTable) -> Result<Vec<Partition>> {
+    let partitions = sqlx::query_as!(
+        Partition,
+        r#"
+    SELECT child.relname::text AS "name!", child.oid AS "oid!",
+        (regexp_match(pg_get_expr(child.relpartbound, parent.oid), 'FROM \(''([^'']+)''\)'))[1]::timestamptz AS lower,
+        (regexp_match(pg_get_expr(child.relpartbound, parent.oid), 'TO \(''([^'']+)''\)'))[1]::timestamptz AS upper
+    FROM pg_inherits
+    JOIN pg_class parent ON parent.oid = pg_inherits.inhparent
+    JOIN pg_class child ON child.oid = pg_inherits.inhrelid
+    JOIN pg_namespace ON pg_namespace.oid = parent.relnamespace
+    WHERE parent.relname = $1::text AND pg_namespace.nspname = current_schema()
+    ORDER BY child.relname"#,
+        table.table_name(),
+    )
+    .fetch_all(pool)
+    .await
+    .inspect_err(monitoring::record_db_error)?;
+
+    Ok(partitions)
+}
+
+/// Fetches the trades of a table in the window, only those of the partition `partition` if
+/// given. The partition is selected through the parent table with the window clamped to its
+/// bounds, which prunes the scan to that partition.
+async fn get_table_trades_in_partition(
+    pool: &PgPool,
+    table: TradeTable,
+    delivery_from: &DateTime<Utc>,
+    delivery_to: &DateTime<Utc>,
+    partition: Option<Oid>,
+) -> Result<Vec<Trade>, Error> {
+    match table {
+        TradeTable::Intraday => sqlx::query_as!(
+            Trade,
+            r#"
+    SELECT id::bigint AS "id!", area AS "area: Area", counter_part AS "counter_part: CounterPart",
+        currency AS "currency: Currency", delivery_start, delivery_end,
+        price, quantity_mwh, trade_side AS "trade_side: TradeSide", trade_type AS "trade_type: TradeType"
+    FROM intraday_trades
+    WHERE delivery_start >= $1 AND delivery_start < $2 AND ($3::oid IS NULL OR tableoid = $3)"#,
+            delivery_from,
+            delivery_to,
+            partition,
+        )
+        .fetch_all(pool)
+        .await,
+        TradeTable::Auction => sqlx::query_as!(
+            Trade,
+            r#"
+    SELECT id::bigint AS "id!", area AS "area: Area", counter_part AS "counter_part: CounterPart",
+        currency AS "currency: Currency", delivery_start, delivery_end,
+        price, quantity_mwh, trade_side AS "trade_side: TradeSide", trade_type AS "trade_type: TradeType"
+    FROM auction_trades
+    WHERE delivery_start >= $1 AND delivery_start < $2 AND ($3::oid IS NULL OR tableoid = $3)"#,
+            delivery_from,
+            delivery_to,
+            partition,
+        )
+        .fetch_all(pool)
+        .await,
+        TradeTable::Imbalance => sqlx::query_as!(
+            Trade,
+            r#"
+    SELECT id::bigint AS "id!", area AS "area: Area", counter_part AS "counter_part: CounterPart",
+        currency AS "currency: Currency", delivery_start, delivery_end,
+        price, quantity_mwh, trade_side AS "trade_side: TradeSide", trade_type AS "trade_type: TradeType"
+    FROM imbalance_trades
+    WHERE delivery_start >= $1 AND delivery_start < $2 AND ($3::oid IS NULL OR tableoid = $3)"#,
+            delivery_from,
+            delivery_to,
+            partition,
+        )
+        .fetch_all(pool)
+        .await,
+    }
+}
+
+/// Streams the trades of all three tables like `get_trades_stream`, but scans each partition
+/// overlapping the window on its own, one partition in memory at a time. For backfills over
+/// many months this is much faster than a single range scan over the parent table. Tables that
+/// aren't partitioned are scanned whole.
+pub async fn get_trades_stream_by_partition<'a>(
+    pool: &'a PgPool,
+    delivery_from: &'a DateTime<Tz>,
+    delivery_to: &'a DateTime<Tz>,
+    units: &'a QuantityUnits,
+    decoding: Decoding,
+) -> Result<TradeStream<'a>> {
+    let delivery_from = delivery_from.with_timezone(&Utc);
+    let delivery_to = delivery_to.with_timezone(&Utc);
+    let mut scans = Vec::new();
+    for table in TradeTable::ALL {
+        let partitions = get_partitions(pool, table).await?;
+        if partitions.is_empty() {
+            scans.push((table, delivery_from, delivery_to, None));
+        } else {
+            scans.extend(partitions.into_iter().filter_map(|partition| {
+                let from = partition
+                    .lower
+                    .map_or(delivery_from, |lower| lower.max(delivery_from));
+                let to = partition
+                    .upper
+                    .map_or(delivery_to, |upper| upper.min(delivery_to));
+                (from < to).then_some((table, from, to, Some(partition.oid)))
+            }));
+        }
+    }
+
+    let trades = stream::iter(scans)
+        .then(move |(table, from, to, partition)| async move {
+            let mut trades = get_table_trades_in_partition(pool, table, &from, &to, partition)
+                .await
+                .inspect_err(monitoring::record_db_error)?;
+            monitoring::record_trades_fetched(table, trades.len());
+            normalize_trades(&mut trades, table, units, decoding)
+                .map_err(|err| Error::Decode(err.into()))?;
+            Ok::<_, Error>(stream::iter(trades.into_iter().map(Ok)))
+        })
+        .try_flatten();
+
+    Ok(Box::pin(trades))
+}
+
+/// Rows per `INSERT` of `insert_trades`, keeping the arrays bound to a statement moderate
+const INSERT_CHUNK_SIZE: usize = 5000;
+
+/// What `insert_trades` does with trades whose id is already in the table
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum OnConflict {
+    /// Fails the insert, leaving the table as it was
+    Fail,
+    /// Keeps the trade in the table
+    Skip,
+    /// Overwrites the trade in the table, e.g. for corrections from the exchange
+    Update,
+}
+
+impl OnConflict {
+    fn clause(&self) -> &'static str {
+        match self {
+            OnConflict::Fail => "",
+            OnConflict::Skip => "ON CONFLICT (id) DO NOTHING",
+            OnConflict::Update => {
+                "ON CONFLICT (id) DO UPDATE SET area = EXCLUDED.area,
+        counter_part = EXCLUDED.counter_part, currency = EXCLUDED.currency,
+        delivery_start = EXCLUDED.delivery_start,
+        delivery_end = EXCLUDED.delivery_end, price = EXCLUDED.price,
+        quantity_mwh = EXCLUDED.quantity_mwh, trade_side = EXCLUDED.trade_side,
+        trade_type = EXCLUDED.trade_type"
+            }
+        }
+    }
+}
+
+/// Writes trades into a trade table, the inverse of reading them: quantities in MW are converted
+/// into the unit of the table, and values are stored as they're decoded. Rows are inserted in
+/// chunks of `INSERT_CHUNK_SIZE` through `UNNEST`, all in one transaction, so a failing chunk
+/// leaves the table as it was. Returns the number of rows inserted or updated.
+pub async fn insert_trades(
+    pool: &PgPool,
+    trades: &[Trade],
+    table: TradeTable,
+    units: &QuantityUnits,
+    on_conflict: OnConflict,
+) -> Result<u64> {
+    if let Some(trade) = trades
+        .iter()
+        .find(|trade| TradeTable::from(Market::from(trade.trade_type)) != table)
+    {
+        bail!(
+            "Trade {} of type {} does not belong in {}",
+            trade.id,
+            trade.trade_type,
+            table.table_name()
+        );
+    }
+    let query = format!(
+        "
+    INSERT INTO {} (id, area, counter_part, currency, delivery_start, delivery_end, price, quantity_mwh, trade_side, trade_type)
+    SELECT * FROM UNNEST($1::int8[], $2::varchar[], $3::varchar[], $4::varchar[], $5::timestamptz[],
+        $6::timestamptz[], $7::numeric[], $8::numeric[], $9::varchar[], $10::varchar[])
+    {}",
+        table.table_name(),
+        on_conflict.clause()
+    );
+
+    let mut transaction = pool.begin().await?;
+    let mut inserted = 0;
+    for chunk in trades.chunks(INSERT_CHUNK_SIZE) {
+        let quantities = chunk
+            .iter()
+            .map(|trade| {
+                let quantity = units.denormalize(
+                    table,
+                    trade.quantity_mwh,
+                    &trade.delivery_start,
+                    &trade.delivery_end,
+                )?;
+                Ok(units.convention().stored(quantity))
+            })
+            .collect::<Result<Vec<_>>>()?;
+        let column = |value: fn(&Trade) -> String| chunk.iter().map(value).collect::<Vec<_>>();
+        let result = sqlx::query(&query)
+            .bind(chunk.iter().map(|trade| trade.id).collect::<Vec<_>>())
+            .bind(column(|trade| trade.area.to_string()))
+            .bind(column(|trade| trade.counter_part.name().to_string()))
+            .bind(column(|trade| trade.currency.to_string()))
+            .bind(
+                chunk
+                    .iter()
+                    .map(|trade| trade.delivery_start)
+                    .collect::<Vec<_>>(),
+            )
+            .bind(
+                chunk
+                    .iter()
+                    .map(|trade| trade.delivery_end)
+                    .collect::<Vec<_>>(),
+            )
+            .bind(chunk.iter().map(|trade| trade.price).collect::<Vec<_>>())
+            .bind(quantities)
+            .bind(column(|trade| trade.trade_side.to_string()))
+            .bind(column(|trade| trade.trade_type.to_string()))
+            .execute(&mut *transaction)
+            .await
+            .with_context(|| format!("Could not insert trades into {}", table.table_name()))?;
+        inserted += result.rows_affected();
+    }
+    transaction.commit().await?;
+
+    Ok(inserted)
+}
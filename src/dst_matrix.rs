@@ -0,0 +1,258 @@
+//! Runs the features that resolve delivery times to local hours and days across the DST
+//! transition days of each timezone we trade in, as the 23 and 25 hour days are where they
+//! break. Each day is covered by an hourly trade of 1 MW per hour, so every feature has to see
+//! as many hours, and as many MWh, as the day is long.
+
+use std::str::FromStr;
+
+use chrono::{DateTime, Days, Duration, NaiveDate, TimeZone};
+use chrono_tz::Europe::{Copenhagen, London, Paris};
+use chrono_tz::Tz;
+use rust_decimal::Decimal;
+
+use crate::blocks::{BlockDefinition, BlockVolumes};
+use crate::fanout::delivery_days;
+use crate::netting::Netting;
+use crate::pipeline::{Pipeline, PipelineDefinition};
+use crate::report::{Metric, Report, SignConvention};
+use crate::seasonality::Seasonality;
+use crate::settlement::IspSchedule;
+use crate::trade::{Area, AreaSelection, MarketSelection, Trade};
+
+/// A local delivery day and how many hours it has
+struct Case {
+    timezone: Tz,
+    area: Area,
+    date: NaiveDate,
+    hours: i64,
+}
+
+fn cases() -> Vec<Case> {
+    let dates = [
+        (NaiveDate::from_ymd_opt(2024, 3, 31).unwrap(), 23),
+        (NaiveDate::from_ymd_opt(2024, 10, 27).unwrap(), 25),
+        (NaiveDate::from_ymd_opt(2024, 5, 1).unwrap(), 24),
+    ];
+    [
+        (Copenhagen, Area::DK1),
+        (London, Area::GB),
+        (Paris, Area::FR),
+    ]
+    .into_iter()
+    .flat_map(|(timezone, area)| {
+        dates.map(|(date, hours)| Case {
+            timezone,
+            area,
+            date,
+            hours,
+        })
+    })
+    .collect()
+}
+
+impl Case {
+    fn midnight(&self, date: NaiveDate) -> DateTime<Tz> {
+        self.timezone
+            .from_local_datetime(&date.and_hms_opt(0, 0, 0).unwrap())
+            .unwrap()
+    }
+
+    fn day(&self) -> (DateTime<Tz>, DateTime<Tz>) {
+        (
+            self.midnight(self.date),
+            self.midnight(self.date + Days::new(1)),
+        )
+    }
+
+    /// A trade of 1 MW at 10 for each hour of the day
+    fn trades(&self) -> Vec<Trade> {
+        let (from, _) = self.day();
+        (0..self.hours)
+            .map(|hour| {
+                let start = (from + Duration::hours(hour)).fixed_offset();
+                Trade::test()
+                    .area(self.area)
+                    .mwh(1)
+                    .price(10)
+                    .hour(&start.to_rfc3339())
+                    .build()
+            })
+            .collect()
+    }
+
+    fn hours(&self) -> Decimal {
+        Decimal::from(self.hours)
+    }
+
+    fn describe(&self) -> String {
+        format!("{} in {}", self.date, self.timezone)
+    }
+}
+
+fn decimal(value: &str) -> Decimal {
+    Decimal::from_str(value).unwrap()
+}
+
+#[test]
+fn days_have_their_hours() {
+    for case in cases() {
+        let (from, to) = case.day();
+        assert_eq!((to - from).num_hours(), case.hours, "{}", case.describe());
+
+        // A window over the day before, the day and the day after splits into the three days
+        let days = delivery_days(
+            &case.midnight(case.date - Days::new(1)),
+            &case.midnight(case.date + Days::new(2)),
+        )
+        .unwrap();
+        assert_eq!(days.len(), 3, "{}", case.describe());
+        assert_eq!(days[1], (from, to), "{}", case.describe());
+    }
+}
+
+#[test]
+fn reports_total_the_hours_of_the_day() {
+    for case in cases() {
+        let (from, to) = case.day();
+        let report = Report::new(&from, &to, case.trades()).unwrap();
+        assert_eq!(
+            report.metric(Metric::MwBought, MarketSelection::All, AreaSelection::All),
+            case.hours(),
+            "{}",
+            case.describe()
+        );
+    }
+}
+
+#[test]
+fn pipelines_bucket_each_local_hour_once() {
+    for case in cases() {
+        let pipeline = |dimension: &str| {
+            let definition: PipelineDefinition = toml::from_str(&format!(
+                r#"
+                name = "dst"
+                enrichers = [{{ local_time = {{ timezone = "{}" }} }}]
+                dimensions = ["{}"]
+                metrics = ["mw_bought"]
+                "#,
+                case.timezone, dimension
+            ))
+            .unwrap();
+            let mut pipeline = Pipeline::from_definition(
+                definition,
+                &IspSchedule::default(),
+                SignConvention::default(),
+            )
+            .unwrap();
+            for trade in case.trades() {
+                pipeline.add_trade(&trade).unwrap();
+            }
+            pipeline.records().collect::<Vec<_>>()
+        };
+
+        let hours = pipeline("delivery_hour");
+        assert_eq!(hours.len() as i64, case.hours, "{}", case.describe());
+        // The hour repeated when the clocks fall back is labelled by its occurrence, in order
+        let repeated: Vec<char> = hours
+            .iter()
+            .filter_map(|row| row[0].chars().nth(13).filter(|c| c.is_alphabetic()))
+            .collect();
+        let expected = if case.hours == 25 {
+            vec!['A', 'B']
+        } else {
+            vec![]
+        };
+        assert_eq!(repeated, expected, "{}", case.describe());
+        assert!(
+            hours.iter().all(|row| decimal(&row[1]) == Decimal::ONE),
+            "{}",
+            case.describe()
+        );
+
+        let days = pipeline("delivery_day");
+        assert_eq!(days.len(), 1, "{}", case.describe());
+        assert_eq!(days[0][0], case.date.to_string(), "{}", case.describe());
+        assert_eq!(decimal(&days[0][1]), case.hours(), "{}", case.describe());
+    }
+}
+
+#[test]
+fn blocks_cover_the_local_hours_of_the_day() {
+    for case in cases() {
+        let definitions: Vec<BlockDefinition> = ["base", "peak", "off_peak_night"]
+            .into_iter()
+            .zip([(0, 24), (8, 20), (0, 8)])
+            .map(|(name, (start_hour, end_hour))| BlockDefinition {
+                name: name.to_string(),
+                areas: Vec::new(),
+                timezone: case.timezone.to_string(),
+                start_hour,
+                end_hour,
+                weekdays_only: false,
+            })
+            .collect();
+        let mut volumes = BlockVolumes::new(&definitions).unwrap();
+        for trade in case.trades() {
+            volumes.add_trade(&trade).unwrap();
+        }
+
+        // The hour skipped or repeated is in the night, so the peak is always 12 hours
+        let bought: Vec<Decimal> = volumes
+            .records()
+            .iter()
+            .map(|row| decimal(&row[2]))
+            .collect();
+        assert_eq!(
+            bought,
+            vec![
+                case.hours(),
+                Decimal::from(12),
+                case.hours() - Decimal::from(16)
+            ],
+            "{}",
+            case.describe()
+        );
+    }
+}
+
+#[test]
+fn hourly_profiles_count_each_occurrence_of_an_hour() {
+    for case in cases() {
+        let (from, to) = case.day();
+        let mut seasonality = Seasonality::new(&from, &to).unwrap();
+        for trade in case.trades() {
+            seasonality.add_trade(&trade).unwrap();
+        }
+
+        let records = seasonality.records();
+        let occurrences: i64 = records
+            .iter()
+            .map(|row| row[4].parse::<i64>().unwrap())
+            .sum();
+        assert_eq!(occurrences, case.hours, "{}", case.describe());
+        // Every hour that occurs averages the 1 MWh traded in each of its occurrences
+        assert!(
+            records
+                .iter()
+                .filter(|row| row[4] != "0")
+                .all(|row| decimal(&row[6]) == Decimal::ONE),
+            "{}",
+            case.describe()
+        );
+    }
+}
+
+#[test]
+fn netting_positions_each_hour_of_the_day() {
+    for case in cases() {
+        let mut netting = Netting::default();
+        for trade in case.trades() {
+            netting.add_trade(&trade).unwrap();
+        }
+
+        let records = netting.records();
+        assert_eq!(records.len() as i64, case.hours, "{}", case.describe());
+        let net: Decimal = records.iter().map(|row| decimal(&row[4])).sum();
+        assert_eq!(net, case.hours(), "{}", case.describe());
+    }
+}
@@ -0,0 +1,101 @@
+use std::path::Path;
+
+use anyhow::Result;
+use rust_decimal::Decimal;
+
+use crate::pipeline::SinkDefinition;
+use crate::report::{BreakdownRow, Report};
+use crate::trade::TradeSide;
+
+pub const BREAKDOWN_HEADER: [&str; 7] = [
+    "area",
+    "currency",
+    "market",
+    "side",
+    "mw",
+    "cash_flow",
+    "gross_profit",
+];
+
+/// What the cell adds to the gross profit: revenue when selling, costs when buying
+fn gross_profit(row: &BreakdownRow) -> Decimal {
+    match row.side {
+        TradeSide::Sell => row.cash_flow,
+        TradeSide::Buy => -row.cash_flow,
+    }
+}
+
+/// A row per area, currency, market and side of the report, ordered like `Report::breakdown`
+pub fn breakdown_records(report: &Report) -> Vec<Vec<String>> {
+    report
+        .breakdown()
+        .iter()
+        .map(|row| {
+            vec![
+                row.area.to_string(),
+                row.currency.to_string(),
+                row.market.to_string(),
+                row.side.to_string(),
+                row.mw.to_string(),
+                row.cash_flow.to_string(),
+                gross_profit(row).to_string(),
+            ]
+        })
+        .collect()
+}
+
+/// Writes the breakdown of the report as CSV to the file, or to stdout without a path
+pub fn write_breakdown_csv(report: &Report, path: Option<&Path>) -> Result<()> {
+    let sink = match path {
+        Some(path) => SinkDefinition::Csv {
+            path: path.to_path_buf(),
+        },
+        None => SinkDefinition::Stdout,
+    };
+    sink.write(
+        &BREAKDOWN_HEADER.map(String::from),
+        breakdown_records(report).into_iter(),
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::trade::{Area, Currency, Trade};
+    use chrono::TimeZone;
+
+    #[test]
+    fn breakdown_is_written_with_the_gross_profit_of_each_cell() {
+        let from = chrono_tz::Europe::Copenhagen
+            .with_ymd_and_hms(2024, 5, 1, 0, 0, 0)
+            .unwrap();
+        let to = from + chrono::Duration::days(1);
+        let report = Report::new(
+            &from,
+            &to,
+            vec![
+                Trade::test().mwh(10).price(40).build(),
+                Trade::test().sell().mwh(4).price(50).build(),
+                Trade::test()
+                    .area(Area::GB)
+                    .currency(Currency::Gbp)
+                    .mwh(2)
+                    .price(30)
+                    .build(),
+            ],
+        )
+        .unwrap();
+
+        let path = std::env::temp_dir().join("export_test.csv");
+        write_breakdown_csv(&report, Some(&path)).unwrap();
+
+        assert_eq!(
+            std::fs::read_to_string(&path).unwrap(),
+            "area,currency,market,side,mw,cash_flow,gross_profit\n\
+             DK1,EUR,intraday,buy,10.0,400.0,-400.0\n\
+             DK1,EUR,intraday,sell,4.0,200.0,200.0\n\
+             GB,GBP,intraday,buy,2.0,60.0,-60.0\n"
+        );
+        std::fs::remove_file(&path).unwrap();
+    }
+}
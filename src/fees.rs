@@ -0,0 +1,155 @@
+use std::collections::HashSet;
+use std::str::FromStr;
+
+use anyhow::{anyhow, bail, Result};
+use rust_decimal::Decimal;
+use serde::Deserialize;
+
+use crate::trade::{CounterPart, Market};
+
+/// A fee charged on the trades of a market and counter part, configured as e.g.
+///
+/// ```toml
+/// [[fees]]
+/// market = "intraday"
+/// per_mwh = 0.11
+///
+/// [[fees]]
+/// market = "intraday"
+/// counter_part = "tradition"
+/// per_mwh = 0.05
+/// percent = 0.02
+/// ```
+///
+/// A rule without a market or counter part applies to all of them. Fees are charged on the
+/// energy in EUR/MWh, or GBP/MWh for GB, plus a percentage of the cash flow.
+#[derive(Debug, Deserialize, Clone)]
+pub struct FeeRule {
+    pub market: Option<String>,
+    pub counter_part: Option<CounterPart>,
+    #[serde(default)]
+    pub per_mwh: Decimal,
+    #[serde(default)]
+    pub percent: Decimal,
+}
+
+#[derive(Debug, Clone)]
+struct Fee {
+    market: Option<Market>,
+    counter_part: Option<CounterPart>,
+    per_mwh: Decimal,
+    percent: Decimal,
+}
+
+impl Fee {
+    fn applies(&self, counter_part: &CounterPart, market: Market) -> bool {
+        self.market.is_none_or(|fee_market| fee_market == market)
+            && self
+                .counter_part
+                .as_ref()
+                .is_none_or(|fee_counter_part| fee_counter_part == counter_part)
+    }
+
+    /// Rules of a counter part are more specific than those of a market
+    fn specificity(&self) -> u8 {
+        2 * u8::from(self.counter_part.is_some()) + u8::from(self.market.is_some())
+    }
+}
+
+/// The fees charged on trades, by the most specific rule applying to a trade, so a broker's
+/// rate replaces the rate of the market rather than adding to it
+#[derive(Debug, Clone, Default)]
+pub struct FeeSchedule {
+    fees: Vec<Fee>,
+}
+
+impl FeeSchedule {
+    pub fn new(rules: &[FeeRule]) -> Result<Self> {
+        let mut seen = HashSet::new();
+        let fees = rules
+            .iter()
+            .map(|rule| {
+                let market = rule
+                    .market
+                    .as_deref()
+                    .map(|market| {
+                        Market::from_str(market).map_err(|_| anyhow!("Unknown market {}", market))
+                    })
+                    .transpose()?;
+                if !seen.insert((market, rule.counter_part.clone())) {
+                    bail!(
+                        "Several fees for market {} and counter part {}",
+                        rule.market.as_deref().unwrap_or("any"),
+                        rule.counter_part
+                            .as_ref()
+                            .map_or("any".to_string(), CounterPart::to_string)
+                    );
+                }
+                Ok(Fee {
+                    market,
+                    counter_part: rule.counter_part.clone(),
+                    per_mwh: rule.per_mwh,
+                    percent: rule.percent,
+                })
+            })
+            .collect::<Result<Vec<Fee>>>()?;
+        Ok(Self { fees })
+    }
+
+    /// The fee of a trade with the energy and cash flow, zero if no rule applies
+    pub fn fee(
+        &self,
+        counter_part: &CounterPart,
+        market: Market,
+        mwh: Decimal,
+        cash_flow: Decimal,
+    ) -> Decimal {
+        self.fees
+            .iter()
+            .filter(|fee| fee.applies(counter_part, market))
+            .max_by_key(|fee| fee.specificity())
+            .map_or(Decimal::ZERO, |fee| {
+                mwh.abs() * fee.per_mwh + cash_flow.abs() * fee.percent / Decimal::ONE_HUNDRED
+            })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use rust_decimal_macros::dec;
+    use std::collections::HashMap;
+
+    #[test]
+    fn the_most_specific_fee_applies() {
+        let rules: Vec<FeeRule> = toml::from_str::<HashMap<String, Vec<FeeRule>>>(
+            r#"
+            [[fees]]
+            per_mwh = 0.5
+
+            [[fees]]
+            market = "intraday"
+            per_mwh = 0.11
+
+            [[fees]]
+            market = "intraday"
+            counter_part = "tradition"
+            per_mwh = 0.05
+            percent = 0.02
+            "#,
+        )
+        .unwrap()
+        .remove("fees")
+        .unwrap();
+        let schedule = FeeSchedule::new(&rules).unwrap();
+        let broker = CounterPart::from("tradition".to_string());
+        let fee = |counter_part, market| schedule.fee(counter_part, market, dec!(10), dec!(500));
+
+        assert_eq!(fee(&CounterPart::EPEX, Market::Intraday), dec!(1.1));
+        assert_eq!(fee(&broker, Market::Intraday), dec!(0.6));
+        assert_eq!(fee(&broker, Market::Auction), dec!(5));
+
+        let duplicated = [rules[1].clone(), rules[1].clone()];
+        assert!(FeeSchedule::new(&duplicated).is_err());
+    }
+}
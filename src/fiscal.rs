@@ -0,0 +1,188 @@
+use std::collections::BTreeMap;
+
+use anyhow::{anyhow, bail, Result};
+use chrono::{DateTime, Datelike, Days, FixedOffset, Months, NaiveDate, TimeZone, Timelike};
+use chrono_tz::Tz;
+use serde::Deserialize;
+
+use crate::report::Report;
+use crate::trade::Trade;
+
+/// How trades are booked into fiscal months by their delivery, e.g. with the hour starting 23:00
+/// on the last day of a month booked in the next month:
+///
+/// ```toml
+/// [fiscal]
+/// timezone = "Europe/Copenhagen"
+/// month_end_cutoff_hour = 23
+/// ```
+#[derive(Debug, Deserialize, Clone)]
+pub struct FiscalConfig {
+    /// Timezone months and the cutoff are local to
+    #[serde(default = "default_timezone")]
+    pub timezone: String,
+    /// Local hour on the last day of a month from which deliveries belong to the next month,
+    /// 24 for calendar months
+    #[serde(default = "default_cutoff_hour")]
+    pub month_end_cutoff_hour: u32,
+}
+
+impl Default for FiscalConfig {
+    fn default() -> Self {
+        Self {
+            timezone: default_timezone(),
+            month_end_cutoff_hour: default_cutoff_hour(),
+        }
+    }
+}
+
+fn default_timezone() -> String {
+    "Europe/Copenhagen".to_string()
+}
+
+fn default_cutoff_hour() -> u32 {
+    24
+}
+
+/// Reports per fiscal month, each covering the deliveries booked in the month. Trades are booked
+/// by the start of their delivery, so a trade delivering across the cutoff is booked as a whole.
+pub struct FiscalMonths {
+    timezone: Tz,
+    cutoff_hour: u32,
+    /// By the first day of the calendar month the fiscal month is named after
+    months: BTreeMap<NaiveDate, Report>,
+}
+
+impl FiscalMonths {
+    pub fn new(config: &FiscalConfig) -> Result<Self> {
+        if config.month_end_cutoff_hour == 0 || config.month_end_cutoff_hour > 24 {
+            bail!(
+                "Month end cutoff has to be an hour of the last day, 1 - 24, not {}",
+                config.month_end_cutoff_hour
+            );
+        }
+        Ok(Self {
+            timezone: config
+                .timezone
+                .parse()
+                .map_err(|_| anyhow!("Unknown timezone in fiscal config: {}", config.timezone))?,
+            cutoff_hour: config.month_end_cutoff_hour,
+            months: BTreeMap::new(),
+        })
+    }
+
+    /// The fiscal month a delivery starting at the time is booked in
+    fn month(&self, delivery_start: &DateTime<FixedOffset>) -> Result<NaiveDate> {
+        let local = delivery_start.with_timezone(&self.timezone).naive_local();
+        let month = local
+            .date()
+            .with_day(1)
+            .ok_or(anyhow!("No first day of the month of {}", local))?;
+        let is_last_day = local.date().succ_opt().is_some_and(|day| day.day() == 1);
+        if is_last_day && local.hour() >= self.cutoff_hour {
+            return month
+                .checked_add_months(Months::new(1))
+                .ok_or(anyhow!("No month after {}", month));
+        }
+        Ok(month)
+    }
+
+    /// Start of the fiscal month, at the cutoff on the last day of the month before
+    fn month_start(&self, month: NaiveDate) -> Result<DateTime<Tz>> {
+        let last_day = month
+            .checked_sub_days(Days::new(1))
+            .ok_or(anyhow!("No month before {}", month))?;
+        let (date, hour) = match self.cutoff_hour {
+            24 => (month, 0),
+            hour => (last_day, hour),
+        };
+        date.and_hms_opt(hour, 0, 0)
+            .and_then(|local| self.timezone.from_local_datetime(&local).earliest())
+            .ok_or(anyhow!(
+                "No local hour {} on {} in {}",
+                hour,
+                date,
+                self.timezone
+            ))
+    }
+
+    pub fn add_trade(&mut self, trade: &Trade) -> Result<()> {
+        let month = self.month(&trade.delivery_start)?;
+        if !self.months.contains_key(&month) {
+            let next_month = month
+                .checked_add_months(Months::new(1))
+                .ok_or(anyhow!("No month after {}", month))?;
+            let report = Report::empty(&self.month_start(month)?, &self.month_start(next_month)?)?;
+            self.months.insert(month, report);
+        }
+        self.months
+            .get_mut(&month)
+            .expect("report of the month was just inserted")
+            .add_trade(trade)
+    }
+
+    /// Trades without a price, which are left out of the reports
+    pub fn skipped_trades(&self) -> usize {
+        self.months.values().map(Report::skipped_trades).sum()
+    }
+
+    /// Rows of the breakdown of each fiscal month, with the local bounds of the month
+    pub fn records(&self) -> Vec<Vec<String>> {
+        let format = "%Y-%m-%dT%H:%M%:z";
+        self.months
+            .iter()
+            .flat_map(|(month, report)| {
+                report.breakdown().into_iter().map(move |row| {
+                    vec![
+                        month.format("%Y-%m").to_string(),
+                        report.delivery_from().format(format).to_string(),
+                        report.delivery_to().format(format).to_string(),
+                        row.area.to_string(),
+                        row.market.to_string(),
+                        row.side.to_string(),
+                        row.mw.to_string(),
+                        row.cash_flow.to_string(),
+                    ]
+                })
+            })
+            .collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn hours_from_the_cutoff_on_the_last_day_are_booked_in_the_next_month() {
+        let mut months = FiscalMonths::new(&FiscalConfig {
+            month_end_cutoff_hour: 23,
+            ..FiscalConfig::default()
+        })
+        .unwrap();
+        for hour in ["2024-10-31T22:00+01:00", "2024-10-31T23:00+01:00"] {
+            months
+                .add_trade(&Trade::test().mwh(10).price(40).hour(hour).build())
+                .unwrap();
+        }
+
+        let records = months.records();
+        assert_eq!(records.len(), 2);
+        assert_eq!(
+            records[0][..3],
+            [
+                "2024-10",
+                "2024-09-30T23:00+02:00",
+                "2024-10-31T23:00+01:00"
+            ]
+        );
+        assert_eq!(
+            records[1][..3],
+            [
+                "2024-11",
+                "2024-10-31T23:00+01:00",
+                "2024-11-30T23:00+01:00"
+            ]
+        );
+    }
+}
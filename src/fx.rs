@@ -0,0 +1,247 @@
+use std::collections::BTreeMap;
+use std::fs::File;
+use std::io::Read;
+use std::path::{Path, PathBuf};
+use std::str::FromStr;
+
+use anyhow::{anyhow, Context, Result};
+use chrono::{DateTime, FixedOffset, NaiveDate};
+use chrono_tz::Tz;
+use rust_decimal::Decimal;
+use serde::Deserialize;
+#[cfg(feature = "runtime")]
+use sqlx::PgPool;
+
+use crate::trade::{Currency, Trade};
+
+/// Rates the cash flows of reports are converted to a single reporting currency at, e.g.
+///
+/// ```toml
+/// [fx]
+/// reporting_currency = "EUR"
+/// fixed_rates = { GBP = 1.17 }
+/// rates_file = "fx_rates.csv"
+/// rates_table = "fx_rates"
+/// ```
+///
+/// Rates are in the reporting currency per unit of the other currency. Daily rates, from the
+/// file or the table with the columns `date`, `currency` and `rate`, apply to the trades
+/// delivered on their local day, and the fixed rate of a currency to its days without one.
+#[derive(Debug, Deserialize, Clone)]
+#[serde(default)]
+pub struct FxConfig {
+    pub reporting_currency: Currency,
+    /// Timezone the days of the daily rates are local to
+    pub timezone: String,
+    pub fixed_rates: BTreeMap<Currency, Decimal>,
+    pub rates_file: Option<PathBuf>,
+    pub rates_table: Option<String>,
+}
+
+impl Default for FxConfig {
+    fn default() -> Self {
+        Self {
+            reporting_currency: Currency::Eur,
+            timezone: "Europe/Copenhagen".to_string(),
+            fixed_rates: BTreeMap::new(),
+            rates_file: None,
+            rates_table: None,
+        }
+    }
+}
+
+/// Daily rates by currency and local day
+pub type DailyRates = BTreeMap<(Currency, NaiveDate), Decimal>;
+
+#[derive(Debug, Deserialize)]
+struct DailyRateRow {
+    date: String,
+    currency: String,
+    rate: String,
+}
+
+pub fn read_daily_rates_file(path: &Path) -> Result<DailyRates> {
+    let file = File::open(path).with_context(|| format!("Could not open {}", path.display()))?;
+    read_daily_rates(file).with_context(|| format!("Invalid FX rates {}", path.display()))
+}
+
+/// Reads a CSV of daily rates with the columns `date`, `currency` and `rate`
+pub fn read_daily_rates<R: Read>(reader: R) -> Result<DailyRates> {
+    let mut reader = csv::Reader::from_reader(reader);
+    let mut rates = DailyRates::new();
+    for (index, row) in reader.deserialize::<DailyRateRow>().enumerate() {
+        let line = index + 2;
+        let row = row.with_context(|| format!("Could not read line {}", line))?;
+        let date = NaiveDate::parse_from_str(&row.date, "%Y-%m-%d")
+            .with_context(|| format!("Invalid date on line {}", line))?;
+        let currency = Currency::from_str(&row.currency.to_uppercase())
+            .map_err(|_| anyhow!("Unknown currency {} on line {}", row.currency, line))?;
+        let rate = Decimal::from_str(&row.rate)
+            .with_context(|| format!("Invalid rate on line {}", line))?;
+        rates.insert((currency, date), rate);
+    }
+    Ok(rates)
+}
+
+/// Converts trades into the reporting currency, see `FxConfig`
+#[derive(Debug, Clone)]
+pub struct FxRates {
+    reporting_currency: Currency,
+    timezone: Tz,
+    fixed: BTreeMap<Currency, Decimal>,
+    daily: DailyRates,
+}
+
+impl FxRates {
+    pub fn new(reporting_currency: Currency, timezone: Tz) -> Self {
+        Self {
+            reporting_currency,
+            timezone,
+            fixed: BTreeMap::new(),
+            daily: DailyRates::new(),
+        }
+    }
+
+    /// The fixed rates and those of the file, without the table
+    pub fn from_config(config: &FxConfig) -> Result<Self> {
+        let timezone = config
+            .timezone
+            .parse()
+            .map_err(|_| anyhow!("Unknown timezone in fx: {}", config.timezone))?;
+        let mut rates = Self::new(config.reporting_currency, timezone)
+            .with_fixed_rates(config.fixed_rates.clone());
+        if let Some(path) = &config.rates_file {
+            rates = rates.with_daily_rates(read_daily_rates_file(path)?);
+        }
+        Ok(rates)
+    }
+
+    /// The rates of the config, with the daily rates of its table
+    #[cfg(feature = "runtime")]
+    pub async fn load(config: &FxConfig, pool: &PgPool) -> Result<Self> {
+        let rates = Self::from_config(config)?;
+        let Some(table) = &config.rates_table else {
+            return Ok(rates);
+        };
+        let query = format!(
+            "SELECT date, currency, rate FROM \"{}\"",
+            table.replace('"', "\"\"")
+        );
+        let rows: Vec<(NaiveDate, String, Decimal)> = sqlx::query_as(&query)
+            .fetch_all(pool)
+            .await
+            .with_context(|| format!("Could not read the FX rates of {}", table))?;
+        let daily = rows
+            .into_iter()
+            .map(|(date, currency, rate)| {
+                let currency = Currency::from_str(&currency.to_uppercase())
+                    .map_err(|_| anyhow!("Unknown currency {} in {}", currency, table))?;
+                Ok(((currency, date), rate))
+            })
+            .collect::<Result<DailyRates>>()?;
+        Ok(rates.with_daily_rates(daily))
+    }
+
+    pub fn with_fixed_rates(mut self, rates: BTreeMap<Currency, Decimal>) -> Self {
+        self.fixed.extend(rates);
+        self
+    }
+
+    /// Adds daily rates, replacing those of the same currency and day
+    pub fn with_daily_rates(mut self, rates: DailyRates) -> Self {
+        self.daily.extend(rates);
+        self
+    }
+
+    pub fn reporting_currency(&self) -> Currency {
+        self.reporting_currency
+    }
+
+    /// The rate of the currency on the local day delivery starts, or its fixed rate
+    pub fn rate(
+        &self,
+        currency: Currency,
+        delivery_start: &DateTime<FixedOffset>,
+    ) -> Result<Decimal> {
+        if currency == self.reporting_currency {
+            return Ok(Decimal::ONE);
+        }
+        let day = delivery_start.with_timezone(&self.timezone).date_naive();
+        self.daily
+            .get(&(currency, day))
+            .or(self.fixed.get(&currency))
+            .copied()
+            .ok_or(anyhow!(
+                "No {} rate for {}, configure one under fx",
+                currency,
+                day
+            ))
+    }
+
+    /// The trade with its price in the reporting currency, so its cash flow is as well
+    pub fn convert(&self, trade: &Trade) -> Result<Trade> {
+        let mut converted = trade.clone();
+        converted.price = self.convert_price(trade.price, trade.currency, &trade.delivery_start)?;
+        converted.currency = self.reporting_currency;
+        Ok(converted)
+    }
+
+    /// The price of a trade in the currency, delivered from the start, in the reporting currency,
+    /// for trades that aren't a `Trade`, e.g. a `TradeForReport`
+    pub fn convert_price(
+        &self,
+        price: Option<Decimal>,
+        currency: Currency,
+        delivery_start: &DateTime<FixedOffset>,
+    ) -> Result<Option<Decimal>> {
+        price
+            .map(|price| Ok(price * self.rate(currency, delivery_start)?))
+            .transpose()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::trade::Area;
+    use chrono_tz::Europe::Copenhagen;
+    use rust_decimal_macros::dec;
+
+    #[test]
+    fn daily_rates_apply_to_their_day_and_fixed_rates_to_the_rest() {
+        let daily = read_daily_rates(
+            "date,currency,rate\n2024-05-01,GBP,1.16\n2024-05-02,gbp,1.18\n".as_bytes(),
+        )
+        .unwrap();
+        let rates = FxRates::new(Currency::Eur, Copenhagen)
+            .with_fixed_rates(BTreeMap::from([(Currency::Gbp, dec!(1.15))]))
+            .with_daily_rates(daily);
+        let gb = |hour: &str| {
+            Trade::test()
+                .area(Area::GB)
+                .currency(Currency::Gbp)
+                .price(100)
+                .hour(hour)
+                .build()
+        };
+
+        let converted = rates.convert(&gb("2024-05-01T12:00+01:00")).unwrap();
+        assert_eq!(converted.price, Some(dec!(116)));
+        assert_eq!(converted.currency, Currency::Eur);
+        // Local to Copenhagen, where 23:30 in London is the next day
+        assert_eq!(
+            rates.convert(&gb("2024-05-01T23:30+01:00")).unwrap().price,
+            Some(dec!(118))
+        );
+        assert_eq!(
+            rates.convert(&gb("2024-05-03T12:00+01:00")).unwrap().price,
+            Some(dec!(115))
+        );
+        let dk1 = Trade::test().price(50).build();
+        assert_eq!(rates.convert(&dk1).unwrap().price, Some(dec!(50)));
+
+        let unrated = FxRates::new(Currency::Eur, Copenhagen);
+        assert!(unrated.convert(&gb("2024-05-01T12:00+01:00")).is_err());
+        assert!(read_daily_rates("date,currency,rate\n2024-05-01,USD,1\n".as_bytes()).is_err());
+    }
+}
@@ -0,0 +1,297 @@
+use std::collections::BTreeMap;
+use std::fs::File;
+use std::io::Read;
+use std::path::Path;
+use std::str::FromStr;
+
+use anyhow::{anyhow, bail, Context, Result};
+use chrono::{Datelike, NaiveDate};
+use chrono_tz::Tz;
+use rust_decimal::Decimal;
+use serde::Deserialize;
+
+use crate::report::contract_length;
+use crate::trade::{Area, Trade};
+
+/// Rates the results of areas settled in another currency than we report in are converted at,
+/// e.g. GB in GBP:
+///
+/// ```toml
+/// [fx_hedge]
+/// currency = "GBP"
+/// reporting_currency = "EUR"
+/// areas = ["GB"]
+/// timezone = "Europe/London"
+///
+/// [fx_hedge.hedge_rates]
+/// "2024-05" = 1.1650
+/// "2024-06" = 1.1720
+/// ```
+///
+/// Rates are in the reporting currency per unit of the settlement currency, and hedge rates are
+/// locked per local delivery month.
+#[derive(Debug, Deserialize, Clone)]
+#[serde(default)]
+pub struct FxHedgeConfig {
+    pub currency: String,
+    pub reporting_currency: String,
+    pub areas: Vec<Area>,
+    /// Timezone the delivery days and months are local to
+    pub timezone: String,
+    pub hedge_rates: BTreeMap<String, Decimal>,
+}
+
+impl Default for FxHedgeConfig {
+    fn default() -> Self {
+        Self {
+            currency: "GBP".to_string(),
+            reporting_currency: "EUR".to_string(),
+            areas: vec![Area::GB],
+            timezone: "Europe/London".to_string(),
+            hedge_rates: BTreeMap::new(),
+        }
+    }
+}
+
+/// Spot rates by delivery day
+pub type SpotRates = BTreeMap<NaiveDate, Decimal>;
+
+#[derive(Debug, Deserialize)]
+struct SpotRateRow {
+    date: String,
+    rate: String,
+}
+
+pub fn read_spot_rates_file(path: &Path) -> Result<SpotRates> {
+    let file = File::open(path).with_context(|| format!("Could not open {}", path.display()))?;
+    read_spot_rates(file).with_context(|| format!("Invalid spot rates {}", path.display()))
+}
+
+/// Reads a CSV of spot rates with the columns `date` and `rate`, a row per day
+pub fn read_spot_rates<R: Read>(reader: R) -> Result<SpotRates> {
+    let mut reader = csv::Reader::from_reader(reader);
+    let mut rates = SpotRates::new();
+    for (index, row) in reader.deserialize::<SpotRateRow>().enumerate() {
+        let line = index + 2;
+        let row = row.with_context(|| format!("Could not read line {}", line))?;
+        let date = NaiveDate::parse_from_str(&row.date, "%Y-%m-%d")
+            .with_context(|| format!("Invalid date on line {}", line))?;
+        let rate = Decimal::from_str(&row.rate)
+            .with_context(|| format!("Invalid rate on line {}", line))?;
+        rates.insert(date, rate);
+    }
+    Ok(rates)
+}
+
+/// The gross profit of a delivery month in the settlement currency, converted at the locked
+/// hedge rate and at the spot rates of its days
+#[derive(Debug, PartialEq)]
+pub struct HedgedPnlRow {
+    /// The local delivery month, e.g. `2024-05`
+    pub month: String,
+    pub pnl: Decimal,
+    pub hedge_rate: Decimal,
+    /// The trading result, without the currency moves since the rate was locked
+    pub hedged_pnl: Decimal,
+    pub spot_pnl: Decimal,
+    /// What the currency moves added to the result, the spot minus the hedged result
+    pub fx_effect: Decimal,
+}
+
+impl HedgedPnlRow {
+    pub fn record(&self) -> Vec<String> {
+        vec![
+            self.month.clone(),
+            self.pnl.round_dp(2).to_string(),
+            self.hedge_rate.to_string(),
+            self.hedged_pnl.round_dp(2).to_string(),
+            self.spot_pnl.round_dp(2).to_string(),
+            self.fx_effect.round_dp(2).to_string(),
+        ]
+    }
+}
+
+/// Separates the trading result of areas settled in a foreign currency from currency moves.
+/// The gross profit of each local delivery day is converted at the day's spot rate and at the
+/// hedge rate of its month, and the difference is the FX effect. Trades in other areas or settled
+/// in other currencies are left out, and trades without a price are skipped like in `Report`.
+#[derive(Debug)]
+pub struct HedgedPnl<'a> {
+    config: &'a FxHedgeConfig,
+    timezone: Tz,
+    spot_rates: SpotRates,
+    daily_pnl: BTreeMap<NaiveDate, Decimal>,
+    skipped_trades: usize,
+}
+
+impl<'a> HedgedPnl<'a> {
+    pub fn new(config: &'a FxHedgeConfig, spot_rates: SpotRates) -> Result<Self> {
+        let timezone = config
+            .timezone
+            .parse()
+            .map_err(|_| anyhow!("Unknown timezone in fx_hedge: {}", config.timezone))?;
+        if let Some(month) = config.hedge_rates.keys().find(|month| !is_month(month)) {
+            bail!("Expected hedge rates by month as YYYY-MM, not {}", month);
+        }
+        Ok(Self {
+            config,
+            timezone,
+            spot_rates,
+            daily_pnl: BTreeMap::new(),
+            skipped_trades: 0,
+        })
+    }
+
+    pub fn add_trade(&mut self, trade: &Trade) -> Result<()> {
+        if !self.config.areas.contains(&trade.area)
+            || trade.currency.to_string() != self.config.currency
+        {
+            return Ok(());
+        }
+        let Some(price) = trade.price else {
+            self.skipped_trades += 1;
+            return Ok(());
+        };
+        // Buys have a positive quantity, so they cost what sells earn
+        let cash_flow = -trade.quantity_mwh
+            * contract_length(&trade.delivery_start, &trade.delivery_end)?
+            * price;
+        let day = trade
+            .delivery_start
+            .with_timezone(&self.timezone)
+            .date_naive();
+        *self.daily_pnl.entry(day).or_default() += cash_flow;
+        Ok(())
+    }
+
+    pub fn skipped_trades(&self) -> usize {
+        self.skipped_trades
+    }
+
+    /// A row per local delivery month with trades. Fails for months without a hedge rate and
+    /// days without a spot rate.
+    pub fn rows(&self) -> Result<Vec<HedgedPnlRow>> {
+        let mut months: BTreeMap<String, Vec<(NaiveDate, Decimal)>> = BTreeMap::new();
+        for (day, pnl) in self.daily_pnl.iter() {
+            let month = format!("{:04}-{:02}", day.year(), day.month());
+            months.entry(month).or_default().push((*day, *pnl));
+        }
+
+        months
+            .into_iter()
+            .map(|(month, days)| {
+                let hedge_rate = *self.config.hedge_rates.get(&month).ok_or(anyhow!(
+                    "No {} hedge rate for {}, configure one under fx_hedge.hedge_rates",
+                    self.config.currency,
+                    month
+                ))?;
+                let pnl: Decimal = days.iter().map(|(_, pnl)| pnl).sum();
+                let spot_pnl = days
+                    .iter()
+                    .map(|(day, pnl)| {
+                        let rate = self
+                            .spot_rates
+                            .get(day)
+                            .ok_or(anyhow!("No spot rate for {}", day))?;
+                        Ok(pnl * rate)
+                    })
+                    .sum::<Result<Decimal>>()?;
+                let hedged_pnl = pnl * hedge_rate;
+                Ok(HedgedPnlRow {
+                    month,
+                    pnl,
+                    hedge_rate,
+                    hedged_pnl,
+                    spot_pnl,
+                    fx_effect: spot_pnl - hedged_pnl,
+                })
+            })
+            .collect()
+    }
+}
+
+/// Whether a month is written as `YYYY-MM`, like the months of the rows
+fn is_month(month: &str) -> bool {
+    NaiveDate::parse_from_str(&format!("{}-01", month), "%Y-%m-%d")
+        .is_ok_and(|first| first.format("%Y-%m").to_string() == month)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::trade::Currency;
+    use rust_decimal_macros::dec;
+
+    #[test]
+    fn fx_effect_is_spot_minus_hedged() {
+        let config: FxHedgeConfig = toml::from_str(
+            r#"
+            [hedge_rates]
+            "2024-05" = 1.15
+            "#,
+        )
+        .unwrap();
+        let spot_rates =
+            read_spot_rates("date,rate\n2024-05-01,1.16\n2024-05-02,1.18\n".as_bytes()).unwrap();
+        let mut pnl = HedgedPnl::new(&config, spot_rates).unwrap();
+        let trades = [
+            // Sold for 1000 on the first and bought for 400 on the second
+            Trade::test()
+                .area(Area::GB)
+                .currency(Currency::Gbp)
+                .sell()
+                .mwh(10)
+                .price(100)
+                .hour("2024-05-01T12:00+01:00")
+                .build(),
+            Trade::test()
+                .area(Area::GB)
+                .currency(Currency::Gbp)
+                .mwh(4)
+                .price(100)
+                .hour("2024-05-02T12:00+01:00")
+                .build(),
+            Trade::test()
+                .area(Area::GB)
+                .currency(Currency::Gbp)
+                .no_price()
+                .build(),
+            // Settled in EUR, in GB as well
+            Trade::test().area(Area::DK1).mwh(10).price(50).build(),
+            Trade::test().area(Area::GB).mwh(10).price(50).build(),
+        ];
+        for trade in trades.iter() {
+            pnl.add_trade(trade).unwrap();
+        }
+        assert_eq!(pnl.skipped_trades(), 1);
+
+        assert_eq!(
+            pnl.rows().unwrap(),
+            vec![HedgedPnlRow {
+                month: "2024-05".to_string(),
+                pnl: dec!(600),
+                hedge_rate: dec!(1.15),
+                hedged_pnl: dec!(690),
+                spot_pnl: dec!(688),
+                fx_effect: dec!(-2),
+            }]
+        );
+
+        // Months need a hedge rate
+        let mut pnl = HedgedPnl::new(&config, SpotRates::new()).unwrap();
+        pnl.add_trade(
+            &Trade::test()
+                .area(Area::GB)
+                .currency(Currency::Gbp)
+                .mwh(1)
+                .price(10)
+                .hour("2024-06-01T12:00+01:00")
+                .build(),
+        )
+        .unwrap();
+        assert!(pnl.rows().is_err());
+
+        let config: FxHedgeConfig = toml::from_str("hedge_rates = { 2024-5 = 1.15 }").unwrap();
+        assert!(HedgedPnl::new(&config, SpotRates::new()).is_err());
+    }
+}
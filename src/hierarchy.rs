@@ -0,0 +1,154 @@
+use std::collections::BTreeMap;
+use std::sync::Arc;
+
+use anyhow::{anyhow, bail, Result};
+use serde::Deserialize;
+
+use crate::trade::{sorted_set, Area, AreaSelection};
+
+/// Countries and regions the areas roll up into, each node listing the areas and other nodes
+/// under it, e.g.
+///
+/// ```toml
+/// [area_hierarchy]
+/// denmark = ["DK1", "DK2"]
+/// sweden = ["SE1", "SE3"]
+/// nordics = ["denmark", "sweden", "NO2"]
+/// uk = ["GB"]
+/// ```
+///
+/// Nodes are resolved to their areas when the config is loaded, so unknown names and cycles fail
+/// early, and selecting a node is a lookup.
+#[derive(Debug, Default, Deserialize, Clone)]
+#[serde(try_from = "BTreeMap<String, Vec<String>>")]
+pub struct AreaHierarchy {
+    /// The areas under each node, shared with the selections of the node
+    nodes: BTreeMap<String, Arc<[Area]>>,
+}
+
+impl TryFrom<BTreeMap<String, Vec<String>>> for AreaHierarchy {
+    type Error = anyhow::Error;
+
+    fn try_from(children: BTreeMap<String, Vec<String>>) -> Result<Self> {
+        if let Some(node) = children.keys().find(|node| known_area(node).is_some()) {
+            bail!("Node {} of the area hierarchy is named like an area", node);
+        }
+        let mut nodes = BTreeMap::new();
+        for node in children.keys() {
+            let mut areas = Vec::new();
+            resolve(&children, node, &mut vec![node.as_str()], &mut areas)?;
+            nodes.insert(node.clone(), sorted_set(areas));
+        }
+        Ok(Self { nodes })
+    }
+}
+
+fn known_area(name: &str) -> Option<Area> {
+    Area::KNOWN
+        .into_iter()
+        .find(|area| area.to_string() == name.to_uppercase())
+}
+
+/// Adds the areas under the last node of the path, which holds the nodes above it to catch cycles
+fn resolve<'a>(
+    children: &'a BTreeMap<String, Vec<String>>,
+    node: &str,
+    path: &mut Vec<&'a str>,
+    areas: &mut Vec<Area>,
+) -> Result<()> {
+    for child in &children[node] {
+        if let Some(area) = known_area(child) {
+            areas.push(area);
+            continue;
+        }
+        if path.contains(&child.as_str()) {
+            bail!(
+                "Area hierarchy has a cycle: {} -> {}",
+                path.join(" -> "),
+                child
+            );
+        }
+        if !children.contains_key(child) {
+            bail!("Unknown area or node {} under {}", child, node);
+        }
+        path.push(child);
+        resolve(children, child, path, areas)?;
+        path.pop();
+    }
+    Ok(())
+}
+
+impl AreaHierarchy {
+    /// The areas under the node, directly or through the nodes under it
+    pub fn areas(&self, node: &str) -> Result<Arc<[Area]>> {
+        self.nodes.get(node).cloned().ok_or(anyhow!(
+            "No node {} in the area hierarchy, nodes are: {}",
+            node,
+            self.nodes.keys().cloned().collect::<Vec<_>>().join(", ")
+        ))
+    }
+
+    /// Selects the areas under the node in report accessors
+    pub fn selection(&self, node: &str) -> Result<AreaSelection> {
+        Ok(AreaSelection::Group(self.areas(node)?))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::report::{Metric, Report};
+    use crate::trade::{MarketSelection, Trade};
+    use chrono::TimeZone;
+    use chrono_tz::Europe::Copenhagen;
+    use rust_decimal_macros::dec;
+
+    #[test]
+    fn nodes_roll_up_the_areas_under_them() {
+        let hierarchy: AreaHierarchy = toml::from_str(
+            r#"
+            denmark = ["DK1", "DK2"]
+            nordics = ["denmark", "SE3"]
+            uk = ["GB"]
+            "#,
+        )
+        .unwrap();
+        assert_eq!(
+            *hierarchy.areas("nordics").unwrap(),
+            [Area::DK1, Area::DK2, Area::SE3]
+        );
+
+        let from = Copenhagen.with_ymd_and_hms(2024, 5, 1, 0, 0, 0).unwrap();
+        let to = Copenhagen.with_ymd_and_hms(2024, 5, 2, 0, 0, 0).unwrap();
+        let report = Report::new(
+            &from,
+            &to,
+            vec![
+                Trade::test().area(Area::DK1).mwh(10).price(40).build(),
+                Trade::test().area(Area::DK2).mwh(5).price(40).build(),
+                Trade::test().area(Area::GB).mwh(1).price(40).build(),
+            ],
+        )
+        .unwrap();
+        let mw_bought = |node| {
+            report.metric(
+                Metric::MwBought,
+                MarketSelection::All,
+                hierarchy.selection(node).unwrap(),
+            )
+        };
+        assert_eq!(mw_bought("nordics"), dec!(15));
+        assert_eq!(mw_bought("uk"), dec!(1));
+
+        let cycle = toml::from_str::<AreaHierarchy>(
+            r#"
+            a = ["b"]
+            b = ["a"]
+            "#,
+        );
+        assert!(cycle
+            .unwrap_err()
+            .to_string()
+            .contains("cycle: a -> b -> a"));
+    }
+}
@@ -0,0 +1,182 @@
+use std::collections::BTreeMap;
+
+use chrono::NaiveDate;
+use chrono_tz::Tz;
+use rust_decimal::Decimal;
+use serde::Deserialize;
+
+use crate::prices::SpotPrices;
+use crate::trade::{Area, Market, Trade};
+
+/// The imbalance settlement prices imbalance trades are costed at, in a table with the columns
+/// `area`, `delivery_start`, `delivery_end` and `price` per imbalance settlement period:
+///
+/// ```toml
+/// [imbalance_cost]
+/// prices_table = "imbalance_prices"
+/// ```
+#[derive(Debug, Deserialize, Clone)]
+pub struct ImbalanceCostConfig {
+    pub prices_table: String,
+}
+
+/// The imbalance of an area on a day, with sells counting negative
+#[derive(Debug, Default, Clone, Copy, PartialEq)]
+struct DayCost {
+    mwh: Decimal,
+    /// Paid for the imbalance at the settlement prices, negative when it was paid to us
+    imbalance_cost: Decimal,
+    /// What the same energy would have cost at the day-ahead prices
+    spot_cost: Decimal,
+    /// Trades left out for lack of a settlement or spot price
+    unpriced_trades: usize,
+}
+
+/// What the imbalance of an area and local delivery day cost, and what it would have cost to
+/// trade out at the day-ahead prices instead
+#[derive(Debug, PartialEq)]
+pub struct ImbalanceCostRow {
+    pub area: Area,
+    pub day: NaiveDate,
+    pub mwh: Decimal,
+    pub imbalance_cost: Decimal,
+    pub spot_cost: Decimal,
+    /// The imbalance cost minus the spot cost, positive when imbalance was more expensive
+    pub cost_vs_spot: Decimal,
+    pub unpriced_trades: usize,
+}
+
+impl ImbalanceCostRow {
+    pub fn record(&self) -> Vec<String> {
+        vec![
+            self.area.to_string(),
+            self.day.to_string(),
+            self.mwh.round_dp(1).to_string(),
+            self.imbalance_cost.round_dp(2).to_string(),
+            self.spot_cost.round_dp(2).to_string(),
+            self.cost_vs_spot.round_dp(2).to_string(),
+            self.unpriced_trades.to_string(),
+        ]
+    }
+}
+
+/// The cost of the imbalance trades at the imbalance settlement prices of their delivery
+/// against its cost at the day-ahead prices, per area and local delivery day. Other trades are
+/// left out, and trades are costed at the settlement prices rather than their own price, which
+/// may be a provisional one.
+#[derive(Debug)]
+pub struct ImbalanceCost<'a> {
+    imbalance_prices: &'a SpotPrices,
+    spot_prices: &'a SpotPrices,
+    timezone: Tz,
+    days: BTreeMap<(Area, NaiveDate), DayCost>,
+}
+
+impl<'a> ImbalanceCost<'a> {
+    pub fn new(
+        imbalance_prices: &'a SpotPrices,
+        spot_prices: &'a SpotPrices,
+        timezone: Tz,
+    ) -> Self {
+        Self {
+            imbalance_prices,
+            spot_prices,
+            timezone,
+            days: BTreeMap::new(),
+        }
+    }
+
+    pub fn add_trade(&mut self, trade: &Trade) {
+        if Market::from(trade.trade_type) != Market::Imbalance {
+            return;
+        }
+        let day = trade
+            .delivery_start
+            .with_timezone(&self.timezone)
+            .date_naive();
+        let cost = self.days.entry((trade.area, day)).or_default();
+        let value = |prices: &SpotPrices| {
+            prices.value(trade.area, &trade.delivery_start, &trade.delivery_end)
+        };
+        let (imbalance, spot) = (value(self.imbalance_prices), value(self.spot_prices));
+        let (Some(imbalance), Some(spot)) = (imbalance, spot) else {
+            cost.unpriced_trades += 1;
+            return;
+        };
+        let hours = Decimal::from((trade.delivery_end - trade.delivery_start).num_seconds())
+            / Decimal::from(3600);
+        cost.mwh += trade.quantity_mwh * hours;
+        cost.imbalance_cost += trade.quantity_mwh * imbalance;
+        cost.spot_cost += trade.quantity_mwh * spot;
+    }
+
+    pub fn rows(&self) -> Vec<ImbalanceCostRow> {
+        self.days
+            .iter()
+            .map(|((area, day), cost)| ImbalanceCostRow {
+                area: *area,
+                day: *day,
+                mwh: cost.mwh,
+                imbalance_cost: cost.imbalance_cost,
+                spot_cost: cost.spot_cost,
+                cost_vs_spot: cost.imbalance_cost - cost.spot_cost,
+                unpriced_trades: cost.unpriced_trades,
+            })
+            .collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::trade::TradeType;
+    use chrono::{DateTime, Duration};
+    use chrono_tz::Europe::Copenhagen;
+    use rust_decimal_macros::dec;
+
+    #[test]
+    fn imbalance_is_costed_against_the_spot_prices_per_day() {
+        let hour = |value: &str| DateTime::parse_from_rfc3339(value).unwrap().to_utc();
+        let (mut imbalance_prices, mut spot_prices) =
+            (SpotPrices::default(), SpotPrices::default());
+        // Settled per quarter hour, two of them at a spike
+        for (quarter, price) in [dec!(40), dec!(40), dec!(200), dec!(200)]
+            .into_iter()
+            .enumerate()
+        {
+            let start = hour("2024-05-01T10:00:00Z") + Duration::minutes(15 * quarter as i64);
+            imbalance_prices.insert(Area::DK1, start, start + Duration::minutes(15), price);
+        }
+        spot_prices.insert(
+            Area::DK1,
+            hour("2024-05-01T10:00:00Z"),
+            hour("2024-05-01T11:00:00Z"),
+            dec!(50),
+        );
+        let imbalance = || Trade::test().trade_type(TradeType::Imbalance).mwh(2);
+        let trades = [
+            imbalance().build(),
+            // Not settled yet
+            imbalance().hour("2024-05-01T13:00+02:00").build(),
+            // Intraday trades aren't imbalance
+            Trade::test().mwh(10).price(50).build(),
+        ];
+        let mut cost = ImbalanceCost::new(&imbalance_prices, &spot_prices, Copenhagen);
+        for trade in trades.iter() {
+            cost.add_trade(trade);
+        }
+
+        assert_eq!(
+            cost.rows(),
+            vec![ImbalanceCostRow {
+                area: Area::DK1,
+                day: NaiveDate::from_ymd_opt(2024, 5, 1).unwrap(),
+                mwh: dec!(2),
+                imbalance_cost: dec!(240),
+                spot_cost: dec!(100),
+                cost_vs_spot: dec!(140),
+                unpriced_trades: 1,
+            }]
+        );
+    }
+}
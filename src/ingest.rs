@@ -0,0 +1,258 @@
+use std::collections::BTreeSet;
+use std::fs::File;
+use std::io::Read;
+use std::path::Path;
+use std::str::FromStr;
+
+use anyhow::{Context, Result};
+use chrono::{DateTime, FixedOffset};
+use rust_decimal::Decimal;
+use serde::Deserialize;
+
+use crate::report::contract_length;
+use crate::trade::TradeTable;
+use crate::trade::{Area, CounterPart, Currency, Market, Trade, TradeSide, TradeType};
+use crate::units::QuantityUnits;
+
+pub mod csv;
+#[cfg(feature = "kafka")]
+pub mod kafka;
+pub mod ndjson;
+
+/// A row of an exchange file, with the columns of the trade tables except `id`.
+/// Fields are read as text, so every problem of a row is reported rather than only the first.
+#[derive(Debug, Deserialize)]
+struct IngestRow {
+    area: String,
+    counter_part: String,
+    currency: String,
+    delivery_start: String,
+    delivery_end: String,
+    price: Option<String>,
+    quantity_mwh: String,
+    trade_side: String,
+    trade_type: String,
+}
+
+/// The parts of a valid row the summary is computed from
+struct ValidRow {
+    delivery_start: DateTime<FixedOffset>,
+    delivery_end: DateTime<FixedOffset>,
+    /// Energy in MWh, after normalizing the quantity from the unit of its table
+    volume_mwh: Decimal,
+}
+
+#[derive(Debug)]
+pub struct RowError {
+    /// Line in the file, counting the header as line 1
+    pub line: u64,
+    pub problems: Vec<String>,
+}
+
+/// Outcome of parsing an exchange file without writing it to the database
+#[derive(Debug, Default)]
+pub struct ValidationSummary {
+    pub rows: usize,
+    pub errors: Vec<RowError>,
+    /// Absolute energy of the valid rows
+    pub volume_mwh: Decimal,
+    pub delivery_from: Option<DateTime<FixedOffset>>,
+    pub delivery_to: Option<DateTime<FixedOffset>>,
+    pub unknown_areas: BTreeSet<String>,
+}
+
+impl ValidationSummary {
+    pub fn is_valid(&self) -> bool {
+        self.errors.is_empty()
+    }
+
+    pub fn print(&self) {
+        for error in &self.errors {
+            println!("line {}: {}", error.line, error.problems.join("; "));
+        }
+        println!("Rows: {}", self.rows);
+        println!("Invalid rows: {}", self.errors.len());
+        println!("Volume (MWh): {}", self.volume_mwh);
+        match (self.delivery_from, self.delivery_to) {
+            (Some(from), Some(to)) => println!("Delivery: {} - {}", from, to),
+            _ => println!("Delivery: -"),
+        }
+        if !self.unknown_areas.is_empty() {
+            let areas: Vec<&str> = self.unknown_areas.iter().map(String::as_str).collect();
+            println!("Unknown areas: {}", areas.join(", "));
+        }
+    }
+
+    fn add_row(&mut self, row: ValidRow) {
+        self.volume_mwh += row.volume_mwh;
+        self.delivery_from = Some(match self.delivery_from {
+            Some(from) => from.min(row.delivery_start),
+            None => row.delivery_start,
+        });
+        self.delivery_to = Some(match self.delivery_to {
+            Some(to) => to.max(row.delivery_end),
+            None => row.delivery_end,
+        });
+    }
+}
+
+pub fn validate_file(path: &Path, units: &QuantityUnits) -> Result<ValidationSummary> {
+    let file = File::open(path).with_context(|| format!("Could not open {}", path.display()))?;
+    validate(file, units)
+}
+
+/// Parses a CSV exchange file completely, collecting the problems of every row
+pub fn validate<R: Read>(reader: R, units: &QuantityUnits) -> Result<ValidationSummary> {
+    let mut reader = ::csv::Reader::from_reader(reader);
+    let headers = reader
+        .headers()
+        .context("Could not read header of exchange file")?
+        .clone();
+    let mut summary = ValidationSummary::default();
+
+    for record in reader.records() {
+        let record = record.context("Could not read exchange file")?;
+        let line = record.position().map_or(0, |position| position.line());
+        summary.rows += 1;
+
+        let row: IngestRow = match record.deserialize(Some(&headers)) {
+            Ok(row) => row,
+            Err(err) => {
+                summary.errors.push(RowError {
+                    line,
+                    problems: vec![err.to_string()],
+                });
+                continue;
+            }
+        };
+        if Area::from_str(&row.area).is_err() {
+            summary.unknown_areas.insert(row.area.clone());
+        }
+        match validate_row(&row, units) {
+            Ok(row) => summary.add_row(row),
+            Err(problems) => summary.errors.push(RowError { line, problems }),
+        }
+    }
+
+    Ok(summary)
+}
+
+fn validate_row(row: &IngestRow, units: &QuantityUnits) -> Result<ValidRow, Vec<String>> {
+    let mut problems = Vec::new();
+
+    if Area::from_str(&row.area).is_err() {
+        problems.push(format!("unknown area {}", row.area));
+    }
+    if CounterPart::from_str(&row.counter_part).is_err() {
+        problems.push(format!("unknown counter part {}", row.counter_part));
+    }
+    if Currency::from_str(&row.currency).is_err() {
+        problems.push(format!("unknown currency {}", row.currency));
+    }
+    if TradeSide::from_str(&row.trade_side).is_err() {
+        problems.push(format!("unknown trade side {}", row.trade_side));
+    }
+    let trade_type = TradeType::from_str(&row.trade_type)
+        .map_err(|_| problems.push(format!("unknown trade type {}", row.trade_type)))
+        .ok();
+    let delivery_start = DateTime::parse_from_rfc3339(&row.delivery_start)
+        .map_err(|err| problems.push(format!("invalid delivery_start: {}", err)))
+        .ok();
+    let delivery_end = DateTime::parse_from_rfc3339(&row.delivery_end)
+        .map_err(|err| problems.push(format!("invalid delivery_end: {}", err)))
+        .ok();
+    if let Some(price) = row.price.as_deref().filter(|price| !price.is_empty()) {
+        if let Err(err) = Decimal::from_str(price) {
+            problems.push(format!("invalid price: {}", err));
+        }
+    }
+    let quantity = Decimal::from_str(&row.quantity_mwh)
+        .map_err(|err| problems.push(format!("invalid quantity_mwh: {}", err)))
+        .ok();
+
+    let (Some(trade_type), Some(delivery_start), Some(delivery_end), Some(quantity)) =
+        (trade_type, delivery_start, delivery_end, quantity)
+    else {
+        return Err(problems);
+    };
+
+    if delivery_end <= delivery_start {
+        problems.push(format!(
+            "delivery_end {} is not after delivery_start {}",
+            delivery_end, delivery_start
+        ));
+        return Err(problems);
+    }
+    if let Some(product_length) = trade_type.product_length() {
+        if delivery_end - delivery_start != product_length {
+            problems.push(format!(
+                "{} trade does not have the product length of {} minutes",
+                trade_type,
+                product_length.num_minutes()
+            ));
+        }
+    }
+    if !problems.is_empty() {
+        return Err(problems);
+    }
+
+    let table = TradeTable::from(Market::from(trade_type));
+    let volume_mwh = units
+        .normalize(table, quantity, &delivery_start, &delivery_end)
+        .and_then(|mw| Ok(mw.abs() * contract_length(&delivery_start, &delivery_end)?))
+        .map_err(|err| vec![err.to_string()])?;
+
+    Ok(ValidRow {
+        delivery_start,
+        delivery_end,
+        volume_mwh,
+    })
+}
+
+/// Converts the quantity of a trade read from a file from the unit and convention configured for
+/// the table of its trade type, like trades fetched from that table
+fn normalize_quantity(trade: &mut Trade, units: &QuantityUnits) -> Result<()> {
+    trade.quantity_mwh = units.normalize(
+        TradeTable::from(Market::from(trade.trade_type)),
+        units
+            .convention()
+            .signed(trade.quantity_mwh, trade.trade_side),
+        &trade.delivery_start,
+        &trade.delivery_end,
+    )?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use rust_decimal_macros::dec;
+
+    #[test]
+    fn every_invalid_row_is_reported_without_stopping() {
+        let file = "\
+area,counter_part,currency,delivery_start,delivery_end,price,quantity_mwh,trade_side,trade_type
+GB,epex,GBP,2024-05-01T12:00:00+01:00,2024-05-01T12:30:00+01:00,50,10,buy,auction_gb_dah_hh
+XX,epex,EUR,2024-05-01T12:00:00+01:00,2024-05-01T13:00:00+01:00,50,10,buy,intraday
+GB,epex,GBP,2024-05-01T12:00:00+01:00,2024-05-01T13:00:00+01:00,,-4,sell,auction_gb_dah_hh
+DK1,nordpool,EUR,2024-05-02T00:00:00+02:00,2024-05-02T01:00:00+02:00,,-2,sell,intraday
+";
+        let summary = validate(file.as_bytes(), &QuantityUnits::default()).unwrap();
+
+        assert_eq!(summary.rows, 4);
+        assert_eq!(
+            summary
+                .errors
+                .iter()
+                .map(|error| error.line)
+                .collect::<Vec<_>>(),
+            vec![3, 4]
+        );
+        assert_eq!(summary.volume_mwh, dec!(7));
+        assert_eq!(summary.unknown_areas, BTreeSet::from(["XX".to_string()]));
+        assert_eq!(
+            summary.delivery_to,
+            DateTime::parse_from_rfc3339("2024-05-02T01:00:00+02:00").ok()
+        );
+    }
+}
@@ -0,0 +1,270 @@
+use std::fs::File;
+use std::io::Read;
+use std::path::Path;
+use std::str::FromStr;
+
+use anyhow::{anyhow, bail, Context, Result};
+use chrono::DateTime;
+use chrono_tz::Tz;
+use rust_decimal::Decimal;
+use serde::Deserialize;
+
+use crate::ingest::normalize_quantity;
+use crate::report::Report;
+use crate::trade::{Area, CounterPart, Currency, Trade, TradeForReport, TradeSide, TradeType};
+use crate::units::QuantityUnits;
+
+/// Which columns of a trade export hold the fields of a trade, the columns of the trade tables
+/// by default. Exports of other layouts are mapped in the config, e.g.
+///
+/// ```toml
+/// [csv_mapping]
+/// delimiter = ";"
+/// id = "TradeId"
+/// area = "DeliveryArea"
+/// quantity_mwh = "Volume"
+/// ```
+///
+/// Values are read like in the trade tables: areas like `DK1`, currencies like `EUR`, sides like `buy`, trade types like
+/// `intraday` and delivery times in RFC 3339. Quantities are in the unit configured for the table
+/// of the trade type, and normalized like trades from the database.
+#[derive(Debug, Deserialize, Clone)]
+#[serde(default)]
+pub struct CsvMapping {
+    pub delimiter: char,
+    /// Column of the trade ids, trades are numbered by their line in the file if not given
+    pub id: Option<String>,
+    pub area: String,
+    pub counter_part: String,
+    pub currency: String,
+    pub delivery_start: String,
+    pub delivery_end: String,
+    /// Column of the prices, trades without a price leave it empty
+    pub price: String,
+    pub quantity_mwh: String,
+    pub trade_side: String,
+    pub trade_type: String,
+}
+
+impl Default for CsvMapping {
+    fn default() -> Self {
+        Self {
+            delimiter: ',',
+            id: None,
+            area: "area".to_string(),
+            counter_part: "counter_part".to_string(),
+            currency: "currency".to_string(),
+            delivery_start: "delivery_start".to_string(),
+            delivery_end: "delivery_end".to_string(),
+            price: "price".to_string(),
+            quantity_mwh: "quantity_mwh".to_string(),
+            trade_side: "trade_side".to_string(),
+            trade_type: "trade_type".to_string(),
+        }
+    }
+}
+
+/// Indices of the mapped columns in the header of a file
+struct Columns {
+    id: Option<usize>,
+    area: usize,
+    counter_part: usize,
+    currency: usize,
+    delivery_start: usize,
+    delivery_end: usize,
+    price: usize,
+    quantity_mwh: usize,
+    trade_side: usize,
+    trade_type: usize,
+}
+
+impl Columns {
+    fn new(headers: &::csv::StringRecord, mapping: &CsvMapping) -> Result<Self> {
+        let mut missing = Vec::new();
+        let mut index = |column: &str| {
+            let index = headers.iter().position(|header| header.trim() == column);
+            if index.is_none() {
+                missing.push(column.to_string());
+            }
+            index.unwrap_or_default()
+        };
+        let columns = Self {
+            id: None,
+            area: index(&mapping.area),
+            counter_part: index(&mapping.counter_part),
+            currency: index(&mapping.currency),
+            delivery_start: index(&mapping.delivery_start),
+            delivery_end: index(&mapping.delivery_end),
+            price: index(&mapping.price),
+            quantity_mwh: index(&mapping.quantity_mwh),
+            trade_side: index(&mapping.trade_side),
+            trade_type: index(&mapping.trade_type),
+        };
+        let id = mapping.id.as_deref().map(&mut index);
+        if !missing.is_empty() {
+            bail!("Missing columns {}", missing.join(", "));
+        }
+        Ok(Self { id, ..columns })
+    }
+
+    fn trade(
+        &self,
+        record: &::csv::StringRecord,
+        line: u64,
+        units: &QuantityUnits,
+    ) -> Result<Trade> {
+        let field = |index: usize| record.get(index).unwrap_or_default().trim();
+        let parse = |index: usize, name: &str| -> Result<Decimal> {
+            Decimal::from_str(field(index)).with_context(|| format!("Invalid {}", name))
+        };
+        let time = |index: usize, name: &str| {
+            DateTime::parse_from_rfc3339(field(index)).with_context(|| format!("Invalid {}", name))
+        };
+
+        let id = match self.id {
+            Some(index) => field(index).parse().context("Invalid id")?,
+            None => line as i64,
+        };
+        let trade_type = TradeType::from_str(field(self.trade_type))
+            .map_err(|_| anyhow!("Unknown trade type {}", field(self.trade_type)))?;
+        let price = match field(self.price) {
+            "" => None,
+            _ => Some(parse(self.price, "price")?),
+        };
+        let mut trade = Trade {
+            id,
+            area: Area::try_from(field(self.area).to_string())?,
+            counter_part: CounterPart::from_str(field(self.counter_part))?,
+            currency: Currency::try_from(field(self.currency).to_string())?,
+            delivery_start: time(self.delivery_start, "delivery_start")?,
+            delivery_end: time(self.delivery_end, "delivery_end")?,
+            price,
+            quantity_mwh: parse(self.quantity_mwh, "quantity_mwh")?,
+            trade_side: TradeSide::from_str(field(self.trade_side))
+                .map_err(|_| anyhow!("Unknown trade side {}", field(self.trade_side)))?,
+            trade_type,
+        };
+        normalize_quantity(&mut trade, units)?;
+        Ok(trade)
+    }
+}
+
+/// Reads the trades of a CSV export, failing on the first row that isn't a valid trade. Unlike
+/// `ingest::validate`, the trades are kept, to aggregate them without the database.
+pub fn read_trades<R: Read>(
+    reader: R,
+    mapping: &CsvMapping,
+    units: &QuantityUnits,
+) -> Result<Vec<Trade>> {
+    let delimiter = u8::try_from(mapping.delimiter)
+        .map_err(|_| anyhow!("Delimiter {} is not a single byte", mapping.delimiter))?;
+    let mut reader = ::csv::ReaderBuilder::new()
+        .delimiter(delimiter)
+        .from_reader(reader);
+    let headers = reader.headers().context("Could not read header")?.clone();
+    let columns = Columns::new(&headers, mapping)?;
+
+    reader
+        .records()
+        .map(|record| {
+            let record = record.context("Could not read trade export")?;
+            let line = record.position().map_or(0, |position| position.line());
+            columns
+                .trade(&record, line, units)
+                .with_context(|| format!("Invalid trade on line {}", line))
+        })
+        .collect()
+}
+
+/// Like `read_trades`, with only the fields of the trades a report needs
+pub fn read_trades_for_report<R: Read>(
+    reader: R,
+    mapping: &CsvMapping,
+    units: &QuantityUnits,
+) -> Result<Vec<TradeForReport>> {
+    Ok(read_trades(reader, mapping, units)?
+        .into_iter()
+        .map(|trade| TradeForReport {
+            area: trade.area,
+            currency: trade.currency,
+            delivery_end: trade.delivery_end,
+            delivery_start: trade.delivery_start,
+            price: trade.price,
+            quantity_mwh: trade.quantity_mwh,
+            trade_side: trade.trade_side,
+            trade_type: trade.trade_type,
+        })
+        .collect())
+}
+
+/// The report of the trades in a CSV export
+pub fn read_report_file(
+    path: &Path,
+    mapping: &CsvMapping,
+    units: &QuantityUnits,
+    delivery_from: &DateTime<Tz>,
+    delivery_to: &DateTime<Tz>,
+) -> Result<Report> {
+    let file = File::open(path).with_context(|| format!("Could not open {}", path.display()))?;
+    let trades = read_trades_for_report(file, mapping, units)
+        .with_context(|| format!("Invalid trade export {}", path.display()))?;
+    Report::new_from_trade_for_report(delivery_from, delivery_to, trades)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::report::Metric;
+    use crate::trade::{AreaSelection, MarketSelection};
+    use chrono::TimeZone;
+    use chrono_tz::Europe::Copenhagen;
+    use rust_decimal_macros::dec;
+
+    #[test]
+    fn mapped_columns_are_read_into_trades() {
+        let export = "\
+TradeId;Zone;Exchange;Ccy;Start;End;Price;Volume;Side;Product
+17;DK1;nordpool;EUR;2024-05-01T12:00:00+02:00;2024-05-01T13:00:00+02:00;40;10;buy;intraday
+18;DK1;nordpool;EUR;2024-05-01T13:00:00+02:00;2024-05-01T14:00:00+02:00;50.5;-4;sell;intraday
+19;DK2;nordpool;EUR;2024-05-01T13:00:00+02:00;2024-05-01T14:00:00+02:00;;-2;sell;intraday
+";
+        let mapping = CsvMapping {
+            delimiter: ';',
+            id: Some("TradeId".to_string()),
+            area: "Zone".to_string(),
+            counter_part: "Exchange".to_string(),
+            currency: "Ccy".to_string(),
+            delivery_start: "Start".to_string(),
+            delivery_end: "End".to_string(),
+            price: "Price".to_string(),
+            quantity_mwh: "Volume".to_string(),
+            trade_side: "Side".to_string(),
+            trade_type: "Product".to_string(),
+        };
+        let units = QuantityUnits::default();
+
+        let trades = read_trades(export.as_bytes(), &mapping, &units).unwrap();
+        assert_eq!(
+            trades.iter().map(|trade| trade.id).collect::<Vec<_>>(),
+            vec![17, 18, 19]
+        );
+        assert_eq!(trades[2].price, None);
+
+        let from = Copenhagen.with_ymd_and_hms(2024, 5, 1, 0, 0, 0).unwrap();
+        let to = Copenhagen.with_ymd_and_hms(2024, 5, 2, 0, 0, 0).unwrap();
+        let trades = read_trades_for_report(export.as_bytes(), &mapping, &units).unwrap();
+        let report = Report::new_from_trade_for_report(&from, &to, trades).unwrap();
+        assert_eq!(report.skipped_trades(), 1);
+        assert_eq!(
+            report.metric(
+                Metric::GrossProfit,
+                MarketSelection::All,
+                AreaSelection::All
+            ),
+            dec!(-198)
+        );
+
+        let error = read_trades(export.as_bytes(), &CsvMapping::default(), &units).unwrap_err();
+        assert!(error.to_string().starts_with("Missing columns area, "));
+    }
+}
@@ -0,0 +1,160 @@
+use anyhow::{anyhow, Context, Result};
+use chrono::DateTime;
+use chrono_tz::Tz;
+use rdkafka::config::ClientConfig;
+use rdkafka::consumer::{Consumer, StreamConsumer};
+use rdkafka::Message;
+use serde::Deserialize;
+
+use crate::cancel::{CancellationToken, Cancelled};
+use crate::ingest::ndjson::JsonMapping;
+use crate::ingest::normalize_quantity;
+use crate::report::Report;
+use crate::trade::Trade;
+use crate::units::QuantityUnits;
+
+/// Where trade events are consumed from, e.g.
+///
+/// ```toml
+/// [kafka]
+/// brokers = "kafka-1:9092,kafka-2:9092"
+/// topic = "trades"
+/// group_id = "trading-aggregator"
+/// ```
+#[derive(Debug, Deserialize, Clone)]
+#[serde(default)]
+pub struct KafkaConfig {
+    /// Comma separated bootstrap servers
+    pub brokers: String,
+    pub topic: String,
+    /// Consumer group the offsets are committed for. Consumers of the same group share the
+    /// partitions of the topic, so give every live report a group of its own.
+    pub group_id: String,
+    /// Where a group without committed offsets starts, `earliest` or `latest`
+    pub auto_offset_reset: String,
+    /// How often the offsets of aggregated events are committed
+    pub commit_interval_ms: u32,
+}
+
+impl Default for KafkaConfig {
+    fn default() -> Self {
+        Self {
+            brokers: "localhost:9092".to_string(),
+            topic: "trades".to_string(),
+            group_id: "trading-aggregator".to_string(),
+            auto_offset_reset: "earliest".to_string(),
+            commit_interval_ms: 5000,
+        }
+    }
+}
+
+/// Consumes trade events, a trade per message serialized like `Trade` in JSON or renamed by the
+/// `JsonMapping`, into a report
+pub struct TradeConsumer {
+    consumer: StreamConsumer,
+    mapping: JsonMapping,
+    units: QuantityUnits,
+}
+
+impl TradeConsumer {
+    /// Subscribes to the topic. Offsets are only stored once the trade of an event is in the
+    /// report, and committed every `commit_interval_ms`, so committed offsets never pass events
+    /// that weren't aggregated.
+    pub fn subscribe(
+        config: &KafkaConfig,
+        mapping: JsonMapping,
+        units: QuantityUnits,
+    ) -> Result<Self> {
+        let consumer: StreamConsumer = ClientConfig::new()
+            .set("bootstrap.servers", &config.brokers)
+            .set("group.id", &config.group_id)
+            .set("auto.offset.reset", &config.auto_offset_reset)
+            .set("enable.auto.commit", "true")
+            .set(
+                "auto.commit.interval.ms",
+                config.commit_interval_ms.to_string(),
+            )
+            .set("enable.auto.offset.store", "false")
+            .create()
+            .context("Could not create Kafka consumer")?;
+        consumer
+            .subscribe(&[&config.topic])
+            .with_context(|| format!("Could not subscribe to Kafka topic {}", config.topic))?;
+        Ok(Self {
+            consumer,
+            mapping,
+            units,
+        })
+    }
+
+    /// Adds the trades of the events delivered in the window to the report until the token is
+    /// cancelled, failing with `Cancelled`. `on_update` is called after every trade added.
+    /// Events that aren't valid trades are reported and skipped, as they'd otherwise block the
+    /// partition for good.
+    pub async fn consume<F>(
+        &self,
+        report: &mut Report,
+        delivery_from: &DateTime<Tz>,
+        delivery_to: &DateTime<Tz>,
+        cancellation: &CancellationToken,
+        mut on_update: F,
+    ) -> Result<()>
+    where
+        F: FnMut(&Report),
+    {
+        loop {
+            let message = tokio::select! {
+                _ = cancellation.cancelled() => return Err(Cancelled.into()),
+                message = self.consumer.recv() => message?,
+            };
+            let added =
+                decode_trade(message.payload(), &self.mapping, &self.units).and_then(|trade| {
+                    let in_window = trade.delivery_start >= *delivery_from
+                        && trade.delivery_start < *delivery_to;
+                    if in_window {
+                        report.add_trade(&trade)?;
+                    }
+                    Ok(in_window)
+                });
+            match added {
+                Ok(true) => on_update(report),
+                Ok(false) => {}
+                Err(err) => eprintln!(
+                    "Warning: skipped event at offset {} of partition {}: {:#}",
+                    message.offset(),
+                    message.partition(),
+                    err
+                ),
+            }
+            self.consumer.store_offset_from_message(&message)?;
+        }
+    }
+}
+
+fn decode_trade(
+    payload: Option<&[u8]>,
+    mapping: &JsonMapping,
+    units: &QuantityUnits,
+) -> Result<Trade> {
+    let payload = payload.ok_or(anyhow!("Event without a payload"))?;
+    let mut trade = mapping.trade(payload).context("Invalid trade")?;
+    normalize_quantity(&mut trade, units)?;
+    Ok(trade)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use rust_decimal_macros::dec;
+
+    #[test]
+    fn events_are_decoded_like_json_lines() {
+        let (mapping, units) = (JsonMapping::default(), QuantityUnits::default());
+        let event = serde_json::to_vec(&Trade::test().mwh(10).price(40).build()).unwrap();
+        let trade = decode_trade(Some(&event), &mapping, &units).unwrap();
+        assert_eq!(trade.quantity_mwh, dec!(10));
+
+        assert!(decode_trade(None, &mapping, &units).is_err());
+        assert!(decode_trade(Some(b"{}"), &mapping, &units).is_err());
+    }
+}
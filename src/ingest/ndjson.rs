@@ -0,0 +1,209 @@
+use std::collections::HashMap;
+use std::fs::File;
+use std::io::{BufRead, BufReader};
+use std::path::Path;
+
+use anyhow::{bail, Context, Result};
+use chrono::DateTime;
+use chrono_tz::Tz;
+use serde::Deserialize;
+use serde_json::Value;
+
+use crate::ingest::normalize_quantity;
+use crate::report::Report;
+use crate::trade::Trade;
+use crate::units::QuantityUnits;
+
+/// The fields of `Trade`, which fields of upstream JSON can be renamed to
+const TRADE_FIELDS: [&str; 10] = [
+    "id",
+    "area",
+    "counter_part",
+    "currency",
+    "delivery_start",
+    "delivery_end",
+    "price",
+    "quantity_mwh",
+    "trade_side",
+    "trade_type",
+];
+
+/// Names upstream systems give the fields of trades in JSON, renamed to the fields of `Trade`
+/// before decoding, so trades of any source are read without a struct of their own, e.g.
+///
+/// ```toml
+/// [json_mapping]
+/// deliveryStart = "delivery_start"
+/// deliveryEnd = "delivery_end"
+/// qty_mwh = "quantity_mwh"
+/// ```
+///
+/// Fields that aren't renamed are read as they are.
+#[derive(Debug, Default, Deserialize, Clone)]
+#[serde(try_from = "HashMap<String, String>")]
+pub struct JsonMapping {
+    renames: HashMap<String, String>,
+}
+
+impl TryFrom<HashMap<String, String>> for JsonMapping {
+    type Error = anyhow::Error;
+
+    fn try_from(renames: HashMap<String, String>) -> Result<Self> {
+        for (field, renamed) in renames.iter() {
+            if !TRADE_FIELDS.contains(&renamed.as_str()) {
+                bail!(
+                    "{} is mapped to {}, which is not a field of trades: {}",
+                    field,
+                    renamed,
+                    TRADE_FIELDS.join(", ")
+                );
+            }
+        }
+        Ok(Self { renames })
+    }
+}
+
+impl JsonMapping {
+    /// Decodes a trade from JSON, with the fields renamed
+    pub fn trade(&self, json: &[u8]) -> Result<Trade> {
+        if self.renames.is_empty() {
+            return Ok(serde_json::from_slice(json)?);
+        }
+        let mut value: Value = serde_json::from_slice(json)?;
+        if let Value::Object(fields) = &mut value {
+            for (field, renamed) in self.renames.iter() {
+                if let Some(field_value) = fields.remove(field) {
+                    fields.insert(renamed.clone(), field_value);
+                }
+            }
+        }
+        Ok(serde_json::from_value(value)?)
+    }
+}
+
+/// The trades of newline-delimited JSON, a trade per line as `Trade` is serialized, e.g.
+///
+/// ```json
+/// {"id":1,"area":"DK1","counter_part":"nordpool","currency":"EUR","delivery_start":"2024-05-01T12:00:00+02:00","delivery_end":"2024-05-01T13:00:00+02:00","price":"40","quantity_mwh":"10","trade_side":"Buy","trade_type":"Intraday"}
+/// ```
+///
+/// or with the fields renamed by the mapping. Blank lines are skipped. Trades are read as they're iterated, so input piped from another
+/// tool is aggregated while it's still being written, and quantities are normalized like in
+/// `csv::read_trades`.
+pub fn read_trades<'a, R: BufRead + 'a>(
+    reader: R,
+    mapping: &'a JsonMapping,
+    units: &'a QuantityUnits,
+) -> impl Iterator<Item = Result<Trade>> + 'a {
+    reader
+        .lines()
+        .enumerate()
+        .filter(|(_, line)| line.as_ref().map_or(true, |line| !line.trim().is_empty()))
+        .map(move |(index, line)| {
+            let line_number = index + 1;
+            let mut trade = mapping
+                .trade(line?.as_bytes())
+                .with_context(|| format!("Invalid trade on line {}", line_number))?;
+            normalize_quantity(&mut trade, units)
+                .with_context(|| format!("Invalid trade on line {}", line_number))?;
+            Ok(trade)
+        })
+}
+
+/// The report of the trades read from newline-delimited JSON, adding them one by one
+pub fn read_report<R: BufRead>(
+    reader: R,
+    mapping: &JsonMapping,
+    units: &QuantityUnits,
+    delivery_from: &DateTime<Tz>,
+    delivery_to: &DateTime<Tz>,
+) -> Result<Report> {
+    let mut report = Report::empty(delivery_from, delivery_to)?;
+    for trade in read_trades(reader, mapping, units) {
+        report.add_trade(&trade?)?;
+    }
+    Ok(report)
+}
+
+pub fn read_report_file(
+    path: &Path,
+    mapping: &JsonMapping,
+    units: &QuantityUnits,
+    delivery_from: &DateTime<Tz>,
+    delivery_to: &DateTime<Tz>,
+) -> Result<Report> {
+    let file = File::open(path).with_context(|| format!("Could not open {}", path.display()))?;
+    read_report(
+        BufReader::new(file),
+        mapping,
+        units,
+        delivery_from,
+        delivery_to,
+    )
+    .with_context(|| format!("Invalid trades in {}", path.display()))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::report::Metric;
+    use crate::trade::{AreaSelection, MarketSelection};
+    use chrono::TimeZone;
+    use chrono_tz::Europe::Copenhagen;
+    use rust_decimal_macros::dec;
+
+    #[test]
+    fn serialized_trades_are_read_back_line_by_line() {
+        let trades = [
+            Trade::test().mwh(10).price(40).build(),
+            Trade::test().sell().mwh(4).price(50).build(),
+        ];
+        let mut lines: Vec<String> = trades
+            .iter()
+            .map(|trade| serde_json::to_string(trade).unwrap())
+            .collect();
+        lines.insert(1, String::new());
+        let input = lines.join("\n");
+
+        let (mapping, units) = (JsonMapping::default(), QuantityUnits::default());
+        let read: Vec<Trade> = read_trades(input.as_bytes(), &mapping, &units)
+            .collect::<Result<_>>()
+            .unwrap();
+        assert_eq!(read.len(), 2);
+        assert_eq!(read[1].quantity_mwh, dec!(-4));
+
+        let from = Copenhagen.with_ymd_and_hms(2024, 5, 1, 0, 0, 0).unwrap();
+        let to = Copenhagen.with_ymd_and_hms(2024, 5, 2, 0, 0, 0).unwrap();
+        let report = read_report(input.as_bytes(), &mapping, &units, &from, &to).unwrap();
+        assert_eq!(
+            report.metric(
+                Metric::GrossProfit,
+                MarketSelection::All,
+                AreaSelection::All
+            ),
+            dec!(-200)
+        );
+
+        let error = read_report("{\"id\":1}".as_bytes(), &mapping, &units, &from, &to).unwrap_err();
+        assert_eq!(error.to_string(), "Invalid trade on line 1");
+    }
+
+    #[test]
+    fn upstream_fields_are_renamed_before_decoding() {
+        let mapping: JsonMapping = toml::from_str(
+            r#"
+            deliveryStart = "delivery_start"
+            deliveryEnd = "delivery_end"
+            qty_mwh = "quantity_mwh"
+            "#,
+        )
+        .unwrap();
+        let upstream = r#"{"id":7,"area":"DK1","counter_part":"nordpool","currency":"EUR","deliveryStart":"2024-05-01T12:00:00+02:00","deliveryEnd":"2024-05-01T13:00:00+02:00","price":"40","qty_mwh":"10","trade_side":"Buy","trade_type":"Intraday"}"#;
+        let trade = mapping.trade(upstream.as_bytes()).unwrap();
+        assert_eq!((trade.id, trade.quantity_mwh), (7, dec!(10)));
+        assert!(JsonMapping::default().trade(upstream.as_bytes()).is_err());
+
+        let unknown = toml::from_str::<JsonMapping>(r#"qty = "quantity""#);
+        assert!(unknown.is_err());
+    }
+}
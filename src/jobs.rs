@@ -0,0 +1,280 @@
+use std::collections::BTreeMap;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::{Arc, Mutex, PoisonError};
+use std::time::Instant;
+
+use anyhow::{bail, Context, Result};
+use chrono::{DateTime, FixedOffset};
+use chrono_tz::Tz;
+use futures::StreamExt;
+use reqwest::StatusCode;
+use serde::{Deserialize, Serialize};
+
+use crate::bounds::describe_window;
+use crate::cancel::CancellationToken;
+use crate::db::TradeStream;
+
+/// A report being built by a long-running service, e.g. for a request to the report server
+#[derive(Debug)]
+struct RunningJob {
+    strategy: String,
+    delivery_from: DateTime<Tz>,
+    delivery_to: DateTime<Tz>,
+    started: Instant,
+    rows: Arc<AtomicU64>,
+    cancellation: CancellationToken,
+}
+
+impl RunningJob {
+    fn status(&self, id: u64) -> JobStatus {
+        JobStatus {
+            id,
+            strategy: self.strategy.clone(),
+            delivery_from: self.delivery_from.fixed_offset(),
+            delivery_to: self.delivery_to.fixed_offset(),
+            window: describe_window(&self.delivery_from, &self.delivery_to),
+            rows: self.rows.load(Ordering::Relaxed),
+            elapsed_seconds: self.started.elapsed().as_secs_f64(),
+            cancelled: self.cancellation.is_cancelled(),
+        }
+    }
+}
+
+/// A running job as listed by the admin API of the report server
+#[derive(Debug, Serialize, Deserialize, PartialEq)]
+pub struct JobStatus {
+    pub id: u64,
+    pub strategy: String,
+    pub delivery_from: DateTime<FixedOffset>,
+    pub delivery_to: DateTime<FixedOffset>,
+    /// The window as described by `bounds::describe_window`
+    pub window: String,
+    /// Trades aggregated so far
+    pub rows: u64,
+    pub elapsed_seconds: f64,
+    /// Whether the job was cancelled and is stopping at its next trade
+    pub cancelled: bool,
+}
+
+impl JobStatus {
+    pub fn record(&self) -> Vec<String> {
+        vec![
+            self.id.to_string(),
+            self.strategy.clone(),
+            self.window.clone(),
+            self.rows.to_string(),
+            format!("{:.1}", self.elapsed_seconds),
+            self.cancelled.to_string(),
+        ]
+    }
+}
+
+#[derive(Debug, Default)]
+struct Jobs {
+    last_id: u64,
+    running: BTreeMap<u64, RunningJob>,
+}
+
+/// The report jobs running in the process, so operators can see what a service is busy with
+/// and cancel a runaway job without restarting it. Clones share the jobs.
+#[derive(Debug, Clone, Default)]
+pub struct JobRegistry {
+    jobs: Arc<Mutex<Jobs>>,
+}
+
+impl JobRegistry {
+    /// Registers a job until the returned `Job` is dropped, whether it finished or failed
+    pub fn start(
+        &self,
+        strategy: &str,
+        delivery_from: &DateTime<Tz>,
+        delivery_to: &DateTime<Tz>,
+    ) -> Job {
+        let mut jobs = self.lock();
+        jobs.last_id += 1;
+        let id = jobs.last_id;
+        let rows = Arc::new(AtomicU64::new(0));
+        let cancellation = CancellationToken::new();
+        jobs.running.insert(
+            id,
+            RunningJob {
+                strategy: strategy.to_string(),
+                delivery_from: *delivery_from,
+                delivery_to: *delivery_to,
+                started: Instant::now(),
+                rows: rows.clone(),
+                cancellation: cancellation.clone(),
+            },
+        );
+        Job {
+            id,
+            rows,
+            cancellation,
+            registry: self.clone(),
+        }
+    }
+
+    /// The running jobs, oldest first
+    pub fn list(&self) -> Vec<JobStatus> {
+        let jobs = self.lock();
+        jobs.running
+            .iter()
+            .map(|(id, job)| job.status(*id))
+            .collect()
+    }
+
+    pub fn inspect(&self, id: u64) -> Option<JobStatus> {
+        self.lock().running.get(&id).map(|job| job.status(id))
+    }
+
+    /// Cancels a running job, which fails with `cancel::Cancelled` at its next trade and is
+    /// listed until then. `None` if no such job is running.
+    pub fn cancel(&self, id: u64) -> Option<JobStatus> {
+        let jobs = self.lock();
+        let job = jobs.running.get(&id)?;
+        job.cancellation.cancel();
+        Some(job.status(id))
+    }
+
+    fn lock(&self) -> std::sync::MutexGuard<'_, Jobs> {
+        self.jobs.lock().unwrap_or_else(PoisonError::into_inner)
+    }
+}
+
+/// A registered job, unregistered when dropped
+#[derive(Debug)]
+pub struct Job {
+    id: u64,
+    rows: Arc<AtomicU64>,
+    cancellation: CancellationToken,
+    registry: JobRegistry,
+}
+
+impl Job {
+    pub fn id(&self) -> u64 {
+        self.id
+    }
+
+    /// Cancelled when the job is, to hand to e.g. `TradeSource::with_cancellation`
+    pub fn cancellation(&self) -> &CancellationToken {
+        &self.cancellation
+    }
+
+    /// Counts the trades of the stream towards the rows of the job as they're aggregated
+    pub fn count_rows<'a>(&self, trades: TradeStream<'a>) -> TradeStream<'a> {
+        let rows = self.rows.clone();
+        Box::pin(trades.inspect(move |trade| {
+            if trade.is_ok() {
+                rows.fetch_add(1, Ordering::Relaxed);
+            }
+        }))
+    }
+}
+
+impl Drop for Job {
+    fn drop(&mut self) {
+        self.registry.lock().running.remove(&self.id);
+    }
+}
+
+/// The admin API of a running report server, e.g. `http://127.0.0.1:8080`, for `jobs`
+pub struct JobsClient {
+    base_url: String,
+    client: reqwest::Client,
+}
+
+impl JobsClient {
+    pub fn new(base_url: &str) -> Self {
+        Self {
+            base_url: base_url.trim_end_matches('/').to_string(),
+            client: reqwest::Client::new(),
+        }
+    }
+
+    pub async fn list(&self) -> Result<Vec<JobStatus>> {
+        let url = format!("{}/jobs", self.base_url);
+        Ok(self
+            .client
+            .get(&url)
+            .send()
+            .await
+            .and_then(|response| response.error_for_status())
+            .with_context(|| format!("Could not list the jobs of {}", self.base_url))?
+            .json()
+            .await?)
+    }
+
+    pub async fn inspect(&self, id: u64) -> Result<JobStatus> {
+        self.job(self.client.get(self.job_url(id)), id).await
+    }
+
+    pub async fn cancel(&self, id: u64) -> Result<JobStatus> {
+        self.job(self.client.delete(self.job_url(id)), id).await
+    }
+
+    fn job_url(&self, id: u64) -> String {
+        format!("{}/jobs/{}", self.base_url, id)
+    }
+
+    async fn job(&self, request: reqwest::RequestBuilder, id: u64) -> Result<JobStatus> {
+        let response = request
+            .send()
+            .await
+            .with_context(|| format!("Could not reach {}", self.base_url))?;
+        if response.status() == StatusCode::NOT_FOUND {
+            bail!("No job {} is running on {}", id, self.base_url);
+        }
+        Ok(response
+            .error_for_status()
+            .with_context(|| format!("Could not get job {} of {}", id, self.base_url))?
+            .json()
+            .await?)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::db::trade_stream;
+    use crate::trade::Trade;
+    use chrono::TimeZone;
+    use chrono_tz::Europe::Copenhagen;
+    use futures::TryStreamExt;
+
+    #[tokio::test]
+    async fn jobs_are_listed_while_running_and_can_be_cancelled() {
+        let registry = JobRegistry::default();
+        let from = Copenhagen.with_ymd_and_hms(2024, 5, 1, 0, 0, 0).unwrap();
+        let to = Copenhagen.with_ymd_and_hms(2024, 6, 1, 0, 0, 0).unwrap();
+        let backfill = registry.start("stream", &from, &to);
+        let request = registry.start("server", &from, &to);
+
+        let trades = vec![Trade::test().build(), Trade::test().sell().build()];
+        let counted: Vec<Trade> = backfill
+            .count_rows(trade_stream(trades))
+            .try_collect()
+            .await
+            .unwrap();
+        assert_eq!(counted.len(), 2);
+
+        let listed = registry.list();
+        assert_eq!(
+            listed
+                .iter()
+                .map(|job| (job.id, job.rows))
+                .collect::<Vec<_>>(),
+            vec![(1, 2), (2, 0)]
+        );
+        assert_eq!(listed[0].window, "May 2024, Europe/Copenhagen, 744 hours");
+
+        assert!(registry.cancel(backfill.id()).unwrap().cancelled);
+        assert!(backfill.cancellation().is_cancelled());
+        assert!(!request.cancellation().is_cancelled());
+
+        // Finished jobs are gone, however they ended
+        drop(backfill);
+        assert!(registry.inspect(1).is_none());
+        assert!(registry.cancel(1).is_none());
+        assert_eq!(registry.list().len(), 1);
+    }
+}
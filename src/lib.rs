@@ -0,0 +1,113 @@
+//! Aggregation of trades from the intraday, auction and imbalance tables into reports of volumes,
+//! cash flows and profit per area, market and side. The binary wires it up as a CLI, other
+//! services can build reports directly:
+//!
+//! ```no_run
+//! # async fn example() -> anyhow::Result<()> {
+//! use chrono::TimeZone;
+//! use chrono_tz::Europe::Copenhagen;
+//! use trading_results_rs::{
+//!     AreaSelection, MarketSelection, Metric, QuantityUnits, ReportBuilder, TradeSource,
+//! };
+//!
+//! let source = TradeSource::connect("postgres://localhost/trades", QuantityUnits::default()).await?;
+//! let from = Copenhagen.with_ymd_and_hms(2024, 5, 1, 0, 0, 0).unwrap();
+//! let to = Copenhagen.with_ymd_and_hms(2024, 6, 1, 0, 0, 0).unwrap();
+//! let report = ReportBuilder::new(&from, &to).build_from_source(&source).await?;
+//! println!(
+//!     "{}",
+//!     report.try_metric(Metric::GrossProfit, MarketSelection::All, AreaSelection::All)?
+//! );
+//! # Ok(())
+//! # }
+//! ```
+
+pub mod allocation;
+pub mod blocks;
+pub mod bounds;
+#[cfg(feature = "runtime")]
+pub mod broadcast;
+#[cfg(feature = "runtime")]
+pub mod cancel;
+#[cfg(feature = "runtime")]
+pub mod clearing;
+#[cfg(feature = "parquet")]
+pub mod columnar;
+pub mod concentration;
+#[cfg(feature = "runtime")]
+pub mod config;
+pub mod contributions;
+pub mod counterparts;
+#[cfg(feature = "runtime")]
+pub mod db;
+#[cfg(all(test, feature = "runtime"))]
+mod dst_matrix;
+pub mod export;
+#[cfg(feature = "runtime")]
+pub mod fanout;
+pub mod fees;
+pub mod fiscal;
+pub mod fx;
+pub mod hedging;
+pub mod hierarchy;
+pub mod imbalance;
+pub mod ingest;
+#[cfg(feature = "runtime")]
+pub mod jobs;
+#[cfg(feature = "runtime")]
+pub mod monitoring;
+pub mod netting;
+pub mod permissions;
+pub mod pipeline;
+pub mod position;
+pub mod prices;
+pub mod quantiles;
+#[cfg(feature = "runtime")]
+pub mod reconciliation;
+#[cfg(feature = "runtime")]
+pub mod replay;
+pub mod report;
+#[cfg(feature = "runtime")]
+pub mod saved;
+#[cfg(feature = "runtime")]
+pub mod scheduler;
+#[cfg(feature = "runtime")]
+pub mod schema;
+#[cfg(feature = "runtime")]
+pub mod search;
+pub mod seasonality;
+#[cfg(feature = "runtime")]
+pub mod secrets;
+#[cfg(feature = "server")]
+pub mod server;
+pub mod settlement;
+#[cfg(feature = "runtime")]
+pub mod signing;
+#[cfg(feature = "runtime")]
+pub mod sinks;
+pub mod snapshot;
+#[cfg(feature = "sqlite")]
+pub mod sqlite;
+pub mod stitch;
+mod sum;
+#[cfg(feature = "runtime")]
+pub mod timing;
+pub mod trade;
+pub mod units;
+#[cfg(feature = "runtime")]
+pub mod validate;
+pub mod vat;
+pub mod warnings;
+#[cfg(feature = "runtime")]
+pub mod watch;
+
+#[cfg(feature = "runtime")]
+pub use db::{trade_stream, OnConflict, TradeSource, TradeStream};
+#[cfg(feature = "runtime")]
+pub use fanout::{aggregate_shared, Aggregation};
+pub use report::{BreakdownRow, Metric, Report, ReportBuilder, SignConvention};
+pub use trade::{
+    Area, AreaSelection, CounterPart, CounterPartSelection, Currency, Decoding, Market,
+    MarketSelection, Trade, TradeSide, TradeTable, TradeType,
+};
+pub use units::{QuantityUnit, QuantityUnits};
@@ -1,200 +1,225 @@
-use futures::TryStreamExt;
 use std::env;
-use std::sync::Arc;
+use std::io;
+use std::process;
 use std::time::Instant;
 
-mod db;
-mod report;
-mod trade;
-
 use anyhow::Result;
-use chrono::prelude::*;
-use chrono_tz::{Europe::Copenhagen, Tz};
-use db::{
-    get_auction_trades_stream, get_imbalance_trades_stream, get_intraday_trades_stream, get_trades,
-    get_trades_for_report, get_trades_stream, init_db_pool,
-};
-use report::Report;
-use sqlx::PgPool;
-use tokio::{sync::mpsc, task};
-use trade::Trade;
-
+use clap::Parser;
+use cli::{Cli, Command};
+use commands::pipeline::Scan;
+use commands::Context;
+use tracing_subscriber::fmt::format::FmtSpan;
+use tracing_subscriber::EnvFilter;
+use trading_results_rs::cancel;
+use trading_results_rs::config::Config;
+use trading_results_rs::db::init_db_pool;
+use trading_results_rs::schema;
+use trading_results_rs::secrets::Secrets;
+use trading_results_rs::timing::RunMetadata;
+use trading_results_rs::warnings::Warnings;
+
+mod cli;
+mod commands;
+
+/// Log filter without `RUST_LOG`, the timings of the strategies and warnings of dependencies
+const DEFAULT_LOG_FILTER: &str = "warn,trading_results_rs=info";
+
+/// Exits with 0 on success, 1 on failure and 2 on success with warnings, e.g. skipped trades.
+/// With `--strict` warnings are a failure. Commands stopped with Ctrl-C exit with 130.
 #[tokio::main]
 async fn main() -> Result<()> {
-    dotenvy::dotenv().expect("Could not load .env");
-    let db_url = env::var("DATABASE_URL")?;
-
-    println!("Initialising sqlx ...");
-
-    let pool = init_db_pool(&db_url).await?;
-
-    let delivery_from = NaiveDate::from_ymd_opt(2024, 1, 1)
-        .unwrap()
-        .and_hms_opt(0, 0, 0)
-        .unwrap();
-    let delivery_from = Copenhagen.from_local_datetime(&delivery_from).unwrap();
-
-    let delivery_to = NaiveDate::from_ymd_opt(2024, 11, 1)
-        .unwrap()
-        .and_hms_opt(0, 0, 0)
-        .unwrap();
-    let delivery_to = Copenhagen.from_local_datetime(&delivery_to).unwrap();
-
-    println!("Create report, standard");
-    let now = Instant::now();
-    let report = create_report(&pool, delivery_from, delivery_to).await?;
-    report.print_key_metrics();
-    println!("Generating report, standard, took: {:.2?}", now.elapsed());
-    println!();
-
-    println!("Create report, simple trade structure (TradeForReport)");
-    let now = Instant::now();
-    let report = create_report_from_simple_trade(&pool, delivery_from, delivery_to).await?;
-    report.print_key_metrics();
-    println!("Generating report, standard, took: {:.2?}", now.elapsed());
-    println!();
-
-    let now = Instant::now();
-    println!("Create report, stream");
-    let report = create_report_stream(&pool, delivery_from, delivery_to).await?;
-    report.print_key_metrics();
-    println!("Generating report, stream, took: {:.2?}", now.elapsed());
-    println!();
-
-    let now = Instant::now();
-    println!("Create report, channels -> Vec<Trace> -> Report::new(trades)");
-    // As we're creating threads for each trade type, we need to use an Arc to share the PgPool reference
-    let arc_pool = Arc::new(pool);
-    let report = create_report_channels(arc_pool, delivery_from, delivery_to).await?;
-    report.print_key_metrics();
-    println!("Generating report, stream, took: {:.2?}", now.elapsed());
-    println!();
-
-    println!("Done :)");
-    Ok(())
-}
-
-async fn create_report(
-    pool: &PgPool,
-    delivery_from: DateTime<Tz>,
-    delivery_to: DateTime<Tz>,
-) -> Result<Report> {
-    println!("Getting from db");
-
-    let now = Instant::now();
-    let trades = get_trades(pool, &delivery_from, &delivery_to).await?;
-    let elapsed = now.elapsed();
-    println!("Getting trades took: {:.2?}", elapsed);
-
-    let now = Instant::now();
-    // In an async-sense, this is a compute heavy task, so we spawn it in a blocking thread
-    let report =
-        task::spawn_blocking(move || Report::new(&delivery_from, &delivery_to, trades)).await??;
-    println!("Report part took: {:.2?}", now.elapsed());
-
-    Ok(report)
+    // Without a .env, e.g. when credentials come from Vault, the environment is used as is
+    dotenvy::dotenv().ok();
+    init_tracing();
+    let cli = Cli::parse();
+
+    let mut warnings = Warnings::default();
+    match run(&cli, &mut warnings).await {
+        Err(err) if cancel::is_cancelled(&err) => {
+            warnings.print();
+            eprintln!("Cancelled");
+            process::exit(cancel::EXIT_CANCELLED);
+        }
+        result => result?,
+    }
+    warnings.finish(cli.strict)
 }
 
-async fn create_report_from_simple_trade(
-    pool: &PgPool,
-    delivery_from: DateTime<Tz>,
-    delivery_to: DateTime<Tz>,
-) -> Result<Report> {
-    println!("Getting from db");
-
-    let now = Instant::now();
-    let trades_for_report = get_trades_for_report(pool, &delivery_from, &delivery_to).await?;
-    let elapsed = now.elapsed();
-    println!("Getting trades took: {:.2?}", elapsed);
-
-    let now = Instant::now();
-    // In an async-sense, this is a compute heavy task, so we spawn it in a blocking thread
-    let report = task::spawn_blocking(move || {
-        Report::new_from_trade_for_report(&delivery_from, &delivery_to, trades_for_report)
-    })
-    .await??;
-    println!("Report part took: {:.2?}", now.elapsed());
+async fn run(cli: &Cli, warnings: &mut Warnings) -> Result<()> {
+    let (delivery_from, delivery_to) = cli.delivery_window()?;
+    let config = Config::load(cli.profile.as_deref())?;
+    let secrets = Secrets::new(config.secrets.clone());
+    let ctx = Context {
+        cli,
+        area_selection: cli.area_selection(&config.area_hierarchy)?,
+        config: &config,
+        secrets: &secrets,
+        delivery_from,
+        delivery_to,
+    };
+
+    // Commands without the database
+    match &cli.command {
+        Some(Command::Ingest { path, ndjson }) => {
+            return commands::ingest::run(path, *ndjson, &ctx, warnings);
+        }
+        Some(Command::Validate { path: Some(path) }) => {
+            return commands::validate::run_file(path, &ctx);
+        }
+        #[cfg(feature = "sqlite")]
+        Some(Command::Demo {
+            out,
+            #[cfg(feature = "server")]
+            serve,
+        }) => {
+            return commands::demo::run(
+                out.as_deref(),
+                #[cfg(feature = "server")]
+                serve.as_deref(),
+                &ctx,
+                warnings,
+            )
+            .await;
+        }
+        Some(Command::Jobs { server, command }) => {
+            return commands::jobs::run(server, command).await;
+        }
+        Some(Command::Verify { path, signature }) => {
+            return commands::verify::run(path, signature.as_deref(), &ctx).await;
+        }
+        Some(Command::CounterParts) => return commands::counter_parts::run(&ctx),
+        Some(Command::MigrateSnapshot) => return commands::migrate_snapshot::run(&ctx),
+        _ => {}
+    }
 
-    Ok(report)
-}
+    let mut run_metadata = RunMetadata::default();
 
-async fn create_report_stream(
-    pool: &PgPool,
-    delivery_from: DateTime<Tz>,
-    delivery_to: DateTime<Tz>,
-) -> Result<Report> {
-    let trades_stream = get_trades_stream(pool, &delivery_from, &delivery_to);
+    cli.progress("Initialising sqlx ...");
 
     let now = Instant::now();
-    let report = Report::new_from_stream(&delivery_from, &delivery_to, trades_stream).await?;
-    println!("Creating report, stream, took: {:.2?}", now.elapsed());
+    let database_url = config
+        .database_url_secret
+        .as_deref()
+        .unwrap_or("DATABASE_URL");
+    let pool = match init_db_pool(&secrets.get(database_url).await?).await {
+        // The credentials may have been rotated since they were cached
+        Err(_) if secrets.rotates() => {
+            secrets.invalidate().await;
+            init_db_pool(&secrets.get(database_url).await?).await?
+        }
+        pool => pool?,
+    };
+    run_metadata.pool_init = now.elapsed();
+    tracing::info!(
+        elapsed_ms = run_metadata.pool_init.as_secs_f64() * 1000.0,
+        "database pool initialised"
+    );
+
+    schema::check_schema(&pool).await?;
+
+    // Scheduled jobs and the forward curve have windows of their own
+    if !matches!(
+        cli.command,
+        Some(Command::CheckSchema | Command::Schedule | Command::ForwardCurve)
+    ) {
+        config
+            .guardrails
+            .check_window(&ctx.delivery_from, &ctx.delivery_to)?;
+    }
 
-    Ok(report)
+    match &cli.command {
+        Some(Command::Report {
+            saved: Some(command),
+        }) => commands::report::run_saved(command, &ctx, &pool, warnings).await,
+        #[cfg(feature = "parquet")]
+        Some(Command::Export {
+            path: Some(directory),
+            parquet: true,
+            trades,
+        }) => {
+            let source = ctx.source(&pool);
+            commands::export::run_parquet(directory, *trades, &ctx, &source, warnings).await
+        }
+        Some(Command::Export { path, .. }) => {
+            commands::export::run(path.as_deref(), &ctx, &pool, warnings).await
+        }
+        Some(Command::Validate { path: None }) => commands::validate::run(&ctx, &pool).await,
+        Some(Command::CheckSchema) => commands::check_schema::run(&pool).await,
+        Some(Command::Replay { speed }) => {
+            commands::replay::run(*speed, &ctx, &pool, warnings).await
+        }
+        Some(Command::Pipeline {
+            names,
+            by_day,
+            by_partition,
+        }) => {
+            let scan = if *by_day {
+                Scan::ByDay
+            } else if *by_partition {
+                Scan::ByPartition
+            } else {
+                Scan::Range
+            };
+            commands::pipeline::run(names, scan, &ctx, &pool, warnings).await
+        }
+        Some(Command::Explain { area, market, side }) => {
+            commands::explain::run(*area, *market, *side, &ctx, &ctx.source(&pool)).await
+        }
+        Some(Command::KeyMetrics { role }) => {
+            commands::key_metrics::run(role.as_deref(), &ctx, &pool, warnings).await
+        }
+        Some(Command::DailyReports) => commands::daily_reports::run(&ctx, &pool, warnings).await,
+        Some(Command::Snapshot) => commands::snapshot::run(&ctx, &pool, warnings).await,
+        Some(Command::StitchedReport) => {
+            commands::stitched_report::run(&ctx, &pool, warnings).await
+        }
+        Some(Command::Clearing) => commands::clearing::run(&ctx, &pool).await,
+        Some(Command::Blocks) => commands::blocks::run(&ctx, &pool).await,
+        Some(Command::FiscalMonths) => commands::fiscal_months::run(&ctx, &pool, warnings).await,
+        Some(Command::Netting) => commands::netting::run(&ctx, &pool).await,
+        Some(Command::ImbalanceCost) => commands::imbalance_cost::run(&ctx, &pool).await,
+        Some(Command::Reconcile { paths }) => {
+            commands::reconcile::run(paths, &ctx, &pool, warnings).await
+        }
+        Some(Command::PriceQuantiles) => {
+            commands::price_quantiles::run(&ctx, &pool, warnings).await
+        }
+        Some(Command::Concentration) => commands::concentration::run(&ctx, &pool, warnings).await,
+        Some(Command::Vat) => commands::vat::run(&ctx, &pool, warnings).await,
+        Some(Command::FxHedge { spot_rates }) => {
+            commands::fx_hedge::run(spot_rates, &ctx, &pool, warnings).await
+        }
+        Some(Command::NetResults) => commands::net_results::run(&ctx, &pool, warnings).await,
+        Some(Command::ForwardCurve) => commands::forward_curve::run(&ctx, &pool).await,
+        Some(Command::SearchTrades { search }) => {
+            commands::search_trades::run(search, &ctx, &pool).await
+        }
+        Some(Command::Schedule) => commands::schedule::run(&ctx, &pool).await,
+        #[cfg(feature = "kafka")]
+        Some(Command::Consume) => commands::consume::run(&ctx).await,
+        #[cfg(feature = "server")]
+        Some(Command::Serve { address }) => commands::serve::run(address, &ctx, &pool).await,
+        Some(Command::Watch { poll_seconds }) => {
+            commands::watch::run(*poll_seconds, &ctx, &pool).await
+        }
+        // The commands above run on the database alone, all others are handled before it
+        _ => commands::report::run(&ctx, &pool, run_metadata, warnings).await,
+    }
 }
 
-async fn create_report_channels(
-    pool: Arc<PgPool>,
-    delivery_from: DateTime<Tz>,
-    delivery_to: DateTime<Tz>,
-) -> Result<Report> {
-    // This is pretty slow as we have to get all trades (send them over the channels as well)
-    // and then collect them into a vector.
-    // It should be pretty doable to create a stream directly from the channels, with something like
-    // https://docs.rs/tokio/latest/tokio/stream/index.html or
-    // https://docs.rs/tokio-stream/latest/tokio_stream/
-    // The next version should be a Channels -> Stream<Trade> -> Report
-    // Then one should be able to create a Channels -> Stream<(quantity_mw, cash_flow)> -> Report to send
-    // as little data over the wire as possible.
-
-    let now = Instant::now();
-
-    let (tx, mut rx) = mpsc::channel(100);
-
-    let intraday_tx = tx.clone();
-    let pool_cloned = Arc::clone(&pool);
-    tokio::spawn(async move {
-        let mut stream = get_intraday_trades_stream(&pool_cloned, &delivery_from, &delivery_to);
-        while let Some(trade) = stream.try_next().await.unwrap() {
-            intraday_tx.send(trade).await.unwrap();
-        }
-    });
-
-    let auction_tx = tx.clone();
-    let pool_cloned = Arc::clone(&pool);
-    tokio::spawn(async move {
-        let mut stream = get_auction_trades_stream(&pool_cloned, &delivery_from, &delivery_to);
-        while let Some(trade) = stream.try_next().await.unwrap() {
-            auction_tx.send(trade).await.unwrap();
-        }
-    });
-
-    let imbalance_tx = tx.clone();
-    let pool_cloned = Arc::clone(&pool);
-    tokio::spawn(async move {
-        let mut stream = get_imbalance_trades_stream(&pool_cloned, &delivery_from, &delivery_to);
-        while let Some(trade) = stream.try_next().await.unwrap() {
-            imbalance_tx.send(trade).await.unwrap();
-        }
-    });
-
-    // The `rx` half of the channel returns `None` once **all** `tx` clones
-    // drop. To ensure `None` is returned, drop the handle owned by the
-    // current task. If this `tx` handle is not dropped, there will always
-    // be a single outstanding `tx` handle.
-    drop(tx);
-
-    println!("Creating channels took: {:.2?}", now.elapsed());
-
-    let now = Instant::now();
-    let mut trades: Vec<Trade> = Vec::new();
-    while let Some(trade) = rx.recv().await {
-        trades.push(trade);
+/// Logs the timings of strategies and other events on stderr, filtered by `RUST_LOG`, e.g.
+/// `RUST_LOG=trading_results_rs=debug`, and as JSON lines with `LOG_FORMAT=json`
+fn init_tracing() {
+    let filter =
+        EnvFilter::try_from_default_env().unwrap_or_else(|_| EnvFilter::new(DEFAULT_LOG_FILTER));
+    // Spans log their duration when they close
+    let subscriber = tracing_subscriber::fmt()
+        .with_env_filter(filter)
+        .with_span_events(FmtSpan::CLOSE)
+        .with_writer(io::stderr);
+    if env::var("LOG_FORMAT").is_ok_and(|format| format == "json") {
+        subscriber.json().init();
+    } else {
+        subscriber.init();
     }
-    println!("Getting trades took: {:.2?}", now.elapsed());
-
-    let now = Instant::now();
-    let report = Report::new(&delivery_from, &delivery_to, trades)?;
-    println!("Creating report, Vec<Trade>, took: {:.2?}", now.elapsed());
-
-    Ok(report)
 }
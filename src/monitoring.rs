@@ -0,0 +1,268 @@
+use std::collections::BTreeMap;
+use std::fmt::Write;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::Mutex;
+
+use anyhow::{Context, Result};
+use serde::Deserialize;
+
+use crate::db::TradeTable;
+use crate::timing::StrategyTimings;
+
+/// Upper bounds in seconds of the buckets report build durations are counted in
+const BUILD_SECONDS_BUCKETS: [f64; 10] = [0.1, 0.5, 1.0, 2.5, 5.0, 10.0, 30.0, 60.0, 120.0, 300.0];
+
+/// Trades fetched from each table, in the order of `TradeTable::ALL`
+static TRADES_FETCHED: [AtomicU64; 3] = [AtomicU64::new(0), AtomicU64::new(0), AtomicU64::new(0)];
+static DB_ERRORS: AtomicU64 = AtomicU64::new(0);
+static REPORT_BUILDS: Mutex<BTreeMap<String, ReportBuilds>> = Mutex::new(BTreeMap::new());
+/// Runs of scheduled jobs by job and whether they succeeded
+static SCHEDULED_RUNS: Mutex<BTreeMap<(String, bool), u64>> = Mutex::new(BTreeMap::new());
+
+/// Where metrics are pushed after runs of the CLI and scheduled jobs, e.g.
+///
+/// ```toml
+/// [monitoring]
+/// push_gateway_url = "http://pushgateway:9091"
+/// job = "trading-aggregator"
+/// ```
+#[derive(Debug, Deserialize, Clone)]
+#[serde(default)]
+pub struct MonitoringConfig {
+    pub push_gateway_url: String,
+    /// Metrics are pushed to the group `/metrics/job/<job>`, replacing those of the last push
+    pub job: String,
+}
+
+impl Default for MonitoringConfig {
+    fn default() -> Self {
+        Self {
+            push_gateway_url: "http://localhost:9091".to_string(),
+            job: "trading-aggregator".to_string(),
+        }
+    }
+}
+
+/// The build durations of a strategy as a histogram, and the trade throughput of its last build
+#[derive(Debug, Default)]
+struct ReportBuilds {
+    /// Builds per bucket of `BUILD_SECONDS_BUCKETS`, not cumulative
+    buckets: [u64; BUILD_SECONDS_BUCKETS.len()],
+    count: u64,
+    sum: f64,
+    trades_per_second: f64,
+}
+
+pub fn record_trades_fetched(table: TradeTable, trades: usize) {
+    TRADES_FETCHED[table_index(table)].fetch_add(trades as u64, Ordering::Relaxed);
+}
+
+/// Counts a failed query, e.g. as `.await.inspect_err(monitoring::record_db_error)?`
+pub fn record_db_error(_: &sqlx::Error) {
+    DB_ERRORS.fetch_add(1, Ordering::Relaxed);
+}
+
+/// Trades fetched from all tables since the process started, so the trades of a build are the
+/// difference before and after it
+pub fn trades_fetched() -> u64 {
+    TRADES_FETCHED
+        .iter()
+        .map(|fetched| fetched.load(Ordering::Relaxed))
+        .sum()
+}
+
+/// Records how long a report took to build with the strategy of the timings, and how many of
+/// the trades it fetched it got through per second
+pub fn record_report_build(timings: &StrategyTimings) {
+    let seconds = timings.total().as_secs_f64();
+    let mut builds = REPORT_BUILDS.lock().expect("metrics lock poisoned");
+    let builds = builds.entry(timings.strategy.clone()).or_default();
+    if let Some(bucket) = BUILD_SECONDS_BUCKETS
+        .iter()
+        .position(|bound| seconds <= *bound)
+    {
+        builds.buckets[bucket] += 1;
+    }
+    builds.count += 1;
+    builds.sum += seconds;
+    if seconds > 0.0 {
+        builds.trades_per_second = timings.trades_fetched() as f64 / seconds;
+    }
+}
+
+pub fn record_scheduled_run(job: &str, succeeded: bool) {
+    let mut runs = SCHEDULED_RUNS.lock().expect("metrics lock poisoned");
+    *runs.entry((job.to_string(), succeeded)).or_default() += 1;
+}
+
+/// The metrics in the Prometheus text format, as scraped from `/metrics` or pushed to a gateway
+pub fn render() -> String {
+    let mut out = String::new();
+    // Writing to a String can't fail
+    let _ = write_metrics(&mut out);
+    out
+}
+
+fn write_metrics(out: &mut String) -> std::fmt::Result {
+    header(
+        out,
+        "trading_aggregator_trades_fetched_total",
+        "counter",
+        "Trades fetched from the database",
+    )?;
+    for table in TradeTable::ALL {
+        writeln!(
+            out,
+            "trading_aggregator_trades_fetched_total{{table=\"{}\"}} {}",
+            table,
+            TRADES_FETCHED[table_index(table)].load(Ordering::Relaxed)
+        )?;
+    }
+
+    header(
+        out,
+        "trading_aggregator_db_errors_total",
+        "counter",
+        "Queries that failed",
+    )?;
+    writeln!(
+        out,
+        "trading_aggregator_db_errors_total {}",
+        DB_ERRORS.load(Ordering::Relaxed)
+    )?;
+
+    let builds = REPORT_BUILDS.lock().expect("metrics lock poisoned");
+    header(
+        out,
+        "trading_aggregator_report_build_seconds",
+        "histogram",
+        "Time to build a report, per strategy",
+    )?;
+    for (strategy, build) in builds.iter() {
+        let mut cumulative = 0;
+        for (bound, count) in BUILD_SECONDS_BUCKETS.iter().zip(build.buckets) {
+            cumulative += count;
+            writeln!(
+                out,
+                "trading_aggregator_report_build_seconds_bucket{{strategy=\"{}\",le=\"{}\"}} {}",
+                strategy, bound, cumulative
+            )?;
+        }
+        writeln!(
+            out,
+            "trading_aggregator_report_build_seconds_bucket{{strategy=\"{}\",le=\"+Inf\"}} {}",
+            strategy, build.count
+        )?;
+        writeln!(
+            out,
+            "trading_aggregator_report_build_seconds_sum{{strategy=\"{}\"}} {}",
+            strategy, build.sum
+        )?;
+        writeln!(
+            out,
+            "trading_aggregator_report_build_seconds_count{{strategy=\"{}\"}} {}",
+            strategy, build.count
+        )?;
+    }
+
+    header(
+        out,
+        "trading_aggregator_trades_per_second",
+        "gauge",
+        "Trades per second of the last report build, per strategy",
+    )?;
+    for (strategy, build) in builds.iter() {
+        writeln!(
+            out,
+            "trading_aggregator_trades_per_second{{strategy=\"{}\"}} {}",
+            strategy, build.trades_per_second
+        )?;
+    }
+    drop(builds);
+
+    header(
+        out,
+        "trading_aggregator_scheduled_runs_total",
+        "counter",
+        "Runs of scheduled jobs, per job and outcome",
+    )?;
+    let runs = SCHEDULED_RUNS.lock().expect("metrics lock poisoned");
+    for ((job, succeeded), count) in runs.iter() {
+        let outcome = if *succeeded { "success" } else { "failure" };
+        writeln!(
+            out,
+            "trading_aggregator_scheduled_runs_total{{job=\"{}\",outcome=\"{}\"}} {}",
+            job, outcome, count
+        )?;
+    }
+    Ok(())
+}
+
+fn header(out: &mut String, name: &str, kind: &str, help: &str) -> std::fmt::Result {
+    writeln!(out, "# HELP {} {}", name, help)?;
+    writeln!(out, "# TYPE {} {}", name, kind)
+}
+
+/// Pushes the metrics to the gateway, for runs that end before Prometheus would scrape them
+pub async fn push(config: &MonitoringConfig) -> Result<()> {
+    let url = format!(
+        "{}/metrics/job/{}",
+        config.push_gateway_url.trim_end_matches('/'),
+        config.job
+    );
+    reqwest::Client::new()
+        .put(&url)
+        .header("content-type", "text/plain; version=0.0.4")
+        .body(render())
+        .send()
+        .await
+        .and_then(|response| response.error_for_status())
+        .with_context(|| format!("Could not push metrics to {}", url))?;
+    Ok(())
+}
+
+fn table_index(table: TradeTable) -> usize {
+    match table {
+        TradeTable::Intraday => 0,
+        TradeTable::Auction => 1,
+        TradeTable::Imbalance => 2,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::timing::Phase;
+    use std::time::Duration;
+
+    #[test]
+    fn builds_are_rendered_as_histograms() {
+        let mut timings = StrategyTimings::new("monitoring_test");
+        timings.record(
+            Phase::Query(TradeTable::Auction),
+            Duration::from_millis(1500),
+        );
+        timings.record(Phase::Aggregation, Duration::from_millis(500));
+        record_trades_fetched(TradeTable::Auction, 1000);
+        record_report_build(&timings);
+
+        let rendered = render();
+        for line in [
+            "trading_aggregator_report_build_seconds_bucket{strategy=\"monitoring_test\",le=\"1\"} 0",
+            "trading_aggregator_report_build_seconds_bucket{strategy=\"monitoring_test\",le=\"2.5\"} 1",
+            "trading_aggregator_report_build_seconds_bucket{strategy=\"monitoring_test\",le=\"+Inf\"} 1",
+            "trading_aggregator_report_build_seconds_sum{strategy=\"monitoring_test\"} 2",
+            "trading_aggregator_trades_per_second{strategy=\"monitoring_test\"} 500",
+        ] {
+            assert!(rendered.lines().any(|rendered| rendered == line), "{}", line);
+        }
+        // The counters are shared by the whole process, so only the lower bound is known
+        let fetched = rendered
+            .lines()
+            .find_map(|line| {
+                line.strip_prefix("trading_aggregator_trades_fetched_total{table=\"auction\"} ")
+            })
+            .unwrap();
+        assert!(fetched.parse::<u64>().unwrap() >= 1000);
+    }
+}
@@ -0,0 +1,142 @@
+use std::collections::BTreeMap;
+
+use anyhow::{anyhow, Result};
+use chrono::{DateTime, Duration, DurationRound, Utc};
+use chrono_tz::Europe::Copenhagen;
+use rust_decimal::Decimal;
+
+use crate::bounds::format_local_hour;
+use crate::trade::{Area, Market, Trade};
+
+/// Net energy bought in the auctions and in the continuous intraday market of an hour
+#[derive(Debug, Default, Clone, Copy, PartialEq)]
+struct HourPosition {
+    auction_mwh: Decimal,
+    intraday_mwh: Decimal,
+}
+
+impl HourPosition {
+    /// The part of the auction position offset by trading the other way in intraday
+    fn traded_out_mwh(&self) -> Decimal {
+        if self.auction_mwh.is_sign_positive() == self.intraday_mwh.is_sign_positive() {
+            Decimal::ZERO
+        } else {
+            self.auction_mwh.abs().min(self.intraday_mwh.abs())
+        }
+    }
+}
+
+/// Intraday continuous volumes netted against the auction position of the same area and hour,
+/// showing how much of the auction exposure was traded out afterwards. Positions are net energy
+/// with sells counting negative. Like the forward curve, trades without a price count, and
+/// imbalance trades are left out as they're not traded.
+#[derive(Default)]
+pub struct Netting {
+    hours: BTreeMap<(Area, DateTime<Utc>), HourPosition>,
+}
+
+impl Netting {
+    /// Adds the energy of the trade to each hour its delivery overlaps
+    pub fn add_trade(&mut self, trade: &Trade) -> Result<()> {
+        let market = Market::from(trade.trade_type);
+        if market == Market::Imbalance {
+            return Ok(());
+        }
+        let (start, end) = (
+            trade.delivery_start.with_timezone(&Utc),
+            trade.delivery_end.with_timezone(&Utc),
+        );
+        let mut hour = start
+            .duration_trunc(Duration::hours(1))
+            .map_err(|err| anyhow!("Could not round {} to the hour: {}", start, err))?;
+
+        while hour < end {
+            let next_hour = hour + Duration::hours(1);
+            let overlap = (next_hour.min(end) - hour.max(start)).num_seconds();
+            let mwh = trade.quantity_mwh * Decimal::from(overlap) / Decimal::from(3600);
+            let position = self.hours.entry((trade.area, hour)).or_default();
+            match market {
+                Market::Auction => position.auction_mwh += mwh,
+                _ => position.intraday_mwh += mwh,
+            }
+            hour = next_hour;
+        }
+        Ok(())
+    }
+
+    /// Rows of the auction, intraday and remaining position per area and local hour, along with
+    /// the energy and share of the auction position traded out
+    pub fn records(&self) -> Vec<Vec<String>> {
+        self.hours
+            .iter()
+            .map(|((area, hour), position)| {
+                let traded_out = position.traded_out_mwh();
+                let share = if position.auction_mwh.is_zero() {
+                    Decimal::ZERO
+                } else {
+                    traded_out / position.auction_mwh.abs()
+                };
+                vec![
+                    area.to_string(),
+                    format_local_hour(&hour.with_timezone(&Copenhagen)),
+                    position.auction_mwh.round_dp(1).to_string(),
+                    position.intraday_mwh.round_dp(1).to_string(),
+                    (position.auction_mwh + position.intraday_mwh)
+                        .round_dp(1)
+                        .to_string(),
+                    traded_out.round_dp(1).to_string(),
+                    share.round_dp(4).to_string(),
+                ]
+            })
+            .collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::trade::TradeType;
+
+    #[test]
+    fn intraday_trading_the_other_way_is_traded_out_of_the_auction_position() {
+        let mut netting = Netting::default();
+        let trades = [
+            Trade::test()
+                .trade_type(TradeType::AuctionEurDahH)
+                .mwh(10)
+                .hour("2024-05-01T12:00+02:00")
+                .build(),
+            Trade::test()
+                .sell()
+                .mwh(4)
+                .hour("2024-05-01T12:00+02:00")
+                .build(),
+            // Buying more in intraday adds to the exposure instead
+            Trade::test()
+                .trade_type(TradeType::AuctionEurDahH)
+                .mwh(5)
+                .hour("2024-05-01T13:00+02:00")
+                .build(),
+            Trade::test().mwh(1).hour("2024-05-01T13:00+02:00").build(),
+        ];
+        for trade in trades.iter() {
+            netting.add_trade(trade).unwrap();
+        }
+
+        assert_eq!(
+            netting.records(),
+            vec![
+                vec![
+                    "DK1",
+                    "2024-05-01T12:00+02:00",
+                    "10",
+                    "-4",
+                    "6",
+                    "4",
+                    "0.40"
+                ],
+                vec!["DK1", "2024-05-01T13:00+02:00", "5", "1", "6", "0", "0"],
+            ]
+        );
+    }
+}
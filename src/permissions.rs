@@ -0,0 +1,101 @@
+use std::collections::HashMap;
+
+use anyhow::{anyhow, Result};
+use rust_decimal::Decimal;
+use serde::Deserialize;
+use serde_json::{json, Map, Value};
+
+use crate::report::{Metric, Report, SignConvention};
+use crate::trade::{AreaSelection, MarketSelection};
+
+#[derive(Debug, Deserialize, Default, Clone, Copy, PartialEq)]
+#[serde(rename_all = "snake_case")]
+pub enum Redaction {
+    /// Leave restricted metrics out of the output
+    #[default]
+    Omit,
+    /// Keep restricted metrics in the output, with the value replaced by "restricted"
+    Mask,
+}
+
+/// What a role may see, configured as e.g.
+///
+/// ```toml
+/// [roles.operations]
+/// hide_monetary = true
+/// redaction = "mask"
+/// ```
+#[derive(Debug, Deserialize, Default, Clone)]
+pub struct RolePermissions {
+    /// Hide all cash/PnL metrics, leaving only volumes
+    #[serde(default)]
+    pub hide_monetary: bool,
+    #[serde(default)]
+    pub hidden_metrics: Vec<Metric>,
+    #[serde(default)]
+    pub redaction: Redaction,
+}
+
+impl RolePermissions {
+    /// Looks up a role in the configured roles. Without a role everything is visible.
+    pub fn for_role(roles: &HashMap<String, RolePermissions>, role: Option<&str>) -> Result<Self> {
+        match role {
+            None => Ok(RolePermissions::default()),
+            Some(role) => roles
+                .get(role)
+                .cloned()
+                .ok_or(anyhow!("Unknown role: {}", role)),
+        }
+    }
+
+    pub fn can_see(&self, metric: Metric) -> bool {
+        let hidden_as_monetary = self.hide_monetary && metric.is_monetary();
+        !hidden_as_monetary && !self.hidden_metrics.contains(&metric)
+    }
+
+    /// Serializes metric values for a caller with these permissions
+    pub fn redact(&self, metrics: impl IntoIterator<Item = (Metric, Decimal)>) -> Value {
+        let output: Map<String, Value> = metrics
+            .into_iter()
+            .filter_map(|(metric, value)| {
+                Some((metric.to_string(), self.redact_value(metric, value)?))
+            })
+            .collect();
+        Value::Object(output)
+    }
+
+    /// Serializes a value of the metric for a caller with these permissions, `None` if it's left
+    /// out of the output
+    pub fn redact_value(&self, metric: Metric, value: Decimal) -> Option<Value> {
+        if self.can_see(metric) {
+            Some(serde_json::to_value(value).unwrap_or_default())
+        } else if self.redaction == Redaction::Mask {
+            Some(Value::String("restricted".to_string()))
+        } else {
+            None
+        }
+    }
+
+    /// The key metrics of the report across all markets and areas, as visible to the caller.
+    /// Like the JSON of the report, `totals` only has the monetary metrics if the trades were all
+    /// settled in the same currency, and `totals_by_currency` has them per currency.
+    pub fn key_metrics(&self, report: &Report, signs: SignConvention) -> Value {
+        let visible = |metrics: Vec<(Metric, Decimal)>| {
+            self.redact(
+                metrics
+                    .into_iter()
+                    .map(|(metric, value)| (metric, signs.apply(metric, value))),
+            )
+        };
+        let (market, area) = (MarketSelection::All, AreaSelection::All);
+        let totals_by_currency: Map<String, Value> = report
+            .monetary_metrics_by_currency(market.clone(), area.clone())
+            .into_iter()
+            .map(|(currency, metrics)| (currency.to_string(), visible(metrics)))
+            .collect();
+        json!({
+            "totals": visible(report.single_currency_metrics(market, area)),
+            "totals_by_currency": totals_by_currency,
+        })
+    }
+}
@@ -0,0 +1,695 @@
+use std::collections::{BTreeMap, HashMap};
+use std::fs::File;
+use std::io::{self, Write};
+use std::path::PathBuf;
+use std::str::FromStr;
+
+use anyhow::{anyhow, Context, Result};
+use chrono::{DateTime, Days, FixedOffset, NaiveDate, Weekday};
+use chrono_tz::{Europe::Copenhagen, Tz};
+use rust_decimal::Decimal;
+use serde::Deserialize;
+use strum_macros::Display;
+
+use crate::bounds::{format_local_hour, format_local_minute};
+use crate::quantiles::PriceStatistics;
+use crate::report::{contract_length, Metric, SignConvention};
+use crate::settlement::IspSchedule;
+use crate::sum::DecimalSum;
+use crate::trade::TradeTable;
+use crate::trade::{Area, CounterPart, Market, Trade, TradeSide, TradeType};
+
+/// A recurring report described in config, e.g.
+///
+/// ```toml
+/// [[pipelines]]
+/// name = "gb-auction-daily"
+/// filters = [{ area = ["GB"] }, { market = ["auction"] }]
+/// enrichers = [{ local_time = { timezone = "Europe/London" } }]
+/// dimensions = ["trade_type", "delivery_day"]
+/// metrics = ["mw_bought", "mw_sold", "gross_profit"]
+/// sink = { csv = { path = "gb_auction_daily.csv" } }
+/// ```
+///
+/// Values in filters use the same spelling as the database columns.
+#[derive(Debug, Deserialize, Clone)]
+pub struct PipelineDefinition {
+    pub name: String,
+    #[serde(default)]
+    pub filters: Vec<FilterDefinition>,
+    #[serde(default)]
+    pub enrichers: Vec<EnricherDefinition>,
+    pub dimensions: Vec<Dimension>,
+    pub metrics: Vec<Metric>,
+    /// Adds the change of each metric since the previous ISO week, requires the
+    /// `delivery_week` dimension
+    #[serde(default)]
+    pub week_over_week: bool,
+    /// Also writes the formula and source tables of each metric column, next to the rows
+    #[serde(default)]
+    pub lineage: bool,
+    #[serde(default)]
+    pub sink: SinkDefinition,
+}
+
+#[derive(Debug, Deserialize, Clone)]
+#[serde(rename_all = "snake_case")]
+pub enum FilterDefinition {
+    Area(Vec<String>),
+    Market(Vec<String>),
+    TradeType(Vec<String>),
+    CounterPart(Vec<String>),
+}
+
+#[derive(Debug, Deserialize, Clone)]
+#[serde(rename_all = "snake_case")]
+pub enum EnricherDefinition {
+    /// Timezone the delivery time dimensions are resolved in, defaults to Europe/Copenhagen
+    LocalTime { timezone: String },
+}
+
+#[derive(Debug, Deserialize, Display, PartialEq, Clone, Copy)]
+#[serde(rename_all = "snake_case")]
+#[strum(serialize_all = "snake_case")]
+pub enum Dimension {
+    Area,
+    Market,
+    TradeType,
+    CounterPart,
+    Side,
+    DeliveryHour,
+    DeliveryDay,
+    /// ISO week of the local delivery start, e.g. `2025-W01` for 30 December 2024
+    DeliveryWeek,
+    DeliveryMonth,
+    /// Start of the imbalance settlement period, imbalance trades are split into their ISPs
+    SettlementPeriod,
+}
+
+#[derive(Debug, Deserialize, Default, Clone)]
+#[serde(rename_all = "snake_case")]
+pub enum SinkDefinition {
+    #[default]
+    Stdout,
+    Csv {
+        path: PathBuf,
+    },
+    Json {
+        path: PathBuf,
+    },
+}
+
+#[derive(Clone)]
+enum Filter {
+    Area(Vec<Area>),
+    Market(Vec<Market>),
+    TradeType(Vec<TradeType>),
+    CounterPart(Vec<CounterPart>),
+}
+
+impl Filter {
+    fn matches(&self, trade: &Trade) -> bool {
+        match self {
+            Filter::Area(areas) => areas.contains(&trade.area),
+            Filter::Market(markets) => markets.contains(&Market::from(trade.trade_type)),
+            Filter::TradeType(trade_types) => trade_types.contains(&trade.trade_type),
+            Filter::CounterPart(counter_parts) => counter_parts.contains(&trade.counter_part),
+        }
+    }
+
+    /// Whether trades of the market can pass the filter
+    fn admits(&self, market: Market) -> bool {
+        match self {
+            Filter::Market(markets) => markets.contains(&market),
+            Filter::TradeType(trade_types) => trade_types
+                .iter()
+                .any(|trade_type| Market::from(*trade_type) == market),
+            Filter::Area(_) | Filter::CounterPart(_) => true,
+        }
+    }
+
+    fn describe(&self) -> String {
+        fn list<T: ToString>(values: &[T]) -> String {
+            values
+                .iter()
+                .map(T::to_string)
+                .collect::<Vec<_>>()
+                .join(", ")
+        }
+        match self {
+            Filter::Area(areas) => format!("area in {}", list(areas)),
+            Filter::Market(markets) => format!("market in {}", list(markets)),
+            Filter::TradeType(trade_types) => format!("trade_type in {}", list(trade_types)),
+            Filter::CounterPart(counter_parts) => {
+                format!("counter_part in {}", list(counter_parts))
+            }
+        }
+    }
+}
+
+fn parse_values<T: FromStr>(values: &[String], kind: &str) -> Result<Vec<T>> {
+    values
+        .iter()
+        .map(|value| {
+            T::from_str(value)
+                .map_err(|_| anyhow!("Unknown {} in pipeline filter: {}", kind, value))
+        })
+        .collect()
+}
+
+/// ISO week-based year and week number, which sorts chronologically
+const WEEK_FORMAT: &str = "%G-W%V";
+
+/// The ISO week before a week formatted with `WEEK_FORMAT`
+fn previous_week(week: &str) -> Option<String> {
+    let (year, week) = week.split_once("-W")?;
+    let monday = NaiveDate::from_isoywd_opt(year.parse().ok()?, week.parse().ok()?, Weekday::Mon)?;
+    Some((monday - Days::new(7)).format(WEEK_FORMAT).to_string())
+}
+
+#[derive(Default, Clone)]
+struct Accumulator {
+    mw: HashMap<TradeSide, DecimalSum>,
+    cash_flow: HashMap<TradeSide, DecimalSum>,
+    prices: PriceStatistics,
+}
+
+impl Accumulator {
+    fn value(&self, metric: Metric) -> Decimal {
+        let get = |map: &HashMap<TradeSide, DecimalSum>, side| {
+            map.get(&side).map_or(Decimal::ZERO, |sum| sum.to_decimal())
+        };
+        match metric {
+            Metric::MwBought => get(&self.mw, TradeSide::Buy).round_dp(1),
+            Metric::MwSold => get(&self.mw, TradeSide::Sell).round_dp(1),
+            Metric::Revenue => get(&self.cash_flow, TradeSide::Sell).round_dp(2),
+            Metric::Costs => get(&self.cash_flow, TradeSide::Buy).round_dp(2),
+            Metric::GrossProfit | Metric::NetCashFlow => (get(&self.cash_flow, TradeSide::Sell)
+                - get(&self.cash_flow, TradeSide::Buy))
+            .round_dp(2),
+            Metric::NetPosition => {
+                (get(&self.mw, TradeSide::Buy) - get(&self.mw, TradeSide::Sell)).round_dp(1)
+            }
+            Metric::MinPrice | Metric::MaxPrice | Metric::MedianPrice => {
+                self.prices.clone().statistic(metric)
+            }
+        }
+    }
+
+    fn merge(&mut self, other: Accumulator) {
+        for (side, sum) in other.mw {
+            self.mw.entry(side).or_default().merge(sum);
+        }
+        for (side, sum) in other.cash_flow {
+            self.cash_flow.entry(side).or_default().merge(sum);
+        }
+        self.prices.merge(&other.prices);
+    }
+}
+
+/// A pipeline assembled from a `PipelineDefinition`, aggregating trades into rows keyed by the
+/// configured dimensions.
+#[derive(Clone)]
+pub struct Pipeline {
+    name: String,
+    filters: Vec<Filter>,
+    timezone: Tz,
+    dimensions: Vec<Dimension>,
+    metrics: Vec<Metric>,
+    sink: SinkDefinition,
+    isp_schedule: IspSchedule,
+    signs: SignConvention,
+    week_over_week: bool,
+    lineage: bool,
+    skipped_trades: usize,
+    rows: BTreeMap<Vec<String>, Accumulator>,
+}
+
+impl Pipeline {
+    pub fn from_definition(
+        definition: PipelineDefinition,
+        isp_schedule: &IspSchedule,
+        signs: SignConvention,
+    ) -> Result<Self> {
+        let filters = definition
+            .filters
+            .iter()
+            .map(|filter| {
+                Ok(match filter {
+                    FilterDefinition::Area(values) => Filter::Area(parse_values(values, "area")?),
+                    FilterDefinition::Market(values) => {
+                        Filter::Market(parse_values(values, "market")?)
+                    }
+                    FilterDefinition::TradeType(values) => {
+                        Filter::TradeType(parse_values(values, "trade type")?)
+                    }
+                    FilterDefinition::CounterPart(values) => {
+                        Filter::CounterPart(parse_values(values, "counter part")?)
+                    }
+                })
+            })
+            .collect::<Result<Vec<_>>>()?;
+
+        if definition.week_over_week && !definition.dimensions.contains(&Dimension::DeliveryWeek) {
+            return Err(anyhow!(
+                "Pipeline {} compares week over week without the delivery_week dimension",
+                definition.name
+            ));
+        }
+
+        let mut timezone = Copenhagen;
+        for enricher in definition.enrichers.iter() {
+            match enricher {
+                EnricherDefinition::LocalTime { timezone: name } => {
+                    timezone = name
+                        .parse()
+                        .map_err(|_| anyhow!("Unknown timezone in pipeline: {}", name))?;
+                }
+            }
+        }
+
+        Ok(Self {
+            name: definition.name,
+            filters,
+            timezone,
+            dimensions: definition.dimensions,
+            metrics: definition.metrics,
+            sink: definition.sink,
+            isp_schedule: isp_schedule.clone(),
+            signs,
+            week_over_week: definition.week_over_week,
+            lineage: definition.lineage,
+            skipped_trades: 0,
+            rows: BTreeMap::new(),
+        })
+    }
+
+    pub fn name(&self) -> &str {
+        &self.name
+    }
+
+    /// Adds the rows of a pipeline cloned from this one that aggregated other trades, e.g.
+    /// another delivery day. Sums are exact, so rows are the same as aggregating all trades
+    /// into one pipeline.
+    pub fn merge(&mut self, other: Pipeline) {
+        self.skipped_trades += other.skipped_trades;
+        for (key, accumulator) in other.rows {
+            self.rows.entry(key).or_default().merge(accumulator);
+        }
+    }
+
+    /// Trades matching the filters that were skipped for not having a price
+    pub fn skipped_trades(&self) -> usize {
+        self.skipped_trades
+    }
+
+    fn dimension_value(
+        &self,
+        dimension: Dimension,
+        trade: &Trade,
+        side: TradeSide,
+        period_start: &DateTime<FixedOffset>,
+    ) -> String {
+        let local_start = period_start.with_timezone(&self.timezone);
+        match dimension {
+            Dimension::Area => trade.area.to_string(),
+            Dimension::Market => Market::from(trade.trade_type).to_string(),
+            Dimension::TradeType => trade.trade_type.to_string(),
+            Dimension::CounterPart => trade.counter_part.to_string(),
+            Dimension::Side => side.to_string(),
+            // Sub-hourly periods, like half-hourly GB products, belong to the hour they start in
+            Dimension::DeliveryHour => format_local_hour(&local_start),
+            Dimension::DeliveryDay => local_start.format("%Y-%m-%d").to_string(),
+            Dimension::DeliveryWeek => local_start.format(WEEK_FORMAT).to_string(),
+            Dimension::DeliveryMonth => local_start.format("%Y-%m").to_string(),
+            Dimension::SettlementPeriod => format_local_minute(&local_start),
+        }
+    }
+
+    /// Aggregates a trade the same way `Report` does: trades without a price are skipped and
+    /// the side is derived from the sign of the quantity.
+    pub fn add_trade(&mut self, trade: &Trade) -> Result<()> {
+        if !self.filters.iter().all(|filter| filter.matches(trade)) {
+            return Ok(());
+        }
+        let Some(price) = trade.price else {
+            self.skipped_trades += 1;
+            return Ok(());
+        };
+
+        let side = if trade.quantity_mwh < Decimal::ZERO {
+            TradeSide::Sell
+        } else {
+            TradeSide::Buy
+        };
+        // Imbalance is settled per ISP, so with settlement periods as a dimension each period
+        // is attributed its own share of the volume instead of assuming hourly periods
+        let periods = if self.dimensions.contains(&Dimension::SettlementPeriod)
+            && Market::from(trade.trade_type) == Market::Imbalance
+        {
+            self.isp_schedule
+                .split(trade.area, &trade.delivery_start, &trade.delivery_end)?
+        } else {
+            vec![(trade.delivery_start, trade.delivery_end)]
+        };
+
+        for (period_start, period_end) in periods {
+            let quantity = trade.quantity_mwh.abs();
+            let hours = contract_length(&period_start, &period_end)?;
+            let key = self
+                .dimensions
+                .iter()
+                .map(|dimension| self.dimension_value(*dimension, trade, side, &period_start))
+                .collect();
+            let accumulator = self.rows.entry(key).or_default();
+            accumulator
+                .mw
+                .entry(side)
+                .or_default()
+                .add_product(&[quantity, hours]);
+            accumulator
+                .cash_flow
+                .entry(side)
+                .or_default()
+                .add_product(&[quantity, hours, price]);
+            accumulator.prices.add(price);
+        }
+
+        Ok(())
+    }
+
+    fn header(&self) -> Vec<String> {
+        let dimensions = self
+            .dimensions
+            .iter()
+            .map(|dimension| dimension.to_string());
+        let metrics = self.metrics.iter().map(|metric| metric.to_string());
+        let changes = self
+            .metrics
+            .iter()
+            .filter(|_| self.week_over_week)
+            .map(|metric| format!("{}_wow", metric));
+        dimensions.chain(metrics).chain(changes).collect()
+    }
+
+    pub(crate) fn records(&self) -> impl Iterator<Item = Vec<String>> + '_ {
+        self.rows.iter().map(|(key, accumulator)| {
+            let value = |accumulator: &Accumulator, metric: Metric| {
+                self.signs.apply(metric, accumulator.value(metric))
+            };
+            let metrics = self
+                .metrics
+                .iter()
+                .map(|metric| value(accumulator, *metric).to_string());
+            // The change since the same row in the previous week, empty without such a row
+            let previous = self.previous_week_row(key);
+            let changes = self
+                .metrics
+                .iter()
+                .filter(|_| self.week_over_week)
+                .map(move |metric| {
+                    previous.map_or(String::new(), |previous| {
+                        (value(accumulator, *metric) - value(previous, *metric)).to_string()
+                    })
+                });
+            key.iter().cloned().chain(metrics).chain(changes).collect()
+        })
+    }
+
+    /// The row with the same dimension values as `key`, except for being a week earlier
+    fn previous_week_row(&self, key: &[String]) -> Option<&Accumulator> {
+        if !self.week_over_week {
+            return None;
+        }
+        let index = self
+            .dimensions
+            .iter()
+            .position(|dimension| *dimension == Dimension::DeliveryWeek)?;
+        let mut previous_key = key.to_vec();
+        previous_key[index] = previous_week(&key[index])?;
+        self.rows.get(&previous_key)
+    }
+
+    /// The formula, source tables and filters behind each metric column
+    fn lineage_records(&self) -> Vec<Vec<String>> {
+        let tables = TradeTable::ALL
+            .into_iter()
+            .filter(|table| {
+                self.filters
+                    .iter()
+                    .all(|filter| filter.admits(table.market()))
+            })
+            .map(|table| table.table_name())
+            .collect::<Vec<_>>()
+            .join(", ");
+        let filters = self
+            .filters
+            .iter()
+            .map(Filter::describe)
+            .collect::<Vec<_>>()
+            .join("; ");
+        let formula = |metric: Metric| {
+            let negated = self.signs.apply(metric, Decimal::ONE) < Decimal::ZERO;
+            format!(
+                "{}{}",
+                metric.formula(),
+                if negated { ", negated" } else { "" }
+            )
+        };
+
+        let metrics = self
+            .metrics
+            .iter()
+            .map(|metric| (metric.to_string(), formula(*metric)));
+        let changes = self
+            .metrics
+            .iter()
+            .filter(|_| self.week_over_week)
+            .map(|metric| {
+                (
+                    format!("{}_wow", metric),
+                    format!("{} − {} of the previous ISO week", metric, metric),
+                )
+            });
+        metrics
+            .chain(changes)
+            .map(|(column, formula)| vec![column, formula, tables.clone(), filters.clone()])
+            .collect()
+    }
+
+    /// The files written by `write`, none when writing to stdout
+    pub fn output_paths(&self) -> Vec<PathBuf> {
+        let lineage = self.lineage.then(|| self.sink.lineage());
+        [Some(&self.sink), lineage.as_ref()]
+            .into_iter()
+            .flatten()
+            .filter_map(|sink| sink.path().cloned())
+            .collect()
+    }
+
+    /// Writes the aggregated rows to the configured sink, followed by their lineage if enabled
+    pub fn write(&self) -> Result<()> {
+        self.sink.write(&self.header(), self.records())?;
+        if self.lineage {
+            if let SinkDefinition::Stdout = self.sink {
+                println!();
+            }
+            let header = ["column", "formula", "source_tables", "filters"].map(String::from);
+            self.sink
+                .lineage()
+                .write(&header, self.lineage_records().into_iter())?;
+        }
+        Ok(())
+    }
+}
+
+impl SinkDefinition {
+    pub fn path(&self) -> Option<&PathBuf> {
+        match self {
+            SinkDefinition::Stdout => None,
+            SinkDefinition::Csv { path } | SinkDefinition::Json { path } => Some(path),
+        }
+    }
+
+    /// Where the lineage of the rows goes: next to the file, e.g. `daily.lineage.csv` for
+    /// `daily.csv`, or after the rows on stdout
+    fn lineage(&self) -> SinkDefinition {
+        let lineage_path =
+            |path: &PathBuf, extension: &str| path.with_extension(format!("lineage.{}", extension));
+        match self {
+            SinkDefinition::Stdout => SinkDefinition::Stdout,
+            SinkDefinition::Csv { path } => SinkDefinition::Csv {
+                path: lineage_path(path, "csv"),
+            },
+            SinkDefinition::Json { path } => SinkDefinition::Json {
+                path: lineage_path(path, "json"),
+            },
+        }
+    }
+
+    /// Writes rows of string values under the header, as CSV or as JSON objects keyed by the header
+    pub fn write<I>(&self, header: &[String], records: I) -> Result<()>
+    where
+        I: Iterator<Item = Vec<String>>,
+    {
+        match self {
+            SinkDefinition::Stdout => write_csv(io::stdout(), header, records),
+            SinkDefinition::Csv { path } => {
+                let file = File::create(path)
+                    .with_context(|| format!("Could not create {}", path.display()))?;
+                write_csv(file, header, records)
+            }
+            SinkDefinition::Json { path } => {
+                let rows: Vec<serde_json::Map<String, serde_json::Value>> = records
+                    .map(|record| {
+                        header
+                            .iter()
+                            .cloned()
+                            .zip(record.into_iter().map(serde_json::Value::String))
+                            .collect()
+                    })
+                    .collect();
+                let mut file = File::create(path)
+                    .with_context(|| format!("Could not create {}", path.display()))?;
+                serde_json::to_writer_pretty(&mut file, &rows)?;
+                file.flush()?;
+                Ok(())
+            }
+        }
+    }
+}
+
+fn write_csv<W, I>(writer: W, header: &[String], records: I) -> Result<()>
+where
+    W: Write,
+    I: Iterator<Item = Vec<String>>,
+{
+    let mut writer = csv::Writer::from_writer(writer);
+    writer.write_record(header)?;
+    for record in records {
+        writer.write_record(record)?;
+    }
+    writer.flush()?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::trade::Currency;
+
+    fn half_hourly_trade(start: &str) -> Trade {
+        Trade::test()
+            .area(Area::GB)
+            .currency(Currency::Gbp)
+            .counter_part(CounterPart::EPEX)
+            .trade_type(TradeType::AuctionGbDahHh)
+            .mwh(10)
+            .price(80)
+            .half_hour(start)
+            .build()
+    }
+
+    #[test]
+    fn half_hourly_trades_are_bucketed_into_their_delivery_hour() {
+        let definition: PipelineDefinition = toml::from_str(
+            r#"
+            name = "gb-hourly"
+            enrichers = [{ local_time = { timezone = "Europe/London" } }]
+            dimensions = ["delivery_hour"]
+            metrics = ["mw_bought", "costs"]
+            "#,
+        )
+        .unwrap();
+        let mut pipeline = Pipeline::from_definition(
+            definition,
+            &IspSchedule::default(),
+            SignConvention::default(),
+        )
+        .unwrap();
+
+        pipeline
+            .add_trade(&half_hourly_trade("2024-05-01T12:00+01:00"))
+            .unwrap();
+        pipeline
+            .add_trade(&half_hourly_trade("2024-05-01T12:30+01:00"))
+            .unwrap();
+
+        let records: Vec<Vec<String>> = pipeline.records().collect();
+        assert_eq!(
+            records,
+            vec![vec!["2024-05-01T12:00+01:00", "10.0", "800.0"]]
+        );
+    }
+
+    #[test]
+    fn weeks_across_the_year_boundary_are_compared_week_over_week() {
+        let definition: PipelineDefinition = toml::from_str(
+            r#"
+            name = "gb-weekly"
+            enrichers = [{ local_time = { timezone = "Europe/London" } }]
+            dimensions = ["delivery_week"]
+            metrics = ["mw_bought"]
+            week_over_week = true
+            "#,
+        )
+        .unwrap();
+        let mut pipeline = Pipeline::from_definition(
+            definition,
+            &IspSchedule::default(),
+            SignConvention::default(),
+        )
+        .unwrap();
+
+        // Monday 23 December 2024 is in 2024-W52, Monday 30 December in 2025-W01
+        for start in [
+            "2024-12-23T12:00+00:00",
+            "2024-12-30T12:00+00:00",
+            "2024-12-30T12:30+00:00",
+        ] {
+            pipeline.add_trade(&half_hourly_trade(start)).unwrap();
+        }
+
+        assert_eq!(
+            pipeline.header(),
+            vec!["delivery_week", "mw_bought", "mw_bought_wow"]
+        );
+        let records: Vec<Vec<String>> = pipeline.records().collect();
+        assert_eq!(
+            records,
+            vec![vec!["2024-W52", "5.0", ""], vec!["2025-W01", "10.0", "5.0"]]
+        );
+    }
+
+    #[test]
+    fn lineage_names_the_tables_the_filters_leave() {
+        let definition: PipelineDefinition = toml::from_str(
+            r#"
+            name = "dk-daily"
+            filters = [{ area = ["DK1", "DK2"] }, { trade_type = ["intraday", "auction_eur_dah_h"] }]
+            dimensions = ["delivery_day"]
+            metrics = ["mw_sold", "gross_profit"]
+            lineage = true
+            "#,
+        )
+        .unwrap();
+        let pipeline =
+            Pipeline::from_definition(definition, &IspSchedule::default(), SignConvention::Trading)
+                .unwrap();
+
+        let tables = "intraday_trades, auction_trades";
+        let filters = "area in DK1, DK2; trade_type in intraday, auction_eur_dah_h";
+        assert_eq!(
+            pipeline.lineage_records(),
+            vec![
+                vec![
+                    "mw_sold",
+                    "Σ |quantity| × delivery hours of sell trades, negated",
+                    tables,
+                    filters
+                ],
+                vec!["gross_profit", "revenue − costs", tables, filters],
+            ]
+        );
+    }
+}
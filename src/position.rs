@@ -0,0 +1,189 @@
+use std::collections::BTreeMap;
+
+use anyhow::{anyhow, bail, Result};
+use chrono::{DateTime, Duration, FixedOffset, Timelike};
+use chrono_tz::{Europe::Copenhagen, Tz};
+use rust_decimal::Decimal;
+use serde::Deserialize;
+
+use crate::pipeline::SinkDefinition;
+use crate::settlement::is_aligned;
+use crate::trade::{Area, Trade};
+
+/// Resolution of the curve, the shortest delivery period traded in any of our markets
+const PERIOD_LENGTH_MINUTES: i64 = 15;
+
+/// Export of the net open position per area for delivery periods from now on, configured as e.g.
+///
+/// ```toml
+/// [forward_curve]
+/// horizon_days = 14
+/// sink = { json = { path = "forward_curve.json" } }
+/// ```
+#[derive(Debug, Deserialize, Clone)]
+#[serde(default)]
+pub struct ForwardCurveConfig {
+    /// How far ahead of now trades are included
+    pub horizon_days: u32,
+    pub sink: SinkDefinition,
+}
+
+impl Default for ForwardCurveConfig {
+    fn default() -> Self {
+        Self {
+            horizon_days: 14,
+            sink: SinkDefinition::default(),
+        }
+    }
+}
+
+/// Net position in MW per area and quarter hour, summed over all markets with sells counting
+/// negative. Trades without a price still count, as they're part of the position either way.
+pub struct ForwardCurve {
+    /// Start of the first period of the curve, the quarter hour of now
+    curve_from: DateTime<FixedOffset>,
+    /// Trades are fetched from a day before the curve, so blocks already delivering are included
+    delivery_from: DateTime<Tz>,
+    delivery_to: DateTime<Tz>,
+    sink: SinkDefinition,
+    positions: BTreeMap<(Area, DateTime<FixedOffset>), Decimal>,
+}
+
+impl ForwardCurve {
+    pub fn new(config: &ForwardCurveConfig, now: DateTime<Tz>) -> Result<Self> {
+        let curve_from = now
+            .with_minute(now.minute() - now.minute() % PERIOD_LENGTH_MINUTES as u32)
+            .and_then(|at| at.with_second(0))
+            .and_then(|at| at.with_nanosecond(0))
+            .ok_or(anyhow!("Could not round {} to a quarter hour", now))?;
+
+        Ok(Self {
+            curve_from: curve_from.fixed_offset(),
+            delivery_from: curve_from - Duration::days(1),
+            delivery_to: curve_from + Duration::days(config.horizon_days.into()),
+            sink: config.sink.clone(),
+            positions: BTreeMap::new(),
+        })
+    }
+
+    pub fn delivery_from(&self) -> &DateTime<Tz> {
+        &self.delivery_from
+    }
+
+    pub fn delivery_to(&self) -> &DateTime<Tz> {
+        &self.delivery_to
+    }
+
+    /// Adds the quantity of a trade to each of its quarter hours that hasn't started yet
+    pub fn add_trade(&mut self, trade: &Trade) -> Result<()> {
+        let period_length = Duration::minutes(PERIOD_LENGTH_MINUTES);
+        let mut start = trade.delivery_start;
+        if !is_aligned(&start, period_length) {
+            bail!(
+                "Delivery period {} - {} in {} is not aligned to quarter hours",
+                trade.delivery_start,
+                trade.delivery_end,
+                trade.area
+            );
+        }
+
+        while start < trade.delivery_end {
+            let end = start + period_length;
+            if end > trade.delivery_end {
+                bail!(
+                    "Delivery period {} - {} in {} ends within a quarter hour",
+                    trade.delivery_start,
+                    trade.delivery_end,
+                    trade.area
+                );
+            }
+            if start >= self.curve_from {
+                *self
+                    .positions
+                    .entry((trade.area, start))
+                    .or_insert(Decimal::ZERO) += trade.quantity_mwh;
+            }
+            start = end;
+        }
+
+        Ok(())
+    }
+
+    /// Rows of the periods that haven't started at `now`, ordered by area and delivery start
+    fn records(&self, now: DateTime<FixedOffset>) -> impl Iterator<Item = Vec<String>> + '_ {
+        let period_length = Duration::minutes(PERIOD_LENGTH_MINUTES);
+        self.positions
+            .iter()
+            .filter(move |((_, start), _)| *start >= now)
+            .map(move |((area, start), net_mw)| {
+                let start = start.with_timezone(&Copenhagen);
+                vec![
+                    area.to_string(),
+                    start.format("%Y-%m-%dT%H:%M%:z").to_string(),
+                    (start + period_length)
+                        .format("%Y-%m-%dT%H:%M%:z")
+                        .to_string(),
+                    net_mw.to_string(),
+                ]
+            })
+    }
+
+    /// Writes the curve of the periods that haven't started at `now` to the configured sink
+    pub fn write(&self, now: DateTime<FixedOffset>) -> Result<()> {
+        let header = ["area", "delivery_start", "delivery_end", "net_mw"].map(String::from);
+        self.sink.write(&header, self.records(now))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::trade::TradeType;
+    use chrono::TimeZone;
+
+    #[test]
+    fn started_periods_are_left_out_and_sells_net_against_buys() {
+        let now = Copenhagen.with_ymd_and_hms(2024, 5, 1, 12, 20, 0).unwrap();
+        let mut curve = ForwardCurve::new(&ForwardCurveConfig::default(), now).unwrap();
+
+        curve
+            .add_trade(
+                &Trade::test()
+                    .trade_type(TradeType::AuctionEurDahH)
+                    .mwh(10)
+                    .no_price()
+                    .hour("2024-05-01T12:00+02:00")
+                    .build(),
+            )
+            .unwrap();
+        curve
+            .add_trade(
+                &Trade::test()
+                    .sell()
+                    .mwh(4)
+                    .no_price()
+                    .quarter_hour("2024-05-01T12:45+02:00")
+                    .build(),
+            )
+            .unwrap();
+
+        let records: Vec<Vec<String>> = curve.records(now.fixed_offset()).collect();
+        assert_eq!(
+            records,
+            vec![
+                vec![
+                    "DK1",
+                    "2024-05-01T12:30+02:00",
+                    "2024-05-01T12:45+02:00",
+                    "10"
+                ],
+                vec![
+                    "DK1",
+                    "2024-05-01T12:45+02:00",
+                    "2024-05-01T13:00+02:00",
+                    "6"
+                ],
+            ]
+        );
+    }
+}
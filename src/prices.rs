@@ -0,0 +1,528 @@
+use std::collections::{BTreeMap, HashMap};
+#[cfg(feature = "runtime")]
+use std::str::FromStr;
+
+use anyhow::Result;
+#[cfg(feature = "runtime")]
+use anyhow::{anyhow, bail, Context};
+#[cfg(feature = "runtime")]
+use chrono::NaiveDateTime;
+use chrono::{DateTime, Duration, FixedOffset, Utc};
+#[cfg(feature = "runtime")]
+use chrono_tz::Tz;
+use rust_decimal::Decimal;
+use serde::Deserialize;
+#[cfg(feature = "runtime")]
+use sqlx::PgPool;
+
+use crate::fx::FxRates;
+#[cfg(feature = "runtime")]
+use crate::secrets::Secrets;
+use crate::trade::{Area, Currency, Trade};
+
+/// Where the day-ahead prices trades are benchmarked against are loaded from, a table with the
+/// columns `area`, `delivery_start`, `delivery_end` and `price`, or the ENTSO-E transparency
+/// platform with the token in a secret:
+///
+/// ```toml
+/// [spot_prices]
+/// table = "spot_prices"
+/// currency = "EUR"
+/// # or
+/// entsoe_token_secret = "ENTSOE_TOKEN"
+/// entsoe_areas = ["DK1", "DK2"]
+/// ```
+#[derive(Debug, Deserialize, Clone)]
+#[serde(default)]
+pub struct SpotPriceConfig {
+    pub table: Option<String>,
+    /// The currency of the prices in the table, EUR by default. ENTSO-E publishes in EUR.
+    pub currency: Currency,
+    pub entsoe_token_secret: Option<String>,
+    pub entsoe_url: String,
+    /// Areas whose prices are fetched from ENTSO-E, all with a bidding zone if empty
+    pub entsoe_areas: Vec<Area>,
+}
+
+impl Default for SpotPriceConfig {
+    fn default() -> Self {
+        Self {
+            table: None,
+            currency: Currency::Eur,
+            entsoe_token_secret: None,
+            entsoe_url: "https://web-api.tp.entsoe.eu/api".to_string(),
+            entsoe_areas: Vec::new(),
+        }
+    }
+}
+
+/// Prices are loaded this long before and after the window, for trades delivered across its
+/// bounds
+#[cfg(feature = "runtime")]
+const LOAD_MARGIN_HOURS: i64 = 24;
+
+#[derive(Debug, Clone, Copy)]
+struct SpotPeriod {
+    end: DateTime<Utc>,
+    price: Decimal,
+}
+
+/// Day-ahead prices per area and delivery period, hourly or quarter-hourly, all in the same
+/// currency, EUR by default
+#[derive(Debug, Clone)]
+pub struct SpotPrices {
+    periods: HashMap<Area, BTreeMap<DateTime<Utc>, SpotPeriod>>,
+    currency: Currency,
+}
+
+impl Default for SpotPrices {
+    fn default() -> Self {
+        Self {
+            periods: HashMap::new(),
+            currency: Currency::Eur,
+        }
+    }
+}
+
+impl SpotPrices {
+    /// The prices as being in the currency
+    pub fn with_currency(mut self, currency: Currency) -> Self {
+        self.currency = currency;
+        self
+    }
+
+    pub fn currency(&self) -> Currency {
+        self.currency
+    }
+
+    pub fn insert(
+        &mut self,
+        area: Area,
+        delivery_start: DateTime<Utc>,
+        delivery_end: DateTime<Utc>,
+        price: Decimal,
+    ) {
+        self.periods.entry(area).or_default().insert(
+            delivery_start,
+            SpotPeriod {
+                end: delivery_end,
+                price,
+            },
+        );
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.periods.is_empty()
+    }
+
+    /// The value at the spot prices of a MW delivered between start and end, `None` if a part
+    /// of the delivery has no price
+    pub fn value(
+        &self,
+        area: Area,
+        delivery_start: &DateTime<FixedOffset>,
+        delivery_end: &DateTime<FixedOffset>,
+    ) -> Option<Decimal> {
+        let periods = self.periods.get(&area)?;
+        let (start, end) = (delivery_start.to_utc(), delivery_end.to_utc());
+        // The period the delivery starts in starts at or before it
+        let first = periods
+            .range(..=start)
+            .next_back()
+            .map_or(start, |(period_start, _)| *period_start);
+        let mut covered = start;
+        let mut value = Decimal::ZERO;
+        for (period_start, period) in periods.range(first..end) {
+            if period.end <= covered {
+                continue;
+            }
+            if *period_start > covered {
+                return None;
+            }
+            let until = period.end.min(end);
+            value += hours(until - covered) * period.price;
+            covered = until;
+        }
+        (covered >= end).then_some(value)
+    }
+
+    /// What the trade gained over trading the same energy at the spot prices: sold above or
+    /// bought below them. `None` for trades without a price, settled in another currency than
+    /// the prices or without spot prices for their delivery.
+    pub fn pnl_vs_spot(&self, trade: &Trade) -> Option<Decimal> {
+        if trade.currency != self.currency {
+            return None;
+        }
+        let price = trade.price?;
+        let mw = trade.quantity_mwh.abs();
+        let at_spot = mw * self.value(trade.area, &trade.delivery_start, &trade.delivery_end)?;
+        let traded = mw * hours(trade.delivery_end - trade.delivery_start) * price;
+        Some(if trade.quantity_mwh < Decimal::ZERO {
+            traded - at_spot
+        } else {
+            at_spot - traded
+        })
+    }
+
+    /// The prices converted to the reporting currency, at the rate of the start of each period,
+    /// to benchmark the trades of reports built with `ReportBuilder::fx`
+    pub fn converted(&self, rates: &FxRates) -> Result<SpotPrices> {
+        let mut converted = SpotPrices::default().with_currency(rates.reporting_currency());
+        for (area, periods) in &self.periods {
+            for (start, period) in periods {
+                let rate = rates.rate(self.currency, &start.fixed_offset())?;
+                converted.insert(*area, *start, period.end, period.price * rate);
+            }
+        }
+        Ok(converted)
+    }
+
+    /// The prices of the periods around the window from the configured table or ENTSO-E
+    #[cfg(feature = "runtime")]
+    pub async fn load(
+        config: &SpotPriceConfig,
+        pool: &PgPool,
+        secrets: &Secrets,
+        delivery_from: &DateTime<Tz>,
+        delivery_to: &DateTime<Tz>,
+    ) -> Result<Self> {
+        match (&config.table, &config.entsoe_token_secret) {
+            (Some(table), _) => Ok(Self::load_table(pool, table, delivery_from, delivery_to)
+                .await?
+                .with_currency(config.currency)),
+            (None, Some(_)) => Self::load_entsoe(config, secrets, delivery_from, delivery_to).await,
+            (None, None) => bail!("Configure a table or an ENTSO-E token under spot_prices"),
+        }
+    }
+
+    /// The prices of the periods around the window from ENTSO-E, with the token of the configured
+    /// secret, for reports of trades read from elsewhere than the database
+    #[cfg(feature = "runtime")]
+    pub async fn load_entsoe(
+        config: &SpotPriceConfig,
+        secrets: &Secrets,
+        delivery_from: &DateTime<Tz>,
+        delivery_to: &DateTime<Tz>,
+    ) -> Result<Self> {
+        let Some(secret) = &config.entsoe_token_secret else {
+            bail!("Configure an ENTSO-E token under spot_prices");
+        };
+        let margin = Duration::hours(LOAD_MARGIN_HOURS);
+        let from = delivery_from.to_utc() - margin;
+        let to = delivery_to.to_utc() + margin;
+        let token = secrets.get(secret).await?;
+        let client = EntsoeClient::new(&config.entsoe_url, &token);
+        let areas = match config.entsoe_areas.as_slice() {
+            [] => Area::KNOWN
+                .into_iter()
+                .filter(|area| bidding_zone(*area).is_some())
+                .collect(),
+            areas => areas.to_vec(),
+        };
+        let mut prices = SpotPrices::default();
+        for area in areas {
+            prices.extend(client.day_ahead_prices(area, &from, &to).await?);
+        }
+        Ok(prices)
+    }
+
+    /// The prices of the periods around the window in a table with the columns `area`,
+    /// `delivery_start`, `delivery_end` and `price`, day-ahead or e.g. imbalance prices
+    #[cfg(feature = "runtime")]
+    pub async fn load_table(
+        pool: &PgPool,
+        table: &str,
+        delivery_from: &DateTime<Tz>,
+        delivery_to: &DateTime<Tz>,
+    ) -> Result<Self> {
+        let margin = Duration::hours(LOAD_MARGIN_HOURS);
+        let from = delivery_from.to_utc() - margin;
+        let to = delivery_to.to_utc() + margin;
+        let query = format!(
+            "SELECT area, delivery_start, delivery_end, price FROM \"{}\" \
+             WHERE delivery_end > $1 AND delivery_start < $2",
+            table.replace('"', "\"\"")
+        );
+        let rows: Vec<(Area, DateTime<Utc>, DateTime<Utc>, Decimal)> = sqlx::query_as(&query)
+            .bind(from)
+            .bind(to)
+            .fetch_all(pool)
+            .await
+            .with_context(|| format!("Could not read the prices of {}", table))?;
+        let mut prices = SpotPrices::default();
+        for (area, start, end, price) in rows {
+            prices.insert(area, start, end, price);
+        }
+        Ok(prices)
+    }
+
+    #[cfg(feature = "runtime")]
+    fn extend(&mut self, other: SpotPrices) {
+        for (area, periods) in other.periods {
+            self.periods.entry(area).or_default().extend(periods);
+        }
+    }
+}
+
+fn hours(duration: Duration) -> Decimal {
+    Decimal::from(duration.num_seconds()) / Decimal::from(3600)
+}
+
+/// The EIC code of the bidding zone of an area on ENTSO-E, which has no prices for GB since it
+/// left the internal market, nor for areas we don't know
+pub fn bidding_zone(area: Area) -> Option<&'static str> {
+    match area {
+        Area::Amp => Some("10Y1001A1001A82H"),
+        Area::DK1 => Some("10YDK-1--------W"),
+        Area::DK2 => Some("10YDK-2--------M"),
+        Area::FR => Some("10YFR-RTE------C"),
+        Area::NL => Some("10YNL----------L"),
+        Area::NO2 => Some("10YNO-2--------T"),
+        Area::SE1 => Some("10Y1001A1001A44P"),
+        Area::SE3 => Some("10Y1001A1001A46L"),
+        Area::GB | Area::Other(_) => None,
+    }
+}
+
+/// Day-ahead prices from the ENTSO-E transparency platform, document type A44
+#[cfg(feature = "runtime")]
+pub struct EntsoeClient {
+    url: String,
+    token: String,
+    client: reqwest::Client,
+}
+
+#[cfg(feature = "runtime")]
+impl EntsoeClient {
+    pub fn new(url: &str, token: &str) -> Self {
+        Self {
+            url: url.to_string(),
+            token: token.to_string(),
+            client: reqwest::Client::new(),
+        }
+    }
+
+    pub async fn day_ahead_prices(
+        &self,
+        area: Area,
+        from: &DateTime<Utc>,
+        to: &DateTime<Utc>,
+    ) -> Result<SpotPrices> {
+        let zone = bidding_zone(area).ok_or(anyhow!(
+            "ENTSO-E has no prices for {}, load them from a table",
+            area
+        ))?;
+        let period = |time: &DateTime<Utc>| time.format("%Y%m%d%H%M").to_string();
+        let document = self
+            .client
+            .get(&self.url)
+            .query(&[
+                ("securityToken", self.token.as_str()),
+                ("documentType", "A44"),
+                ("in_Domain", zone),
+                ("out_Domain", zone),
+                ("periodStart", &period(from)),
+                ("periodEnd", &period(to)),
+            ])
+            .send()
+            .await
+            .and_then(|response| response.error_for_status())
+            .with_context(|| format!("Could not fetch the day-ahead prices of {}", area))?
+            .text()
+            .await?;
+        parse_day_ahead_prices(area, &document)
+            .with_context(|| format!("Invalid day-ahead prices of {} from ENTSO-E", area))
+    }
+}
+
+#[cfg(feature = "runtime")]
+#[derive(Debug, Deserialize)]
+struct PriceDocument {
+    #[serde(rename = "TimeSeries", default)]
+    time_series: Vec<TimeSeries>,
+}
+
+#[cfg(feature = "runtime")]
+#[derive(Debug, Deserialize)]
+struct TimeSeries {
+    #[serde(rename = "Period", default)]
+    periods: Vec<PricePeriod>,
+}
+
+#[cfg(feature = "runtime")]
+#[derive(Debug, Deserialize)]
+struct PricePeriod {
+    #[serde(rename = "timeInterval")]
+    time_interval: TimeInterval,
+    resolution: String,
+    #[serde(rename = "Point", default)]
+    points: Vec<PricePoint>,
+}
+
+#[cfg(feature = "runtime")]
+#[derive(Debug, Deserialize)]
+struct TimeInterval {
+    start: String,
+    end: String,
+}
+
+#[cfg(feature = "runtime")]
+#[derive(Debug, Deserialize)]
+struct PricePoint {
+    position: usize,
+    #[serde(rename = "price.amount")]
+    price: String,
+}
+
+/// Reads an ENTSO-E price document. Points equal to the one before are left out of documents,
+/// so positions without a point take the price of the last one. Documents without time series,
+/// e.g. acknowledgements that there is no data, have no prices.
+#[cfg(feature = "runtime")]
+pub fn parse_day_ahead_prices(area: Area, document: &str) -> Result<SpotPrices> {
+    let document: PriceDocument = quick_xml::de::from_str(document)?;
+    let time = |value: &str| {
+        NaiveDateTime::parse_from_str(value, "%Y-%m-%dT%H:%MZ")
+            .map(|time| time.and_utc())
+            .with_context(|| format!("Invalid time {}", value))
+    };
+    let mut prices = SpotPrices::default();
+    for period in document
+        .time_series
+        .iter()
+        .flat_map(|series| &series.periods)
+    {
+        let minutes = period
+            .resolution
+            .strip_prefix("PT")
+            .and_then(|resolution| resolution.strip_suffix('M'))
+            .and_then(|minutes| minutes.parse().ok())
+            .ok_or(anyhow!("Unknown resolution {}", period.resolution))?;
+        let resolution = Duration::minutes(minutes);
+        let (start, end) = (
+            time(&period.time_interval.start)?,
+            time(&period.time_interval.end)?,
+        );
+        let points: BTreeMap<usize, &str> = period
+            .points
+            .iter()
+            .map(|point| (point.position, point.price.as_str()))
+            .collect();
+        let mut price = None;
+        let mut delivery_start = start;
+        let mut position = 1;
+        while delivery_start < end {
+            if let Some(point) = points.get(&position) {
+                price = Some(
+                    Decimal::from_str(point)
+                        .with_context(|| format!("Invalid price {} at {}", point, position))?,
+                );
+            }
+            let price = price.ok_or(anyhow!("No price at the start of {}", start))?;
+            prices.insert(area, delivery_start, delivery_start + resolution, price);
+            delivery_start += resolution;
+            position += 1;
+        }
+    }
+    Ok(prices)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use rust_decimal_macros::dec;
+
+    fn time(value: &str) -> DateTime<Utc> {
+        DateTime::parse_from_rfc3339(value).unwrap().to_utc()
+    }
+
+    #[test]
+    fn trades_are_benchmarked_against_the_spot_prices_of_their_delivery() {
+        let mut prices = SpotPrices::default();
+        prices.insert(
+            Area::DK1,
+            time("2024-05-01T10:00:00Z"),
+            time("2024-05-01T11:00:00Z"),
+            dec!(40),
+        );
+        // Quarter hours, like the day-ahead auction since October 2025
+        for (quarter, price) in [dec!(60), dec!(60), dec!(80), dec!(80)]
+            .into_iter()
+            .enumerate()
+        {
+            let start = time("2024-05-01T11:00:00Z") + Duration::minutes(15 * quarter as i64);
+            prices.insert(Area::DK1, start, start + Duration::minutes(15), price);
+        }
+
+        let sold = Trade::test().sell().mwh(10).price(50).build();
+        assert_eq!(prices.pnl_vs_spot(&sold), Some(dec!(100)));
+        let bought = Trade::test()
+            .mwh(10)
+            .price(50)
+            .hour("2024-05-01T13:00+02:00")
+            .build();
+        assert_eq!(prices.pnl_vs_spot(&bought), Some(dec!(200)));
+        // Delivered partly without a price
+        let mut two_hours = Trade::test().mwh(10).price(50).build();
+        two_hours.delivery_end += Duration::hours(2);
+        assert_eq!(prices.pnl_vs_spot(&two_hours), None);
+        let gb = Trade::test()
+            .area(Area::GB)
+            .currency(Currency::Gbp)
+            .mwh(10)
+            .price(50)
+            .build();
+        assert_eq!(prices.pnl_vs_spot(&gb), None);
+        // Settled in another currency than the prices
+        let in_gbp = Trade::test()
+            .currency(Currency::Gbp)
+            .sell()
+            .mwh(10)
+            .price(50)
+            .build();
+        assert_eq!(prices.pnl_vs_spot(&in_gbp), None);
+    }
+
+    #[cfg(feature = "runtime")]
+    #[test]
+    fn missing_points_of_entsoe_documents_repeat_the_last_price() {
+        let document = r#"<?xml version="1.0" encoding="UTF-8"?>
+            <Publication_MarketDocument xmlns="urn:iec62325.351:tc57wg16:451-3:publicationdocument:7:3">
+              <mRID>1</mRID>
+              <TimeSeries>
+                <mRID>1</mRID>
+                <Period>
+                  <timeInterval>
+                    <start>2024-04-30T22:00Z</start>
+                    <end>2024-05-01T01:00Z</end>
+                  </timeInterval>
+                  <resolution>PT60M</resolution>
+                  <Point><position>1</position><price.amount>41.5</price.amount></Point>
+                  <Point><position>3</position><price.amount>-2.1</price.amount></Point>
+                </Period>
+              </TimeSeries>
+            </Publication_MarketDocument>"#;
+        let prices = parse_day_ahead_prices(Area::DK1, document).unwrap();
+        let value = |start: &str, end: &str| {
+            prices.value(
+                Area::DK1,
+                &DateTime::parse_from_rfc3339(start).unwrap(),
+                &DateTime::parse_from_rfc3339(end).unwrap(),
+            )
+        };
+
+        assert_eq!(
+            value("2024-05-01T00:00:00+02:00", "2024-05-01T03:00:00+02:00"),
+            Some(dec!(80.9))
+        );
+        assert_eq!(
+            value("2024-05-01T01:00:00+02:00", "2024-05-01T04:00:00+02:00"),
+            None
+        );
+        assert!(parse_day_ahead_prices(
+            Area::DK1,
+            "<Acknowledgement_MarketDocument><mRID>1</mRID></Acknowledgement_MarketDocument>"
+        )
+        .unwrap()
+        .is_empty());
+    }
+}
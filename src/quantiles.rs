@@ -0,0 +1,318 @@
+use std::collections::BTreeMap;
+use std::f64::consts::PI;
+
+use anyhow::Result;
+use rust_decimal::prelude::{FromPrimitive, ToPrimitive};
+use rust_decimal::Decimal;
+use serde::{Deserialize, Serialize};
+
+use crate::report::Metric;
+use crate::trade::{Area, Market, Trade, TradeSide};
+
+/// Compression of the digests, bounding them to a few hundred centroids. Quantiles are then
+/// typically within a fraction of a percent of the exact ones, closer towards the tails.
+const COMPRESSION: f64 = 100.0;
+
+/// Values buffered before they're merged into the centroids
+const BUFFER_SIZE: usize = 500;
+
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+struct Centroid {
+    mean: f64,
+    weight: f64,
+}
+
+/// A merging t-digest (Dunning & Ertl), summarizing a distribution in a bounded number of
+/// centroids, small around the tails and larger around the median, from which quantiles are
+/// interpolated in a single pass without keeping the values.
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct TDigest {
+    centroids: Vec<Centroid>,
+    buffer: Vec<Centroid>,
+    min: f64,
+    max: f64,
+}
+
+/// The k1 scale function, mapping a quantile to the index of its centroid
+fn scale(quantile: f64) -> f64 {
+    COMPRESSION / (2.0 * PI) * (2.0 * quantile - 1.0).asin()
+}
+
+fn inverse_scale(k: f64) -> f64 {
+    ((k * 2.0 * PI / COMPRESSION).sin() + 1.0) / 2.0
+}
+
+impl TDigest {
+    pub fn add(&mut self, value: f64) {
+        if self.is_empty() {
+            (self.min, self.max) = (value, value);
+        }
+        self.min = self.min.min(value);
+        self.max = self.max.max(value);
+        self.buffer.push(Centroid {
+            mean: value,
+            weight: 1.0,
+        });
+        if self.buffer.len() >= BUFFER_SIZE {
+            self.compress();
+        }
+    }
+
+    pub fn merge(&mut self, other: &TDigest) {
+        if other.is_empty() {
+            return;
+        }
+        if self.is_empty() {
+            (self.min, self.max) = (other.min, other.max);
+        }
+        self.min = self.min.min(other.min);
+        self.max = self.max.max(other.max);
+        self.buffer
+            .extend(other.centroids.iter().chain(other.buffer.iter()));
+        self.compress();
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.centroids.is_empty() && self.buffer.is_empty()
+    }
+
+    /// Number of values added
+    pub fn count(&self) -> f64 {
+        self.centroids
+            .iter()
+            .chain(self.buffer.iter())
+            .map(|centroid| centroid.weight)
+            .sum()
+    }
+
+    /// Merges the buffered values into the centroids, combining neighbours as long as the
+    /// combined centroid stays within one unit of the scale function
+    fn compress(&mut self) {
+        if self.buffer.is_empty() {
+            return;
+        }
+        let mut centroids = std::mem::take(&mut self.centroids);
+        centroids.append(&mut self.buffer);
+        centroids.sort_by(|a, b| a.mean.total_cmp(&b.mean));
+        let total: f64 = centroids.iter().map(|centroid| centroid.weight).sum();
+
+        let mut merged: Vec<Centroid> = Vec::new();
+        let mut weight_before = 0.0;
+        let mut limit = total * inverse_scale(scale(0.0) + 1.0);
+        for centroid in centroids {
+            match merged.last_mut() {
+                Some(last) if weight_before + last.weight + centroid.weight <= limit => {
+                    last.mean += (centroid.mean - last.mean) * centroid.weight
+                        / (last.weight + centroid.weight);
+                    last.weight += centroid.weight;
+                }
+                last => {
+                    if let Some(last) = last {
+                        weight_before += last.weight;
+                    }
+                    limit = total * inverse_scale(scale(weight_before / total) + 1.0);
+                    merged.push(centroid);
+                }
+            }
+        }
+        self.centroids = merged;
+    }
+
+    /// The approximate quantile, e.g. 0.5 for the median, interpolated between the centres of
+    /// the centroids around it. `None` without values.
+    pub fn quantile(&mut self, quantile: f64) -> Option<f64> {
+        self.compress();
+        let total = self.count();
+        if total == 0.0 {
+            return None;
+        }
+        let target = quantile.clamp(0.0, 1.0) * total;
+
+        // The centre of each centroid is at its weight before plus half its own weight, with the
+        // extremes at the ends
+        let mut previous = (0.0, self.min);
+        let mut weight_before = 0.0;
+        for centroid in self.centroids.iter() {
+            let centre = (weight_before + centroid.weight / 2.0, centroid.mean);
+            if target < centre.0 {
+                return Some(interpolate(previous, centre, target));
+            }
+            previous = centre;
+            weight_before += centroid.weight;
+        }
+        Some(interpolate(previous, (total, self.max), target))
+    }
+}
+
+/// The price statistics of `Metric` over the prices traded at, in memory bounded however many
+/// trades there are: the lowest and highest prices are exact, and the median is interpolated
+/// from a `TDigest`, exact while there are too few prices for its centroids to merge.
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct PriceStatistics {
+    min: Option<Decimal>,
+    max: Option<Decimal>,
+    digest: TDigest,
+}
+
+impl PriceStatistics {
+    pub fn add(&mut self, price: Decimal) {
+        self.min = Some(self.min.map_or(price, |min| min.min(price)));
+        self.max = Some(self.max.map_or(price, |max| max.max(price)));
+        if let Some(price) = price.to_f64() {
+            self.digest.add(price);
+        }
+    }
+
+    pub fn merge(&mut self, other: &PriceStatistics) {
+        if let Some(min) = other.min {
+            self.min = Some(self.min.map_or(min, |own| own.min(min)));
+        }
+        if let Some(max) = other.max {
+            self.max = Some(self.max.map_or(max, |own| own.max(max)));
+        }
+        self.digest.merge(&other.digest);
+    }
+
+    /// The price statistic rounded to cents, zero without any prices or for other metrics
+    pub fn statistic(&mut self, metric: Metric) -> Decimal {
+        let value = match metric {
+            Metric::MinPrice => self.min,
+            Metric::MaxPrice => self.max,
+            Metric::MedianPrice => self.digest.quantile(0.5).and_then(Decimal::from_f64),
+            _ => None,
+        };
+        value.unwrap_or_default().round_dp(2)
+    }
+}
+
+fn interpolate((x0, y0): (f64, f64), (x1, y1): (f64, f64), x: f64) -> f64 {
+    if x1 <= x0 {
+        return y1;
+    }
+    y0 + (y1 - y0) * (x - x0) / (x1 - x0)
+}
+
+/// Approximate p10, p50 and p90 of the prices traded at per area, market and side, per trade
+/// regardless of its volume like the price statistics of `Report`. Sides are derived from the
+/// sign of the quantity, and trades without a price are skipped.
+#[derive(Debug, Default, Clone)]
+pub struct PriceQuantiles {
+    digests: BTreeMap<(Area, Market, TradeSide), TDigest>,
+    skipped_trades: usize,
+}
+
+impl PriceQuantiles {
+    pub fn add_trade(&mut self, trade: &Trade) -> Result<()> {
+        let Some(price) = trade.price.and_then(|price| price.to_f64()) else {
+            self.skipped_trades += 1;
+            return Ok(());
+        };
+        let side = if trade.quantity_mwh < Decimal::ZERO {
+            TradeSide::Sell
+        } else {
+            TradeSide::Buy
+        };
+        self.digests
+            .entry((trade.area, Market::from(trade.trade_type), side))
+            .or_default()
+            .add(price);
+        Ok(())
+    }
+
+    pub fn merge(&mut self, other: Self) {
+        for (key, digest) in other.digests {
+            self.digests.entry(key).or_default().merge(&digest);
+        }
+        self.skipped_trades += other.skipped_trades;
+    }
+
+    pub fn skipped_trades(&self) -> usize {
+        self.skipped_trades
+    }
+
+    /// Rows of the number of trades and the quantiles of their prices per area, market and side
+    pub fn records(&mut self) -> Vec<Vec<String>> {
+        self.digests
+            .iter_mut()
+            .map(|((area, market, side), digest)| {
+                let mut quantile = |quantile| {
+                    digest
+                        .quantile(quantile)
+                        .and_then(Decimal::from_f64)
+                        .map_or(String::new(), |price| price.round_dp(2).to_string())
+                };
+                let quantiles = [quantile(0.1), quantile(0.5), quantile(0.9)];
+                let mut record = vec![
+                    area.to_string(),
+                    market.to_string(),
+                    side.to_string(),
+                    digest.count().to_string(),
+                ];
+                record.extend(quantiles);
+                record
+            })
+            .collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn price_statistics_stay_bounded_and_the_median_close() {
+        let mut statistics = PriceStatistics::default();
+        for index in 0..100_000u64 {
+            statistics.add(Decimal::new(((index * 7_919) % 100_000 + 1) as i64, 2));
+        }
+
+        assert!(statistics.digest.centroids.len() + statistics.digest.buffer.len() < 700);
+        assert_eq!(statistics.statistic(Metric::MinPrice), Decimal::new(1, 2));
+        assert_eq!(statistics.statistic(Metric::MaxPrice), Decimal::from(1_000));
+        let median = statistics.statistic(Metric::MedianPrice);
+        assert!(
+            (median - Decimal::from(500)).abs() < Decimal::from(3),
+            "{}",
+            median
+        );
+        assert_eq!(
+            PriceStatistics::default().statistic(Metric::MedianPrice),
+            Decimal::ZERO
+        );
+    }
+
+    #[test]
+    fn quantiles_of_a_long_stream_are_close_to_the_exact_ones() {
+        let mut digest = TDigest::default();
+        // Prices 1 - 10000 in a scrambled order
+        for index in 0..10_000u64 {
+            digest.add(((index * 7_919) % 10_000 + 1) as f64);
+        }
+
+        assert!(digest.centroids.len() < 200);
+        assert_eq!(digest.count(), 10_000.0);
+        for (quantile, exact) in [(0.1, 1_000.0), (0.5, 5_000.0), (0.9, 9_000.0)] {
+            let approximate = digest.quantile(quantile).unwrap();
+            assert!(
+                (approximate - exact).abs() < 25.0,
+                "p{} is {}, not about {}",
+                quantile * 100.0,
+                approximate,
+                exact
+            );
+        }
+        assert_eq!(digest.quantile(0.0), Some(1.0));
+        assert_eq!(digest.quantile(1.0), Some(10_000.0));
+
+        let mut quantiles = PriceQuantiles::default();
+        for price in [40, 50, 60] {
+            quantiles
+                .add_trade(&Trade::test().mwh(1).price(price).build())
+                .unwrap();
+        }
+        assert_eq!(
+            quantiles.records(),
+            vec![vec!["DK1", "intraday", "buy", "3", "40", "50", "60"]]
+        );
+    }
+}
@@ -0,0 +1,256 @@
+use std::collections::BTreeMap;
+use std::fs::File;
+use std::io::Read;
+use std::path::Path;
+use std::str::FromStr;
+
+use anyhow::{anyhow, Context, Result};
+use chrono::{DateTime, Days, NaiveDate, NaiveTime, TimeZone};
+use chrono_tz::Tz;
+use rust_decimal::Decimal;
+use serde::{Deserialize, Serialize};
+
+use crate::report::contract_length;
+use crate::trade::{CounterPart, Trade};
+
+/// How our trades are compared to the end of day summaries of the exchanges, e.g.
+///
+/// ```toml
+/// [reconciliation]
+/// tolerance = 0.001
+/// alert_webhook = "https://hooks.example.com/reconciliation"
+/// ```
+#[derive(Debug, Deserialize, Clone)]
+#[serde(default)]
+pub struct ReconciliationConfig {
+    /// Timezone the delivery days of the summaries are local to
+    pub timezone: String,
+    /// Largest difference in volume or turnover, relative to the exchange's, that isn't a mismatch
+    pub tolerance: Decimal,
+    /// URL the mismatches are posted to as JSON, when there are any
+    pub alert_webhook: Option<String>,
+}
+
+impl Default for ReconciliationConfig {
+    fn default() -> Self {
+        Self {
+            timezone: "Europe/Copenhagen".to_string(),
+            tolerance: Decimal::new(1, 3),
+            alert_webhook: None,
+        }
+    }
+}
+
+/// Energy and gross cash flow traded with a counter part on a delivery day, regardless of side
+#[derive(Debug, Default, Clone, Copy, PartialEq, Serialize)]
+pub struct DailyTotals {
+    pub volume_mwh: Decimal,
+    pub turnover: Decimal,
+}
+
+/// A row of an exchange's end of day summary. Fields are read as text, like exchange files, so
+/// decimals are parsed exactly.
+#[derive(Debug, Deserialize)]
+struct SummaryRow {
+    counter_part: String,
+    delivery_day: String,
+    volume_mwh: String,
+    turnover: String,
+}
+
+pub type ExchangeTotals = BTreeMap<(CounterPart, NaiveDate), DailyTotals>;
+
+pub fn read_summary_file(path: &Path, totals: &mut ExchangeTotals) -> Result<()> {
+    let file = File::open(path).with_context(|| format!("Could not open {}", path.display()))?;
+    read_summary(file, totals).with_context(|| format!("Invalid summary {}", path.display()))
+}
+
+/// Adds the totals of a CSV summary with the columns `counter_part`, `delivery_day`,
+/// `volume_mwh` and `turnover`. Rows of the same counter part and day, e.g. one per market,
+/// are summed.
+pub fn read_summary<R: Read>(reader: R, totals: &mut ExchangeTotals) -> Result<()> {
+    let mut reader = csv::Reader::from_reader(reader);
+    for (index, row) in reader.deserialize::<SummaryRow>().enumerate() {
+        let line = index + 2;
+        let row = row.with_context(|| format!("Could not read line {}", line))?;
+        let day = NaiveDate::parse_from_str(&row.delivery_day, "%Y-%m-%d")
+            .with_context(|| format!("Invalid delivery_day on line {}", line))?;
+        let decimal = |value: &str, column: &str| {
+            Decimal::from_str(value).with_context(|| format!("Invalid {} on line {}", column, line))
+        };
+        let entry = totals
+            .entry((CounterPart::from(row.counter_part), day))
+            .or_default();
+        entry.volume_mwh += decimal(&row.volume_mwh, "volume_mwh")?;
+        entry.turnover += decimal(&row.turnover, "turnover")?;
+    }
+    Ok(())
+}
+
+/// Our totals next to the exchange's for a counter part and delivery day
+#[derive(Debug, Serialize)]
+pub struct ReconciliationRow {
+    pub counter_part: CounterPart,
+    pub delivery_day: NaiveDate,
+    pub exchange: DailyTotals,
+    pub ours: DailyTotals,
+    pub mismatch: bool,
+}
+
+impl ReconciliationRow {
+    pub fn record(&self) -> Vec<String> {
+        vec![
+            self.counter_part.to_string(),
+            self.delivery_day.to_string(),
+            self.exchange.volume_mwh.to_string(),
+            self.ours.volume_mwh.round_dp(3).to_string(),
+            self.exchange.turnover.to_string(),
+            self.ours.turnover.round_dp(2).to_string(),
+            if self.mismatch { "mismatch" } else { "ok" }.to_string(),
+        ]
+    }
+}
+
+/// Whether ours differs from the exchange's value by more than the tolerance, relative to the
+/// exchange's. Anything but zero is a mismatch against a zero reported by the exchange.
+fn differs(exchange: Decimal, ours: Decimal, tolerance: Decimal) -> bool {
+    if exchange.is_zero() {
+        return !ours.is_zero();
+    }
+    (ours - exchange).abs() / exchange.abs() > tolerance
+}
+
+/// Our totals per counter part and delivery day, compared to the totals the exchanges report
+/// for the same days. Trades are counted like in `Concentration`, but trades without a price
+/// still count towards the volume, as the exchange has them as well.
+pub struct Reconciliation {
+    timezone: Tz,
+    tolerance: Decimal,
+    exchange: ExchangeTotals,
+    ours: BTreeMap<(CounterPart, NaiveDate), DailyTotals>,
+}
+
+impl Reconciliation {
+    pub fn new(config: &ReconciliationConfig, exchange: ExchangeTotals) -> Result<Self> {
+        Ok(Self {
+            timezone: config.timezone.parse().map_err(|_| {
+                anyhow!(
+                    "Unknown timezone in reconciliation config: {}",
+                    config.timezone
+                )
+            })?,
+            tolerance: config.tolerance,
+            exchange,
+            ours: BTreeMap::new(),
+        })
+    }
+
+    /// The delivery window covering every day of the summaries
+    pub fn delivery_window(&self) -> Result<(DateTime<Tz>, DateTime<Tz>)> {
+        let days = || self.exchange.keys().map(|(_, day)| *day);
+        let (Some(first), Some(last)) = (days().min(), days().max()) else {
+            return Err(anyhow!("No days in the summaries to reconcile"));
+        };
+        let start_of = |day: NaiveDate| {
+            self.timezone
+                .from_local_datetime(&day.and_time(NaiveTime::MIN))
+                .earliest()
+                .ok_or(anyhow!("No start of {} in {}", day, self.timezone))
+        };
+        let after_last = last
+            .checked_add_days(Days::new(1))
+            .ok_or(anyhow!("No day after {}", last))?;
+        Ok((start_of(first)?, start_of(after_last)?))
+    }
+
+    pub fn add_trade(&mut self, trade: &Trade) -> Result<()> {
+        let day = trade
+            .delivery_start
+            .with_timezone(&self.timezone)
+            .date_naive();
+        let mwh =
+            trade.quantity_mwh.abs() * contract_length(&trade.delivery_start, &trade.delivery_end)?;
+        let totals = self
+            .ours
+            .entry((trade.counter_part.clone(), day))
+            .or_default();
+        totals.volume_mwh += mwh;
+        if let Some(price) = trade.price {
+            totals.turnover += mwh * price.abs();
+        }
+        Ok(())
+    }
+
+    /// A row per counter part and day of the summaries
+    pub fn rows(&self) -> Vec<ReconciliationRow> {
+        self.exchange
+            .iter()
+            .map(|((counter_part, day), &exchange)| {
+                let ours = self
+                    .ours
+                    .get(&(counter_part.clone(), *day))
+                    .copied()
+                    .unwrap_or_default();
+                ReconciliationRow {
+                    counter_part: counter_part.clone(),
+                    delivery_day: *day,
+                    exchange,
+                    ours,
+                    mismatch: differs(exchange.volume_mwh, ours.volume_mwh, self.tolerance)
+                        || differs(exchange.turnover, ours.turnover, self.tolerance),
+                }
+            })
+            .collect()
+    }
+}
+
+/// Posts the mismatches to the webhook as `{"mismatches": [...]}`
+pub async fn alert(url: &str, mismatches: &[&ReconciliationRow]) -> Result<()> {
+    reqwest::Client::new()
+        .post(url)
+        .json(&serde_json::json!({ "mismatches": mismatches }))
+        .send()
+        .await?
+        .error_for_status()
+        .with_context(|| format!("Could not post reconciliation alert to {}", url))?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn days_missing_trades_are_mismatches() {
+        let summary = "\
+counter_part,delivery_day,volume_mwh,turnover
+nordpool,2024-05-01,10,400
+nordpool,2024-05-02,5.0,200.00
+";
+        let mut totals = ExchangeTotals::new();
+        read_summary(summary.as_bytes(), &mut totals).unwrap();
+        let mut reconciliation =
+            Reconciliation::new(&ReconciliationConfig::default(), totals).unwrap();
+
+        let (from, to) = reconciliation.delivery_window().unwrap();
+        assert_eq!(from.to_rfc3339(), "2024-05-01T00:00:00+02:00");
+        assert_eq!(to.to_rfc3339(), "2024-05-03T00:00:00+02:00");
+
+        // The trade file of the second day is missing a trade of 1 MWh
+        for (mwh, hour) in [
+            (10, "2024-05-01T12:00+02:00"),
+            (4, "2024-05-02T12:00+02:00"),
+        ] {
+            reconciliation
+                .add_trade(&Trade::test().mwh(mwh).price(40).hour(hour).build())
+                .unwrap();
+        }
+
+        let statuses: Vec<String> = reconciliation
+            .rows()
+            .iter()
+            .map(|row| row.record()[6].clone())
+            .collect();
+        assert_eq!(statuses, vec!["ok", "mismatch"]);
+    }
+}
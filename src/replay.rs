@@ -0,0 +1,66 @@
+use std::time::Duration;
+
+use anyhow::{bail, Result};
+use chrono::{DateTime, FixedOffset};
+use chrono_tz::Tz;
+use futures::TryStreamExt;
+use sqlx::PgPool;
+
+use crate::db::get_trades_stream_ordered;
+use crate::report::Report;
+use crate::trade::{Decoding, Trade};
+use crate::units::QuantityUnits;
+
+/// Replays historical trades into an incrementally updated `Report`.
+///
+/// The trade tables have no execution timestamp, so trades are replayed in delivery order and
+/// the replay clock follows `delivery_start`: with a speed of 3600, one hour of deliveries is
+/// replayed per second. A speed of `None` replays as fast as the trades can be fetched.
+pub struct Replay {
+    speed: Option<f64>,
+}
+
+impl Replay {
+    pub fn new(speed: Option<f64>) -> Result<Self> {
+        if speed.is_some_and(|speed| !speed.is_finite() || speed <= 0.0) {
+            bail!("Replay speed has to be a positive number");
+        }
+
+        Ok(Self { speed })
+    }
+
+    /// Runs the replay, calling `on_trade` with each trade and the report after it was added.
+    pub async fn run<F>(
+        &self,
+        pool: &PgPool,
+        delivery_from: &DateTime<Tz>,
+        delivery_to: &DateTime<Tz>,
+        units: &QuantityUnits,
+        decoding: Decoding,
+        mut on_trade: F,
+    ) -> Result<Report>
+    where
+        F: FnMut(&Trade, &Report),
+    {
+        let mut report = Report::empty(delivery_from, delivery_to)?;
+        let mut trades =
+            get_trades_stream_ordered(pool, delivery_from, delivery_to, units, decoding);
+        let mut previous_delivery_start: Option<DateTime<FixedOffset>> = None;
+
+        while let Some(trade) = trades.try_next().await? {
+            if let (Some(speed), Some(previous)) = (self.speed, previous_delivery_start) {
+                let replay_delta = (trade.delivery_start - previous).num_milliseconds();
+                if replay_delta > 0 {
+                    let wait = Duration::from_secs_f64(replay_delta as f64 / 1000.0 / speed);
+                    tokio::time::sleep(wait).await;
+                }
+            }
+            previous_delivery_start = Some(trade.delivery_start);
+
+            report.add_trade(&trade)?;
+            on_trade(&trade, &report);
+        }
+
+        Ok(report)
+    }
+}
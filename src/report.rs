@@ -1,214 +1,1709 @@
+#[cfg(feature = "runtime")]
 use futures::TryStreamExt;
-use std::{collections::HashMap, pin::Pin};
+#[cfg(feature = "runtime")]
+use std::pin::Pin;
+use std::{
+    borrow::Cow,
+    collections::{BTreeMap, BTreeSet, HashMap},
+    sync::{Arc, LazyLock},
+};
 use strum::IntoEnumIterator;
 
-use anyhow::{anyhow, bail, Result};
-use chrono::{DateTime, FixedOffset};
+#[cfg(feature = "runtime")]
+use anyhow::{anyhow, Context};
+use anyhow::{bail, Result};
+#[cfg(feature = "runtime")]
+use chrono::Utc;
+use chrono::{DateTime, Duration, FixedOffset};
 use chrono_tz::Tz;
+#[cfg(feature = "runtime")]
 use futures::Stream;
-use rust_decimal::{prelude::FromPrimitive, Decimal};
+use rayon::prelude::*;
+use rust_decimal::Decimal;
 use serde::{Deserialize, Serialize};
+#[cfg(feature = "runtime")]
 use sqlx::Error; // Should probably map/use anyhow::Error instead in the stream
+#[cfg(feature = "runtime")]
+use sqlx::PgPool;
+use strum_macros::{Display, EnumIter};
+use tracing::instrument;
+#[cfg(feature = "runtime")]
+use tracing::{field, Span};
 
+use crate::bounds::{describe_window, WindowFilter};
+use crate::contributions::{Contribution, ContributionLog};
+#[cfg(feature = "runtime")]
+use crate::db::{TradeSource, TradeStream};
+use crate::fees::FeeSchedule;
+use crate::fx::FxRates;
+use crate::prices::SpotPrices;
+use crate::quantiles::PriceStatistics;
+use crate::sum::DecimalSum;
 use crate::trade::{
-    Area, AreaSelection, Market, MarketSelection, Trade, TradeForReport, TradeSide, TradeType,
+    Area, AreaSelection, CounterPartSelection, Currency, Market, MarketSelection, Trade,
+    TradeForReport, TradeSide, TradeType,
 };
 
+/// Version of the code saved along with reports, so a saved result can be traced to the
+/// aggregation that produced it
+pub const CODE_VERSION: &str = env!("CARGO_PKG_VERSION");
+
+/// Trades per partial report of `Report::new_parallel`, large enough that aggregating a chunk
+/// outweighs merging its report
+const PARALLEL_CHUNK_SIZE: usize = 50_000;
+
+/// The metrics a report exposes, named as in config and outputs
+#[derive(Debug, Serialize, Deserialize, Display, EnumIter, PartialEq, Eq, Hash, Clone, Copy)]
+#[serde(rename_all = "snake_case")]
+#[strum(serialize_all = "snake_case")]
+pub enum Metric {
+    MwBought,
+    MwSold,
+    Revenue,
+    Costs,
+    GrossProfit,
+    /// MWh bought minus sold, negative for a short position
+    NetPosition,
+    /// Revenue minus costs, signed by direction under any sign convention: received positive,
+    /// paid negative
+    NetCashFlow,
+    /// Lowest price traded at, per trade regardless of its volume
+    MinPrice,
+    MaxPrice,
+    /// Median of the prices traded at, per trade regardless of its volume, see
+    /// `quantiles::PriceStatistics` for how it's approximated in bounded memory
+    MedianPrice,
+}
+
+impl Metric {
+    /// Whether the metric is a cash amount or price rather than a volume
+    pub fn is_monetary(&self) -> bool {
+        match self {
+            Metric::MwBought | Metric::MwSold | Metric::NetPosition => false,
+            Metric::Revenue
+            | Metric::Costs
+            | Metric::GrossProfit
+            | Metric::NetCashFlow
+            | Metric::MinPrice
+            | Metric::MaxPrice
+            | Metric::MedianPrice => true,
+        }
+    }
+}
+
+impl Metric {
+    /// How the metric is derived from trades, for lineage in exports
+    pub fn formula(&self) -> &'static str {
+        match self {
+            Metric::MwBought => "Σ |quantity| × delivery hours of buy trades",
+            Metric::MwSold => "Σ |quantity| × delivery hours of sell trades",
+            Metric::Revenue => "Σ |quantity| × delivery hours × price of sell trades",
+            Metric::Costs => "Σ |quantity| × delivery hours × price of buy trades",
+            Metric::GrossProfit => "revenue − costs",
+            Metric::NetPosition => "mw bought − mw sold",
+            Metric::NetCashFlow => "revenue − costs, received positive and paid negative",
+            Metric::MinPrice => "min price of trades",
+            Metric::MaxPrice => "max price of trades",
+            Metric::MedianPrice => {
+                "median price of trades, interpolated from a t-digest of their prices"
+            }
+        }
+    }
+}
+
+/// How metrics are signed in outputs. Aggregations always work on magnitudes, the convention is
+/// only applied when values are output, configured as e.g.
+///
+/// ```toml
+/// sign_convention = "accounting"
+/// ```
+#[derive(Debug, Deserialize, Default, Clone, Copy, PartialEq)]
+#[serde(rename_all = "snake_case")]
+pub enum SignConvention {
+    /// All metrics positive, except a loss as gross profit
+    #[default]
+    Magnitude,
+    /// Volumes signed by direction: bought positive, sold negative
+    Trading,
+    /// Cash flows signed by direction: revenue positive, costs negative
+    Accounting,
+}
+
+impl SignConvention {
+    pub fn apply(&self, metric: Metric, value: Decimal) -> Decimal {
+        match (self, metric) {
+            (SignConvention::Trading, Metric::MwSold)
+            | (SignConvention::Accounting, Metric::Costs) => -value,
+            _ => value,
+        }
+    }
+}
+
+/// What reports do with trades without a price, which can understate volumes when they're
+/// skipped, configured as e.g.
+///
+/// ```toml
+/// missing_prices = "count_volume"
+/// ```
+///
+/// Trades without a price are counted either way, see `Report::missing_price_count`.
+#[derive(Debug, Deserialize, Default, Clone, Copy, PartialEq)]
+#[serde(rename_all = "snake_case")]
+pub enum MissingPricePolicy {
+    /// Left out of every metric
+    #[default]
+    Skip,
+    /// Adding a trade without a price fails
+    Error,
+    /// The energy counts towards the volumes, but not the cash flows or prices
+    CountVolume,
+}
+
+/// How far before a window trades are fetched with `BoundaryPolicy::ProRata`, so contracts of
+/// up to a day that start before the window count with their share inside it
+const PRO_RATA_LOOKBACK_HOURS: i64 = 24;
+
+/// How trades delivered partly outside the window of a report are counted, set in the config:
+///
+/// ```toml
+/// boundary_trades = "pro_rata"
+/// ```
+#[derive(Debug, Deserialize, Default, Clone, Copy, PartialEq)]
+#[serde(rename_all = "snake_case")]
+pub enum BoundaryPolicy {
+    /// Counted in full in the window their delivery starts in
+    #[default]
+    Whole,
+    /// Only the share of the delivery inside the window counts, so a trade delivered over the
+    /// end of one window and the start of the next is split between their reports
+    ProRata,
+}
+
+impl BoundaryPolicy {
+    /// Where trades have to be fetched from for a window, from before its start with `ProRata`.
+    /// Contracts longer than a day that start before the lookback are left out.
+    pub fn fetch_from(&self, delivery_from: &DateTime<Tz>) -> DateTime<Tz> {
+        match self {
+            BoundaryPolicy::Whole => *delivery_from,
+            BoundaryPolicy::ProRata => *delivery_from - Duration::hours(PRO_RATA_LOOKBACK_HOURS),
+        }
+    }
+
+    /// The trade as counted in the window, `None` if it isn't. Trades are taken as they are
+    /// with `Whole`, as fetching them selects those starting in the window. Pro-rated trades keep their
+    /// delivery period, so product lengths still hold, with the quantity scaled by the share of
+    /// the period inside the window, which scales their energy and cash flow alike.
+    pub fn apply<'t>(
+        &self,
+        trade: &'t Trade,
+        delivery_from: &DateTime<Tz>,
+        delivery_to: &DateTime<Tz>,
+    ) -> Option<Cow<'t, Trade>> {
+        let quantity = self.counted_quantity(
+            trade.quantity_mwh,
+            &trade.delivery_start,
+            &trade.delivery_end,
+            delivery_from,
+            delivery_to,
+        )?;
+        if quantity == trade.quantity_mwh {
+            Some(Cow::Borrowed(trade))
+        } else {
+            let mut clipped = trade.clone();
+            clipped.quantity_mwh = quantity;
+            Some(Cow::Owned(clipped))
+        }
+    }
+
+    /// The quantity of a trade delivered over the period as counted in the window, see `apply`
+    fn counted_quantity(
+        &self,
+        quantity: Decimal,
+        delivery_start: &DateTime<FixedOffset>,
+        delivery_end: &DateTime<FixedOffset>,
+        delivery_from: &DateTime<Tz>,
+        delivery_to: &DateTime<Tz>,
+    ) -> Option<Decimal> {
+        let length = (*delivery_end - *delivery_start).num_seconds();
+        if *self == BoundaryPolicy::Whole || length <= 0 {
+            return Some(quantity);
+        }
+        let inside = ((*delivery_end).min(delivery_to.fixed_offset())
+            - (*delivery_start).max(delivery_from.fixed_offset()))
+        .num_seconds();
+        if inside <= 0 {
+            None
+        } else if inside == length {
+            Some(quantity)
+        } else {
+            Some(quantity * Decimal::from(inside) / Decimal::from(length))
+        }
+    }
+}
+
 #[derive(Debug)]
 pub struct Report {
-    _delivery_from: DateTime<Tz>,
-    _delivery_to: DateTime<Tz>,
-    areas: HashMap<Area, ReportEntry>,
+    delivery_from: DateTime<Tz>,
+    delivery_to: DateTime<Tz>,
+    /// The cells of each area, apart per currency the trades were settled in
+    entries: HashMap<(Area, Currency), ReportEntry>,
+    /// Trades without a price, which only contribute to volumes with `CountVolume`
+    skipped_trades: usize,
+    missing_prices: MissingPricePolicy,
+    /// What each trade contributed to its cell, only kept when retaining trades
+    contributions: Option<ContributionLog>,
+    /// The currency all cash flows were converted to, otherwise those of the trades
+    reporting_currency: Option<Currency>,
+    /// Charges fees on the trades added, none without a schedule
+    fee_schedule: Option<FeeSchedule>,
+    /// Benchmarks the trades added against the day-ahead prices, when given
+    spot_prices: Option<Arc<SpotPrices>>,
+}
+
+/// What a report is built from. The leaner inputs leave out fields of the trades, so some
+/// options of a `ReportBuilder` can't be applied to them, see `ReportBuilder::check`.
+#[derive(Debug, Display, Clone, Copy, PartialEq, Eq)]
+pub enum ReportInput {
+    /// Whole trades, to which every option applies
+    #[strum(to_string = "trades")]
+    Trades,
+    /// `TradeForReport`, without the ids and counter parts of the trades
+    #[strum(to_string = "trades for reports")]
+    TradesForReport,
+    /// `TradeAggregateInput` of trades taken through `ReportBuilder::input`, without their ids
+    #[strum(to_string = "aggregate inputs")]
+    AggregateInputs,
+    /// Sums computed in the database, see `db::get_aggregated_report_rows`
+    #[strum(to_string = "sums of the database")]
+    AggregatedRows,
+}
+
+/// Builds reports over a delivery window from trades, a stream of them or a `TradeSource`
+#[derive(Clone)]
+pub struct ReportBuilder {
+    delivery_from: DateTime<Tz>,
+    delivery_to: DateTime<Tz>,
+    /// Bytes of contributions kept in memory when retaining trades
+    retention_budget: Option<usize>,
+    missing_prices: MissingPricePolicy,
+    counter_parts: CounterPartSelection,
+    boundary_trades: BoundaryPolicy,
+    window_filter: WindowFilter,
+    fx: Option<FxRates>,
+    fees: Option<FeeSchedule>,
+    spot_prices: Option<Arc<SpotPrices>>,
+}
+
+impl ReportBuilder {
+    pub fn new(delivery_from: &DateTime<Tz>, delivery_to: &DateTime<Tz>) -> Self {
+        Self {
+            delivery_from: *delivery_from,
+            delivery_to: *delivery_to,
+            retention_budget: None,
+            missing_prices: MissingPricePolicy::default(),
+            counter_parts: CounterPartSelection::default(),
+            boundary_trades: BoundaryPolicy::default(),
+            window_filter: WindowFilter::default(),
+            fx: None,
+            fees: None,
+            spot_prices: None,
+        }
+    }
+
+    /// Keeps what each trade contributed, so cells can be traced back with `Report::explain`.
+    /// Contributions beyond the memory budget in bytes are spilled to a temporary file.
+    pub fn retain_trades(mut self, memory_budget: usize) -> Self {
+        self.retention_budget = Some(memory_budget);
+        self
+    }
+
+    /// How trades without a price are aggregated, skipped by default
+    pub fn missing_prices(mut self, policy: MissingPricePolicy) -> Self {
+        self.missing_prices = policy;
+        self
+    }
+
+    /// Only aggregates the trades of the counter parts, all of them by default. Applies to the
+    /// trades the builder builds from, not to those added to its reports afterwards.
+    pub fn counter_parts(mut self, selection: CounterPartSelection) -> Self {
+        self.counter_parts = selection;
+        self
+    }
+
+    /// How trades delivered partly outside the window are counted, in full by their delivery
+    /// start by default. Like the counter parts, applies to the trades the builder builds from.
+    pub fn boundary_trades(mut self, policy: BoundaryPolicy) -> Self {
+        self.boundary_trades = policy;
+        self
+    }
+
+    /// Which trades `build_from_source` fetches for the window, those starting in it by
+    /// default. Selecting overlapping trades makes sense with `BoundaryPolicy::ProRata`, as they
+    /// otherwise count in full in every window they overlap.
+    pub fn window_filter(mut self, filter: WindowFilter) -> Self {
+        self.window_filter = filter;
+        self
+    }
+
+    /// Converts the cash flows of the trades the builder builds from to the reporting currency
+    /// of the rates, so they add up across areas settled in different currencies
+    pub fn fx(mut self, rates: FxRates) -> Self {
+        self.fx = Some(rates);
+        self
+    }
+
+    /// Charges the fees of the schedule on the trades, see `Report::with_fees`
+    pub fn fees(mut self, schedule: FeeSchedule) -> Self {
+        self.fees = Some(schedule);
+        self
+    }
+
+    /// Benchmarks the trades against the day-ahead prices, see `Report::with_spot_prices`. With
+    /// `fx`, the prices have to be converted as well, see `SpotPrices::converted`.
+    pub fn spot_prices(mut self, prices: Arc<SpotPrices>) -> Self {
+        self.spot_prices = Some(prices);
+        self
+    }
+
+    pub fn delivery_from(&self) -> &DateTime<Tz> {
+        &self.delivery_from
+    }
+
+    pub fn delivery_to(&self) -> &DateTime<Tz> {
+        &self.delivery_to
+    }
+
+    pub fn boundary_policy(&self) -> BoundaryPolicy {
+        self.boundary_trades
+    }
+
+    /// The filter the trades of the window are fetched with, see `window_filter`
+    pub fn fetch_filter(&self) -> WindowFilter {
+        self.window_filter
+    }
+
+    /// The rates cash flows are converted with, see `fx`
+    pub fn fx_rates(&self) -> Option<&FxRates> {
+        self.fx.as_ref()
+    }
+
+    /// Fails for the options that reports built from the input can't apply, so reports of the
+    /// same options agree whatever they're built from
+    pub fn check(&self, input: ReportInput) -> Result<()> {
+        let mut unsupported = Vec::new();
+        if self.retention_budget.is_some() && input != ReportInput::Trades {
+            unsupported.push("retain_trades");
+        }
+        if self.counter_parts != CounterPartSelection::All
+            && matches!(
+                input,
+                ReportInput::TradesForReport | ReportInput::AggregatedRows
+            )
+        {
+            unsupported.push("counter_parts");
+        }
+        // The sums of the database are over days with different rates
+        if self.fx.is_some() && input == ReportInput::AggregatedRows {
+            unsupported.push("fx");
+        }
+        // Fees are charged by counter part, which only whole trades carry
+        if self.fees.is_some() && input != ReportInput::Trades {
+            unsupported.push("fees");
+        }
+        // Like fees, trades are only benchmarked as they're added whole
+        if self.spot_prices.is_some() && input != ReportInput::Trades {
+            unsupported.push("spot_prices");
+        }
+        if !unsupported.is_empty() {
+            bail!(
+                "Reports built from {} can't apply {}",
+                input,
+                unsupported.join(", ")
+            );
+        }
+        Ok(())
+    }
+
+    /// A report without trades yet, to be filled with `Report::add_trade`
+    pub fn empty(&self) -> Result<Report> {
+        let mut report = Report::empty(&self.delivery_from, &self.delivery_to)?
+            .with_missing_prices(self.missing_prices)?;
+        report.reporting_currency = self.fx.as_ref().map(FxRates::reporting_currency);
+        if let Some(schedule) = &self.fees {
+            report = report.with_fees(schedule.clone());
+        }
+        if let Some(prices) = &self.spot_prices {
+            report = report.with_spot_prices(prices.clone());
+        }
+        Ok(match self.retention_budget {
+            Some(memory_budget) => report.with_retention(memory_budget),
+            None => report,
+        })
+    }
+
+    pub fn build<'t>(&self, trades: impl IntoIterator<Item = &'t Trade>) -> Result<Report> {
+        let mut report = self.empty()?;
+        for trade in trades {
+            self.add_trade(&mut report, trade)?;
+        }
+        Ok(report)
+    }
+
+    /// Like `build`, aggregating chunks of the trades on the rayon thread pool and merging the
+    /// partial reports, see `Report::new_parallel`. Reports retaining trades can't be merged,
+    /// so they're built on the calling thread.
+    pub fn build_parallel(&self, trades: &[Trade]) -> Result<Report> {
+        if trades.len() <= PARALLEL_CHUNK_SIZE || self.retention_budget.is_some() {
+            return self.build(trades);
+        }
+        trades
+            .par_chunks(PARALLEL_CHUNK_SIZE)
+            .map(|chunk| self.build(chunk))
+            .try_reduce_with(Report::merge)
+            .unwrap_or_else(|| self.empty())
+    }
+
+    /// Builds the report from trades fetched without their ids and counter parts, counted and
+    /// converted like whole trades, failing for the options that need them
+    pub fn build_from_trades_for_report(&self, trades: Vec<TradeForReport>) -> Result<Report> {
+        self.check(ReportInput::TradesForReport)?;
+        let mut report = self.empty()?;
+        for mut trade in trades {
+            let Some(quantity) = self.boundary_trades.counted_quantity(
+                trade.quantity_mwh,
+                &trade.delivery_start,
+                &trade.delivery_end,
+                &self.delivery_from,
+                &self.delivery_to,
+            ) else {
+                continue;
+            };
+            trade.quantity_mwh = quantity;
+            if let Some(rates) = &self.fx {
+                trade.price =
+                    rates.convert_price(trade.price, trade.currency, &trade.delivery_start)?;
+                trade.currency = rates.reporting_currency();
+            }
+            report.add_trade_for_report(&trade)?;
+        }
+        Ok(report)
+    }
+
+    /// Builds the report from sums computed in the database, which `db::get_aggregated_report_rows`
+    /// selects and pro-rates like the builder, failing for the options they can't apply
+    pub fn build_from_aggregated_rows(&self, rows: Vec<AggregatedRow>) -> Result<Report> {
+        self.check(ReportInput::AggregatedRows)?;
+        Report::from_aggregated_rows(&self.delivery_from, &self.delivery_to, rows)?
+            .with_missing_prices(self.missing_prices)
+    }
+
+    #[cfg(feature = "runtime")]
+    pub async fn build_from_stream(&self, mut trades: TradeStream<'_>) -> Result<Report> {
+        let mut report = self.empty()?;
+        while let Some(trade) = trades.try_next().await? {
+            self.add_trade(&mut report, &trade)?;
+        }
+        Ok(report)
+    }
+
+    /// Streams the trades of the window from the source into a report
+    #[cfg(feature = "runtime")]
+    pub async fn build_from_source(&self, source: &TradeSource) -> Result<Report> {
+        self.build_from_stream(self.trades(source)).await
+    }
+
+    /// Streams the trades of the source the report is built from, for consumers of the stream
+    /// in between, e.g. to count them. Selected with the window filter, from before the window
+    /// to pro-rate trades starting earlier if they aren't selected by overlap.
+    #[cfg(feature = "runtime")]
+    pub fn trades(&self, source: &TradeSource) -> TradeStream<'static> {
+        source.stream_filtered(&self.fetch_from(), &self.delivery_to, self.window_filter)
+    }
+
+    /// Where the trades of the window have to be fetched from, see `BoundaryPolicy::fetch_from`
+    pub fn fetch_from(&self) -> DateTime<Tz> {
+        match self.window_filter {
+            WindowFilter::DeliveryStart => self.boundary_trades.fetch_from(&self.delivery_from),
+            WindowFilter::Overlap => self.delivery_from,
+        }
+    }
+
+    /// The trade as the builder aggregates it, `None` if it isn't: selected by counter part,
+    /// counted by the boundary policy and converted to the reporting currency. For consumers
+    /// aggregating the trades of a report alongside it, e.g. the seasonality.
+    pub fn prepare<'t>(&self, trade: &'t Trade) -> Result<Option<Cow<'t, Trade>>> {
+        if !self.counter_parts.contains(&trade.counter_part) {
+            return Ok(None);
+        }
+        let Some(trade) = self
+            .boundary_trades
+            .apply(trade, &self.delivery_from, &self.delivery_to)
+        else {
+            return Ok(None);
+        };
+        Ok(Some(match &self.fx {
+            Some(rates) => Cow::Owned(rates.convert(&trade)?),
+            None => trade,
+        }))
+    }
+
+    /// The input of the trade as the builder aggregates it, see `prepare`, to be added to a
+    /// report of the builder with `Report::add_input`
+    pub fn input(&self, trade: &Trade) -> Result<Option<TradeAggregateInput>> {
+        self.prepare(trade)?
+            .map(|trade| TradeAggregateInput::from_trade(&trade))
+            .transpose()
+    }
+
+    fn add_trade(&self, report: &mut Report, trade: &Trade) -> Result<()> {
+        match self.prepare(trade)? {
+            Some(trade) => report.add_trade(&trade),
+            None => Ok(()),
+        }
+    }
+}
+
+/// One cell of the report: the aggregated volume and cash flow of an area, market and side in
+/// a currency
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq)]
+pub struct BreakdownRow {
+    pub area: Area,
+    pub currency: Currency,
+    pub market: Market,
+    pub side: TradeSide,
+    pub mw: Decimal,
+    pub cash_flow: Decimal,
+}
+
+/// The full report as exported by `Report::to_json`
+#[derive(Debug, Serialize)]
+struct ReportJson<'a> {
+    delivery_from: &'a DateTime<Tz>,
+    delivery_to: &'a DateTime<Tz>,
+    /// The timezone of the window, which the offsets of its bounds don't identify
+    timezone: &'a str,
+    /// The window as described by `bounds::describe_window`
+    window: String,
+    skipped_trades: usize,
+    missing_prices: MissingPricesJson,
+    /// Each metric across all markets and areas, the monetary ones only if the trades were all
+    /// settled in the same currency
+    totals: BTreeMap<String, Decimal>,
+    /// The monetary metrics per currency the trades were settled in, along with the fees, zero
+    /// without a fee schedule, the net profit and the gains over the day-ahead prices, zero
+    /// without spot prices
+    totals_by_currency: BTreeMap<Currency, BTreeMap<String, Decimal>>,
+    unbenchmarked_trades: usize,
+    breakdown: Vec<BreakdownRow>,
+}
+
+/// The trades without a price in a report, whatever the policy
+#[derive(Debug, Serialize)]
+struct MissingPricesJson {
+    count: usize,
+    mwh: Decimal,
+}
+
+/// The parts of `ReportJson` a report is read back from
+#[derive(Debug, Deserialize)]
+struct StoredReportJson {
+    delivery_from: DateTime<FixedOffset>,
+    delivery_to: DateTime<FixedOffset>,
+    timezone: String,
+    skipped_trades: usize,
+    breakdown: Vec<BreakdownRow>,
+}
+
+/// A report saved with `Report::save`, with when and by which version it was generated
+#[cfg(feature = "runtime")]
+#[derive(Debug)]
+pub struct StoredReport {
+    pub id: i64,
+    pub generated_at: DateTime<Utc>,
+    pub code_version: String,
+    pub report: Report,
+}
+
+/// A row of the reports table, with the report as JSON text
+#[cfg(feature = "runtime")]
+struct StoredReportRow {
+    id: i64,
+    delivery_from: DateTime<Utc>,
+    delivery_to: DateTime<Utc>,
+    timezone: String,
+    generated_at: DateTime<Utc>,
+    code_version: String,
+    report: String,
 }
 
 impl Report {
+    /// Creates a report without any trades, to be filled incrementally with `add_trade` and
+    /// `add_trades`
+    pub fn empty(delivery_from: &DateTime<Tz>, delivery_to: &DateTime<Tz>) -> Result<Self> {
+        if delivery_to < delivery_from {
+            bail!("delivery_from has to be before delivery_to");
+        }
+
+        Ok(Report {
+            delivery_from: *delivery_from,
+            delivery_to: *delivery_to,
+            entries: HashMap::new(),
+            skipped_trades: 0,
+            missing_prices: MissingPricePolicy::default(),
+            contributions: None,
+            reporting_currency: None,
+            fee_schedule: None,
+            spot_prices: None,
+        })
+    }
+
+    /// Aggregates trades without a price by the policy from now on. Reports are built skipping
+    /// them, so the policy can also be applied to a built report, which fails with `Error` if
+    /// it has any.
+    pub fn with_missing_prices(mut self, policy: MissingPricePolicy) -> Result<Self> {
+        if policy == MissingPricePolicy::Error && self.skipped_trades > 0 {
+            bail!(
+                "{} trades without a price, {} MWh, in {}",
+                self.skipped_trades,
+                self.missing_price_mwh(MarketSelection::All, AreaSelection::All),
+                describe_window(&self.delivery_from, &self.delivery_to)
+            );
+        }
+        self.missing_prices = policy;
+        Ok(self)
+    }
+
+    /// Charges the fees of the schedule on the trades with a price added from now on, by their
+    /// market and counter part. Trades added without their counter part, from `TradeForReport`,
+    /// inputs or database sums, are charged nothing.
+    pub fn with_fees(mut self, schedule: FeeSchedule) -> Self {
+        self.fee_schedule = Some(schedule);
+        self
+    }
+
+    /// Benchmarks the trades with a price added from now on against the day-ahead prices of
+    /// their delivery, see `pnl_vs_spot`. Like fees, only for trades added with `add_trade`.
+    pub fn with_spot_prices(mut self, prices: Arc<SpotPrices>) -> Self {
+        self.spot_prices = Some(prices);
+        self
+    }
+
+    /// Keeps the contributions of the trades added from now on, so cells can be traced back to
+    /// their trades with `explain`. Costs memory per trade, so it's off by default.
+    fn with_retention(mut self, memory_budget: usize) -> Self {
+        self.contributions = Some(ContributionLog::new(memory_budget));
+        self
+    }
+
+    /// What each trade contributed to a cell, in the order they were added, or an error without
+    /// retention. Ids are per table, which is the table of the market.
+    pub fn explain(
+        &mut self,
+        area: Area,
+        market: Market,
+        side: TradeSide,
+    ) -> Result<Vec<Contribution>> {
+        let Some(contributions) = self.contributions.as_mut() else {
+            bail!("Trades are not retained, build the report with retain_trades to explain it");
+        };
+        contributions.for_cell(area, market, side)
+    }
+
+    #[instrument(level = "debug", skip_all, fields(trades = trades.len()))]
     pub fn new(
         delivery_from: &DateTime<Tz>,
         delivery_to: &DateTime<Tz>,
         trades: Vec<Trade>,
     ) -> Result<Self> {
-        if delivery_to < delivery_from {
-            bail!("delivery_from has to be before delivery_to");
-        }
+        let mut report = Report::empty(delivery_from, delivery_to)?;
+        report.add_trades(&trades)?;
+        Ok(report)
+    }
 
-        let mut areas = HashMap::new();
+    /// Like `new`, aggregating chunks of the trades on the rayon thread pool and merging the
+    /// partial reports, for windows of millions of trades. Fewer trades than a chunk are
+    /// aggregated on the calling thread, as splitting them costs more than it saves.
+    #[instrument(level = "debug", skip_all, fields(trades = trades.len()))]
+    pub fn new_parallel(
+        delivery_from: &DateTime<Tz>,
+        delivery_to: &DateTime<Tz>,
+        trades: Vec<Trade>,
+    ) -> Result<Self> {
+        ReportBuilder::new(delivery_from, delivery_to).build_parallel(&trades)
+    }
 
-        for trade in trades.iter() {
-            let area = trade.area;
-            areas
-                .entry(area)
-                .or_insert(ReportEntry::new(area))
-                .add_trade(trade)?;
+    pub fn delivery_from(&self) -> &DateTime<Tz> {
+        &self.delivery_from
+    }
+
+    pub fn delivery_to(&self) -> &DateTime<Tz> {
+        &self.delivery_to
+    }
+
+    /// All non-empty cells of the report, sorted by area, market, side and currency. With
+    /// `CountVolume`, the MW include trades without a price.
+    pub fn breakdown(&self) -> Vec<BreakdownRow> {
+        let counted = self.missing_prices == MissingPricePolicy::CountVolume;
+        let mut rows: Vec<BreakdownRow> = self
+            .entries
+            .values()
+            .flat_map(|entry| {
+                let mut cells: Vec<(TradeSide, Market)> = entry.mw.keys().copied().collect();
+                if counted {
+                    cells.extend(
+                        entry
+                            .missing_price_mw
+                            .keys()
+                            .filter(|cell| !entry.mw.contains_key(cell)),
+                    );
+                }
+                cells.into_iter().map(move |(side, market)| {
+                    let market_selection = MarketSelection::Specific(market);
+                    let mut mw = total(&entry.mw, side, &market_selection);
+                    if counted {
+                        mw += total(&entry.missing_price_mw, side, &market_selection);
+                    }
+                    BreakdownRow {
+                        area: entry.area,
+                        currency: entry.currency,
+                        market,
+                        side,
+                        mw,
+                        cash_flow: total(&entry.cash_flow, side, &market_selection),
+                    }
+                })
+            })
+            .collect();
+        rows.sort_by_key(|row| (row.area, row.market, row.side, row.currency));
+        rows
+    }
+
+    pub fn skipped_trades(&self) -> usize {
+        self.skipped_trades
+    }
+
+    /// Areas of the report we don't know, only decoded when decoding is lenient
+    pub fn other_areas(&self) -> Vec<Area> {
+        let areas: BTreeSet<Area> = self
+            .entries
+            .keys()
+            .map(|(area, _)| *area)
+            .filter(|area| matches!(area, Area::Other(_)))
+            .collect();
+        areas.into_iter().collect()
+    }
+
+    /// The window, totals and per area, market and side breakdown of the report as JSON. Unlike
+    /// `print_key_metrics` it doesn't consume the report, and metrics are all positive, i.e. in
+    /// the magnitude sign convention.
+    pub fn to_json(&self) -> Result<String> {
+        // Amounts of different currencies are only totalled per currency
+        let (market, area) = (MarketSelection::All, AreaSelection::All);
+        let totals = self
+            .single_currency_metrics(market.clone(), area.clone())
+            .into_iter()
+            .map(|(metric, total)| (metric.to_string(), total))
+            .collect();
+        let totals_by_currency = self
+            .monetary_metrics_by_currency(market.clone(), area.clone())
+            .into_iter()
+            .map(|(currency, metrics)| {
+                let report = self.in_currency(currency);
+                let mut totals: BTreeMap<String, Decimal> = metrics
+                    .into_iter()
+                    .map(|(metric, total)| (metric.to_string(), total))
+                    .collect();
+                totals.extend(
+                    [
+                        ("fees", report.fees(market.clone(), area.clone())),
+                        (
+                            "net_profit",
+                            report.net_profit(market.clone(), area.clone()),
+                        ),
+                        (
+                            "pnl_vs_spot",
+                            report.pnl_vs_spot(market.clone(), area.clone()),
+                        ),
+                    ]
+                    .map(|(name, total)| (name.to_string(), total)),
+                );
+                (currency, totals)
+            })
+            .collect();
+        Ok(serde_json::to_string(&ReportJson {
+            delivery_from: &self.delivery_from,
+            delivery_to: &self.delivery_to,
+            timezone: self.delivery_from.timezone().name(),
+            window: describe_window(&self.delivery_from, &self.delivery_to),
+            skipped_trades: self.skipped_trades,
+            missing_prices: MissingPricesJson {
+                count: self.missing_price_count(MarketSelection::All, AreaSelection::All),
+                mwh: self.missing_price_mwh(MarketSelection::All, AreaSelection::All),
+            },
+            totals,
+            totals_by_currency,
+            unbenchmarked_trades: self
+                .unbenchmarked_trades(MarketSelection::All, AreaSelection::All),
+            breakdown: self.breakdown(),
+        })?)
+    }
+
+    /// Adds a trade to the report, so long-running services can keep a report up to date as
+    /// trades arrive instead of rebuilding it. The totals after adding trades are the same as
+    /// those of a report built from all of them at once.
+    pub fn add_trade(&mut self, trade: &Trade) -> Result<()> {
+        if trade.price.is_none() {
+            if self.missing_prices == MissingPricePolicy::Error {
+                bail!("Trade {} in {} has no price", trade.id, trade.area);
+            }
+            self.skipped_trades += 1;
         }
+        let (area, currency) = (trade.area, trade.currency);
+        // Not through `entry`, as the fee schedule and spot prices are read while it's borrowed
+        let entry = self
+            .entries
+            .entry((area, currency))
+            .or_insert_with(|| ReportEntry::new(area, currency));
+        entry.add_trade(trade)?;
 
-        let report = Report {
-            _delivery_from: *delivery_from,
-            _delivery_to: *delivery_to,
-            areas,
+        let Some(price) = trade.price else {
+            return Ok(());
         };
+        let side = if trade.quantity_mwh < Decimal::ZERO {
+            TradeSide::Sell
+        } else {
+            TradeSide::Buy
+        };
+        let market = Market::from(trade.trade_type);
+        let mw =
+            trade.quantity_mwh.abs() * contract_length(&trade.delivery_start, &trade.delivery_end)?;
+        if let Some(schedule) = &self.fee_schedule {
+            let fee = schedule.fee(&trade.counter_part, market, mw, mw * price);
+            entry.fees.entry((side, market)).or_default().add(fee);
+        }
+        if let Some(prices) = &self.spot_prices {
+            match prices.pnl_vs_spot(trade) {
+                Some(pnl) => entry.spot_pnl.entry((side, market)).or_default().add(pnl),
+                None => {
+                    *entry
+                        .unbenchmarked_trades
+                        .entry((side, market))
+                        .or_default() += 1
+                }
+            }
+        }
+        if let Some(contributions) = self.contributions.as_mut() {
+            contributions.push(Contribution {
+                id: trade.id,
+                area,
+                market,
+                side,
+                mw,
+                cash_flow: mw * price,
+            })?;
+        }
+        Ok(())
+    }
 
-        Ok(report)
+    /// Adds a batch of trades, see `add_trade`. Fails at the first trade that can't be added,
+    /// with the trades before it added.
+    pub fn add_trades<'t>(&mut self, trades: impl IntoIterator<Item = &'t Trade>) -> Result<()> {
+        for trade in trades {
+            self.add_trade(trade)?;
+        }
+        Ok(())
+    }
+
+    /// Combines two partial reports, e.g. of consecutive days aggregated in parallel or of the
+    /// same window from different sources, summing their cells. The merged report covers both
+    /// windows, which have to be in the same timezone and overlap or touch, as the merged window
+    /// would otherwise include time neither report has trades for. Reports retaining trades for
+    /// `explain` can't be merged.
+    pub fn merge(mut self, other: Report) -> Result<Report> {
+        if self.delivery_from.timezone() != other.delivery_from.timezone() {
+            bail!(
+                "Can't merge reports in {} and {}",
+                self.delivery_from.timezone(),
+                other.delivery_from.timezone()
+            );
+        }
+        if self.delivery_to < other.delivery_from || other.delivery_to < self.delivery_from {
+            bail!(
+                "Can't merge reports of {} and {}, which leave a gap between them",
+                describe_window(&self.delivery_from, &self.delivery_to),
+                describe_window(&other.delivery_from, &other.delivery_to)
+            );
+        }
+        if self.contributions.is_some() || other.contributions.is_some() {
+            bail!("Reports retaining trades can't be merged");
+        }
+        if self.reporting_currency != other.reporting_currency {
+            bail!("Can't merge reports whose cash flows are in different currencies");
+        }
+
+        self.delivery_from = self.delivery_from.min(other.delivery_from);
+        self.delivery_to = self.delivery_to.max(other.delivery_to);
+        self.skipped_trades += other.skipped_trades;
+        for ((area, currency), entry) in other.entries {
+            self.entry(area, currency).merge(entry);
+        }
+        Ok(self)
+    }
+
+    /// The cells of the area in the currency
+    fn entry(&mut self, area: Area, currency: Currency) -> &mut ReportEntry {
+        self.entries
+            .entry((area, currency))
+            .or_insert_with(|| ReportEntry::new(area, currency))
+    }
+
+    /// Adds the fields of a trade sent on their own, see `TradeAggregateInput`. Inputs carry no
+    /// trade id, so they aren't retained for `explain`, and inputs without a price are counted
+    /// even with `MissingPricePolicy::Error`, failing when the policy is applied.
+    pub fn add_input(&mut self, input: &TradeAggregateInput) {
+        if input.price.is_none() {
+            self.skipped_trades += 1;
+        }
+        self.entry(input.area, input.currency).add_input(input);
     }
 
+    #[instrument(level = "debug", skip_all, fields(trades = trades.len()))]
     pub fn new_from_trade_for_report(
         delivery_from: &DateTime<Tz>,
         delivery_to: &DateTime<Tz>,
         trades: Vec<TradeForReport>,
     ) -> Result<Self> {
-        if delivery_to < delivery_from {
-            bail!("delivery_from has to be before delivery_to");
+        ReportBuilder::new(delivery_from, delivery_to).build_from_trades_for_report(trades)
+    }
+
+    /// Adds a trade fetched without its id, which is never retained, like `add_input`
+    fn add_trade_for_report(&mut self, trade: &TradeForReport) -> Result<()> {
+        if trade.price.is_none() {
+            self.skipped_trades += 1;
         }
+        self.entry(trade.area, trade.currency)
+            .add_trade_for_report(trade)
+    }
 
-        let mut areas = HashMap::new();
+    /// Builds the report from sums computed in the database, see `db::get_aggregated_report_rows`.
+    /// Only the sums travel, so the prices of single trades aren't known and the price
+    /// statistics are zero, and product lengths aren't checked.
+    #[instrument(level = "debug", skip_all, fields(rows = rows.len()))]
+    pub fn from_aggregated_rows(
+        delivery_from: &DateTime<Tz>,
+        delivery_to: &DateTime<Tz>,
+        rows: Vec<AggregatedRow>,
+    ) -> Result<Self> {
+        let mut report = Report::empty(delivery_from, delivery_to)?;
 
-        for trade in trades.iter() {
-            let area = trade.area;
-            areas
-                .entry(area)
-                .or_insert(ReportEntry::new(area))
-                .add_trade_for_report(trade)?;
+        for row in rows.iter() {
+            report.skipped_trades += usize::try_from(row.skipped_trades)?;
+            let entry = report.entry(row.area, row.currency);
+            let key = (row.side(), Market::from(row.trade_type));
+            if row.skipped_trades > 0 {
+                *entry.missing_price_trades.entry(key).or_default() +=
+                    usize::try_from(row.skipped_trades)?;
+                entry
+                    .missing_price_mw
+                    .entry(key)
+                    .or_default()
+                    .add(row.unpriced_mwh);
+            }
+            if row.priced_trades == 0 {
+                continue;
+            }
+            entry.mw.entry(key).or_default().add(row.mwh);
+            entry.cash_flow.entry(key).or_default().add(row.cash_flow);
         }
 
-        let report = Report {
-            _delivery_from: *delivery_from,
-            _delivery_to: *delivery_to,
-            areas,
-        };
+        Ok(report)
+    }
 
+    /// Builds the report from the cells of a breakdown, e.g. of a stored snapshot. Like
+    /// `from_aggregated_rows`, the prices of single trades aren't known, so the price statistics
+    /// are zero, and neither are trades without a price.
+    pub fn from_breakdown(
+        delivery_from: &DateTime<Tz>,
+        delivery_to: &DateTime<Tz>,
+        rows: &[BreakdownRow],
+    ) -> Result<Self> {
+        let mut report = Report::empty(delivery_from, delivery_to)?;
+        for row in rows {
+            let entry = report.entry(row.area, row.currency);
+            let key = (row.side, row.market);
+            entry.mw.entry(key).or_default().add(row.mw);
+            entry.cash_flow.entry(key).or_default().add(row.cash_flow);
+        }
         Ok(report)
     }
 
+    /// Reads a report exported with `to_json` back, in its window and timezone. Like
+    /// `from_breakdown`, only the cells and the number of trades without a price are restored,
+    /// so the price statistics are zero.
+    pub fn from_json(json: &str) -> Result<Self> {
+        let stored: StoredReportJson = serde_json::from_str(json)?;
+        let timezone: Tz = stored
+            .timezone
+            .parse()
+            .map_err(|_| anyhow::anyhow!("Unknown timezone {}", stored.timezone))?;
+        let mut report = Report::from_breakdown(
+            &stored.delivery_from.with_timezone(&timezone),
+            &stored.delivery_to.with_timezone(&timezone),
+            &stored.breakdown,
+        )?;
+        report.skipped_trades = stored.skipped_trades;
+        Ok(report)
+    }
+
+    /// Saves the report as JSON with its window, the time and the code version to the reports
+    /// table, building a history of past runs dashboards can read instead of aggregating again.
+    /// The table is created by the migrations in `migrations/`. Returns the id of the saved
+    /// report.
+    #[cfg(feature = "runtime")]
+    pub async fn save(&self, pool: &PgPool) -> Result<i64> {
+        sqlx::query_scalar!(
+            "INSERT INTO reports (delivery_from, delivery_to, timezone, code_version, report)
+            VALUES ($1, $2, $3, $4, $5::text::jsonb) RETURNING id",
+            self.delivery_from.to_utc(),
+            self.delivery_to.to_utc(),
+            self.delivery_from.timezone().name(),
+            CODE_VERSION,
+            self.to_json()?,
+        )
+        .fetch_one(pool)
+        .await
+        .with_context(|| {
+            format!(
+                "Could not save the report of {}",
+                describe_window(&self.delivery_from, &self.delivery_to)
+            )
+        })
+    }
+
+    /// The saved report of the id, see `from_json` for what is restored
+    #[cfg(feature = "runtime")]
+    pub async fn load(pool: &PgPool, id: i64) -> Result<StoredReport> {
+        let row = sqlx::query_as!(
+            StoredReportRow,
+            r#"SELECT id, delivery_from, delivery_to, timezone, generated_at, code_version,
+                report::text AS "report!"
+            FROM reports WHERE id = $1"#,
+            id,
+        )
+        .fetch_optional(pool)
+        .await
+        .with_context(|| format!("Could not read saved report {}", id))?;
+        Self::stored(row.ok_or(anyhow!("No saved report {}", id))?)
+    }
+
+    /// The report of the window saved last, `None` if it was never saved
+    #[cfg(feature = "runtime")]
+    pub async fn load_latest(
+        pool: &PgPool,
+        delivery_from: &DateTime<Tz>,
+        delivery_to: &DateTime<Tz>,
+    ) -> Result<Option<StoredReport>> {
+        let row = sqlx::query_as!(
+            StoredReportRow,
+            r#"SELECT id, delivery_from, delivery_to, timezone, generated_at, code_version,
+                report::text AS "report!"
+            FROM reports WHERE delivery_from = $1 AND delivery_to = $2
+            ORDER BY generated_at DESC, id DESC LIMIT 1"#,
+            delivery_from.to_utc(),
+            delivery_to.to_utc(),
+        )
+        .fetch_optional(pool)
+        .await
+        .with_context(|| {
+            format!(
+                "Could not read the saved reports of {}",
+                describe_window(delivery_from, delivery_to)
+            )
+        })?;
+        row.map(Self::stored).transpose()
+    }
+
+    /// Reads the report of a row back, which must be of the window and timezone of the row
+    #[cfg(feature = "runtime")]
+    fn stored(row: StoredReportRow) -> Result<StoredReport> {
+        let report = Report::from_json(&row.report)
+            .with_context(|| format!("Invalid saved report {}", row.id))?;
+        if report.delivery_from.to_utc() != row.delivery_from
+            || report.delivery_to.to_utc() != row.delivery_to
+            || report.delivery_from.timezone().name() != row.timezone
+        {
+            bail!(
+                "Saved report {} is of {} in {}, not of its row's window in {}",
+                row.id,
+                describe_window(&report.delivery_from, &report.delivery_to),
+                report.delivery_from.timezone().name(),
+                row.timezone
+            );
+        }
+        Ok(StoredReport {
+            id: row.id,
+            generated_at: row.generated_at,
+            code_version: row.code_version,
+            report,
+        })
+    }
+
+    /// Aggregates the trades as they arrive. The span counts them once the stream ends.
+    #[cfg(feature = "runtime")]
+    #[instrument(level = "debug", skip_all, fields(trades = field::Empty))]
     pub async fn new_from_stream<'a>(
         delivery_from: &DateTime<Tz>,
         delivery_to: &DateTime<Tz>,
         mut trades_iter: Pin<Box<dyn Stream<Item = Result<Trade, Error>> + Send + 'a>>,
     ) -> Result<Self> {
-        if delivery_to < delivery_from {
-            bail!("delivery_from has to be before delivery_to");
-        }
-
-        let mut areas = HashMap::new();
+        let mut report = Report::empty(delivery_from, delivery_to)?;
 
+        let mut trades = 0;
         while let Some(trade) = trades_iter.try_next().await? {
-            let area = trade.area;
-            areas
-                .entry(area)
-                .or_insert(ReportEntry::new(area))
-                .add_trade(&trade)?;
+            report.add_trade(&trade)?;
+            trades += 1;
         }
-
-        let report = Report {
-            _delivery_from: *delivery_from,
-            _delivery_to: *delivery_to,
-            areas,
-        };
+        Span::current().record("trades", trades);
 
         Ok(report)
     }
 
-    pub fn print_key_metrics(self) {
-        println!(
-            "Total gross profit: {:?}",
-            self.gross_profit(MarketSelection::All, AreaSelection::All)
-        );
+    pub fn print_key_metrics(
+        self,
+        signs: SignConvention,
+        market: MarketSelection,
+        area: AreaSelection,
+    ) {
+        let total = |metric| signs.apply(metric, self.metric(metric, market.clone(), area.clone()));
+        // Amounts of selections spanning currencies are shown per currency instead of summed
+        let currencies = self.currencies(area.clone());
+        let per_currency = |amount: &dyn Fn(&Report) -> String| match currencies.len() {
+            0 | 1 => amount(&self),
+            _ => currencies
+                .iter()
+                .map(|currency| format!("{} {}", currency, amount(&self.in_currency(*currency))))
+                .collect::<Vec<_>>()
+                .join(", "),
+        };
+        let money = |metric: Metric| {
+            per_currency(&|report| {
+                let amount = report.metric(metric, market.clone(), area.clone());
+                format!("{:?}", signs.apply(metric, amount))
+            })
+        };
         println!(
-            "Total revenue: {:?}",
-            self.revenue(MarketSelection::All, AreaSelection::All)
+            "Window: {}",
+            describe_window(&self.delivery_from, &self.delivery_to)
         );
+        println!("Total gross profit: {}", money(Metric::GrossProfit));
+        println!("Total revenue: {}", money(Metric::Revenue));
+        println!("Total costs: {}", money(Metric::Costs));
+        if self.fee_schedule.is_some() {
+            // Fees are paid like costs
+            println!(
+                "Total fees: {}",
+                per_currency(&|report| format!(
+                    "{:?}",
+                    signs.apply(Metric::Costs, report.fees(market.clone(), area.clone()))
+                ))
+            );
+            println!(
+                "Net profit: {}",
+                per_currency(&|report| format!(
+                    "{:?}",
+                    report.net_profit(market.clone(), area.clone())
+                ))
+            );
+        }
+        if self.spot_prices.is_some() {
+            println!(
+                "P&L vs day-ahead: {} ({} trades without spot prices)",
+                per_currency(&|report| format!(
+                    "{:?}",
+                    report.pnl_vs_spot(market.clone(), area.clone())
+                )),
+                self.unbenchmarked_trades(market.clone(), area.clone())
+            );
+        }
+        println!("Total mw sold: {:?}", total(Metric::MwSold));
+        println!("Total mw bought: {:?}", total(Metric::MwBought));
+        println!("Net position: {:?}", total(Metric::NetPosition));
         println!(
-            "Total costs: {:?}",
-            self.costs(MarketSelection::All, AreaSelection::All)
+            "Without a price: {} trades, {:?} MWh",
+            self.missing_price_count(market.clone(), area.clone()),
+            self.missing_price_mwh(market.clone(), area.clone())
         );
         println!(
-            "Total mw sold: {:?}",
-            self.mw_sold(MarketSelection::All, AreaSelection::All)
+            "Price range: {}",
+            per_currency(&|report| {
+                let price = |metric| {
+                    signs.apply(metric, report.metric(metric, market.clone(), area.clone()))
+                };
+                format!(
+                    "{:?} - {:?} (median {:?})",
+                    price(Metric::MinPrice),
+                    price(Metric::MaxPrice),
+                    price(Metric::MedianPrice)
+                )
+            })
         );
+        let price =
+            |vwap: Option<Decimal>| vwap.map_or("-".to_string(), |vwap| format!("{:?}", vwap));
         println!(
-            "Total mw bought: {:?}",
-            self.mw_bought(MarketSelection::All, AreaSelection::All)
+            "VWAP bought: {} sold: {}",
+            per_currency(&|report| price(report.vwap_buy(market.clone(), area.clone()))),
+            per_currency(&|report| price(report.vwap_sell(market.clone(), area.clone())))
         );
     }
 
-    fn aggregate_metric<F>(
+    fn aggregate_metric<F>(
+        &self,
+        market: &MarketSelection,
+        area_selection: &AreaSelection,
+        aggregator: F,
+    ) -> Decimal
+    where
+        F: Fn(&ReportEntry, &MarketSelection) -> Decimal,
+    {
+        self.entries
+            .values()
+            .filter(|entry| area_selection.contains(entry.area))
+            .map(|entry| aggregator(entry, market))
+            .sum()
+    }
+
+    /// The total of a metric in the selection, volumes rounded to 0.1 MW and cash flows to cents.
+    /// Cash flows of different currencies are summed, so outside the crate only `try_metric` and
+    /// `metric_by_currency` read the metrics.
+    pub(crate) fn metric(
+        &self,
+        metric: Metric,
+        market: MarketSelection,
+        area: AreaSelection,
+    ) -> Decimal {
+        let sum = |aggregator: fn(&ReportEntry, &MarketSelection) -> Decimal| {
+            self.aggregate_metric(&market, &area, aggregator)
+        };
+        let bought =
+            || sum(ReportEntry::mw_bought) + self.counted_volume(TradeSide::Buy, &market, &area);
+        let sold =
+            || sum(ReportEntry::mw_sold) + self.counted_volume(TradeSide::Sell, &market, &area);
+        match metric {
+            Metric::MwBought => bought().round_dp(1),
+            Metric::MwSold => sold().round_dp(1),
+            Metric::Revenue => sum(ReportEntry::revenue).round_dp(2),
+            Metric::Costs => sum(ReportEntry::costs).round_dp(2),
+            // MWh bought minus sold
+            Metric::NetPosition => (bought() - sold()).round_dp(1),
+            // Cash received minus paid, the same as the gross profit
+            Metric::GrossProfit | Metric::NetCashFlow => sum(ReportEntry::gross_profit).round_dp(2),
+            Metric::MinPrice | Metric::MaxPrice | Metric::MedianPrice => {
+                self.prices(&market, &area).statistic(metric)
+            }
+        }
+    }
+
+    /// The currencies the trades of the selection were settled in, the reporting currency if
+    /// they were converted
+    pub fn currencies(&self, area: AreaSelection) -> BTreeSet<Currency> {
+        self.entries
+            .keys()
+            .filter(|(trade_area, _)| area.contains(*trade_area))
+            .map(|(_, currency)| *currency)
+            .collect()
+    }
+
+    /// The cells of the report in the currency, to compute the metrics of a single currency
+    /// with. Contributions aren't kept.
+    pub(crate) fn in_currency(&self, currency: Currency) -> Report {
+        let entries: HashMap<(Area, Currency), ReportEntry> = self
+            .entries
+            .iter()
+            .filter(|((_, entry_currency), _)| *entry_currency == currency)
+            .map(|(key, entry)| (*key, entry.clone()))
+            .collect();
+        Report {
+            delivery_from: self.delivery_from,
+            delivery_to: self.delivery_to,
+            skipped_trades: entries
+                .values()
+                .flat_map(|entry| entry.missing_price_trades.values())
+                .sum(),
+            entries,
+            missing_prices: self.missing_prices,
+            contributions: None,
+            reporting_currency: self.reporting_currency,
+            fee_schedule: self.fee_schedule.clone(),
+            spot_prices: self.spot_prices.clone(),
+        }
+    }
+
+    /// The metric per currency the trades of the selection were settled in, rather than failing
+    /// like `try_metric` for e.g. cash flows in GBP and EUR. Volumes are split by currency as
+    /// well.
+    pub fn metric_by_currency(
+        &self,
+        metric: Metric,
+        market: MarketSelection,
+        area: AreaSelection,
+    ) -> BTreeMap<Currency, Decimal> {
+        self.currencies(area.clone())
+            .into_iter()
+            .map(|currency| {
+                let report = self.in_currency(currency);
+                (
+                    currency,
+                    report.metric(metric, market.clone(), area.clone()),
+                )
+            })
+            .collect()
+    }
+
+    /// The total of a metric in the selection, volumes rounded to 0.1 MW and cash flows to cents.
+    /// Fails for monetary metrics of selections with trades settled in different currencies
+    /// instead of summing them.
+    pub fn try_metric(
+        &self,
+        metric: Metric,
+        market: MarketSelection,
+        area: AreaSelection,
+    ) -> Result<Decimal> {
+        let currencies = self.currencies(area.clone());
+        if metric.is_monetary() && currencies.len() > 1 {
+            let currencies: Vec<String> = currencies.iter().map(Currency::to_string).collect();
+            bail!(
+                "{} would mix {}, select trades of a single currency or use metric_by_currency",
+                metric,
+                currencies.join(" and ")
+            );
+        }
+        Ok(self.metric(metric, market, area))
+    }
+
+    /// Each metric of the selection, leaving out the monetary ones if its trades were settled
+    /// in different currencies, see `try_metric`
+    pub(crate) fn single_currency_metrics(
+        &self,
+        market: MarketSelection,
+        area: AreaSelection,
+    ) -> Vec<(Metric, Decimal)> {
+        Metric::iter()
+            .filter_map(|metric| {
+                let total = self.try_metric(metric, market.clone(), area.clone());
+                Some((metric, total.ok()?))
+            })
+            .collect()
+    }
+
+    /// The monetary metrics of the selection per currency its trades were settled in
+    pub(crate) fn monetary_metrics_by_currency(
         &self,
         market: MarketSelection,
-        area_selection: AreaSelection,
-        aggregator: F,
-    ) -> Decimal
-    where
-        F: Fn(&ReportEntry, MarketSelection) -> Decimal,
-    {
-        match area_selection {
-            AreaSelection::Specific(area) => self
-                .areas
-                .get(&area)
-                .map_or(Decimal::ZERO, |entry| aggregator(entry, market)),
-            AreaSelection::All => self
-                .areas
-                .values()
-                .map(|entry| aggregator(entry, market))
-                .sum(),
+        area: AreaSelection,
+    ) -> BTreeMap<Currency, Vec<(Metric, Decimal)>> {
+        self.currencies(area.clone())
+            .into_iter()
+            .map(|currency| {
+                let report = self.in_currency(currency);
+                let metrics = Metric::iter()
+                    .filter(Metric::is_monetary)
+                    .map(|metric| (metric, report.metric(metric, market.clone(), area.clone())))
+                    .collect();
+                (currency, metrics)
+            })
+            .collect()
+    }
+
+    /// Energy of the trades without a price on a side that counts towards the volumes, which
+    /// is none unless the policy is `CountVolume`
+    fn counted_volume(
+        &self,
+        side: TradeSide,
+        market: &MarketSelection,
+        area: &AreaSelection,
+    ) -> Decimal {
+        if self.missing_prices != MissingPricePolicy::CountVolume {
+            return Decimal::ZERO;
         }
+        self.aggregate_metric(market, area, |entry, market| {
+            total(&entry.missing_price_mw, side, market)
+        })
+    }
+
+    /// Trades without a price in the selection, whatever the policy. Unlike `skipped_trades`,
+    /// it can be narrowed to markets and areas.
+    pub fn missing_price_count(&self, market: MarketSelection, area: AreaSelection) -> usize {
+        self.entries
+            .values()
+            .filter(|entry| area.contains(entry.area))
+            .flat_map(|entry| entry.missing_price_trades.iter())
+            .filter(|((_, trade_market), _)| market.contains(*trade_market))
+            .map(|(_, trades)| trades)
+            .sum()
+    }
+
+    /// Energy of the trades without a price in the selection, bought and sold, whatever the
+    /// policy. Rounded like the volume metrics.
+    pub fn missing_price_mwh(&self, market: MarketSelection, area: AreaSelection) -> Decimal {
+        let summed = self.aggregate_metric(&market, &area, |entry, market| {
+            total(&entry.missing_price_mw, TradeSide::Buy, market)
+                + total(&entry.missing_price_mw, TradeSide::Sell, market)
+        });
+        summed.round_dp(1)
+    }
+
+    /// The statistics of the prices traded at in the selection, one per trade
+    fn prices(&self, market: &MarketSelection, area: &AreaSelection) -> PriceStatistics {
+        let mut statistics = PriceStatistics::default();
+        self.entries
+            .values()
+            .filter(|entry| area.contains(entry.area))
+            .flat_map(|entry| entry.prices.iter())
+            .filter(|((_, trade_market), _)| market.contains(*trade_market))
+            .for_each(|(_, prices)| statistics.merge(prices));
+        statistics
     }
 
+    #[deprecated(
+        since = "0.1.1",
+        note = "use `Report::try_metric(Metric::Revenue, ..)`"
+    )]
     pub fn revenue(&self, market: MarketSelection, area: AreaSelection) -> Decimal {
-        let summed = self.aggregate_metric(market, area, |entry, market| entry.revenue(market));
-        summed.round_dp(2)
+        self.metric(Metric::Revenue, market, area)
     }
 
+    #[deprecated(since = "0.1.1", note = "use `Report::try_metric(Metric::Costs, ..)`")]
     pub fn costs(&self, market: MarketSelection, area: AreaSelection) -> Decimal {
-        let summed = self.aggregate_metric(market, area, |entry, market| entry.costs(market));
-        summed.round_dp(2)
+        self.metric(Metric::Costs, market, area)
     }
 
+    #[deprecated(since = "0.1.1", note = "use `Report::try_metric(Metric::MwSold, ..)`")]
     pub fn mw_sold(&self, market: MarketSelection, area: AreaSelection) -> Decimal {
-        let summed = self.aggregate_metric(market, area, |entry, market| entry.mw_sold(market));
-        summed.round_dp(1)
+        self.metric(Metric::MwSold, market, area)
     }
 
+    #[deprecated(
+        since = "0.1.1",
+        note = "use `Report::try_metric(Metric::MwBought, ..)`"
+    )]
     pub fn mw_bought(&self, market: MarketSelection, area: AreaSelection) -> Decimal {
-        let summed = self.aggregate_metric(market, area, |entry, market| entry.mw_bought(market));
-        summed.round_dp(1)
+        self.metric(Metric::MwBought, market, area)
     }
 
+    #[deprecated(
+        since = "0.1.1",
+        note = "use `Report::try_metric(Metric::GrossProfit, ..)`"
+    )]
     pub fn gross_profit(&self, market: MarketSelection, area: AreaSelection) -> Decimal {
-        let summed =
-            self.aggregate_metric(market, area, |entry, market| entry.gross_profit(market));
-        summed.round_dp(2)
+        self.metric(Metric::GrossProfit, market, area)
+    }
+
+    #[deprecated(
+        since = "0.1.1",
+        note = "use `Report::try_metric(Metric::NetPosition, ..)`"
+    )]
+    pub fn net_position(&self, market: MarketSelection, area: AreaSelection) -> Decimal {
+        self.metric(Metric::NetPosition, market, area)
+    }
+
+    #[deprecated(
+        since = "0.1.1",
+        note = "use `Report::try_metric(Metric::NetCashFlow, ..)`"
+    )]
+    pub fn net_cash_flow(&self, market: MarketSelection, area: AreaSelection) -> Decimal {
+        self.metric(Metric::NetCashFlow, market, area)
+    }
+
+    /// Volume weighted average price bought at in the selection, `None` when nothing was bought
+    pub fn vwap_buy(&self, market: MarketSelection, area: AreaSelection) -> Option<Decimal> {
+        let costs = self.aggregate_metric(&market, &area, |entry, market| entry.costs(market));
+        let mw = self.aggregate_metric(&market, &area, |entry, market| entry.mw_bought(market));
+        vwap(costs, mw)
+    }
+
+    /// Volume weighted average price sold at in the selection, `None` when nothing was sold
+    pub fn vwap_sell(&self, market: MarketSelection, area: AreaSelection) -> Option<Decimal> {
+        let revenue = self.aggregate_metric(&market, &area, |entry, market| entry.revenue(market));
+        let mw = self.aggregate_metric(&market, &area, |entry, market| entry.mw_sold(market));
+        vwap(revenue, mw)
+    }
+
+    /// Fees charged on the trades of the selection, zero without a fee schedule, see `with_fees`
+    pub fn fees(&self, market: MarketSelection, area: AreaSelection) -> Decimal {
+        self.aggregate_metric(&market, &area, ReportEntry::fees)
+            .round_dp(2)
+    }
+
+    /// The gross profit of the selection after its fees
+    pub fn net_profit(&self, market: MarketSelection, area: AreaSelection) -> Decimal {
+        let gross_profit = self.aggregate_metric(&market, &area, ReportEntry::gross_profit);
+        (gross_profit - self.aggregate_metric(&market, &area, ReportEntry::fees)).round_dp(2)
+    }
+
+    /// What the trades of the selection gained over trading the same energy at the day-ahead
+    /// prices, selling above and buying below them. Zero without spot prices, see
+    /// `with_spot_prices`, and trades without spot prices are left out.
+    pub fn pnl_vs_spot(&self, market: MarketSelection, area: AreaSelection) -> Decimal {
+        self.aggregate_metric(&market, &area, ReportEntry::pnl_vs_spot)
+            .round_dp(2)
+    }
+
+    /// Trades with a price left out of `pnl_vs_spot` for lack of spot prices of their delivery
+    pub fn unbenchmarked_trades(&self, market: MarketSelection, area: AreaSelection) -> usize {
+        self.entries
+            .values()
+            .filter(|entry| area.contains(entry.area))
+            .flat_map(|entry| entry.unbenchmarked_trades.iter())
+            .filter(|((_, trade_market), _)| market.contains(*trade_market))
+            .map(|(_, trades)| trades)
+            .sum()
+    }
+}
+
+/// Cash flow per MW, rounded like the monetary metrics
+fn vwap(cash_flow: Decimal, mw: Decimal) -> Option<Decimal> {
+    (!mw.is_zero()).then(|| (cash_flow / mw).round_dp(2))
+}
+
+/// The fields of a trade a report aggregates, a fraction of the size of a `Trade`, so they can
+/// be sent between tasks instead of whole trades
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct TradeAggregateInput {
+    pub area: Area,
+    pub currency: Currency,
+    pub market: Market,
+    /// From the sign of the quantity, like in `Report::add_trade`
+    pub side: TradeSide,
+    /// Energy of the trade, the absolute quantity times the contract length
+    pub mwh: Decimal,
+    /// `None` for trades without a price, which the report skips
+    pub price: Option<Decimal>,
+}
+
+impl TradeAggregateInput {
+    /// The input of the trade, failing on product lengths a report would reject
+    pub fn from_trade(trade: &Trade) -> Result<Self> {
+        check_product_length(trade.trade_type, &trade.delivery_start, &trade.delivery_end)?;
+        let side = if trade.quantity_mwh < Decimal::ZERO {
+            TradeSide::Sell
+        } else {
+            TradeSide::Buy
+        };
+        Ok(Self {
+            area: trade.area,
+            currency: trade.currency,
+            market: Market::from(trade.trade_type),
+            side,
+            mwh: trade.quantity_mwh.abs()
+                * contract_length(&trade.delivery_start, &trade.delivery_end)?,
+            price: trade.price,
+        })
+    }
+}
+
+/// Fails for trade types with a fixed product length delivered over a different period
+fn check_product_length(
+    trade_type: TradeType,
+    delivery_start: &DateTime<FixedOffset>,
+    delivery_end: &DateTime<FixedOffset>,
+) -> Result<()> {
+    if let Some(product_length) = trade_type.product_length() {
+        let delivery_length = *delivery_end - *delivery_start;
+        if delivery_length != product_length {
+            bail!(
+                "{} trade delivered {} - {} does not have the product length of {} minutes",
+                trade_type,
+                delivery_start,
+                delivery_end,
+                product_length.num_minutes()
+            );
+        }
+    }
+    Ok(())
+}
+
+/// Sums of the trades of an area, currency, trade type and side, as computed by the database
+#[derive(Debug)]
+#[cfg_attr(feature = "runtime", derive(sqlx::FromRow))]
+pub struct AggregatedRow {
+    pub area: Area,
+    pub currency: Currency,
+    pub trade_type: TradeType,
+    /// Whether the quantities were negative, as the side is derived from the sign like in
+    /// `Report::add_trade`
+    pub sold: bool,
+    /// Energy of the trades with a price
+    pub mwh: Decimal,
+    /// Energy times price of the trades with a price
+    pub cash_flow: Decimal,
+    /// Energy of the trades without a price, see `MissingPricePolicy::CountVolume`
+    pub unpriced_mwh: Decimal,
+    pub priced_trades: i64,
+    pub skipped_trades: i64,
+}
+
+impl AggregatedRow {
+    fn side(&self) -> TradeSide {
+        if self.sold {
+            TradeSide::Sell
+        } else {
+            TradeSide::Buy
+        }
     }
 }
 
-#[derive(Debug, Serialize, Deserialize)]
+/// The cells of an area in a currency, whose cash flows, prices, fees and gains over the
+/// day-ahead prices are all in that currency
+#[derive(Debug, Clone, Serialize, Deserialize)]
 struct ReportEntry {
     area: Area,
-    mw: HashMap<(TradeSide, Market), Decimal>,
-    cash_flow: HashMap<(TradeSide, Market), Decimal>,
+    currency: Currency,
+    mw: HashMap<(TradeSide, Market), DecimalSum>,
+    cash_flow: HashMap<(TradeSide, Market), DecimalSum>,
+    prices: HashMap<(TradeSide, Market), PriceStatistics>,
+    /// Energy and number of the trades without a price, kept apart from those with one
+    missing_price_mw: HashMap<(TradeSide, Market), DecimalSum>,
+    missing_price_trades: HashMap<(TradeSide, Market), usize>,
+    /// Fees charged on the trades, see `Report::with_fees`
+    #[serde(default)]
+    fees: HashMap<(TradeSide, Market), DecimalSum>,
+    /// Gains over the day-ahead prices, and the trades without them, see
+    /// `Report::with_spot_prices`
+    #[serde(default)]
+    spot_pnl: HashMap<(TradeSide, Market), DecimalSum>,
+    #[serde(default)]
+    unbenchmarked_trades: HashMap<(TradeSide, Market), usize>,
 }
 
 impl ReportEntry {
-    fn new(area: Area) -> Self {
+    fn new(area: Area, currency: Currency) -> Self {
         Self {
             area,
+            currency,
             mw: HashMap::new(),
             cash_flow: HashMap::new(),
+            prices: HashMap::new(),
+            missing_price_mw: HashMap::new(),
+            missing_price_trades: HashMap::new(),
+            fees: HashMap::new(),
+            spot_pnl: HashMap::new(),
+            unbenchmarked_trades: HashMap::new(),
         }
     }
 
     fn add_trade_from_parts(
         &mut self,
-        area: Area,
+        (area, currency): (Area, Currency),
         trade_price: Option<Decimal>,
         quantity_mwh: Decimal,
         trade_type: TradeType,
         delivery_start: &DateTime<FixedOffset>,
         delivery_end: &DateTime<FixedOffset>,
     ) -> Result<()> {
-        if area != self.area {
-            bail!("Trade area has to match ReportEntry area");
+        if (area, currency) != (self.area, self.currency) {
+            bail!("Trade area and currency have to match those of the ReportEntry");
         }
-        let Some(trade_price) = trade_price else {
-            return Ok(());
-        };
+        check_product_length(trade_type, delivery_start, delivery_end)?;
 
         let trade_side = if quantity_mwh < Decimal::ZERO {
             TradeSide::Sell
@@ -218,21 +1713,81 @@ impl ReportEntry {
         let market = Market::from(trade_type);
         let contract_length = contract_length(delivery_start, delivery_end)?;
 
-        let abs_length_adjusted_quantity = quantity_mwh.abs() * contract_length;
+        let abs_quantity = quantity_mwh.abs();
+        let Some(trade_price) = trade_price else {
+            self.add_missing_price((trade_side, market), &[abs_quantity, contract_length]);
+            return Ok(());
+        };
 
-        *self.mw.entry((trade_side, market)).or_insert(Decimal::ZERO) +=
-            abs_length_adjusted_quantity;
-        *self
-            .cash_flow
+        self.mw
+            .entry((trade_side, market))
+            .or_default()
+            .add_product(&[abs_quantity, contract_length]);
+        self.cash_flow
             .entry((trade_side, market))
-            .or_insert(Decimal::ZERO) += abs_length_adjusted_quantity * trade_price;
+            .or_default()
+            .add_product(&[abs_quantity, contract_length, trade_price]);
+        self.prices
+            .entry((trade_side, market))
+            .or_default()
+            .add(trade_price);
 
         Ok(())
     }
 
+    fn merge(&mut self, other: ReportEntry) {
+        for (key, mw) in other.mw {
+            self.mw.entry(key).or_default().merge(mw);
+        }
+        for (key, cash_flow) in other.cash_flow {
+            self.cash_flow.entry(key).or_default().merge(cash_flow);
+        }
+        for (key, prices) in other.prices {
+            self.prices.entry(key).or_default().merge(&prices);
+        }
+        for (key, mw) in other.missing_price_mw {
+            self.missing_price_mw.entry(key).or_default().merge(mw);
+        }
+        for (key, trades) in other.missing_price_trades {
+            *self.missing_price_trades.entry(key).or_default() += trades;
+        }
+        for (key, fees) in other.fees {
+            self.fees.entry(key).or_default().merge(fees);
+        }
+        for (key, pnl) in other.spot_pnl {
+            self.spot_pnl.entry(key).or_default().merge(pnl);
+        }
+        for (key, trades) in other.unbenchmarked_trades {
+            *self.unbenchmarked_trades.entry(key).or_default() += trades;
+        }
+    }
+
+    /// Counts a trade without a price in a cell, with its energy as the product of the factors
+    fn add_missing_price(&mut self, key: (TradeSide, Market), factors: &[Decimal]) {
+        self.missing_price_mw
+            .entry(key)
+            .or_default()
+            .add_product(factors);
+        *self.missing_price_trades.entry(key).or_default() += 1;
+    }
+
+    fn add_input(&mut self, input: &TradeAggregateInput) {
+        let Some(price) = input.price else {
+            self.add_missing_price((input.side, input.market), &[input.mwh]);
+            return;
+        };
+        let key = (input.side, input.market);
+        self.mw.entry(key).or_default().add(input.mwh);
+        self.cash_flow
+            .entry(key)
+            .or_default()
+            .add_product(&[input.mwh, price]);
+        self.prices.entry(key).or_default().add(price);
+    }
+
     fn add_trade(&mut self, trade: &Trade) -> Result<()> {
         self.add_trade_from_parts(
-            trade.area,
+            (trade.area, trade.currency),
             trade.price,
             trade.quantity_mwh,
             trade.trade_type,
@@ -243,7 +1798,7 @@ impl ReportEntry {
 
     fn add_trade_for_report(&mut self, trade: &TradeForReport) -> Result<()> {
         self.add_trade_from_parts(
-            trade.area,
+            (trade.area, trade.currency),
             trade.price,
             trade.quantity_mwh,
             trade.trade_type,
@@ -252,90 +1807,960 @@ impl ReportEntry {
         )
     }
 
-    fn revenue(&self, market: MarketSelection) -> Decimal {
-        match market {
-            MarketSelection::Specific(market) => *self
-                .cash_flow
-                .get(&(TradeSide::Sell, market))
-                .unwrap_or(&Decimal::ZERO),
-            MarketSelection::All => Market::iter()
-                .map(|market| {
-                    *self
-                        .cash_flow
-                        .get(&(TradeSide::Sell, market))
-                        .unwrap_or(&Decimal::ZERO)
-                })
-                .sum(),
+    fn revenue(&self, market: &MarketSelection) -> Decimal {
+        total(&self.cash_flow, TradeSide::Sell, market)
+    }
+
+    fn costs(&self, market: &MarketSelection) -> Decimal {
+        total(&self.cash_flow, TradeSide::Buy, market)
+    }
+
+    fn mw_sold(&self, market: &MarketSelection) -> Decimal {
+        total(&self.mw, TradeSide::Sell, market)
+    }
+
+    fn mw_bought(&self, market: &MarketSelection) -> Decimal {
+        total(&self.mw, TradeSide::Buy, market)
+    }
+
+    fn gross_profit(&self, market: &MarketSelection) -> Decimal {
+        self.revenue(market) - self.costs(market)
+    }
+
+    fn fees(&self, market: &MarketSelection) -> Decimal {
+        total(&self.fees, TradeSide::Buy, market) + total(&self.fees, TradeSide::Sell, market)
+    }
+
+    fn pnl_vs_spot(&self, market: &MarketSelection) -> Decimal {
+        total(&self.spot_pnl, TradeSide::Buy, market)
+            + total(&self.spot_pnl, TradeSide::Sell, market)
+    }
+}
+
+/// The sum of a side over the selected markets
+fn total(
+    sums: &HashMap<(TradeSide, Market), DecimalSum>,
+    side: TradeSide,
+    market: &MarketSelection,
+) -> Decimal {
+    let get = |market| {
+        sums.get(&(side, market))
+            .map_or(Decimal::ZERO, |sum| sum.to_decimal())
+    };
+    match market {
+        MarketSelection::Specific(market) => get(*market),
+        MarketSelection::All | MarketSelection::Multiple(_) => Market::iter()
+            .filter(|selected| market.contains(*selected))
+            .map(get)
+            .sum(),
+    }
+}
+
+/// Seconds of the shortest products, of which the lengths of the common ones are multiples
+const QUARTER_HOUR_SECONDS: i64 = 900;
+
+/// Lengths of products of whole quarter hours, up to a day across the autumn DST transition,
+/// indexed by their number of quarter hours. Nearly all trades are of these lengths, so they're
+/// only divided out once.
+static CONTRACT_LENGTHS: LazyLock<Vec<Decimal>> = LazyLock::new(|| {
+    (0..=100)
+        .map(|quarter_hours| hours_of_seconds(quarter_hours * QUARTER_HOUR_SECONDS))
+        .collect()
+});
+
+fn hours_of_seconds(seconds: i64) -> Decimal {
+    Decimal::from(seconds) / Decimal::new(36000, 1)
+}
+
+/// Hours delivered between start and end, looked up for lengths of whole quarter hours
+pub fn contract_length(
+    delivery_start: &DateTime<FixedOffset>,
+    delivery_end: &DateTime<FixedOffset>,
+) -> Result<Decimal> {
+    let seconds = (*delivery_end - *delivery_start).num_seconds();
+    if seconds % QUARTER_HOUR_SECONDS == 0 {
+        let precomputed = usize::try_from(seconds / QUARTER_HOUR_SECONDS)
+            .ok()
+            .and_then(|quarter_hours| CONTRACT_LENGTHS.get(quarter_hours));
+        if let Some(contract_length) = precomputed {
+            return Ok(*contract_length);
         }
     }
+    Ok(hours_of_seconds(seconds))
+}
 
-    fn costs(&self, market: MarketSelection) -> Decimal {
-        match market {
-            MarketSelection::Specific(market) => *self
-                .cash_flow
-                .get(&(TradeSide::Buy, market))
-                .unwrap_or(&Decimal::ZERO),
-            MarketSelection::All => Market::iter()
-                .map(|market| {
-                    *self
-                        .cash_flow
-                        .get(&(TradeSide::Buy, market))
-                        .unwrap_or(&Decimal::ZERO)
-                })
-                .sum(),
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::fees::FeeRule;
+    use crate::prices::SpotPrices;
+    use crate::trade::CounterPart;
+    use chrono::TimeZone;
+    use chrono_tz::Europe::London;
+    use rust_decimal_macros::dec;
+
+    fn gb_auction_trade(trade_type: TradeType) -> crate::trade::TradeBuilder {
+        Trade::test()
+            .area(Area::GB)
+            .currency(Currency::Gbp)
+            .counter_part(crate::trade::CounterPart::EPEX)
+            .trade_type(trade_type)
+            .price(80)
+    }
+
+    /// The trade as fetched for reports
+    fn for_report(trade: &Trade) -> TradeForReport {
+        TradeForReport {
+            area: trade.area,
+            currency: trade.currency,
+            delivery_end: trade.delivery_end,
+            delivery_start: trade.delivery_start,
+            price: trade.price,
+            quantity_mwh: trade.quantity_mwh,
+            trade_side: trade.trade_side,
+            trade_type: trade.trade_type,
+        }
+    }
+
+    fn report(trades: Vec<Trade>) -> Result<Report> {
+        let from = London.with_ymd_and_hms(2024, 5, 1, 0, 0, 0).unwrap();
+        let to = London.with_ymd_and_hms(2024, 5, 2, 0, 0, 0).unwrap();
+        Report::new(&from, &to, trades)
+    }
+
+    #[test]
+    fn half_hourly_contract_length_is_half_an_hour() {
+        let start = DateTime::parse_from_rfc3339("2024-05-01T12:00:00+01:00").unwrap();
+        let end = DateTime::parse_from_rfc3339("2024-05-01T12:30:00+01:00").unwrap();
+        assert_eq!(contract_length(&start, &end).unwrap(), dec!(0.5));
+
+        // Precomputed lengths are the ones divided out for the others
+        let odd_end = DateTime::parse_from_rfc3339("2024-05-01T12:07:00+01:00").unwrap();
+        assert_eq!(
+            contract_length(&start, &odd_end).unwrap(),
+            dec!(7) / dec!(60)
+        );
+        for quarter_hours in [1, 4, 96, 100, 101, -4] {
+            let end = start + chrono::Duration::minutes(15 * quarter_hours);
+            let length = contract_length(&start, &end).unwrap();
+            let divided =
+                Decimal::from(quarter_hours * 900) / Decimal::from_str_exact("3600.0").unwrap();
+            assert_eq!((length, length.scale()), (divided, divided.scale()));
+        }
+    }
+
+    #[test]
+    fn half_hourly_auction_quantity_is_power_over_half_an_hour() {
+        let report = report(vec![
+            gb_auction_trade(TradeType::AuctionGbDahHh)
+                .buy()
+                .mwh(10)
+                .half_hour("2024-05-01T12:00+01:00")
+                .build(),
+            gb_auction_trade(TradeType::AuctionGbId1Hh)
+                .sell()
+                .mwh(4)
+                .half_hour("2024-05-01T12:30+01:00")
+                .build(),
+        ])
+        .unwrap();
+
+        let auction = MarketSelection::Specific(Market::Auction);
+        let gb = AreaSelection::Specific(Area::GB);
+        assert_eq!(
+            report.metric(Metric::MwBought, auction.clone(), gb.clone()),
+            dec!(5)
+        );
+        assert_eq!(
+            report.metric(Metric::Costs, auction.clone(), gb.clone()),
+            dec!(400)
+        );
+        assert_eq!(
+            report.metric(Metric::MwSold, auction.clone(), gb.clone()),
+            dec!(2)
+        );
+        assert_eq!(
+            report.metric(Metric::Revenue, auction.clone(), gb.clone()),
+            dec!(160)
+        );
+        assert_eq!(
+            report.metric(Metric::GrossProfit, auction.clone(), gb.clone()),
+            dec!(-240)
+        );
+        assert_eq!(
+            report.metric(Metric::NetPosition, auction.clone(), gb.clone()),
+            dec!(3)
+        );
+        assert_eq!(
+            report.metric(Metric::NetCashFlow, auction.clone(), gb.clone()),
+            dec!(-240)
+        );
+    }
+
+    #[test]
+    fn half_hourly_and_hourly_products_deliver_the_same_energy_per_hour() {
+        let hourly = report(vec![gb_auction_trade(TradeType::AuctionGbDahH)
+            .mwh(10)
+            .hour("2024-05-01T12:00+01:00")
+            .build()])
+        .unwrap();
+        let half_hourly = report(vec![
+            gb_auction_trade(TradeType::AuctionGbDahHh)
+                .mwh(10)
+                .half_hour("2024-05-01T12:00+01:00")
+                .build(),
+            gb_auction_trade(TradeType::AuctionGbDahHh)
+                .mwh(10)
+                .half_hour("2024-05-01T12:30+01:00")
+                .build(),
+        ])
+        .unwrap();
+
+        for metric in Metric::iter() {
+            assert_eq!(
+                hourly.metric(metric, MarketSelection::All, AreaSelection::All),
+                half_hourly.metric(metric, MarketSelection::All, AreaSelection::All),
+                "{}",
+                metric
+            );
         }
     }
 
-    fn mw_sold(&self, market: MarketSelection) -> Decimal {
-        match market {
-            MarketSelection::Specific(market) => *self
-                .mw
-                .get(&(TradeSide::Sell, market))
-                .unwrap_or(&Decimal::ZERO),
-            MarketSelection::All => Market::iter()
-                .map(|market| {
-                    *self
-                        .mw
-                        .get(&(TradeSide::Sell, market))
-                        .unwrap_or(&Decimal::ZERO)
+    #[test]
+    fn cells_are_explained_by_their_trades_with_retention() {
+        let from = chrono_tz::Europe::Copenhagen
+            .with_ymd_and_hms(2024, 5, 1, 0, 0, 0)
+            .unwrap();
+        let to = from + chrono::Duration::days(1);
+        let mut report = Report::empty(&from, &to).unwrap().with_retention(0);
+        for (id, trade) in [
+            Trade::test().build(),
+            Trade::test().sell().build(),
+            Trade::test().build(),
+            Trade::test().no_price().build(),
+        ]
+        .into_iter()
+        .enumerate()
+        {
+            report
+                .add_trade(&Trade {
+                    id: id as i64,
+                    ..trade
                 })
-                .sum(),
+                .unwrap();
+        }
+
+        let mut ids = |side| {
+            report
+                .explain(Area::DK1, Market::Intraday, side)
+                .unwrap()
+                .iter()
+                .map(|contribution| contribution.id)
+                .collect::<Vec<_>>()
+        };
+        assert_eq!(ids(TradeSide::Buy), vec![0, 2]);
+        assert_eq!(ids(TradeSide::Sell), vec![1]);
+        let sold = report
+            .explain(Area::DK1, Market::Intraday, TradeSide::Sell)
+            .unwrap();
+        assert_eq!(sold[0].mw, Trade::test().build().quantity_mwh.abs());
+        assert!(report
+            .explain(Area::DK2, Market::Intraday, TradeSide::Buy)
+            .unwrap()
+            .is_empty());
+        assert!(Report::empty(&from, &to)
+            .unwrap()
+            .explain(Area::DK1, Market::Intraday, TradeSide::Buy)
+            .is_err());
+    }
+
+    #[test]
+    fn price_statistics_span_both_sides_and_ignore_volume() {
+        let report = report(vec![
+            Trade::test().mwh(100).price(40).build(),
+            Trade::test().sell().mwh(1).price(70).build(),
+            Trade::test().mwh(1).price(45).build(),
+            Trade::test().sell().mwh(1).price(50).build(),
+            Trade::test().no_price().build(),
+        ])
+        .unwrap();
+        let metric = |metric| report.metric(metric, MarketSelection::All, AreaSelection::All);
+
+        assert_eq!(metric(Metric::MinPrice), dec!(40));
+        assert_eq!(metric(Metric::MaxPrice), dec!(70));
+        assert_eq!(metric(Metric::MedianPrice), dec!(47.5));
+    }
+
+    #[test]
+    fn json_has_the_full_breakdown_without_consuming_the_report() {
+        let report = report(vec![
+            Trade::test().mwh(10).price(40).build(),
+            Trade::test()
+                .area(Area::DK2)
+                .sell()
+                .mwh(4)
+                .price(50)
+                .build(),
+        ])
+        .unwrap();
+
+        let json: serde_json::Value = serde_json::from_str(&report.to_json().unwrap()).unwrap();
+        assert_eq!(json["totals"]["gross_profit"], serde_json::json!("-200.0"));
+        assert_eq!(
+            json["breakdown"],
+            serde_json::json!([
+                {"area": "DK1", "currency": "EUR", "market": "Intraday", "side": "Buy", "mw": "10.0", "cash_flow": "400.0"},
+                {"area": "DK2", "currency": "EUR", "market": "Intraday", "side": "Sell", "mw": "4.0", "cash_flow": "200.0"},
+            ])
+        );
+        assert_eq!(report.skipped_trades(), 0);
+    }
+
+    #[test]
+    fn half_hourly_product_with_hourly_delivery_is_rejected() {
+        let result = report(vec![gb_auction_trade(TradeType::AuctionGbId2Hh)
+            .mwh(10)
+            .hour("2024-05-01T12:00+01:00")
+            .build()]);
+
+        assert!(result.is_err());
+    }
+
+    #[test]
+    fn vwap_weighs_prices_by_energy_across_areas() {
+        let report = report(vec![
+            Trade::test().mwh(10).price(40).build(),
+            Trade::test().area(Area::DK2).mwh(30).price(60).build(),
+            Trade::test()
+                .sell()
+                .mwh(4)
+                .price(80)
+                .half_hour("2024-05-01T12:00+02:00")
+                .trade_type(TradeType::Intraday)
+                .build(),
+        ])
+        .unwrap();
+
+        let all = MarketSelection::All;
+        assert_eq!(
+            report.vwap_buy(all.clone(), AreaSelection::All),
+            Some(dec!(55))
+        );
+        assert_eq!(
+            report.vwap_buy(all.clone(), AreaSelection::Specific(Area::DK1)),
+            Some(dec!(40))
+        );
+        assert_eq!(
+            report.vwap_sell(all.clone(), AreaSelection::All),
+            Some(dec!(80))
+        );
+        assert_eq!(
+            report.vwap_sell(all.clone(), AreaSelection::Specific(Area::DK2)),
+            None
+        );
+    }
+
+    #[test]
+    fn aggregated_rows_give_the_metrics_of_the_trades_they_sum() {
+        let trades = report(vec![
+            Trade::test().mwh(10).price(40).build(),
+            Trade::test().mwh(20).price(55).build(),
+            Trade::test().sell().mwh(4).price(80).build(),
+            Trade::test().sell().mwh(1).no_price().build(),
+        ])
+        .unwrap();
+        let row =
+            |sold, mwh, cash_flow, unpriced_mwh, priced_trades, skipped_trades| AggregatedRow {
+                area: Area::DK1,
+                currency: Currency::Eur,
+                trade_type: TradeType::Intraday,
+                sold,
+                mwh,
+                cash_flow,
+                unpriced_mwh,
+                priced_trades,
+                skipped_trades,
+            };
+        let aggregated = Report::from_aggregated_rows(
+            trades.delivery_from(),
+            trades.delivery_to(),
+            vec![
+                row(false, dec!(30), dec!(1500), dec!(0), 2, 0),
+                row(true, dec!(4), dec!(320), dec!(1), 1, 1),
+            ],
+        )
+        .unwrap();
+
+        let (all, areas) = (MarketSelection::All, AreaSelection::All);
+        assert_eq!(aggregated.skipped_trades(), trades.skipped_trades());
+        assert_eq!(
+            aggregated.metric(Metric::GrossProfit, all.clone(), areas.clone()),
+            trades.metric(Metric::GrossProfit, all.clone(), areas.clone())
+        );
+        assert_eq!(
+            aggregated.vwap_buy(all.clone(), areas.clone()),
+            Some(dec!(50))
+        );
+        assert_eq!(aggregated.breakdown(), trades.breakdown());
+
+        // The energy without a price counts towards the volumes like that of the trades
+        let counted = trades
+            .with_missing_prices(MissingPricePolicy::CountVolume)
+            .unwrap();
+        let aggregated = aggregated
+            .with_missing_prices(MissingPricePolicy::CountVolume)
+            .unwrap();
+        assert_eq!(aggregated.breakdown(), counted.breakdown());
+        assert_eq!(
+            aggregated.metric(Metric::MwSold, all.clone(), areas.clone()),
+            dec!(5)
+        );
+        assert_eq!(
+            aggregated.missing_price_mwh(all.clone(), areas.clone()),
+            counted.missing_price_mwh(all, areas)
+        );
+    }
+
+    #[test]
+    fn inputs_aggregate_like_the_trades_they_were_taken_from() {
+        let trades = || {
+            vec![
+                gb_auction_trade(TradeType::AuctionGbDahHh)
+                    .mwh(10)
+                    .half_hour("2024-05-01T12:00+01:00")
+                    .build(),
+                Trade::test().sell().mwh(4).price(80).build(),
+                Trade::test().mwh(1).no_price().build(),
+            ]
+        };
+        let from_trades = report(trades()).unwrap();
+        let mut from_inputs =
+            Report::empty(from_trades.delivery_from(), from_trades.delivery_to()).unwrap();
+        for trade in trades().iter() {
+            from_inputs.add_input(&TradeAggregateInput::from_trade(trade).unwrap());
         }
+
+        assert_eq!(from_inputs.breakdown(), from_trades.breakdown());
+        assert_eq!(from_inputs.skipped_trades(), 1);
+        let hourly_half_hour = gb_auction_trade(TradeType::AuctionGbDahHh)
+            .mwh(10)
+            .hour("2024-05-01T12:00+01:00")
+            .build();
+        assert!(TradeAggregateInput::from_trade(&hourly_half_hour).is_err());
+    }
+
+    #[test]
+    fn batches_added_over_time_total_like_one_build() {
+        let trades = vec![
+            Trade::test().mwh(10).price(40).build(),
+            Trade::test()
+                .area(Area::DK2)
+                .sell()
+                .mwh(4)
+                .price(80)
+                .build(),
+            Trade::test().mwh(1).no_price().build(),
+        ];
+        let mut incremental = report(Vec::new()).unwrap();
+        incremental.add_trades(&trades[..1]).unwrap();
+        incremental.add_trades(&trades[1..]).unwrap();
+
+        assert_eq!(incremental.breakdown(), report(trades).unwrap().breakdown());
+        assert_eq!(incremental.skipped_trades(), 1);
+    }
+
+    #[test]
+    fn merged_days_total_like_the_report_of_both() {
+        let day = |day: u32| {
+            let from = London.with_ymd_and_hms(2024, 5, day, 0, 0, 0).unwrap();
+            let to = London.with_ymd_and_hms(2024, 5, day + 1, 0, 0, 0).unwrap();
+            Report::empty(&from, &to).unwrap()
+        };
+        let trades = [
+            Trade::test().mwh(10).price(40).build(),
+            Trade::test()
+                .sell()
+                .mwh(4)
+                .price(80)
+                .hour("2024-05-02T12:00+02:00")
+                .build(),
+            Trade::test().area(Area::DK2).mwh(1).no_price().build(),
+        ];
+        let mut first = day(1);
+        first.add_trades([&trades[0], &trades[2]]).unwrap();
+        let mut second = day(2);
+        second.add_trade(&trades[1]).unwrap();
+        let mut both = first.merge(second).unwrap();
+
+        let mut whole = day(1).merge(day(2)).unwrap();
+        whole.add_trades(&trades).unwrap();
+        assert_eq!(both.breakdown(), whole.breakdown());
+        assert_eq!(both.skipped_trades(), 1);
+        assert_eq!(
+            (*both.delivery_from(), *both.delivery_to()),
+            (*whole.delivery_from(), *whole.delivery_to())
+        );
+        assert_eq!(
+            both.metric(
+                Metric::MedianPrice,
+                MarketSelection::All,
+                AreaSelection::All
+            ),
+            dec!(60)
+        );
+
+        both = both.merge(day(2)).unwrap();
+        assert_eq!(both.breakdown(), whole.breakdown());
+        assert!(day(1).merge(day(3)).is_err());
     }
 
-    fn mw_bought(&self, market: MarketSelection) -> Decimal {
-        match market {
-            MarketSelection::Specific(market) => *self
-                .mw
-                .get(&(TradeSide::Buy, market))
-                .unwrap_or(&Decimal::ZERO),
-            MarketSelection::All => Market::iter()
-                .map(|market| {
-                    *self
-                        .mw
-                        .get(&(TradeSide::Buy, market))
-                        .unwrap_or(&Decimal::ZERO)
+    #[test]
+    fn parallel_aggregation_totals_like_a_single_thread() {
+        let from = London.with_ymd_and_hms(2024, 5, 1, 0, 0, 0).unwrap();
+        let to = London.with_ymd_and_hms(2024, 5, 2, 0, 0, 0).unwrap();
+        let trades = || {
+            (0..2 * PARALLEL_CHUNK_SIZE as i64 + 1)
+                .map(|i| {
+                    let trade = Trade::test()
+                        .area([Area::DK1, Area::DK2][i as usize % 2])
+                        .mwh(i % 50 + 1);
+                    match i % 3 {
+                        0 => trade.sell().price(i % 100),
+                        1 => trade.price(i % 70),
+                        _ => trade.no_price(),
+                    }
+                    .build()
                 })
-                .sum(),
+                .collect::<Vec<_>>()
+        };
+
+        let parallel = Report::new_parallel(&from, &to, trades()).unwrap();
+        let single = Report::new(&from, &to, trades()).unwrap();
+        assert_eq!(parallel.breakdown(), single.breakdown());
+        assert_eq!(parallel.skipped_trades(), single.skipped_trades());
+        let metric = |report: &Report, metric| {
+            report.metric(metric, MarketSelection::All, AreaSelection::All)
+        };
+        for price in [Metric::MinPrice, Metric::MaxPrice] {
+            assert_eq!(metric(&parallel, price), metric(&single, price));
         }
+        // The digests of the chunks merge into slightly different centroids than one digest
+        let median = metric(&parallel, Metric::MedianPrice) - metric(&single, Metric::MedianPrice);
+        assert!(median.abs() < dec!(0.5), "{}", median);
     }
 
-    fn gross_profit(&self, market: MarketSelection) -> Decimal {
-        self.revenue(market) - self.costs(market)
+    #[test]
+    fn trades_without_a_price_follow_the_policy() {
+        let trades = || {
+            vec![
+                Trade::test().mwh(10).price(50).build(),
+                Trade::test().sell().mwh(4).no_price().build(),
+                Trade::test().area(Area::DK2).mwh(1).no_price().build(),
+            ]
+        };
+        let (all, dk1) = (MarketSelection::All, AreaSelection::Specific(Area::DK1));
+
+        let skipped = report(trades()).unwrap();
+        assert_eq!(
+            skipped.missing_price_count(all.clone(), AreaSelection::All),
+            2
+        );
+        assert_eq!(skipped.missing_price_count(all.clone(), dk1.clone()), 1);
+        assert_eq!(skipped.missing_price_mwh(all.clone(), dk1.clone()), dec!(4));
+        assert_eq!(
+            skipped.metric(Metric::NetPosition, all.clone(), dk1.clone()),
+            dec!(10)
+        );
+
+        let counted = report(trades())
+            .unwrap()
+            .with_missing_prices(MissingPricePolicy::CountVolume)
+            .unwrap();
+        assert_eq!(
+            counted.metric(Metric::MwSold, all.clone(), dk1.clone()),
+            dec!(4)
+        );
+        assert_eq!(
+            counted.metric(Metric::NetPosition, all.clone(), dk1.clone()),
+            dec!(6)
+        );
+        assert_eq!(
+            counted.metric(Metric::Revenue, all.clone(), dk1.clone()),
+            dec!(0)
+        );
+        assert_eq!(counted.vwap_sell(all.clone(), dk1.clone()), None);
+        // Cells with only trades without a price show up in the breakdown
+        assert_eq!(counted.breakdown().len(), 3);
+
+        assert!(report(trades())
+            .unwrap()
+            .with_missing_prices(MissingPricePolicy::Error)
+            .is_err());
+        let mut strict = report(vec![])
+            .unwrap()
+            .with_missing_prices(MissingPricePolicy::Error)
+            .unwrap();
+        assert!(strict.add_trade(&trades()[0]).is_ok());
+        assert!(strict.add_trade(&trades()[1]).is_err());
     }
-}
 
-fn contract_length(
-    delivery_start: &DateTime<FixedOffset>,
-    delivery_end: &DateTime<FixedOffset>,
-) -> Result<Decimal> {
-    let time_delta = *delivery_end - *delivery_start;
+    #[test]
+    fn cash_flows_are_kept_apart_by_currency() {
+        let trades = vec![
+            Trade::test().sell().mwh(10).price(50).build(),
+            gb_auction_trade(TradeType::AuctionGbDahHh)
+                .sell()
+                .mwh(10)
+                .half_hour("2024-05-01T12:00+01:00")
+                .build(),
+        ];
+        let report = report(trades.clone()).unwrap();
+        let all = MarketSelection::All;
+
+        assert_eq!(
+            report.metric_by_currency(Metric::Revenue, all.clone(), AreaSelection::All),
+            BTreeMap::from([(Currency::Eur, dec!(500)), (Currency::Gbp, dec!(400))])
+        );
+        assert!(report
+            .try_metric(Metric::Revenue, all.clone(), AreaSelection::All)
+            .is_err());
+        // Volumes add up across currencies, and single currencies are fine
+        assert_eq!(
+            report
+                .try_metric(Metric::MwSold, all.clone(), AreaSelection::All)
+                .unwrap(),
+            dec!(15)
+        );
+        assert_eq!(
+            report
+                .try_metric(
+                    Metric::Revenue,
+                    all.clone(),
+                    AreaSelection::Specific(Area::GB)
+                )
+                .unwrap(),
+            dec!(400)
+        );
+
+        let json: serde_json::Value = serde_json::from_str(&report.to_json().unwrap()).unwrap();
+        assert_eq!(json["totals_by_currency"]["GBP"]["revenue"], "400.0");
+        assert_eq!(json["totals"]["mw_sold"], "15.0");
+        assert!(json["totals"].get("revenue").is_none());
+
+        // Converted to a reporting currency, they add up again
+        let from = London.with_ymd_and_hms(2024, 5, 1, 0, 0, 0).unwrap();
+        let to = London.with_ymd_and_hms(2024, 5, 2, 0, 0, 0).unwrap();
+        let rates = FxRates::new(Currency::Eur, London)
+            .with_fixed_rates(BTreeMap::from([(Currency::Gbp, dec!(1.2))]));
+        let builder = ReportBuilder::new(&from, &to).fx(rates);
+        let converted = builder.build(&trades).unwrap();
+        assert_eq!(
+            converted.currencies(AreaSelection::All),
+            BTreeSet::from([Currency::Eur])
+        );
+        assert_eq!(
+            converted
+                .try_metric(Metric::Revenue, all.clone(), AreaSelection::All)
+                .unwrap(),
+            dec!(980)
+        );
+
+        // Alike from the leaner inputs, but not from sums over days of different rates
+        let mut from_inputs = builder.empty().unwrap();
+        for trade in trades.iter() {
+            from_inputs.add_input(&builder.input(trade).unwrap().unwrap());
+        }
+        assert_eq!(from_inputs.breakdown(), converted.breakdown());
+        let from_for_report = builder
+            .build_from_trades_for_report(trades.iter().map(for_report).collect())
+            .unwrap();
+        assert_eq!(from_for_report.breakdown(), converted.breakdown());
+        assert_eq!(
+            builder
+                .check(ReportInput::AggregatedRows)
+                .unwrap_err()
+                .to_string(),
+            "Reports built from sums of the database can't apply fx"
+        );
+        assert!(report.merge(converted).is_err());
+    }
+
+    #[test]
+    fn fees_are_deducted_from_the_gross_profit() {
+        let from = London.with_ymd_and_hms(2024, 5, 1, 0, 0, 0).unwrap();
+        let to = London.with_ymd_and_hms(2024, 5, 2, 0, 0, 0).unwrap();
+        let broker = CounterPart::from("tradition".to_string());
+        let rule = |market: &str, counter_part: Option<CounterPart>, per_mwh, percent| FeeRule {
+            market: Some(market.to_string()),
+            counter_part,
+            per_mwh,
+            percent,
+        };
+        let schedule = FeeSchedule::new(&[
+            rule("intraday", None, dec!(0.1), dec!(0)),
+            rule("intraday", Some(broker.clone()), dec!(0), dec!(1)),
+        ])
+        .unwrap();
+        let trades = [
+            Trade::test().sell().mwh(10).price(50).build(),
+            Trade::test()
+                .counter_part(broker)
+                .sell()
+                .mwh(10)
+                .price(60)
+                .build(),
+            // Auctions are charged nothing, and trades without a price aren't charged either
+            Trade::test()
+                .trade_type(TradeType::AuctionEurDahH)
+                .mwh(10)
+                .price(40)
+                .build(),
+            Trade::test().no_price().mwh(10).build(),
+        ];
+        let builder = ReportBuilder::new(&from, &to).fees(schedule);
+        let mut report = builder.build(&trades).unwrap();
+        let (all, intraday) = (
+            MarketSelection::All,
+            MarketSelection::Specific(Market::Intraday),
+        );
+
+        // 10 MWh at 0.1, and 1% of 600
+        assert_eq!(report.fees(all.clone(), AreaSelection::All), dec!(7));
+        assert_eq!(
+            report.net_profit(all.clone(), AreaSelection::All),
+            dec!(693)
+        );
+        assert_eq!(
+            report.net_profit(intraday.clone(), AreaSelection::All),
+            dec!(1093)
+        );
+
+        // Trades added later are charged too, and merged reports keep their fees
+        report
+            .add_trade(&Trade::test().sell().mwh(10).price(50).build())
+            .unwrap();
+        let merged = report.merge(Report::empty(&from, &to).unwrap()).unwrap();
+        assert_eq!(merged.fees(all.clone(), AreaSelection::All), dec!(8));
+
+        // Only whole trades carry the counter parts fees are charged by
+        assert_eq!(
+            builder
+                .build_from_trades_for_report(trades.iter().map(for_report).collect())
+                .unwrap_err()
+                .to_string(),
+            "Reports built from trades for reports can't apply fees"
+        );
+        builder.check(ReportInput::AggregateInputs).unwrap_err();
+    }
+
+    #[test]
+    fn trades_are_benchmarked_against_the_day_ahead_prices() {
+        let from = London.with_ymd_and_hms(2024, 5, 1, 0, 0, 0).unwrap();
+        let to = London.with_ymd_and_hms(2024, 5, 2, 0, 0, 0).unwrap();
+        let hour = |value: &str| DateTime::parse_from_rfc3339(value).unwrap().to_utc();
+        let mut prices = SpotPrices::default();
+        prices.insert(
+            Area::DK1,
+            hour("2024-05-01T10:00:00Z"),
+            hour("2024-05-01T11:00:00Z"),
+            dec!(45),
+        );
+        let trades = [
+            Trade::test().sell().mwh(10).price(50).build(),
+            Trade::test().mwh(10).price(42).build(),
+            // No spot price
+            Trade::test().area(Area::DK2).mwh(10).price(42).build(),
+        ];
+        let builder = ReportBuilder::new(&from, &to).spot_prices(Arc::new(prices));
+        let report = builder.build(&trades).unwrap();
+
+        // Sold 5 above and bought 3 below the spot price
+        assert_eq!(
+            report.pnl_vs_spot(MarketSelection::All, AreaSelection::All),
+            dec!(80)
+        );
+        assert_eq!(
+            report.unbenchmarked_trades(MarketSelection::All, AreaSelection::All),
+            1
+        );
+        assert_eq!(
+            builder
+                .check(ReportInput::AggregatedRows)
+                .unwrap_err()
+                .to_string(),
+            "Reports built from sums of the database can't apply spot_prices"
+        );
+    }
+
+    #[test]
+    fn reports_are_read_back_from_their_json() {
+        let from = London.with_ymd_and_hms(2024, 5, 1, 0, 0, 0).unwrap();
+        let to = London.with_ymd_and_hms(2024, 5, 2, 0, 0, 0).unwrap();
+        let report = report(vec![
+            Trade::test().sell().mwh(10).price(50).build(),
+            Trade::test()
+                .trade_type(TradeType::AuctionEurDahH)
+                .mwh(4)
+                .price(40)
+                .build(),
+            Trade::test().no_price().mwh(1).build(),
+        ])
+        .unwrap();
+
+        let read = Report::from_json(&report.to_json().unwrap()).unwrap();
+        assert_eq!((read.delivery_from, read.delivery_to), (from, to));
+        assert_eq!(read.delivery_from.timezone(), London);
+        assert_eq!(read.breakdown(), report.breakdown());
+        assert_eq!(read.skipped_trades(), 1);
+        for metric in [Metric::GrossProfit, Metric::NetPosition] {
+            assert_eq!(
+                read.metric(metric, MarketSelection::All, AreaSelection::All),
+                report.metric(metric, MarketSelection::All, AreaSelection::All)
+            );
+        }
+        assert!(Report::from_json("{}").is_err());
+    }
+
+    #[test]
+    fn boundary_trades_are_pro_rated_into_the_window() {
+        let from = London.with_ymd_and_hms(2024, 5, 1, 0, 0, 0).unwrap();
+        let to = London.with_ymd_and_hms(2024, 5, 2, 0, 0, 0).unwrap();
+        let delivered = |start: &str, end: &str| {
+            let mut trade = Trade::test().mwh(10).price(50).build();
+            trade.delivery_start = DateTime::parse_from_rfc3339(start).unwrap();
+            trade.delivery_end = DateTime::parse_from_rfc3339(end).unwrap();
+            trade
+        };
+        let trades = [
+            // Two of its four hours are inside the window
+            delivered("2024-04-30T22:00:00+01:00", "2024-05-01T02:00:00+01:00"),
+            delivered("2024-05-01T12:00:00+01:00", "2024-05-01T13:00:00+01:00"),
+            // One of its two hours
+            delivered("2024-05-01T23:00:00+01:00", "2024-05-02T01:00:00+01:00"),
+            delivered("2024-04-30T12:00:00+01:00", "2024-04-30T13:00:00+01:00"),
+        ];
+        let (all, dk1) = (MarketSelection::All, AreaSelection::Specific(Area::DK1));
+
+        // In full, as given
+        let whole = ReportBuilder::new(&from, &to).build(&trades).unwrap();
+        assert_eq!(
+            whole.metric(Metric::MwBought, all.clone(), dk1.clone()),
+            dec!(80)
+        );
+
+        let builder = ReportBuilder::new(&from, &to).boundary_trades(BoundaryPolicy::ProRata);
+        assert_eq!(builder.fetch_from(), from - Duration::days(1));
+        // Trades selected by overlap need no lookback
+        let overlapping = ReportBuilder::new(&from, &to)
+            .boundary_trades(BoundaryPolicy::ProRata)
+            .window_filter(WindowFilter::Overlap);
+        assert_eq!(overlapping.fetch_from(), from);
+        let pro_rata = builder.build(&trades).unwrap();
+        assert_eq!(
+            pro_rata.metric(Metric::MwBought, all.clone(), dk1.clone()),
+            dec!(40)
+        );
+        assert_eq!(
+            pro_rata.metric(Metric::Costs, all.clone(), dk1.clone()),
+            dec!(2000)
+        );
+        assert_eq!(pro_rata.vwap_buy(all, dk1), Some(dec!(50)));
+
+        // Trades without their ids are counted alike
+        let from_for_report = builder
+            .build_from_trades_for_report(trades.iter().map(for_report).collect())
+            .unwrap();
+        assert_eq!(from_for_report.breakdown(), pro_rata.breakdown());
+    }
+
+    #[test]
+    fn options_reports_of_the_input_cant_apply_fail_the_check() {
+        let from = London.with_ymd_and_hms(2024, 5, 1, 0, 0, 0).unwrap();
+        let to = London.with_ymd_and_hms(2024, 5, 2, 0, 0, 0).unwrap();
+        let builder = ReportBuilder::new(&from, &to);
+        for input in [
+            ReportInput::Trades,
+            ReportInput::TradesForReport,
+            ReportInput::AggregateInputs,
+            ReportInput::AggregatedRows,
+        ] {
+            builder.check(input).unwrap();
+        }
 
-    let delta_seconds = Decimal::from_i64(time_delta.num_seconds())
-        .ok_or(anyhow!("Could not convert duration to seconds"))?;
-    let seconds_per_hour = Decimal::from_str_exact("3600.0")?;
+        let builder = builder
+            .retain_trades(1024)
+            .counter_parts(CounterPartSelection::Specific(CounterPart::from(
+                "epex".to_string(),
+            )));
+        builder.check(ReportInput::Trades).unwrap();
+        assert_eq!(
+            builder
+                .check(ReportInput::AggregateInputs)
+                .unwrap_err()
+                .to_string(),
+            "Reports built from aggregate inputs can't apply retain_trades"
+        );
+        assert_eq!(
+            builder
+                .build_from_aggregated_rows(Vec::new())
+                .unwrap_err()
+                .to_string(),
+            "Reports built from sums of the database can't apply retain_trades, counter_parts"
+        );
+    }
+
+    #[test]
+    fn multiple_selections_cover_each_of_their_values() {
+        let trades = vec![
+            Trade::test().sell().mwh(10).price(50).build(),
+            Trade::test()
+                .area(Area::DK2)
+                .sell()
+                .mwh(2)
+                .price(40)
+                .build(),
+            Trade::test().area(Area::GB).sell().mwh(1).price(30).build(),
+            gb_auction_trade(TradeType::AuctionGbDahHh)
+                .sell()
+                .half_hour("2024-05-01T12:00+01:00")
+                .build(),
+        ];
+        let report = report(trades).unwrap();
+        let revenue = |market, area| report.metric(Metric::Revenue, market, area);
 
-    let contract_length = delta_seconds / seconds_per_hour;
+        let danish = AreaSelection::multiple([Area::DK2, Area::DK1, Area::DK2]);
+        assert_eq!(danish, AreaSelection::multiple([Area::DK1, Area::DK2]));
+        assert_eq!(revenue(MarketSelection::All, danish.clone()), dec!(580));
+        let both = MarketSelection::multiple([Market::Intraday, Market::Auction]);
+        let all = AreaSelection::All;
+        assert_eq!(
+            revenue(both.clone(), all.clone()),
+            revenue(MarketSelection::All, all.clone())
+        );
+        assert_eq!(
+            revenue(MarketSelection::multiple([Market::Auction]), all.clone()),
+            revenue(MarketSelection::Specific(Market::Auction), all.clone())
+        );
 
-    Ok(contract_length)
+        let from = London.with_ymd_and_hms(2024, 5, 1, 0, 0, 0).unwrap();
+        let to = London.with_ymd_and_hms(2024, 5, 2, 0, 0, 0).unwrap();
+        let trades = [
+            gb_auction_trade(TradeType::AuctionGbDahHh)
+                .sell()
+                .half_hour("2024-05-01T12:00+01:00")
+                .build(),
+            Trade::test().area(Area::GB).sell().mwh(1).price(30).build(),
+        ];
+        let epex = ReportBuilder::new(&from, &to)
+            .counter_parts(CounterPartSelection::multiple([
+                crate::trade::CounterPart::EPEX,
+                crate::trade::CounterPart::EPEX,
+            ]))
+            .build(&trades)
+            .unwrap();
+        assert_eq!(
+            epex.metric(Metric::MwSold, MarketSelection::All, all.clone()),
+            dec!(0.5)
+        );
+    }
 }
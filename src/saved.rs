@@ -0,0 +1,204 @@
+use anyhow::{anyhow, bail, Context, Result};
+use sqlx::PgPool;
+
+use crate::pipeline::PipelineDefinition;
+
+/// Table of the saved report definitions in the trade database, created as
+/// `CREATE TABLE saved_reports (name text PRIMARY KEY, definition text NOT NULL,
+/// updated_at timestamptz DEFAULT now())`
+const SAVED_REPORTS_TABLE: &str = "saved_reports";
+
+/// Words starting the clauses of a definition, in the order they're written
+const CLAUSES: [&str; 4] = ["by", "where", "in", "to"];
+
+/// Parses a saved report definition into the pipeline it runs as. Definitions are one line,
+/// the metrics followed by optional clauses for the dimensions, filters, timezone and sink:
+///
+/// ```text
+/// mw_bought, gross_profit by trade_type, delivery_day where area = GB and market = auction
+///     in Europe/London to csv gb_monthly.csv
+/// ```
+///
+/// Names and values are spelled like in the pipelines of the config, which the definition is
+/// translated to, so both are validated the same way.
+pub fn parse_definition(name: &str, source: &str) -> Result<PipelineDefinition> {
+    let mut clauses: Vec<(&str, Vec<&str>)> = vec![("metrics", Vec::new())];
+    for word in source.split_whitespace() {
+        if CLAUSES.contains(&word) {
+            if clauses.iter().any(|(clause, _)| *clause == word) {
+                bail!("Definition has more than one {} clause", word);
+            }
+            clauses.push((word, Vec::new()));
+        } else if let Some((_, words)) = clauses.last_mut() {
+            words.push(word);
+        }
+    }
+
+    let list = |words: &[&str]| -> toml::Value {
+        words
+            .join(" ")
+            .split(',')
+            .map(str::trim)
+            .filter(|item| !item.is_empty())
+            .map(|item| toml::Value::String(item.to_string()))
+            .collect::<Vec<_>>()
+            .into()
+    };
+    let mut table = toml::Table::new();
+    table.insert("name".to_string(), name.into());
+    table.insert("dimensions".to_string(), toml::Value::Array(Vec::new()));
+    for (clause, words) in clauses {
+        if words.is_empty() {
+            bail!("The {} clause of the definition is empty", clause);
+        }
+        match clause {
+            "metrics" => {
+                table.insert("metrics".to_string(), list(&words));
+            }
+            "by" => {
+                table.insert("dimensions".to_string(), list(&words));
+            }
+            "where" => {
+                let filters = words
+                    .join(" ")
+                    .split(" and ")
+                    .map(|filter| {
+                        let (field, values) = filter.split_once('=').ok_or(anyhow!(
+                            "Expected a filter as field = values, not {}",
+                            filter
+                        ))?;
+                        let mut filter = toml::Table::new();
+                        filter.insert(field.trim().to_string(), list(&[values]));
+                        Ok(toml::Value::Table(filter))
+                    })
+                    .collect::<Result<Vec<_>>>()?;
+                table.insert("filters".to_string(), filters.into());
+            }
+            "in" => {
+                let [timezone] = words[..] else {
+                    bail!("Expected a single timezone, not {}", words.join(" "));
+                };
+                let mut local_time = toml::Table::new();
+                local_time.insert("timezone".to_string(), timezone.into());
+                let mut enricher = toml::Table::new();
+                enricher.insert("local_time".to_string(), local_time.into());
+                table.insert(
+                    "enrichers".to_string(),
+                    vec![toml::Value::Table(enricher)].into(),
+                );
+            }
+            _ => {
+                let sink: toml::Value = match words[..] {
+                    ["stdout"] => "stdout".into(),
+                    [format @ ("csv" | "json"), path] => {
+                        let mut target = toml::Table::new();
+                        target.insert("path".to_string(), path.into());
+                        let mut sink = toml::Table::new();
+                        sink.insert(format.to_string(), target.into());
+                        sink.into()
+                    }
+                    _ => bail!(
+                        "Expected the sink as stdout, csv <path> or json <path>, not {}",
+                        words.join(" ")
+                    ),
+                };
+                table.insert("sink".to_string(), sink);
+            }
+        }
+    }
+
+    table
+        .try_into()
+        .with_context(|| format!("Invalid report definition {}", name))
+}
+
+/// Report definitions saved in the database by name, so recurring reports are data that the
+/// CLI and scheduler run by name rather than scripts
+pub struct SavedReports<'a> {
+    pool: &'a PgPool,
+}
+
+impl<'a> SavedReports<'a> {
+    pub fn new(pool: &'a PgPool) -> Self {
+        Self { pool }
+    }
+
+    /// Saves the definition under the name, replacing a definition of the same name. Fails
+    /// without saving if the definition doesn't parse.
+    pub async fn save(&self, name: &str, source: &str) -> Result<()> {
+        parse_definition(name, source)?;
+        let query = format!(
+            "INSERT INTO {} (name, definition) VALUES ($1, $2) \
+             ON CONFLICT (name) DO UPDATE SET definition = EXCLUDED.definition, updated_at = now()",
+            SAVED_REPORTS_TABLE
+        );
+        sqlx::query(&query)
+            .bind(name)
+            .bind(source)
+            .execute(self.pool)
+            .await
+            .with_context(|| format!("Could not save report {}", name))?;
+        Ok(())
+    }
+
+    pub async fn definition(&self, name: &str) -> Result<PipelineDefinition> {
+        let query = format!(
+            "SELECT definition FROM {} WHERE name = $1",
+            SAVED_REPORTS_TABLE
+        );
+        let source: Option<String> = sqlx::query_scalar(&query)
+            .bind(name)
+            .fetch_optional(self.pool)
+            .await
+            .with_context(|| format!("Could not read saved report {}", name))?;
+        let source = source.ok_or(anyhow!("No saved report named {}", name))?;
+        parse_definition(name, &source)
+    }
+
+    /// Names and definitions of all saved reports, ordered by name
+    pub async fn list(&self) -> Result<Vec<(String, String)>> {
+        let query = format!(
+            "SELECT name, definition FROM {} ORDER BY name",
+            SAVED_REPORTS_TABLE
+        );
+        sqlx::query_as(&query)
+            .fetch_all(self.pool)
+            .await
+            .context("Could not read saved reports")
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::pipeline::{Dimension, FilterDefinition, SinkDefinition};
+    use crate::report::Metric;
+
+    #[test]
+    fn definitions_translate_to_pipelines() {
+        let definition = parse_definition(
+            "gb-monthly",
+            "mw_bought, gross_profit by trade_type, delivery_day \
+             where area = GB and market = auction,imbalance in Europe/London to csv gb.csv",
+        )
+        .unwrap();
+        assert_eq!(definition.metrics, [Metric::MwBought, Metric::GrossProfit]);
+        assert_eq!(
+            definition.dimensions,
+            [Dimension::TradeType, Dimension::DeliveryDay]
+        );
+        assert!(matches!(
+            &definition.filters[..],
+            [FilterDefinition::Area(areas), FilterDefinition::Market(markets)]
+                if areas == &["GB"] && markets == &["auction", "imbalance"]
+        ));
+        assert!(matches!(definition.sink, SinkDefinition::Csv { .. }));
+
+        let totals = parse_definition("totals", "gross_profit").unwrap();
+        assert!(totals.dimensions.is_empty() && totals.filters.is_empty());
+
+        assert!(parse_definition("typo", "gross_profit by delivery_dya").is_err());
+        assert!(parse_definition("twice", "gross_profit by area by side").is_err());
+        assert!(parse_definition("empty", "gross_profit where").is_err());
+    }
+}
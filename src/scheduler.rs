@@ -0,0 +1,296 @@
+use std::collections::HashMap;
+use std::fs;
+use std::future::Future;
+use std::path::PathBuf;
+
+use anyhow::{anyhow, Context, Result};
+use chrono::{DateTime, Duration, Local, NaiveTime, TimeZone};
+use chrono_tz::{Europe::Copenhagen, Tz};
+use serde::{Deserialize, Serialize};
+
+use crate::cancel::{or_cancelled, CancellationToken};
+
+/// Jobs run daily at a local (Europe/Copenhagen) time, configured as e.g.
+///
+/// ```toml
+/// [scheduler]
+/// state_path = "scheduler_state.json"
+/// max_catch_up_runs = 3
+///
+/// [[scheduler.jobs]]
+/// name = "nightly-snapshot"
+/// at = "06:00"
+/// action = "snapshot"
+/// ```
+#[derive(Debug, Deserialize, Clone)]
+#[serde(default)]
+pub struct SchedulerConfig {
+    pub state_path: PathBuf,
+    /// Whether runs missed while the scheduler was down are executed on startup
+    pub catch_up: bool,
+    /// The most recent missed runs to execute per job, older ones are skipped
+    pub max_catch_up_runs: usize,
+    pub jobs: Vec<ScheduledJob>,
+}
+
+impl Default for SchedulerConfig {
+    fn default() -> Self {
+        Self {
+            state_path: PathBuf::from("scheduler_state.json"),
+            catch_up: true,
+            max_catch_up_runs: 1,
+            jobs: Vec::new(),
+        }
+    }
+}
+
+#[derive(Debug, Deserialize, Clone)]
+pub struct ScheduledJob {
+    pub name: String,
+    /// Local time of day in `HH:MM`
+    pub at: String,
+    pub action: JobAction,
+}
+
+#[derive(Debug, Deserialize, Clone)]
+#[serde(rename_all = "snake_case")]
+pub enum JobAction {
+    /// Append a snapshot of the report to the snapshot log
+    Snapshot,
+    /// Run the named pipelines, sharing one scan
+    Pipelines(Vec<String>),
+    /// Run the named reports saved in the database, sharing one scan
+    SavedReports(Vec<String>),
+}
+
+impl ScheduledJob {
+    fn time_of_day(&self) -> Result<NaiveTime> {
+        NaiveTime::parse_from_str(&self.at, "%H:%M")
+            .with_context(|| format!("Invalid time {} for job {}", self.at, self.name))
+    }
+
+    /// Scheduled runs after `after` up to and including `until`, oldest first.
+    /// Days where the time doesn't exist (spring-forward) have no run.
+    fn runs_between(
+        &self,
+        after: &DateTime<Tz>,
+        until: &DateTime<Tz>,
+    ) -> Result<Vec<DateTime<Tz>>> {
+        let time_of_day = self.time_of_day()?;
+        let mut runs = Vec::new();
+        let mut date = after.date_naive();
+
+        while date <= until.date_naive() {
+            if let Some(run) = Copenhagen
+                .from_local_datetime(&date.and_time(time_of_day))
+                .earliest()
+            {
+                if run > *after && run <= *until {
+                    runs.push(run);
+                }
+            }
+            date += Duration::days(1);
+        }
+
+        Ok(runs)
+    }
+}
+
+/// Executes the action of a job for a scheduled time
+pub trait JobRunner {
+    fn run(
+        &self,
+        job: &ScheduledJob,
+        scheduled_at: DateTime<Tz>,
+    ) -> impl Future<Output = Result<()>>;
+}
+
+/// When each job last ran, persisted so missed runs can be detected after a restart
+#[derive(Debug, Default, Serialize, Deserialize)]
+struct ScheduleState {
+    last_runs: HashMap<String, DateTime<chrono::FixedOffset>>,
+}
+
+pub struct Scheduler {
+    config: SchedulerConfig,
+    state: ScheduleState,
+}
+
+impl Scheduler {
+    pub fn load(config: SchedulerConfig) -> Result<Self> {
+        for job in config.jobs.iter() {
+            job.time_of_day()?;
+        }
+
+        let state = if config.state_path.exists() {
+            let content = fs::read_to_string(&config.state_path).with_context(|| {
+                format!(
+                    "Could not read scheduler state {}",
+                    config.state_path.display()
+                )
+            })?;
+            serde_json::from_str(&content).with_context(|| {
+                format!("Invalid scheduler state {}", config.state_path.display())
+            })?
+        } else {
+            ScheduleState::default()
+        };
+
+        Ok(Self { config, state })
+    }
+
+    fn last_run(&self, job: &ScheduledJob) -> Option<DateTime<Tz>> {
+        self.state
+            .last_runs
+            .get(&job.name)
+            .map(|last_run| last_run.with_timezone(&Copenhagen))
+    }
+
+    fn record_run(&mut self, job: &ScheduledJob, scheduled_at: &DateTime<Tz>) -> Result<()> {
+        self.state
+            .last_runs
+            .insert(job.name.clone(), scheduled_at.fixed_offset());
+        fs::write(
+            &self.config.state_path,
+            serde_json::to_string_pretty(&self.state)?,
+        )
+        .with_context(|| {
+            format!(
+                "Could not write scheduler state {}",
+                self.config.state_path.display()
+            )
+        })
+    }
+
+    /// Detects runs missed since the persisted last run of each job, and executes the most
+    /// recent ones within the catch-up limit. Jobs that never ran start from now.
+    pub async fn catch_up<R: JobRunner>(&mut self, runner: &R, now: &DateTime<Tz>) -> Result<()> {
+        for job in self.config.jobs.clone() {
+            let Some(last_run) = self.last_run(&job) else {
+                self.record_run(&job, now)?;
+                continue;
+            };
+
+            let missed = job.runs_between(&last_run, now)?;
+            if missed.is_empty() {
+                continue;
+            }
+            if !self.config.catch_up {
+                println!("Skipping {} missed runs of {}", missed.len(), job.name);
+                self.record_run(&job, missed.last().unwrap())?;
+                continue;
+            }
+
+            let skipped = missed.len().saturating_sub(self.config.max_catch_up_runs);
+            if skipped > 0 {
+                println!(
+                    "Skipping {} missed runs of {}, beyond the catch-up limit",
+                    skipped, job.name
+                );
+            }
+            for scheduled_at in missed.into_iter().skip(skipped) {
+                println!("Catching up on {} scheduled at {}", job.name, scheduled_at);
+                runner.run(&job, scheduled_at).await?;
+                self.record_run(&job, &scheduled_at)?;
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Catches up on missed runs and then runs the jobs at their scheduled times, until the
+    /// token is cancelled. A job cancelled while running isn't recorded as run, so it's caught up
+    /// on after a restart.
+    pub async fn run<R: JobRunner>(
+        mut self,
+        runner: &R,
+        cancellation: &CancellationToken,
+    ) -> Result<()> {
+        let now = Local::now().with_timezone(&Copenhagen);
+        or_cancelled(cancellation, self.catch_up(runner, &now)).await?;
+
+        loop {
+            let now = Local::now().with_timezone(&Copenhagen);
+            // The next run of each job is within the next two days, also across DST changes
+            let horizon = now + Duration::days(2);
+            let mut next_runs = Vec::new();
+            for job in self.config.jobs.iter() {
+                if let Some(run) = job.runs_between(&now, &horizon)?.into_iter().next() {
+                    next_runs.push((run, job.clone()));
+                }
+            }
+            let (scheduled_at, job) = next_runs
+                .into_iter()
+                .min_by_key(|(run, _)| *run)
+                .ok_or(anyhow!("No jobs to schedule"))?;
+
+            let wait = (scheduled_at - now).to_std().unwrap_or_default();
+            or_cancelled(cancellation, async {
+                tokio::time::sleep(wait).await;
+                Ok(())
+            })
+            .await?;
+
+            println!("Running {} scheduled at {}", job.name, scheduled_at);
+            or_cancelled(cancellation, runner.run(&job, scheduled_at)).await?;
+            self.record_run(&job, &scheduled_at)?;
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::cell::RefCell;
+
+    struct RecordingRunner(RefCell<Vec<DateTime<Tz>>>);
+
+    impl JobRunner for RecordingRunner {
+        async fn run(&self, _job: &ScheduledJob, scheduled_at: DateTime<Tz>) -> Result<()> {
+            self.0.borrow_mut().push(scheduled_at);
+            Ok(())
+        }
+    }
+
+    #[tokio::test]
+    async fn only_the_most_recent_missed_runs_are_caught_up() {
+        let state_path = std::env::temp_dir().join("scheduler_catch_up_test.json");
+        let _ = fs::remove_file(&state_path);
+        let config: SchedulerConfig = toml::from_str(&format!(
+            r#"
+            state_path = {:?}
+            max_catch_up_runs = 2
+
+            [[jobs]]
+            name = "daily"
+            at = "06:00"
+            action = "snapshot"
+            "#,
+            state_path
+        ))
+        .unwrap();
+        let at = |day, hour| {
+            Copenhagen
+                .with_ymd_and_hms(2024, 11, day, hour, 0, 0)
+                .unwrap()
+        };
+
+        let mut scheduler = Scheduler::load(config).unwrap();
+        let runner = RecordingRunner(RefCell::new(Vec::new()));
+        scheduler.catch_up(&runner, &at(1, 7)).await.unwrap();
+        assert!(runner.0.borrow().is_empty());
+
+        // Down from the 1st until after the run on the 5th, missing four runs
+        scheduler.catch_up(&runner, &at(5, 8)).await.unwrap();
+        assert_eq!(*runner.0.borrow(), vec![at(4, 6), at(5, 6)]);
+
+        // The state survives a restart
+        let config = SchedulerConfig {
+            state_path: state_path.clone(),
+            ..Default::default()
+        };
+        let scheduler = Scheduler::load(config).unwrap();
+        assert_eq!(scheduler.state.last_runs["daily"], at(5, 6).fixed_offset());
+        fs::remove_file(&state_path).unwrap();
+    }
+}
@@ -0,0 +1,269 @@
+use std::str::FromStr;
+
+use anyhow::{bail, Result};
+use sqlx::PgPool;
+
+use crate::db::TradeTable;
+use crate::trade::{Area, CounterPart, Currency, TradeSide, TradeType};
+
+struct ExpectedColumn {
+    name: &'static str,
+    /// Postgres' internal type names accepted, as found in `information_schema.columns.udt_name`
+    udt_names: &'static [&'static str],
+    nullable: bool,
+    /// Validates the distinct values of text columns decoded into enums
+    known_value: Option<fn(&str) -> bool>,
+}
+
+const EXPECTED_COLUMNS: [ExpectedColumn; 10] = [
+    ExpectedColumn {
+        name: "id",
+        // Tables still on 32-bit ids are read as 64-bit, see `db`
+        udt_names: &["int8", "int4"],
+        nullable: false,
+        known_value: None,
+    },
+    ExpectedColumn {
+        name: "area",
+        udt_names: &["varchar"],
+        nullable: false,
+        known_value: Some(|value| Area::from_str(value).is_ok()),
+    },
+    ExpectedColumn {
+        name: "counter_part",
+        udt_names: &["varchar"],
+        nullable: false,
+        known_value: Some(|value| CounterPart::from_str(value).is_ok()),
+    },
+    ExpectedColumn {
+        name: "currency",
+        udt_names: &["varchar"],
+        nullable: false,
+        known_value: Some(|value| Currency::from_str(value).is_ok()),
+    },
+    ExpectedColumn {
+        name: "delivery_start",
+        udt_names: &["timestamptz"],
+        nullable: false,
+        known_value: None,
+    },
+    ExpectedColumn {
+        name: "delivery_end",
+        udt_names: &["timestamptz"],
+        nullable: false,
+        known_value: None,
+    },
+    ExpectedColumn {
+        name: "price",
+        udt_names: &["numeric"],
+        nullable: true,
+        known_value: None,
+    },
+    ExpectedColumn {
+        name: "quantity_mwh",
+        udt_names: &["numeric"],
+        nullable: false,
+        known_value: None,
+    },
+    ExpectedColumn {
+        name: "trade_side",
+        udt_names: &["varchar"],
+        nullable: false,
+        known_value: Some(|value| TradeSide::from_str(value).is_ok()),
+    },
+    ExpectedColumn {
+        name: "trade_type",
+        udt_names: &["varchar"],
+        nullable: false,
+        known_value: Some(|value| TradeType::from_str(value).is_ok()),
+    },
+];
+
+/// Optional column of the auction trades with the filled part of partially cleared orders
+pub const CLEARED_QUANTITY_COLUMN: &str = "cleared_quantity_mwh";
+
+/// Columns of the reports table `report::Report::save` inserts into, created by the migrations
+const REPORTS_COLUMNS: [ExpectedColumn; 7] = [
+    ExpectedColumn {
+        name: "id",
+        udt_names: &["int8"],
+        nullable: false,
+        known_value: None,
+    },
+    ExpectedColumn {
+        name: "delivery_from",
+        udt_names: &["timestamptz"],
+        nullable: false,
+        known_value: None,
+    },
+    ExpectedColumn {
+        name: "delivery_to",
+        udt_names: &["timestamptz"],
+        nullable: false,
+        known_value: None,
+    },
+    ExpectedColumn {
+        name: "timezone",
+        udt_names: &["text"],
+        nullable: false,
+        known_value: None,
+    },
+    ExpectedColumn {
+        name: "generated_at",
+        udt_names: &["timestamptz"],
+        nullable: false,
+        known_value: None,
+    },
+    ExpectedColumn {
+        name: "code_version",
+        udt_names: &["text"],
+        nullable: false,
+        known_value: None,
+    },
+    ExpectedColumn {
+        name: "report",
+        udt_names: &["jsonb"],
+        nullable: false,
+        known_value: None,
+    },
+];
+
+/// Table reports are saved to, see `REPORTS_COLUMNS`
+const REPORTS_TABLE: &str = "reports";
+
+/// Columns of a table as name, udt name and nullability, empty if the table doesn't exist
+async fn table_columns(pool: &PgPool, table_name: &str) -> Result<Vec<(String, String, String)>> {
+    Ok(sqlx::query_as(
+        "
+    SELECT column_name::text, udt_name::text, is_nullable::text
+    FROM information_schema.columns
+    WHERE table_schema = current_schema() AND table_name = $1",
+    )
+    .bind(table_name)
+    .fetch_all(pool)
+    .await?)
+}
+
+/// Adds the columns of the table that differ from the expected ones to the problems
+async fn check_columns(
+    pool: &PgPool,
+    table_name: &str,
+    columns: &[(String, String, String)],
+    expected_columns: &[ExpectedColumn],
+    problems: &mut Vec<String>,
+) -> Result<()> {
+    for expected in expected_columns {
+        let Some((_, udt_name, is_nullable)) =
+            columns.iter().find(|(name, _, _)| name == expected.name)
+        else {
+            problems.push(format!("{}.{} is missing", table_name, expected.name));
+            continue;
+        };
+
+        if !expected.udt_names.contains(&udt_name.as_str()) {
+            problems.push(format!(
+                "{}.{} has type {}, expected {}",
+                table_name,
+                expected.name,
+                udt_name,
+                expected.udt_names.join(" or ")
+            ));
+            continue;
+        }
+        if is_nullable == "YES" && !expected.nullable {
+            problems.push(format!(
+                "{}.{} is nullable, expected NOT NULL",
+                table_name, expected.name
+            ));
+        }
+
+        let Some(known_value) = expected.known_value else {
+            continue;
+        };
+        let values: Vec<String> = sqlx::query_scalar(&format!(
+            "SELECT DISTINCT {0} FROM {1} WHERE {0} IS NOT NULL",
+            expected.name, table_name
+        ))
+        .fetch_all(pool)
+        .await?;
+        let unknown_values: Vec<String> = values
+            .into_iter()
+            .filter(|value| !known_value(value))
+            .collect();
+        if !unknown_values.is_empty() {
+            problems.push(format!(
+                "{}.{} contains unknown values: {}",
+                table_name,
+                expected.name,
+                unknown_values.join(", ")
+            ));
+        }
+    }
+
+    Ok(())
+}
+
+/// Verifies that the trade tables have the columns and types the queries in `db` decode into,
+/// and that enum-like text columns only contain values we know how to parse. The reports table
+/// is only needed to save reports, so it's checked if it exists, see `check_reports_schema`.
+/// All problems are collected and reported together, so a broken database fails before any
+/// report is generated instead of halfway through a stream.
+pub async fn check_schema(pool: &PgPool) -> Result<()> {
+    let mut problems = Vec::new();
+
+    for table in TradeTable::ALL {
+        let table_name = table.table_name();
+        let columns = table_columns(pool, table_name).await?;
+
+        if columns.is_empty() {
+            problems.push(format!("table {} does not exist", table_name));
+            continue;
+        }
+
+        if table == TradeTable::Auction {
+            let cleared_quantity = columns
+                .iter()
+                .find(|(name, _, _)| name == CLEARED_QUANTITY_COLUMN);
+            if let Some((_, udt_name, _)) = cleared_quantity.filter(|(_, udt, _)| udt != "numeric")
+            {
+                problems.push(format!(
+                    "{}.{} has type {}, expected numeric",
+                    table_name, CLEARED_QUANTITY_COLUMN, udt_name
+                ));
+            }
+        }
+
+        check_columns(pool, table_name, &columns, &EXPECTED_COLUMNS, &mut problems).await?;
+    }
+
+    let columns = table_columns(pool, REPORTS_TABLE).await?;
+    if !columns.is_empty() {
+        check_columns(
+            pool,
+            REPORTS_TABLE,
+            &columns,
+            &REPORTS_COLUMNS,
+            &mut problems,
+        )
+        .await?;
+    }
+
+    if !problems.is_empty() {
+        bail!("Schema check failed:\n  {}", problems.join("\n  "));
+    }
+
+    Ok(())
+}
+
+/// Verifies that the reports table exists, as created by the migrations in `migrations/`.
+/// Its columns are checked by `check_schema` on startup already.
+pub async fn check_reports_schema(pool: &PgPool) -> Result<()> {
+    if table_columns(pool, REPORTS_TABLE).await?.is_empty() {
+        bail!(
+            "Table {} does not exist, create it with the migrations in migrations/",
+            REPORTS_TABLE
+        );
+    }
+
+    Ok(())
+}
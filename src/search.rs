@@ -0,0 +1,235 @@
+use std::fmt;
+use std::io::Write;
+use std::str::FromStr;
+
+use anyhow::{anyhow, Context, Result};
+use chrono::{DateTime, FixedOffset};
+use rust_decimal::Decimal;
+use sqlx::{FromRow, PgPool, Row};
+
+use crate::db::TradeTable;
+use crate::trade::{CounterPart, Trade};
+use crate::units::QuantityUnits;
+
+const DEFAULT_PAGE_SIZE: i64 = 1000;
+
+/// Criteria of a trade search across all trade tables. All criteria are optional and ranges
+/// are inclusive. Quantities are matched in MW, after normalizing them from the unit of their
+/// table, and trades match a delivery window when their delivery period overlaps it.
+#[derive(Debug)]
+pub struct TradeSearch {
+    pub price_min: Option<Decimal>,
+    pub price_max: Option<Decimal>,
+    pub quantity_min: Option<Decimal>,
+    pub quantity_max: Option<Decimal>,
+    pub counter_parts: Vec<CounterPart>,
+    pub delivery_from: Option<DateTime<FixedOffset>>,
+    pub delivery_to: Option<DateTime<FixedOffset>>,
+    pub page_size: i64,
+}
+
+impl Default for TradeSearch {
+    fn default() -> Self {
+        Self {
+            price_min: None,
+            price_max: None,
+            quantity_min: None,
+            quantity_max: None,
+            counter_parts: Vec::new(),
+            delivery_from: None,
+            delivery_to: None,
+            page_size: DEFAULT_PAGE_SIZE,
+        }
+    }
+}
+
+/// Position after the last trade of a page. Trades are paged through ordered by table and id,
+/// so pages stay stable while new trades are inserted. The `table` and `id` columns of the
+/// output give the cursor to continue after a trade.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct SearchCursor {
+    pub table: TradeTable,
+    pub id: i64,
+}
+
+/// Formats the cursor as `FromStr` parses it, e.g. `auction:1234`
+impl fmt::Display for SearchCursor {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}:{}", self.table, self.id)
+    }
+}
+
+impl FromStr for SearchCursor {
+    type Err = anyhow::Error;
+
+    /// Parses cursors formatted as `<table>:<id>`, e.g. `auction:1234`
+    fn from_str(value: &str) -> Result<Self> {
+        let (table, id) = value
+            .split_once(':')
+            .ok_or(anyhow!("Invalid cursor {}, expected <table>:<id>", value))?;
+        let table = TradeTable::ALL
+            .into_iter()
+            .find(|candidate| candidate.to_string() == table)
+            .ok_or(anyhow!("Unknown table in cursor: {}", table))?;
+        Ok(Self {
+            table,
+            id: id
+                .parse()
+                .with_context(|| format!("Invalid id in cursor {}", value))?,
+        })
+    }
+}
+
+fn source(table: TradeTable) -> i32 {
+    TradeTable::ALL
+        .iter()
+        .position(|candidate| *candidate == table)
+        .unwrap_or_default() as i32
+}
+
+impl TradeSearch {
+    fn matches_quantity(&self, quantity: Decimal) -> bool {
+        self.quantity_min.is_none_or(|min| quantity >= min)
+            && self.quantity_max.is_none_or(|max| quantity <= max)
+    }
+
+    /// Fetches the next page of matching trades after the cursor, with the cursor of the page
+    /// after it or `None` when all tables have been searched. A page can hold fewer than
+    /// `page_size` trades, as quantities are only matched after normalizing them.
+    pub async fn page(
+        &self,
+        pool: &PgPool,
+        after: Option<SearchCursor>,
+        units: &QuantityUnits,
+    ) -> Result<(Vec<(TradeTable, Trade)>, Option<SearchCursor>)> {
+        // Tables partitioned by delivery_start can only be pruned on a plain comparison with it,
+        // not when it's behind an `IS NULL OR`, so that condition is only added when bounded
+        let delivery_start_bound = match self.delivery_to {
+            Some(_) => "delivery_start < $7",
+            None => "$7::timestamptz IS NULL",
+        };
+        let branches: Vec<String> = TradeTable::ALL
+            .iter()
+            .map(|table| {
+                format!(
+                    "
+    SELECT id::bigint AS id, area, counter_part, currency, delivery_start, delivery_end, price, quantity_mwh, trade_side, trade_type, {} AS source
+    FROM {}
+    WHERE ($3::numeric IS NULL OR price >= $3) AND ($4::numeric IS NULL OR price <= $4)
+        AND (cardinality($5::text[]) = 0 OR counter_part = ANY($5))
+        AND ($6::timestamptz IS NULL OR delivery_end > $6)
+        AND {}",
+                    source(*table),
+                    table.table_name(),
+                    delivery_start_bound
+                )
+            })
+            .collect();
+        let query = format!(
+            "
+    SELECT * FROM ({}) AS trades
+    WHERE (source, id) > ($1, $2)
+    ORDER BY source, id
+    LIMIT $8",
+            branches.join("\n    UNION ALL")
+        );
+
+        let (after_source, after_id) =
+            after.map_or((-1, 0), |cursor| (source(cursor.table), cursor.id));
+        let counter_parts: Vec<String> = self
+            .counter_parts
+            .iter()
+            .map(|counter_part| counter_part.to_string())
+            .collect();
+        let rows = sqlx::query(&query)
+            .bind(after_source)
+            .bind(after_id)
+            .bind(self.price_min)
+            .bind(self.price_max)
+            .bind(counter_parts)
+            .bind(self.delivery_from)
+            .bind(self.delivery_to)
+            .bind(self.page_size)
+            .fetch_all(pool)
+            .await?;
+
+        let mut trades = Vec::with_capacity(rows.len());
+        let mut next = None;
+        for row in rows.iter() {
+            let table = TradeTable::ALL[row.try_get::<i32, _>("source")? as usize];
+            let mut trade = Trade::from_row(row)?;
+            next = Some(SearchCursor {
+                table,
+                id: trade.id,
+            });
+            if !units.is_normalized(table) {
+                trade.quantity_mwh = units.normalize(
+                    table,
+                    units
+                        .convention()
+                        .signed(trade.quantity_mwh, trade.trade_side),
+                    &trade.delivery_start,
+                    &trade.delivery_end,
+                )?;
+            }
+            if self.matches_quantity(trade.quantity_mwh) {
+                trades.push((table, trade));
+            }
+        }
+
+        // A short page means there are no trades left after it
+        if (rows.len() as i64) < self.page_size {
+            next = None;
+        }
+        Ok((trades, next))
+    }
+
+    /// Streams all matching trades as CSV, fetching them a page at a time
+    pub async fn write_csv<W: Write>(
+        &self,
+        pool: &PgPool,
+        mut cursor: Option<SearchCursor>,
+        units: &QuantityUnits,
+        writer: W,
+    ) -> Result<()> {
+        let mut writer = csv::Writer::from_writer(writer);
+        writer.write_record([
+            "table",
+            "id",
+            "area",
+            "counter_part",
+            "currency",
+            "delivery_start",
+            "delivery_end",
+            "price",
+            "quantity_mw",
+            "trade_side",
+            "trade_type",
+        ])?;
+
+        loop {
+            let (trades, next) = self.page(pool, cursor, units).await?;
+            for (table, trade) in trades {
+                writer.write_record([
+                    table.to_string(),
+                    trade.id.to_string(),
+                    trade.area.to_string(),
+                    trade.counter_part.to_string(),
+                    trade.currency.to_string(),
+                    trade.delivery_start.to_rfc3339(),
+                    trade.delivery_end.to_rfc3339(),
+                    trade.price.map_or(String::new(), |price| price.to_string()),
+                    trade.quantity_mwh.to_string(),
+                    trade.trade_side.to_string(),
+                    trade.trade_type.to_string(),
+                ])?;
+            }
+            writer.flush()?;
+
+            match next {
+                Some(next) => cursor = Some(next),
+                None => return Ok(()),
+            }
+        }
+    }
+}
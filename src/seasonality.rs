@@ -0,0 +1,157 @@
+use std::collections::BTreeMap;
+
+use anyhow::{anyhow, bail, Result};
+use chrono::{DateTime, Datelike, Duration, DurationRound, Timelike, Utc, Weekday};
+use chrono_tz::Tz;
+use rust_decimal::Decimal;
+
+use crate::trade::{Area, Trade};
+
+const HOURS_PER_WEEK: usize = 168;
+
+/// Profit and energy traded in an hour of the week, summed over its occurrences in the window
+#[derive(Debug, Default, Clone, Copy, PartialEq)]
+struct HourTotals {
+    pnl: Decimal,
+    mwh: Decimal,
+}
+
+/// Average profit and volume per hour of the week, Monday 00:00 - 01:00 being hour 0, per area.
+/// Energy and cash flow are split onto the local hours a trade delivers in, sells adding to and
+/// buys taking from the profit like in `Report`, and averages are over the occurrences of the
+/// hour in the window, so an hour skipped by the spring DST transition counts once less.
+/// Trades without a price are skipped.
+pub struct Seasonality {
+    timezone: Tz,
+    /// How often each hour of the week occurs in the window
+    occurrences: [u32; HOURS_PER_WEEK],
+    areas: BTreeMap<Area, [HourTotals; HOURS_PER_WEEK]>,
+    skipped_trades: usize,
+}
+
+/// Hour of the week of the local time, counted from Monday midnight
+fn hour_of_week(time: &DateTime<Tz>) -> usize {
+    time.weekday().num_days_from_monday() as usize * 24 + time.hour() as usize
+}
+
+fn round_down_to_hour(time: DateTime<Utc>) -> Result<DateTime<Utc>> {
+    time.duration_trunc(Duration::hours(1))
+        .map_err(|err| anyhow!("Could not round {} to the hour: {}", time, err))
+}
+
+impl Seasonality {
+    pub fn new(delivery_from: &DateTime<Tz>, delivery_to: &DateTime<Tz>) -> Result<Self> {
+        if delivery_to < delivery_from {
+            bail!("delivery_from has to be before delivery_to");
+        }
+        let timezone = delivery_from.timezone();
+        let mut occurrences = [0; HOURS_PER_WEEK];
+        let mut hour = round_down_to_hour(delivery_from.with_timezone(&Utc))?;
+        while hour < *delivery_to {
+            occurrences[hour_of_week(&hour.with_timezone(&timezone))] += 1;
+            hour += Duration::hours(1);
+        }
+        Ok(Self {
+            timezone,
+            occurrences,
+            areas: BTreeMap::new(),
+            skipped_trades: 0,
+        })
+    }
+
+    pub fn add_trade(&mut self, trade: &Trade) -> Result<()> {
+        let Some(price) = trade.price else {
+            self.skipped_trades += 1;
+            return Ok(());
+        };
+        let (start, end) = (
+            trade.delivery_start.with_timezone(&Utc),
+            trade.delivery_end.with_timezone(&Utc),
+        );
+        let hours = self
+            .areas
+            .entry(trade.area)
+            .or_insert([HourTotals::default(); HOURS_PER_WEEK]);
+        let mut hour = round_down_to_hour(start)?;
+
+        while hour < end {
+            let next_hour = hour + Duration::hours(1);
+            let overlap = (next_hour.min(end) - hour.max(start)).num_seconds();
+            let mwh = trade.quantity_mwh * Decimal::from(overlap) / Decimal::from(3600);
+            let totals = &mut hours[hour_of_week(&hour.with_timezone(&self.timezone))];
+            totals.mwh += mwh.abs();
+            totals.pnl -= mwh * price;
+            hour = next_hour;
+        }
+        Ok(())
+    }
+
+    pub fn skipped_trades(&self) -> usize {
+        self.skipped_trades
+    }
+
+    /// Rows of the average profit and volume of each hour of the week per area, all 168 hours of
+    /// every area traded in
+    pub fn records(&self) -> Vec<Vec<String>> {
+        self.areas
+            .iter()
+            .flat_map(|(area, hours)| {
+                hours.iter().enumerate().map(move |(hour, totals)| {
+                    let average = |total: Decimal| match self.occurrences[hour] {
+                        0 => Decimal::ZERO,
+                        occurrences => total / Decimal::from(occurrences),
+                    };
+                    let weekday = Weekday::try_from((hour / 24) as u8)
+                        .expect("hours of the week are within seven days");
+                    vec![
+                        area.to_string(),
+                        hour.to_string(),
+                        weekday.to_string(),
+                        (hour % 24).to_string(),
+                        self.occurrences[hour].to_string(),
+                        average(totals.pnl).round_dp(2).to_string(),
+                        average(totals.mwh).round_dp(1).to_string(),
+                    ]
+                })
+            })
+            .collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use chrono::TimeZone;
+    use chrono_tz::Europe::Copenhagen;
+
+    #[test]
+    fn hours_are_averaged_over_their_occurrences_in_the_window() {
+        // Two weeks starting on a Monday
+        let from = Copenhagen.with_ymd_and_hms(2024, 5, 6, 0, 0, 0).unwrap();
+        let to = Copenhagen.with_ymd_and_hms(2024, 5, 20, 0, 0, 0).unwrap();
+        let mut seasonality = Seasonality::new(&from, &to).unwrap();
+        for trade in [
+            Trade::test()
+                .mwh(10)
+                .price(40)
+                .hour("2024-05-06T12:00+02:00")
+                .build(),
+            Trade::test()
+                .sell()
+                .mwh(4)
+                .price(50)
+                .hour("2024-05-13T12:00+02:00")
+                .build(),
+        ] {
+            seasonality.add_trade(&trade).unwrap();
+        }
+
+        let records = seasonality.records();
+        assert_eq!(records.len(), 168);
+        assert_eq!(
+            records[12],
+            vec!["DK1", "12", "Mon", "12", "2", "-100", "7"]
+        );
+        assert_eq!(records[13][4..], ["2", "0", "0"]);
+    }
+}
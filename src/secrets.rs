@@ -0,0 +1,147 @@
+use std::collections::HashMap;
+use std::env;
+use std::time::{Duration, Instant};
+
+use anyhow::{anyhow, Context, Result};
+use serde::Deserialize;
+use tokio::sync::Mutex;
+
+/// Where credentials are read from, configured as e.g.
+///
+/// ```toml
+/// [secrets]
+/// source = "vault"
+/// address = "https://vault.internal:8200"
+/// path = "trading-aggregator"
+/// ```
+#[derive(Debug, Deserialize, Default, Clone)]
+#[serde(rename_all = "snake_case", tag = "source")]
+pub enum SecretsConfig {
+    /// Environment variables, including the ones from `.env`
+    #[default]
+    Env,
+    /// A HashiCorp Vault KV v2 secret, with one key per credential
+    Vault {
+        address: String,
+        #[serde(default = "default_vault_mount")]
+        mount: String,
+        path: String,
+        /// Environment variable holding the Vault token
+        #[serde(default = "default_vault_token_env")]
+        token_env: String,
+        /// How long fetched secrets are cached when Vault doesn't give a lease duration
+        #[serde(default = "default_cache_seconds")]
+        cache_seconds: u64,
+    },
+}
+
+fn default_vault_mount() -> String {
+    "secret".to_string()
+}
+
+fn default_vault_token_env() -> String {
+    "VAULT_TOKEN".to_string()
+}
+
+fn default_cache_seconds() -> u64 {
+    300
+}
+
+#[derive(Deserialize)]
+struct VaultResponse {
+    #[serde(default)]
+    lease_duration: u64,
+    data: VaultData,
+}
+
+#[derive(Deserialize)]
+struct VaultData {
+    data: HashMap<String, String>,
+}
+
+struct CachedSecrets {
+    values: HashMap<String, String>,
+    expires: Instant,
+}
+
+/// Credentials such as `DATABASE_URL`, or the API keys of ingestion clients named
+/// `<COUNTERPART>_API_KEY`. Secrets from Vault are cached until their lease expires, and can be
+/// invalidated when a credential is rejected so a rotated one is fetched.
+pub struct Secrets {
+    config: SecretsConfig,
+    client: reqwest::Client,
+    cache: Mutex<Option<CachedSecrets>>,
+}
+
+impl Secrets {
+    pub fn new(config: SecretsConfig) -> Self {
+        Self {
+            config,
+            client: reqwest::Client::new(),
+            cache: Mutex::new(None),
+        }
+    }
+
+    /// Whether a rejected credential may be rotated, making a retry with a fresh one worthwhile
+    pub fn rotates(&self) -> bool {
+        !matches!(self.config, SecretsConfig::Env)
+    }
+
+    pub async fn get(&self, name: &str) -> Result<String> {
+        match &self.config {
+            SecretsConfig::Env => env::var(name).with_context(|| format!("{} is not set", name)),
+            SecretsConfig::Vault {
+                address,
+                mount,
+                path,
+                token_env,
+                cache_seconds,
+            } => {
+                let mut cache = self.cache.lock().await;
+                let expired = cache
+                    .as_ref()
+                    .is_none_or(|cached| cached.expires <= Instant::now());
+                if expired {
+                    let token =
+                        env::var(token_env).with_context(|| format!("{} is not set", token_env))?;
+                    let url = format!(
+                        "{}/v1/{}/data/{}",
+                        address.trim_end_matches('/'),
+                        mount,
+                        path
+                    );
+                    let response: VaultResponse = self
+                        .client
+                        .get(&url)
+                        .header("X-Vault-Token", token)
+                        .send()
+                        .await
+                        .and_then(|response| response.error_for_status())
+                        .with_context(|| format!("Could not read secrets from {}", url))?
+                        .json()
+                        .await
+                        .with_context(|| format!("Invalid secrets response from {}", url))?;
+                    let ttl = match response.lease_duration {
+                        0 => *cache_seconds,
+                        lease_duration => lease_duration,
+                    };
+                    *cache = Some(CachedSecrets {
+                        values: response.data.data,
+                        expires: Instant::now() + Duration::from_secs(ttl),
+                    });
+                }
+
+                cache
+                    .as_ref()
+                    .and_then(|cached| cached.values.get(name))
+                    .cloned()
+                    .ok_or(anyhow!("No secret {} in {}/{}", name, mount, path))
+            }
+        }
+    }
+
+    /// Drops cached secrets, so the next lookup fetches the current, possibly rotated, ones
+    pub async fn invalidate(&self) {
+        *self.cache.lock().await = None;
+    }
+}
@@ -0,0 +1,936 @@
+use std::collections::{BTreeMap, HashMap};
+use std::future::Future;
+use std::hash::Hash;
+use std::str::FromStr;
+use std::sync::{Arc, Mutex, PoisonError};
+use std::time::Instant;
+
+use anyhow::{anyhow, Result};
+use axum::extract::{Path, Query, State};
+use axum::http::{header, HeaderMap, StatusCode};
+use axum::response::{IntoResponse, Response};
+use axum::routing::get;
+use axum::{Json, Router};
+use chrono::{DateTime, FixedOffset};
+use chrono_tz::Tz;
+use futures::future::{BoxFuture, Shared};
+use futures::FutureExt;
+use rust_decimal::Decimal;
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use tokio::net::TcpListener;
+
+use crate::bounds::{describe_window, parse_delivery_day};
+use crate::cancel;
+use crate::config::Config;
+use crate::db::TradeSource;
+use crate::hierarchy::AreaHierarchy;
+use crate::jobs::{Job, JobRegistry, JobStatus};
+use crate::monitoring;
+use crate::permissions::RolePermissions;
+use crate::report::{Metric, ReportBuilder};
+use crate::search::{SearchCursor, TradeSearch};
+use crate::timing::{Phase, StrategyTimings};
+use crate::trade::{
+    Area, AreaSelection, CounterPart, Currency, Market, MarketSelection, Trade, TradeSide,
+    TradeTable,
+};
+
+/// Serves the metrics of reports over HTTP, streaming the trades of each request's window from
+/// the source like the stream strategy:
+///
+/// ```text
+/// GET /report?from=2024-05-01&to=2024-06-01&area=DK1&market=intraday
+/// ```
+///
+/// `from` and `to` are read like `--from` and `--to`, so `to` is the first day after the window.
+/// Reports are built as configured, like those of the commands. `area`, or
+/// `region` for a node of the area hierarchy, and `market` narrow the metrics, all areas and
+/// markets by default. `GET /metrics` serves the metrics of the process for Prometheus to
+/// scrape, see `monitoring`.
+///
+/// `GET /report/explain?from=2024-05-01&to=2024-06-01&area=DK1&market=intraday&side=sell` lists
+/// what each trade contributed to the cell of the report, built like those of `/report`, so the
+/// cash flows of a cell sum to its revenue or costs there.
+///
+/// `GET /trades/search` searches the trades of all tables like `search-trades`, taking its flags
+/// as parameters, e.g. `price_min=40&counter_part=epex,nordpool`, and serves a page of the
+/// matches with the cursor to continue after them as `after`, `null` after the last page.
+/// Searching needs the tables of Postgres.
+///
+/// With roles configured, the role of the caller is read from the `X-Role` header, set by the
+/// proxy authenticating the callers, and the metrics it may not see are redacted as for
+/// `key-metrics`. Requests without a role, or with one that isn't configured, are forbidden.
+///
+/// Each report request is a job while it's built. `GET /jobs` lists them, `GET /jobs/{id}`
+/// shows one and `DELETE /jobs/{id}` cancels it, failing its request with 409 Conflict.
+/// Identical requests arriving while a report is built share it rather than scanning again.
+pub struct ReportServer {
+    source: TradeSource,
+    /// Builds the reports like the commands, see `Config::report_builder`
+    config: Config,
+    jobs: JobRegistry,
+    in_flight: Arc<InFlight<ReportKey, Arc<BuiltReport>>>,
+}
+
+/// The header with the role of the caller
+const ROLE_HEADER: &str = "x-role";
+
+#[derive(Debug, Deserialize)]
+struct ReportQuery {
+    from: String,
+    to: String,
+    area: Option<String>,
+    region: Option<String>,
+    market: Option<String>,
+}
+
+/// A report query with its values parsed, so requests spelling the same query differently,
+/// e.g. `area=dk1` and `area=DK1`, are identical
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+struct ReportKey {
+    from: DateTime<Tz>,
+    to: DateTime<Tz>,
+    area: AreaSelection,
+    market: MarketSelection,
+}
+
+/// The metrics of a report for the selection of a query in the configured sign convention,
+/// shared by the requests for it whatever their role
+#[derive(Debug)]
+struct BuiltReport {
+    delivery_from: DateTime<Tz>,
+    delivery_to: DateTime<Tz>,
+    skipped_trades: usize,
+    metrics: Vec<(Metric, Decimal)>,
+    metrics_by_currency: BTreeMap<Currency, Vec<(Metric, Decimal)>>,
+}
+
+#[derive(Debug, Serialize)]
+struct ReportResponse {
+    delivery_from: DateTime<Tz>,
+    delivery_to: DateTime<Tz>,
+    /// The window as described by `bounds::describe_window`
+    window: String,
+    skipped_trades: usize,
+    /// Each metric of the selection the caller may see, the monetary ones only if its trades
+    /// were all settled in the same currency
+    metrics: Value,
+    /// The monetary metrics of the selection per currency its trades were settled in
+    metrics_by_currency: BTreeMap<Currency, Value>,
+}
+
+impl BuiltReport {
+    fn visible_to(&self, permissions: &RolePermissions) -> ReportResponse {
+        ReportResponse {
+            delivery_from: self.delivery_from,
+            delivery_to: self.delivery_to,
+            window: describe_window(&self.delivery_from, &self.delivery_to),
+            skipped_trades: self.skipped_trades,
+            metrics: permissions.redact(self.metrics.clone()),
+            metrics_by_currency: self
+                .metrics_by_currency
+                .iter()
+                .map(|(currency, metrics)| (*currency, permissions.redact(metrics.clone())))
+                .collect(),
+        }
+    }
+}
+
+#[derive(Debug, Deserialize)]
+struct ExplainQuery {
+    from: String,
+    to: String,
+    area: String,
+    market: String,
+    side: String,
+}
+
+#[derive(Debug, Serialize)]
+struct ExplainResponse {
+    delivery_from: DateTime<Tz>,
+    delivery_to: DateTime<Tz>,
+    window: String,
+    area: Area,
+    market: Market,
+    side: TradeSide,
+    /// What each trade contributed to the cell, in the order they were aggregated
+    trades: Vec<ExplainedTrade>,
+}
+
+/// A trade of a cell with the table it's stored in, as ids are per table. Its volume and cash
+/// flow are redacted like the metrics of the cell they count towards.
+#[derive(Debug, Serialize)]
+struct ExplainedTrade {
+    table: TradeTable,
+    id: i64,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    mw: Option<Value>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    cash_flow: Option<Value>,
+}
+
+/// Most trades served per page of a search, whatever the page size requested
+const MAX_SEARCH_PAGE_SIZE: i64 = 10_000;
+
+/// The criteria of a search, like the flags of `search-trades`
+#[derive(Debug, Deserialize)]
+struct SearchQuery {
+    price_min: Option<Decimal>,
+    price_max: Option<Decimal>,
+    quantity_min: Option<Decimal>,
+    quantity_max: Option<Decimal>,
+    /// Counter parts to match any of, separated by commas
+    counter_part: Option<String>,
+    delivery_from: Option<DateTime<FixedOffset>>,
+    delivery_to: Option<DateTime<FixedOffset>>,
+    page_size: Option<i64>,
+    after: Option<String>,
+}
+
+#[derive(Debug, Serialize)]
+struct SearchResponse {
+    trades: Vec<SearchHit>,
+    /// The cursor of the next page, `None` after the last one
+    next: Option<String>,
+}
+
+/// A matching trade with the table it's stored in, as ids are per table
+#[derive(Debug, Serialize)]
+struct SearchHit {
+    table: TradeTable,
+    #[serde(flatten)]
+    trade: Trade,
+}
+
+/// Requests that can't be served, as a status and a JSON error message
+#[derive(Debug)]
+enum ApiError {
+    BadRequest(anyhow::Error),
+    /// The caller's role is missing or not configured
+    Forbidden(anyhow::Error),
+    NotFound(anyhow::Error),
+    /// The job of the request was cancelled over `DELETE /jobs/{id}`
+    Cancelled(anyhow::Error),
+    Internal(anyhow::Error),
+}
+
+impl ApiError {
+    /// The error for each of the requests sharing a failed report
+    fn shared(&self) -> Self {
+        match self {
+            ApiError::BadRequest(err) => ApiError::BadRequest(anyhow!("{:#}", err)),
+            ApiError::Forbidden(err) => ApiError::Forbidden(anyhow!("{:#}", err)),
+            ApiError::NotFound(err) => ApiError::NotFound(anyhow!("{:#}", err)),
+            ApiError::Cancelled(err) => ApiError::Cancelled(anyhow!("{:#}", err)),
+            ApiError::Internal(err) => ApiError::Internal(anyhow!("{:#}", err)),
+        }
+    }
+}
+
+impl IntoResponse for ApiError {
+    fn into_response(self) -> Response {
+        let (status, err) = match self {
+            ApiError::BadRequest(err) => (StatusCode::BAD_REQUEST, err),
+            ApiError::Forbidden(err) => (StatusCode::FORBIDDEN, err),
+            ApiError::NotFound(err) => (StatusCode::NOT_FOUND, err),
+            ApiError::Cancelled(err) => (StatusCode::CONFLICT, err),
+            ApiError::Internal(err) => {
+                eprintln!("Request failed: {:#}", err);
+                (StatusCode::INTERNAL_SERVER_ERROR, err)
+            }
+        };
+        let body = serde_json::json!({ "error": format!("{:#}", err) });
+        (status, Json(body)).into_response()
+    }
+}
+
+/// A build shared by the requests waiting on it
+type SharedBuild<T> = Shared<BoxFuture<'static, Result<T, Arc<ApiError>>>>;
+
+/// Builds in flight by key, so identical requests arriving while one is built, e.g. from
+/// dashboards refreshing in sync, wait on that build instead of each starting their own (single
+/// flight). Builds run on a task of their own until they finish, even if the requests waiting on
+/// them are gone, and are removed once they have, so later requests build afresh.
+struct InFlight<K, T> {
+    builds: Mutex<HashMap<K, SharedBuild<T>>>,
+}
+
+impl<K, T> Default for InFlight<K, T> {
+    fn default() -> Self {
+        Self {
+            builds: Mutex::new(HashMap::new()),
+        }
+    }
+}
+
+impl<K, T> InFlight<K, T>
+where
+    K: Eq + Hash + Clone + Send + 'static,
+    T: Clone + Send + Sync + 'static,
+{
+    /// The build in flight for the key, or the one `build` starts if there is none
+    fn get_or_start<F>(self: &Arc<Self>, key: K, build: impl FnOnce() -> F) -> SharedBuild<T>
+    where
+        F: Future<Output = Result<T, ApiError>> + Send + 'static,
+    {
+        let mut builds = self.builds.lock().unwrap_or_else(PoisonError::into_inner);
+        if let Some(shared) = builds.get(&key) {
+            return shared.clone();
+        }
+        let (in_flight, task_key, build) = (Arc::clone(self), key.clone(), build());
+        // The task waits on the lock until the build is inserted, so it's always removed after
+        let task = tokio::spawn(async move {
+            let result = build.await.map_err(Arc::new);
+            in_flight
+                .builds
+                .lock()
+                .unwrap_or_else(PoisonError::into_inner)
+                .remove(&task_key);
+            result
+        });
+        let shared = task
+            .map(|joined| {
+                joined.unwrap_or_else(|err| Err(Arc::new(ApiError::Internal(err.into()))))
+            })
+            .boxed()
+            .shared();
+        builds.insert(key, shared.clone());
+        shared
+    }
+
+    #[cfg(test)]
+    fn len(&self) -> usize {
+        self.builds
+            .lock()
+            .unwrap_or_else(PoisonError::into_inner)
+            .len()
+    }
+}
+
+/// The window of the `from` and `to` of a query, read like `--from` and `--to`
+fn window(from: &str, to: &str) -> Result<(DateTime<Tz>, DateTime<Tz>)> {
+    let (delivery_from, delivery_to) = (parse_delivery_day(from)?, parse_delivery_day(to)?);
+    if delivery_from >= delivery_to {
+        return Err(anyhow!(
+            "Delivery window has to end after it starts, not {} - {}",
+            from,
+            to
+        ));
+    }
+    Ok((delivery_from, delivery_to))
+}
+
+impl ReportQuery {
+    fn window(&self) -> Result<(DateTime<Tz>, DateTime<Tz>)> {
+        window(&self.from, &self.to)
+    }
+
+    fn area(&self, hierarchy: &AreaHierarchy) -> Result<AreaSelection> {
+        match (&self.area, &self.region) {
+            (Some(_), Some(_)) => Err(anyhow!("Select either an area or a region")),
+            (Some(area), None) => Ok(AreaSelection::Specific(Area::try_from(
+                area.to_uppercase(),
+            )?)),
+            (None, Some(region)) => hierarchy.selection(region),
+            (None, None) => Ok(AreaSelection::All),
+        }
+    }
+
+    fn market(&self) -> Result<MarketSelection> {
+        match &self.market {
+            Some(market) => Ok(MarketSelection::Specific(
+                Market::from_str(market).map_err(|_| anyhow!("Unknown market {}", market))?,
+            )),
+            None => Ok(MarketSelection::All),
+        }
+    }
+}
+
+impl ExplainQuery {
+    /// The cell of the query, which has to be a single area, market and side
+    fn cell(&self) -> Result<(Area, Market, TradeSide)> {
+        let area = Area::try_from(self.area.to_uppercase())?;
+        let market = Market::from_str(&self.market)
+            .map_err(|_| anyhow!("Unknown market {}", self.market))?;
+        let side =
+            TradeSide::from_str(&self.side).map_err(|_| anyhow!("Unknown side {}", self.side))?;
+        Ok((area, market, side))
+    }
+}
+
+impl SearchQuery {
+    fn search(&self) -> Result<(TradeSearch, Option<SearchCursor>)> {
+        let page_size = self.page_size.unwrap_or(TradeSearch::default().page_size);
+        if !(1..=MAX_SEARCH_PAGE_SIZE).contains(&page_size) {
+            return Err(anyhow!(
+                "page_size has to be between 1 and {}, not {}",
+                MAX_SEARCH_PAGE_SIZE,
+                page_size
+            ));
+        }
+        let counter_parts = self
+            .counter_part
+            .iter()
+            .flat_map(|counter_parts| counter_parts.split(','))
+            .map(CounterPart::from_str)
+            .collect::<Result<_>>()?;
+        let search = TradeSearch {
+            price_min: self.price_min,
+            price_max: self.price_max,
+            quantity_min: self.quantity_min,
+            quantity_max: self.quantity_max,
+            counter_parts,
+            delivery_from: self.delivery_from,
+            delivery_to: self.delivery_to,
+            page_size,
+        };
+        let after = self
+            .after
+            .as_deref()
+            .map(SearchCursor::from_str)
+            .transpose()?;
+        Ok((search, after))
+    }
+}
+
+impl ReportServer {
+    pub fn new(source: TradeSource, config: &Config) -> Self {
+        Self {
+            source,
+            config: config.clone(),
+            jobs: JobRegistry::default(),
+            in_flight: Arc::default(),
+        }
+    }
+
+    pub fn router(self) -> Router {
+        Router::new()
+            .route("/report", get(report))
+            .route("/report/explain", get(explain))
+            .route("/trades/search", get(search_trades))
+            .route("/metrics", get(metrics))
+            .route("/jobs", get(list_jobs))
+            .route("/jobs/{id}", get(inspect_job).delete(cancel_job))
+            .with_state(Arc::new(self))
+    }
+
+    /// The builder of the reports of the window, as configured, see `Config::report_builder`
+    async fn report_builder(
+        &self,
+        source: &TradeSource,
+        from: &DateTime<Tz>,
+        to: &DateTime<Tz>,
+    ) -> Result<ReportBuilder, ApiError> {
+        Ok(self
+            .config
+            .report_builder(source.pool(), from, to)
+            .await
+            .map_err(ApiError::Internal)?
+            .missing_prices(self.config.missing_prices))
+    }
+
+    /// What the caller may see, by the role of the `X-Role` header. Without roles configured
+    /// everything is visible to everyone.
+    fn permissions(&self, headers: &HeaderMap) -> Result<RolePermissions, ApiError> {
+        if self.config.roles.is_empty() {
+            return Ok(RolePermissions::default());
+        }
+        let role = headers
+            .get(ROLE_HEADER)
+            .ok_or(ApiError::Forbidden(anyhow!(
+                "Missing the {} header",
+                ROLE_HEADER
+            )))?
+            .to_str()
+            .map_err(|err| ApiError::Forbidden(err.into()))?;
+        RolePermissions::for_role(&self.config.roles, Some(role)).map_err(ApiError::Forbidden)
+    }
+
+    /// Serves requests on the address, e.g. `127.0.0.1:8080`, until the process is stopped
+    pub async fn serve(self, address: &str) -> Result<()> {
+        let listener = TcpListener::bind(address).await?;
+        println!("Listening on {}", listener.local_addr()?);
+        axum::serve(listener, self.router()).await?;
+        Ok(())
+    }
+}
+
+async fn report(
+    State(server): State<Arc<ReportServer>>,
+    headers: HeaderMap,
+    Query(query): Query<ReportQuery>,
+) -> Result<Json<ReportResponse>, ApiError> {
+    let permissions = server.permissions(&headers)?;
+    let (from, to) = query.window().map_err(ApiError::BadRequest)?;
+    server
+        .config
+        .guardrails
+        .check_window(&from, &to)
+        .map_err(ApiError::BadRequest)?;
+    let key = ReportKey {
+        from,
+        to,
+        area: query
+            .area(&server.config.area_hierarchy)
+            .map_err(ApiError::BadRequest)?,
+        market: query.market().map_err(ApiError::BadRequest)?,
+    };
+
+    let build = server
+        .in_flight
+        .get_or_start(key.clone(), || build_report(Arc::clone(&server), key));
+    let built = build.await.map_err(|err| err.shared())?;
+    Ok(Json(built.visible_to(&permissions)))
+}
+
+async fn build_report(
+    server: Arc<ReportServer>,
+    key: ReportKey,
+) -> Result<Arc<BuiltReport>, ApiError> {
+    let ReportKey {
+        from,
+        to,
+        area,
+        market,
+    } = key;
+    // Throughput is counted from the trades fetched by the process, so requests served at the
+    // same time count each other's trades
+    let mut timings = StrategyTimings::new("server");
+    let now = Instant::now();
+    let job = server.jobs.start("server", &from, &to);
+    let source = server
+        .source
+        .clone()
+        .with_cancellation(job.cancellation().clone());
+    let builder = server.report_builder(&source, &from, &to).await?;
+    let report = builder
+        .build_from_stream(job.count_rows(builder.trades(&source)))
+        .await
+        .map_err(|err| build_failed(err, &job))?;
+    timings.record(Phase::Stream, now.elapsed());
+    timings.finish();
+    let signed = |metrics: Vec<(Metric, Decimal)>| -> Vec<(Metric, Decimal)> {
+        metrics
+            .into_iter()
+            .map(|(metric, value)| (metric, server.config.sign_convention.apply(metric, value)))
+            .collect()
+    };
+    let metrics = signed(report.single_currency_metrics(market.clone(), area.clone()));
+    let metrics_by_currency = report
+        .monetary_metrics_by_currency(market, area)
+        .into_iter()
+        .map(|(currency, metrics)| (currency, signed(metrics)))
+        .collect();
+    Ok(Arc::new(BuiltReport {
+        delivery_from: from,
+        delivery_to: to,
+        skipped_trades: report.skipped_trades(),
+        metrics,
+        metrics_by_currency,
+    }))
+}
+
+async fn explain(
+    State(server): State<Arc<ReportServer>>,
+    headers: HeaderMap,
+    Query(query): Query<ExplainQuery>,
+) -> Result<Json<ExplainResponse>, ApiError> {
+    let permissions = server.permissions(&headers)?;
+    let (from, to) = window(&query.from, &query.to).map_err(ApiError::BadRequest)?;
+    server
+        .config
+        .guardrails
+        .check_window(&from, &to)
+        .map_err(ApiError::BadRequest)?;
+    let (area, market, side) = query.cell().map_err(ApiError::BadRequest)?;
+
+    let job = server.jobs.start("explain", &from, &to);
+    let source = server
+        .source
+        .clone()
+        .with_cancellation(job.cancellation().clone());
+    let builder = server
+        .report_builder(&source, &from, &to)
+        .await?
+        .retain_trades(server.config.retention.memory_budget());
+    let mut report = builder
+        .build_from_stream(job.count_rows(builder.trades(&source)))
+        .await
+        .map_err(|err| build_failed(err, &job))?;
+    let table = TradeTable::from(market);
+    let (volume, cash_flow) = match side {
+        TradeSide::Buy => (Metric::MwBought, Metric::Costs),
+        TradeSide::Sell => (Metric::MwSold, Metric::Revenue),
+    };
+    let trades = report
+        .explain(area, market, side)
+        .map_err(ApiError::Internal)?
+        .into_iter()
+        .map(|contribution| ExplainedTrade {
+            table,
+            id: contribution.id,
+            mw: permissions.redact_value(volume, contribution.mw),
+            cash_flow: permissions.redact_value(cash_flow, contribution.cash_flow),
+        })
+        .collect();
+    Ok(Json(ExplainResponse {
+        window: describe_window(&from, &to),
+        delivery_from: from,
+        delivery_to: to,
+        area,
+        market,
+        side,
+        trades,
+    }))
+}
+
+/// The error of a request whose report failed to build, 409 if its job was cancelled
+fn build_failed(err: anyhow::Error, job: &Job) -> ApiError {
+    if cancel::is_cancelled(&err) {
+        ApiError::Cancelled(anyhow!("Job {} was cancelled", job.id()))
+    } else {
+        ApiError::Internal(err)
+    }
+}
+
+async fn search_trades(
+    State(server): State<Arc<ReportServer>>,
+    Query(query): Query<SearchQuery>,
+) -> Result<Json<SearchResponse>, ApiError> {
+    let (search, after) = query.search().map_err(ApiError::BadRequest)?;
+    let pool = server.source.pool().ok_or(ApiError::BadRequest(anyhow!(
+        "Trades can only be searched in the tables of Postgres"
+    )))?;
+    let (trades, next) = search
+        .page(pool, after, server.source.units())
+        .await
+        .map_err(ApiError::Internal)?;
+    Ok(Json(SearchResponse {
+        trades: trades
+            .into_iter()
+            .map(|(table, trade)| SearchHit { table, trade })
+            .collect(),
+        next: next.map(|cursor| cursor.to_string()),
+    }))
+}
+
+async fn list_jobs(State(server): State<Arc<ReportServer>>) -> Json<Vec<JobStatus>> {
+    Json(server.jobs.list())
+}
+
+async fn inspect_job(
+    State(server): State<Arc<ReportServer>>,
+    Path(id): Path<u64>,
+) -> Result<Json<JobStatus>, ApiError> {
+    server.jobs.inspect(id).map(Json).ok_or(no_job(id))
+}
+
+async fn cancel_job(
+    State(server): State<Arc<ReportServer>>,
+    Path(id): Path<u64>,
+) -> Result<Json<JobStatus>, ApiError> {
+    let job = server.jobs.cancel(id).ok_or(no_job(id))?;
+    eprintln!("Cancelled job {} for {}", job.id, job.window);
+    Ok(Json(job))
+}
+
+fn no_job(id: u64) -> ApiError {
+    ApiError::NotFound(anyhow!("No job {} is running", id))
+}
+
+async fn metrics() -> impl IntoResponse {
+    (
+        [(header::CONTENT_TYPE, "text/plain; version=0.0.4")],
+        monitoring::render(),
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use chrono::TimeZone;
+    use chrono_tz::Europe::Copenhagen;
+    use std::sync::atomic::{AtomicUsize, Ordering};
+    use tokio::sync::watch;
+
+    #[test]
+    fn queries_select_the_window_area_and_market() {
+        let query = parse("from=2024-05-01&to=2024-05-31&area=dk1&market=intraday");
+        assert_eq!(
+            query.window().unwrap(),
+            (
+                Copenhagen.with_ymd_and_hms(2024, 5, 1, 0, 0, 0).unwrap(),
+                Copenhagen.with_ymd_and_hms(2024, 5, 31, 0, 0, 0).unwrap()
+            )
+        );
+        assert!(matches!(
+            query.area(&AreaHierarchy::default()).unwrap(),
+            AreaSelection::Specific(Area::DK1)
+        ));
+        assert!(matches!(
+            query.market().unwrap(),
+            MarketSelection::Specific(Market::Intraday)
+        ));
+
+        let query = parse("from=2024-05-02&to=2024-05-01&region=nordics");
+        assert!(query.window().is_err());
+        assert!(query.area(&AreaHierarchy::default()).is_err());
+    }
+
+    #[tokio::test]
+    async fn identical_requests_share_one_scan() {
+        let in_flight: Arc<InFlight<ReportKey, usize>> = Arc::default();
+        let scans = Arc::new(AtomicUsize::new(0));
+        let (release, released) = watch::channel(false);
+        let key = |query: &str| {
+            let query = parse(query);
+            let (from, to) = query.window().unwrap();
+            ReportKey {
+                from,
+                to,
+                area: query.area(&AreaHierarchy::default()).unwrap(),
+                market: query.market().unwrap(),
+            }
+        };
+        let scan = || {
+            let (scans, mut released) = (Arc::clone(&scans), released.clone());
+            async move {
+                let scan = scans.fetch_add(1, Ordering::SeqCst) + 1;
+                released.wait_for(|released| *released).await.unwrap();
+                Ok(scan)
+            }
+        };
+
+        let first = in_flight.get_or_start(key("from=2024-05-01&to=2024-05-31&area=dk1"), scan);
+        let second = in_flight.get_or_start(key("from=2024-05-01&to=2024-05-31&area=DK1"), scan);
+        // Another area is another report
+        let other = in_flight.get_or_start(key("from=2024-05-01&to=2024-05-31&area=DK2"), scan);
+        assert_eq!(in_flight.len(), 2);
+        release.send(true).unwrap();
+        let (first, second) = tokio::join!(first, second);
+        assert_eq!((first.unwrap(), second.unwrap()), (1, 1));
+        other.await.unwrap();
+        assert_eq!(scans.load(Ordering::SeqCst), 2);
+        assert_eq!(in_flight.len(), 0);
+
+        // Finished builds aren't shared with later requests
+        let later = in_flight.get_or_start(key("from=2024-05-01&to=2024-05-31&area=dk1"), scan);
+        assert_eq!(later.await.unwrap(), 3);
+    }
+
+    #[cfg(feature = "sqlite")]
+    #[tokio::test]
+    async fn reports_are_built_as_configured() {
+        use crate::fx::FxConfig;
+        use rust_decimal_macros::dec;
+
+        let currencies = |config: Config| async move {
+            let source = crate::sqlite::demo_source().await.unwrap();
+            let server = Arc::new(ReportServer::new(source, &config));
+            let query = parse("from=2024-05-01&to=2024-05-08&area=GB");
+            let (from, to) = query.window().unwrap();
+            let key = ReportKey {
+                from,
+                to,
+                area: query.area(&AreaHierarchy::default()).unwrap(),
+                market: MarketSelection::All,
+            };
+            let response = build_report(server, key).await.unwrap();
+            response
+                .metrics_by_currency
+                .keys()
+                .copied()
+                .collect::<Vec<_>>()
+        };
+        assert_eq!(currencies(Config::default()).await, [Currency::Gbp]);
+        let converting_gbp = Config {
+            fx: Some(FxConfig {
+                fixed_rates: BTreeMap::from([(Currency::Gbp, dec!(1.17))]),
+                ..FxConfig::default()
+            }),
+            ..Config::default()
+        };
+        assert_eq!(currencies(converting_gbp).await, [Currency::Eur]);
+    }
+
+    #[cfg(feature = "sqlite")]
+    #[tokio::test]
+    async fn explained_trades_sum_to_the_served_cell() {
+        use crate::fx::FxConfig;
+        use rust_decimal_macros::dec;
+
+        // Converted, so the trades only sum to the cell if explained as configured
+        let config = Config {
+            fx: Some(FxConfig {
+                fixed_rates: BTreeMap::from([(Currency::Gbp, dec!(1.17))]),
+                ..FxConfig::default()
+            }),
+            ..Config::default()
+        };
+        let source = crate::sqlite::demo_source().await.unwrap();
+        let server = Arc::new(ReportServer::new(source, &config));
+        let explain = |query: &str| {
+            let uri = format!("/report/explain?{}", query).parse().unwrap();
+            let query: ExplainQuery = Query::try_from_uri(&uri).unwrap().0;
+            explain(State(Arc::clone(&server)), HeaderMap::new(), Query(query))
+        };
+        let explained = explain("from=2024-05-01&to=2024-05-08&area=gb&market=auction&side=buy")
+            .await
+            .unwrap();
+        assert!(!explained.trades.is_empty());
+        let cash_flow: Decimal = explained
+            .trades
+            .iter()
+            .map(|trade| serde_json::from_value::<Decimal>(trade.cash_flow.clone().unwrap()))
+            .sum::<Result<_, _>>()
+            .unwrap();
+
+        let query = parse("from=2024-05-01&to=2024-05-08&area=GB&market=auction");
+        let (from, to) = query.window().unwrap();
+        let key = ReportKey {
+            from,
+            to,
+            area: query.area(&AreaHierarchy::default()).unwrap(),
+            market: query.market().unwrap(),
+        };
+        let served = build_report(Arc::clone(&server), key).await.unwrap();
+        let costs = served
+            .metrics
+            .iter()
+            .find_map(|(metric, value)| (*metric == Metric::Costs).then_some(*value));
+        assert_eq!(
+            costs.unwrap(),
+            config
+                .sign_convention
+                .apply(Metric::Costs, cash_flow.round_dp(2))
+        );
+
+        let region = explain("from=2024-05-01&to=2024-05-08&area=nordics&market=auction&side=buy");
+        assert!(matches!(region.await, Err(ApiError::BadRequest(_))));
+    }
+
+    #[cfg(feature = "sqlite")]
+    #[tokio::test]
+    async fn restricted_roles_cant_read_restricted_metrics() {
+        use crate::permissions::Redaction;
+
+        let operations = RolePermissions {
+            hide_monetary: true,
+            redaction: Redaction::Mask,
+            ..RolePermissions::default()
+        };
+        let auditor = RolePermissions {
+            hidden_metrics: vec![Metric::GrossProfit],
+            ..RolePermissions::default()
+        };
+        let config = Config {
+            roles: HashMap::from([
+                ("operations".to_string(), operations),
+                ("auditor".to_string(), auditor),
+            ]),
+            ..Config::default()
+        };
+        let source = crate::sqlite::demo_source().await.unwrap();
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let address = listener.local_addr().unwrap();
+        let router = ReportServer::new(source, &config).router();
+        tokio::spawn(async move { axum::serve(listener, router).await });
+
+        let get = |path: &str, role: Option<&str>| {
+            let mut request = reqwest::Client::new().get(format!("http://{}{}", address, path));
+            if let Some(role) = role {
+                request = request.header(ROLE_HEADER, role);
+            }
+            async move {
+                let response = request.send().await.unwrap();
+                let status = response.status();
+                let body: Value = response.json().await.unwrap();
+                (status, body)
+            }
+        };
+        let report = "/report?from=2024-05-01&to=2024-05-08&area=DK1";
+
+        let (status, body) = get(report, Some("operations")).await;
+        assert_eq!(status, StatusCode::OK);
+        let metrics = &body["metrics"];
+        assert_ne!(metrics["mw_bought"], "restricted");
+        assert_eq!(metrics["costs"], "restricted");
+        assert_eq!(metrics["gross_profit"], "restricted");
+        assert_eq!(body["metrics_by_currency"]["EUR"]["revenue"], "restricted");
+
+        let (status, body) = get(report, Some("auditor")).await;
+        assert_eq!(status, StatusCode::OK);
+        assert!(body["metrics"]["costs"].is_string());
+        assert!(body["metrics"].get("gross_profit").is_none());
+
+        let explain =
+            "/report/explain?from=2024-05-01&to=2024-05-08&area=DK1&market=auction&side=buy";
+        let (status, body) = get(explain, Some("operations")).await;
+        assert_eq!(status, StatusCode::OK);
+        let trades = body["trades"].as_array().unwrap();
+        assert!(!trades.is_empty());
+        assert!(trades
+            .iter()
+            .all(|trade| trade["mw"] != "restricted" && trade["cash_flow"] == "restricted"));
+
+        // With roles configured, callers without one of them see nothing
+        assert_eq!(get(report, None).await.0, StatusCode::FORBIDDEN);
+        assert_eq!(get(report, Some("trader")).await.0, StatusCode::FORBIDDEN);
+        assert_eq!(get(explain, None).await.0, StatusCode::FORBIDDEN);
+    }
+
+    #[test]
+    fn search_queries_are_read_like_the_flags() {
+        let query = parse_search(
+            "price_min=40&counter_part=epex,nordpool&delivery_from=2024-05-01T00:00:00Z&page_size=2&after=auction:12",
+        );
+        let (search, after) = query.search().unwrap();
+        assert_eq!(search.price_min, Some(Decimal::from(40)));
+        assert_eq!(search.price_max, None);
+        assert_eq!(
+            search.counter_parts,
+            [CounterPart::EPEX, CounterPart::NORDPOOL]
+        );
+        assert_eq!(
+            search.delivery_from,
+            Some(DateTime::parse_from_rfc3339("2024-05-01T00:00:00Z").unwrap())
+        );
+        assert_eq!(search.page_size, 2);
+        assert_eq!(
+            after,
+            Some(SearchCursor {
+                table: TradeTable::Auction,
+                id: 12
+            })
+        );
+        assert_eq!(after.unwrap().to_string(), "auction:12");
+
+        let (search, after) = parse_search("").search().unwrap();
+        assert_eq!(search.page_size, TradeSearch::default().page_size);
+        assert!(search.counter_parts.is_empty() && after.is_none());
+
+        assert!(parse_search("page_size=0").search().is_err());
+        assert!(parse_search("page_size=10001").search().is_err());
+        assert!(parse_search("after=auction").search().is_err());
+    }
+
+    #[cfg(feature = "sqlite")]
+    #[tokio::test]
+    async fn searches_need_postgres() {
+        let source = crate::sqlite::demo_source().await.unwrap();
+        let server = Arc::new(ReportServer::new(source, &Config::default()));
+        let searched = search_trades(State(server), Query(parse_search(""))).await;
+        assert!(matches!(searched, Err(ApiError::BadRequest(_))));
+    }
+
+    fn parse(query: &str) -> ReportQuery {
+        let uri = format!("/report?{}", query).parse().unwrap();
+        Query::try_from_uri(&uri).unwrap().0
+    }
+
+    fn parse_search(query: &str) -> SearchQuery {
+        let uri = format!("/trades/search?{}", query).parse().unwrap();
+        Query::try_from_uri(&uri).unwrap().0
+    }
+}
@@ -0,0 +1,76 @@
+use std::collections::HashMap;
+
+use anyhow::{bail, Result};
+use chrono::{DateTime, Duration, FixedOffset, Timelike};
+use serde::Deserialize;
+
+use crate::trade::Area;
+
+/// Imbalance settlement periods (ISPs) per area. Areas are settled hourly until their
+/// configured go-live of 15-minute ISPs, e.g.
+///
+/// ```toml
+/// [imbalance.isp_15min_go_live]
+/// DK1 = "2025-03-19T00:00:00+01:00"
+/// ```
+#[derive(Debug, Default, Deserialize, Clone)]
+pub struct IspSchedule {
+    #[serde(default)]
+    isp_15min_go_live: HashMap<Area, DateTime<FixedOffset>>,
+}
+
+impl IspSchedule {
+    pub fn isp_length(&self, area: Area, at: &DateTime<FixedOffset>) -> Duration {
+        match self.isp_15min_go_live.get(&area) {
+            Some(go_live) if at >= go_live => Duration::minutes(15),
+            _ => Duration::hours(1),
+        }
+    }
+
+    /// Splits a delivery period into the settlement periods of the area, so a trade can be
+    /// attributed to each ISP with the correct period length. Fails if the period does not
+    /// start and end on ISP boundaries, as it then can't be settled per period.
+    pub fn split(
+        &self,
+        area: Area,
+        delivery_start: &DateTime<FixedOffset>,
+        delivery_end: &DateTime<FixedOffset>,
+    ) -> Result<Vec<(DateTime<FixedOffset>, DateTime<FixedOffset>)>> {
+        let mut periods = Vec::new();
+        let mut start = *delivery_start;
+
+        while start < *delivery_end {
+            let length = self.isp_length(area, &start);
+            if !is_aligned(&start, length) {
+                bail!(
+                    "Delivery period {} - {} in {} is not aligned to {} minute settlement periods",
+                    delivery_start,
+                    delivery_end,
+                    area,
+                    length.num_minutes()
+                );
+            }
+            let end = start + length;
+            if end > *delivery_end {
+                bail!(
+                    "Delivery period {} - {} in {} ends within a {} minute settlement period",
+                    delivery_start,
+                    delivery_end,
+                    area,
+                    length.num_minutes()
+                );
+            }
+            periods.push((start, end));
+            start = end;
+        }
+
+        Ok(periods)
+    }
+}
+
+pub fn is_aligned(at: &DateTime<FixedOffset>, length: Duration) -> bool {
+    // Offsets are whole hours for all our areas, so alignment can be checked on local minutes
+    at.second() == 0
+        && at.nanosecond() == 0
+        && i64::from(at.minute()) % length.num_minutes().min(60) == 0
+}
@@ -0,0 +1,130 @@
+use std::fs;
+use std::path::{Path, PathBuf};
+
+use anyhow::{anyhow, bail, Context, Result};
+use base64::{engine::general_purpose::STANDARD as BASE64, Engine};
+use ring::signature::{Ed25519KeyPair, KeyPair, UnparsedPublicKey, ED25519};
+use serde::Deserialize;
+
+use crate::secrets::Secrets;
+
+/// Detached Ed25519 signatures of snapshot logs and exported files, configured as e.g.
+///
+/// ```toml
+/// [signing]
+/// # Secret holding the base64 encoded 32 byte seed of the private key
+/// key_secret = "REPORT_SIGNING_KEY"
+/// # Base64 encoded public key, so files can be verified without the private key
+/// public_key = "..."
+/// ```
+#[derive(Debug, Deserialize, Clone, Default)]
+pub struct SigningConfig {
+    pub key_secret: Option<String>,
+    pub public_key: Option<String>,
+}
+
+/// The signature of a file is stored next to it, e.g. `daily.csv.sig` for `daily.csv`
+pub fn signature_path(path: &Path) -> PathBuf {
+    let mut signature = path.as_os_str().to_owned();
+    signature.push(".sig");
+    PathBuf::from(signature)
+}
+
+pub struct Signer {
+    key_pair: Ed25519KeyPair,
+}
+
+impl Signer {
+    pub fn from_seed(seed: &str) -> Result<Self> {
+        let seed = BASE64
+            .decode(seed.trim())
+            .context("Signing key is not valid base64")?;
+        let key_pair = Ed25519KeyPair::from_seed_unchecked(&seed)
+            .map_err(|_| anyhow!("Signing key has to be a 32 byte Ed25519 seed"))?;
+        Ok(Self { key_pair })
+    }
+
+    /// The signer of the config, `None` when signing isn't configured
+    pub async fn from_config(
+        config: Option<&SigningConfig>,
+        secrets: &Secrets,
+    ) -> Result<Option<Self>> {
+        match config.and_then(|config| config.key_secret.as_ref()) {
+            Some(name) => Ok(Some(Self::from_seed(&secrets.get(name).await?)?)),
+            None => Ok(None),
+        }
+    }
+
+    pub fn public_key(&self) -> String {
+        BASE64.encode(self.key_pair.public_key().as_ref())
+    }
+
+    /// Signs the current content of the file, returning where the signature was written
+    pub fn sign_file(&self, path: &Path) -> Result<PathBuf> {
+        let content =
+            fs::read(path).with_context(|| format!("Could not read {}", path.display()))?;
+        let signature = signature_path(path);
+        fs::write(
+            &signature,
+            BASE64.encode(self.key_pair.sign(&content).as_ref()),
+        )
+        .with_context(|| format!("Could not write {}", signature.display()))?;
+        Ok(signature)
+    }
+}
+
+/// Signs each of the files when there is a signer, i.e. signing is configured
+pub fn sign_files(signer: Option<&Signer>, paths: &[PathBuf]) -> Result<()> {
+    if let Some(signer) = signer {
+        for path in paths {
+            signer.sign_file(path)?;
+        }
+    }
+    Ok(())
+}
+
+/// Checks the file against its signature with the base64 encoded public key
+pub fn verify_file(public_key: &str, path: &Path, signature: &Path) -> Result<()> {
+    let public_key = BASE64
+        .decode(public_key.trim())
+        .context("Public key is not valid base64")?;
+    let content = fs::read(path).with_context(|| format!("Could not read {}", path.display()))?;
+    let encoded = fs::read_to_string(signature)
+        .with_context(|| format!("Could not read signature {}", signature.display()))?;
+    let decoded = BASE64
+        .decode(encoded.trim())
+        .with_context(|| format!("Signature {} is not valid base64", signature.display()))?;
+
+    if UnparsedPublicKey::new(&ED25519, public_key)
+        .verify(&content, &decoded)
+        .is_err()
+    {
+        bail!(
+            "{} does not match its signature, it was changed or signed with another key",
+            path.display()
+        );
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn changed_files_fail_verification() {
+        let signer = Signer::from_seed(&BASE64.encode([7u8; 32])).unwrap();
+        let path = std::env::temp_dir().join("signing_test.csv");
+        fs::write(&path, "area,gross_profit\nDK1,100\n").unwrap();
+
+        let signature = signer.sign_file(&path).unwrap();
+        assert_eq!(signature, std::env::temp_dir().join("signing_test.csv.sig"));
+        verify_file(&signer.public_key(), &path, &signature).unwrap();
+
+        fs::write(&path, "area,gross_profit\nDK1,1000\n").unwrap();
+        assert!(verify_file(&signer.public_key(), &path, &signature).is_err());
+
+        fs::remove_file(&path).unwrap();
+        fs::remove_file(&signature).unwrap();
+    }
+}
@@ -0,0 +1,375 @@
+use std::collections::BTreeMap;
+use std::fs::{self, File, OpenOptions};
+use std::io::{BufRead, BufReader, Write};
+use std::path::PathBuf;
+
+use anyhow::{anyhow, bail, Context, Result};
+use chrono::{DateTime, FixedOffset, Local};
+use rust_decimal::Decimal;
+use serde::{Deserialize, Serialize};
+
+use crate::report::{BreakdownRow, Report};
+use crate::trade::{Area, Currency, Market, TradeSide};
+
+type SnapshotKey = (Area, Currency, Market, TradeSide);
+
+fn key(row: &BreakdownRow) -> SnapshotKey {
+    (row.area, row.currency, row.market, row.side)
+}
+
+/// Version of the snapshot records, stored with every record. Bump it whenever the meaning or
+/// shape of the records changes, and add a step to `migrate_record` upgrading the previous
+/// version. Records from before versioning have no version and count as version 1.
+pub const SNAPSHOT_SCHEMA_VERSION: u32 = 3;
+
+fn unversioned() -> u32 {
+    1
+}
+
+/// Upgrades a record by a single version, from `version` to `version + 1`
+fn migrate_record(record: serde_json::Value, version: u32) -> Result<serde_json::Value> {
+    match version {
+        // Version 2 only added the version itself, which is set by the caller
+        1 => Ok(record),
+        // Version 3 keyed the cells by currency. Up to version 2 trades had no currency of their
+        // own, and GB was settled in GBP and every other area in EUR.
+        2 => {
+            let mut record = record;
+            let currency =
+                |area: Option<&serde_json::Value>| match area.and_then(|area| area.as_str()) {
+                    Some("GB") => "GBP",
+                    _ => "EUR",
+                };
+            for field in ["rows", "changed"] {
+                if let Some(rows) = record.get_mut(field).and_then(|rows| rows.as_array_mut()) {
+                    for row in rows.iter_mut().filter_map(|row| row.as_object_mut()) {
+                        let currency = currency(row.get("area"));
+                        row.insert("currency".to_string(), currency.into());
+                    }
+                }
+            }
+            if let Some(keys) = record
+                .get_mut("removed")
+                .and_then(|keys| keys.as_array_mut())
+            {
+                for key in keys.iter_mut().filter_map(|key| key.as_array_mut()) {
+                    let currency = currency(key.first());
+                    key.insert(1, currency.into());
+                }
+            }
+            Ok(record)
+        }
+        _ => bail!("No migration from snapshot schema version {}", version),
+    }
+}
+
+/// The aggregated state of a report at the time it was taken
+#[derive(Debug, Clone, PartialEq)]
+pub struct ReportSnapshot {
+    pub taken_at: DateTime<FixedOffset>,
+    pub delivery_from: DateTime<FixedOffset>,
+    pub delivery_to: DateTime<FixedOffset>,
+    pub rows: BTreeMap<SnapshotKey, (Decimal, Decimal)>,
+}
+
+impl ReportSnapshot {
+    pub fn from_report(report: &Report) -> Self {
+        let rows = report
+            .breakdown()
+            .into_iter()
+            .map(|row| (key(&row), (row.mw, row.cash_flow)))
+            .collect();
+
+        Self {
+            taken_at: Local::now().fixed_offset(),
+            delivery_from: report.delivery_from().fixed_offset(),
+            delivery_to: report.delivery_to().fixed_offset(),
+            rows,
+        }
+    }
+
+    pub(crate) fn breakdown(&self) -> Vec<BreakdownRow> {
+        self.rows
+            .iter()
+            .map(
+                |(&(area, currency, market, side), &(mw, cash_flow))| BreakdownRow {
+                    area,
+                    currency,
+                    market,
+                    side,
+                    mw,
+                    cash_flow,
+                },
+            )
+            .collect()
+    }
+}
+
+/// A line of the snapshot log along with the schema version it was written with
+#[derive(Debug, Serialize, Deserialize)]
+struct VersionedRecord {
+    #[serde(default = "unversioned")]
+    schema_version: u32,
+    #[serde(flatten)]
+    record: SnapshotRecord,
+}
+
+/// A line of the snapshot log. Deltas only contain the cells that changed or disappeared
+/// compared to the previous record.
+#[derive(Debug, Serialize, Deserialize)]
+#[serde(tag = "kind", rename_all = "snake_case")]
+enum SnapshotRecord {
+    Full {
+        taken_at: DateTime<FixedOffset>,
+        delivery_from: DateTime<FixedOffset>,
+        delivery_to: DateTime<FixedOffset>,
+        rows: Vec<BreakdownRow>,
+    },
+    Delta {
+        taken_at: DateTime<FixedOffset>,
+        delivery_from: DateTime<FixedOffset>,
+        delivery_to: DateTime<FixedOffset>,
+        changed: Vec<BreakdownRow>,
+        removed: Vec<SnapshotKey>,
+    },
+}
+
+/// Append-only JSON lines log of report snapshots. Every `full_every`th record is a full
+/// snapshot, the ones in between are deltas against the previous snapshot, so reading a
+/// snapshot replays at most `full_every - 1` deltas on top of a full one.
+pub struct SnapshotLog {
+    path: PathBuf,
+    full_every: usize,
+}
+
+impl SnapshotLog {
+    pub fn new(path: impl Into<PathBuf>, full_every: usize) -> Result<Self> {
+        if full_every == 0 {
+            bail!("Snapshots need a full snapshot at least every 1 record");
+        }
+
+        Ok(Self {
+            path: path.into(),
+            full_every,
+        })
+    }
+
+    /// Reconstructs all snapshots in the log, oldest first
+    pub fn read_all(&self) -> Result<Vec<ReportSnapshot>> {
+        if !self.path.exists() {
+            return Ok(Vec::new());
+        }
+
+        let file = File::open(&self.path)
+            .with_context(|| format!("Could not open snapshot log {}", self.path.display()))?;
+        let mut snapshots: Vec<ReportSnapshot> = Vec::new();
+
+        for (index, line) in BufReader::new(file).lines().enumerate() {
+            let line = line?;
+            let version: VersionOnly = serde_json::from_str(&line)
+                .with_context(|| format!("Invalid snapshot record on line {}", index + 1))?;
+            // Deltas of one version can't be applied to snapshots of another, and neither can
+            // snapshots of different versions be compared, so logs are migrated as a whole
+            if version.schema_version != SNAPSHOT_SCHEMA_VERSION {
+                bail!(
+                    "Snapshot record on line {} of {} has schema version {}, but version {} is \
+                     required. Run migrate-snapshot to upgrade the log",
+                    index + 1,
+                    self.path.display(),
+                    version.schema_version,
+                    SNAPSHOT_SCHEMA_VERSION
+                );
+            }
+            let VersionedRecord { record, .. } = serde_json::from_str(&line)
+                .with_context(|| format!("Invalid snapshot record on line {}", index + 1))?;
+
+            let snapshot = match record {
+                SnapshotRecord::Full {
+                    taken_at,
+                    delivery_from,
+                    delivery_to,
+                    rows,
+                } => ReportSnapshot {
+                    taken_at,
+                    delivery_from,
+                    delivery_to,
+                    rows: rows
+                        .into_iter()
+                        .map(|row| (key(&row), (row.mw, row.cash_flow)))
+                        .collect(),
+                },
+                SnapshotRecord::Delta {
+                    taken_at,
+                    delivery_from,
+                    delivery_to,
+                    changed,
+                    removed,
+                } => {
+                    let Some(previous) = snapshots.last() else {
+                        bail!("Snapshot log starts with a delta on line {}", index + 1);
+                    };
+                    let mut rows = previous.rows.clone();
+                    for key in removed {
+                        rows.remove(&key);
+                    }
+                    for row in changed {
+                        rows.insert(key(&row), (row.mw, row.cash_flow));
+                    }
+                    ReportSnapshot {
+                        taken_at,
+                        delivery_from,
+                        delivery_to,
+                        rows,
+                    }
+                }
+            };
+            snapshots.push(snapshot);
+        }
+
+        Ok(snapshots)
+    }
+
+    /// Appends a snapshot, as a delta against the latest snapshot unless a full one is due
+    pub fn append(&self, snapshot: &ReportSnapshot) -> Result<()> {
+        let previous = self.read_all()?;
+
+        let record = match previous.last() {
+            Some(last) if previous.len() % self.full_every != 0 => {
+                let changed = snapshot
+                    .breakdown()
+                    .into_iter()
+                    .filter(|row| last.rows.get(&key(row)) != Some(&(row.mw, row.cash_flow)))
+                    .collect();
+                let removed = last
+                    .rows
+                    .keys()
+                    .filter(|key| !snapshot.rows.contains_key(key))
+                    .copied()
+                    .collect();
+                SnapshotRecord::Delta {
+                    taken_at: snapshot.taken_at,
+                    delivery_from: snapshot.delivery_from,
+                    delivery_to: snapshot.delivery_to,
+                    changed,
+                    removed,
+                }
+            }
+            _ => SnapshotRecord::Full {
+                taken_at: snapshot.taken_at,
+                delivery_from: snapshot.delivery_from,
+                delivery_to: snapshot.delivery_to,
+                rows: snapshot.breakdown(),
+            },
+        };
+
+        let mut file = OpenOptions::new()
+            .create(true)
+            .append(true)
+            .open(&self.path)
+            .with_context(|| format!("Could not open snapshot log {}", self.path.display()))?;
+        let record = VersionedRecord {
+            schema_version: SNAPSHOT_SCHEMA_VERSION,
+            record,
+        };
+        writeln!(file, "{}", serde_json::to_string(&record)?)?;
+
+        Ok(())
+    }
+
+    /// Upgrades all records in the log to the current schema version, returning how many were
+    /// upgraded. The migrated log is written next to the log and then moved over it, so the log
+    /// is left as it was if the migration fails.
+    pub fn migrate(&self) -> Result<usize> {
+        if !self.path.exists() {
+            return Ok(0);
+        }
+
+        let file = File::open(&self.path)
+            .with_context(|| format!("Could not open snapshot log {}", self.path.display()))?;
+        let mut lines = Vec::new();
+        let mut migrated = 0;
+
+        for (index, line) in BufReader::new(file).lines().enumerate() {
+            let mut record: serde_json::Value = serde_json::from_str(&line?)
+                .with_context(|| format!("Invalid snapshot record on line {}", index + 1))?;
+            let version: VersionOnly = serde_json::from_value(record.clone())
+                .with_context(|| format!("Invalid snapshot record on line {}", index + 1))?;
+            if version.schema_version > SNAPSHOT_SCHEMA_VERSION {
+                bail!(
+                    "Snapshot record on line {} has schema version {}, which is newer than the \
+                     supported version {}",
+                    index + 1,
+                    version.schema_version,
+                    SNAPSHOT_SCHEMA_VERSION
+                );
+            }
+
+            for from in version.schema_version..SNAPSHOT_SCHEMA_VERSION {
+                record = migrate_record(record, from)
+                    .with_context(|| format!("Could not migrate line {}", index + 1))?;
+            }
+            if version.schema_version != SNAPSHOT_SCHEMA_VERSION {
+                record
+                    .as_object_mut()
+                    .ok_or(anyhow!(
+                        "Snapshot record on line {} is not an object",
+                        index + 1
+                    ))?
+                    .insert("schema_version".to_string(), SNAPSHOT_SCHEMA_VERSION.into());
+                migrated += 1;
+            }
+            lines.push(serde_json::to_string(&record)?);
+        }
+
+        let migrated_path = self.path.with_extension("migrating");
+        let mut file = File::create(&migrated_path)
+            .with_context(|| format!("Could not create {}", migrated_path.display()))?;
+        for line in lines {
+            writeln!(file, "{}", line)?;
+        }
+        file.sync_all()?;
+        fs::rename(&migrated_path, &self.path)
+            .with_context(|| format!("Could not replace snapshot log {}", self.path.display()))?;
+
+        Ok(migrated)
+    }
+}
+
+#[derive(Deserialize)]
+struct VersionOnly {
+    #[serde(default = "unversioned")]
+    schema_version: u32,
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn unversioned_logs_are_refused_until_migrated() {
+        let path = std::env::temp_dir().join("snapshot_migration_test.jsonl");
+        let unversioned = r#"{"kind":"full","taken_at":"2024-05-02T06:00:00+02:00","delivery_from":"2024-05-01T00:00:00+02:00","delivery_to":"2024-05-02T00:00:00+02:00","rows":[{"area":"DK1","market":"Intraday","side":"Buy","mw":"10","cash_flow":"500"},{"area":"GB","market":"Intraday","side":"Buy","mw":"2","cash_flow":"60"}]}
+{"kind":"delta","taken_at":"2024-05-03T06:00:00+02:00","delivery_from":"2024-05-01T00:00:00+02:00","delivery_to":"2024-05-02T00:00:00+02:00","changed":[],"removed":[["DK1","Intraday","Buy"]]}
+{"schema_version":2,"kind":"delta","taken_at":"2024-05-04T06:00:00+02:00","delivery_from":"2024-05-01T00:00:00+02:00","delivery_to":"2024-05-02T00:00:00+02:00","changed":[],"removed":[["GB","Intraday","Buy"]]}
+"#;
+        fs::write(&path, unversioned).unwrap();
+        let log = SnapshotLog::new(&path, 2).unwrap();
+
+        assert!(log.read_all().is_err());
+        assert_eq!(log.migrate().unwrap(), 3);
+        assert_eq!(log.migrate().unwrap(), 0);
+        let snapshots = log.read_all().unwrap();
+        assert_eq!(snapshots.len(), 3);
+        assert_eq!(
+            snapshots[0].rows.keys().copied().collect::<Vec<_>>(),
+            vec![
+                (Area::DK1, Currency::Eur, Market::Intraday, TradeSide::Buy),
+                (Area::GB, Currency::Gbp, Market::Intraday, TradeSide::Buy)
+            ]
+        );
+        assert_eq!(snapshots[1].rows.len(), 1);
+        assert!(snapshots[2].rows.is_empty());
+
+        fs::remove_file(&path).unwrap();
+    }
+}
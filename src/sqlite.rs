@@ -0,0 +1,269 @@
+//! Trades of a SQLite database with tables laid out like the trade tables, which back a
+//! `TradeSource` built with `TradeSource::sqlite`. The `demo` command runs on the synthetic
+//! trades bundled in `demo/trades.sql`, so reports, exports and the API can be tried without
+//! a database server.
+//!
+//! SQLite has no types for times and decimals, so times are stored as seconds since the epoch
+//! and decimals as text. Queries aren't checked against the `.sqlx` metadata, which is for
+//! Postgres, but the bundled trades are read back in the tests.
+
+use std::str::FromStr;
+
+use anyhow::{anyhow, Context, Result};
+use chrono::{DateTime, FixedOffset};
+use chrono_tz::Tz;
+use futures::{stream, TryStreamExt};
+use rust_decimal::Decimal;
+use sqlx::sqlite::{SqlitePool, SqlitePoolOptions};
+use sqlx::{Error, FromRow};
+
+use crate::bounds::WindowFilter;
+use crate::db::{normalize_trades, TradeSource, TradeStream};
+use crate::trade::{Decoding, Trade, TradeTable};
+use crate::units::QuantityUnits;
+
+/// Synthetic trades delivered hourly in the first week of May 2024, with quantities in MW and
+/// signed
+pub const DEMO_TRADES: &str = include_str!("../demo/trades.sql");
+
+/// A trade as stored
+#[derive(FromRow)]
+struct TradeRow {
+    id: i64,
+    area: String,
+    counter_part: String,
+    currency: String,
+    delivery_start: i64,
+    delivery_end: i64,
+    price: Option<String>,
+    quantity_mwh: String,
+    trade_side: String,
+    trade_type: String,
+}
+
+impl TryFrom<TradeRow> for Trade {
+    type Error = anyhow::Error;
+
+    fn try_from(row: TradeRow) -> Result<Self> {
+        let time = |seconds: i64| {
+            DateTime::from_timestamp(seconds, 0)
+                .map(|time| time.with_timezone(&FixedOffset::east_opt(0).unwrap()))
+                .ok_or(anyhow!(
+                    "Invalid delivery time of trade {}: {}",
+                    row.id,
+                    seconds
+                ))
+        };
+        let decimal = |value: &str| {
+            Decimal::from_str(value)
+                .with_context(|| format!("Invalid decimal of trade {}: {}", row.id, value))
+        };
+        Ok(Trade {
+            id: row.id,
+            // Unknown areas are failed by `normalize_trades` unless decoding is lenient
+            area: Decoding::Lenient.area(row.area.clone())?,
+            counter_part: row.counter_part.clone().into(),
+            currency: row.currency.clone().try_into()?,
+            delivery_start: time(row.delivery_start)?,
+            delivery_end: time(row.delivery_end)?,
+            price: row.price.as_deref().map(decimal).transpose()?,
+            quantity_mwh: decimal(&row.quantity_mwh)?,
+            trade_side: row.trade_side.clone().try_into()?,
+            trade_type: row.trade_type.clone().try_into()?,
+        })
+    }
+}
+
+/// An in-memory database with the demo trades
+pub async fn demo_pool() -> Result<SqlitePool> {
+    // Each connection to `:memory:` opens a database of its own, so the pool keeps a single one
+    // for as long as it lives
+    let pool = SqlitePoolOptions::new()
+        .max_connections(1)
+        .idle_timeout(None)
+        .max_lifetime(None)
+        .connect("sqlite::memory:")
+        .await
+        .context("Failed to create the demo database")?;
+    sqlx::raw_sql(DEMO_TRADES)
+        .execute(&pool)
+        .await
+        .context("Failed to load the demo trades")?;
+    Ok(pool)
+}
+
+/// A source of the demo trades
+pub async fn demo_source() -> Result<TradeSource> {
+    Ok(TradeSource::sqlite(
+        demo_pool().await?,
+        QuantityUnits::default(),
+    ))
+}
+
+/// Fetches the trades of a table delivered in the window, selected with the filter, with ids
+/// above the given one, in id order. Quantities are as stored.
+async fn get_table_trades(
+    pool: &SqlitePool,
+    table: TradeTable,
+    delivery_from: &DateTime<Tz>,
+    delivery_to: &DateTime<Tz>,
+    filter: WindowFilter,
+    after_id: i64,
+) -> Result<Vec<Trade>> {
+    let selected = match filter {
+        WindowFilter::DeliveryStart => "delivery_start >= $1 AND delivery_start < $2",
+        WindowFilter::Overlap => "delivery_end > $1 AND delivery_start < $2",
+    };
+    let query = format!(
+        "
+    SELECT id, area, counter_part, currency, delivery_start, delivery_end, price, quantity_mwh, trade_side, trade_type
+    FROM {}
+    WHERE {} AND id > $3
+    ORDER BY id",
+        table.table_name(),
+        selected
+    );
+    let rows: Vec<TradeRow> = sqlx::query_as(&query)
+        .bind(delivery_from.timestamp())
+        .bind(delivery_to.timestamp())
+        .bind(after_id)
+        .fetch_all(pool)
+        .await?;
+    rows.into_iter().map(Trade::try_from).collect()
+}
+
+/// Fetches the trades of the three tables delivered in the window, ordered by delivery start
+async fn get_trades(
+    pool: &SqlitePool,
+    delivery_from: &DateTime<Tz>,
+    delivery_to: &DateTime<Tz>,
+    filter: WindowFilter,
+    units: &QuantityUnits,
+    decoding: Decoding,
+) -> Result<Vec<Trade>> {
+    let mut trades = Vec::new();
+    for table in [
+        TradeTable::Intraday,
+        TradeTable::Auction,
+        TradeTable::Imbalance,
+    ] {
+        let mut table_trades =
+            get_table_trades(pool, table, delivery_from, delivery_to, filter, 0).await?;
+        normalize_trades(&mut table_trades, table, units, decoding)?;
+        trades.extend(table_trades);
+    }
+    trades.sort_by_key(|trade| trade.delivery_start);
+    Ok(trades)
+}
+
+/// Streams the trades of the window, selected with the filter, ordered by delivery start. The
+/// trades are fetched at once, keeping the single connection of an in-memory database for as
+/// short as possible, which suits the demo trades but not a database of production size.
+pub fn get_trades_stream(
+    pool: &SqlitePool,
+    delivery_from: &DateTime<Tz>,
+    delivery_to: &DateTime<Tz>,
+    units: &QuantityUnits,
+    decoding: Decoding,
+    filter: WindowFilter,
+) -> TradeStream<'static> {
+    let (pool, delivery_from, delivery_to, units) =
+        (pool.clone(), *delivery_from, *delivery_to, units.clone());
+    let trades = async move {
+        get_trades(
+            &pool,
+            &delivery_from,
+            &delivery_to,
+            filter,
+            &units,
+            decoding,
+        )
+        .await
+        .map_err(|err| Error::Decode(err.into()))
+    };
+    Box::pin(
+        stream::once(trades)
+            .map_ok(|trades| stream::iter(trades.into_iter().map(Ok)))
+            .try_flatten(),
+    )
+}
+
+/// Fetches the trades of a table delivered in the window with ids above the given one, in id
+/// order, like `db::get_trades_after_id`
+pub async fn get_trades_after_id(
+    pool: &SqlitePool,
+    table: TradeTable,
+    after_id: i64,
+    delivery_from: &DateTime<Tz>,
+    delivery_to: &DateTime<Tz>,
+    units: &QuantityUnits,
+    decoding: Decoding,
+) -> Result<Vec<Trade>> {
+    let mut trades = get_table_trades(
+        pool,
+        table,
+        delivery_from,
+        delivery_to,
+        WindowFilter::DeliveryStart,
+        after_id,
+    )
+    .await?;
+    normalize_trades(&mut trades, table, units, decoding)?;
+    Ok(trades)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use chrono::TimeZone;
+    use chrono_tz::Europe::Copenhagen;
+
+    use crate::report::Metric;
+    use crate::report::ReportBuilder;
+    use crate::trade::{Area, AreaSelection, Currency, MarketSelection, TradeSide};
+
+    #[tokio::test]
+    async fn the_demo_trades_are_read_back() {
+        let source = demo_source().await.unwrap();
+        let from = Copenhagen.with_ymd_and_hms(2024, 5, 1, 0, 0, 0).unwrap();
+        let to = Copenhagen.with_ymd_and_hms(2024, 5, 2, 0, 0, 0).unwrap();
+
+        let trades: Vec<Trade> = source.stream(&from, &to).try_collect().await.unwrap();
+        assert_eq!(trades.len(), 24 * (4 + 4 + 2));
+        assert!(trades
+            .windows(2)
+            .all(|pair| pair[0].delivery_start <= pair[1].delivery_start));
+        let first = &trades[0];
+        assert_eq!(first.area, Area::DK1);
+        assert_eq!(first.delivery_start, from);
+        assert_eq!(first.price, Some(Decimal::new(2800, 2)));
+        assert!(trades
+            .iter()
+            .all(|trade| (trade.currency == Currency::Gbp) == (trade.area == Area::GB)));
+        assert!(trades
+            .iter()
+            .all(|trade| trade.quantity_mwh.is_sign_negative()
+                == (trade.trade_side == TradeSide::Sell)));
+
+        let later = source
+            .trades_after_id(TradeTable::Intraday, 90, &from, &to)
+            .await
+            .unwrap();
+        assert_eq!(later.first().map(|trade| trade.id), Some(91));
+        assert_eq!(later.len(), 96 - 90);
+
+        let report = ReportBuilder::new(&from, &to)
+            .build_from_source(&source)
+            .await
+            .unwrap();
+        assert_eq!(report.skipped_trades(), 0);
+        assert!(!report
+            .metric(
+                Metric::GrossProfit,
+                MarketSelection::All,
+                AreaSelection::All
+            )
+            .is_zero());
+    }
+}
@@ -0,0 +1,179 @@
+use std::collections::BTreeMap;
+
+use anyhow::Result;
+use chrono::{DateTime, Duration, FixedOffset};
+use chrono_tz::Tz;
+use serde::Serialize;
+
+use crate::report::Report;
+use crate::snapshot::ReportSnapshot;
+
+/// The finalized snapshots covering the start of a window, chained without gaps, and where the
+/// live tail aggregated from trades begins
+#[derive(Debug)]
+pub struct StitchPlan {
+    pub finalized: Vec<ReportSnapshot>,
+    pub live_from: DateTime<Tz>,
+}
+
+impl StitchPlan {
+    /// Chains snapshots from the start of the window. A snapshot counts as finalized once it was
+    /// taken at least `finalized_after` after its window ended, as trades of a delivery are
+    /// corrected until then; of snapshots of the same window the latest is used. Chaining stops
+    /// at the first gap, so everything from there on is aggregated live.
+    pub fn new(
+        snapshots: Vec<ReportSnapshot>,
+        delivery_from: &DateTime<Tz>,
+        delivery_to: &DateTime<Tz>,
+        finalized_after: Duration,
+    ) -> Self {
+        let mut latest: BTreeMap<(DateTime<FixedOffset>, DateTime<FixedOffset>), ReportSnapshot> =
+            BTreeMap::new();
+        for snapshot in snapshots {
+            let finalized = snapshot.taken_at >= snapshot.delivery_to + finalized_after;
+            let in_window =
+                snapshot.delivery_from >= *delivery_from && snapshot.delivery_to <= *delivery_to;
+            if !finalized || !in_window || snapshot.delivery_from >= snapshot.delivery_to {
+                continue;
+            }
+            let window = (snapshot.delivery_from, snapshot.delivery_to);
+            if latest
+                .get(&window)
+                .is_none_or(|previous| previous.taken_at <= snapshot.taken_at)
+            {
+                latest.insert(window, snapshot);
+            }
+        }
+
+        let mut finalized = Vec::new();
+        let mut live_from = *delivery_from;
+        // Of the snapshots starting where the chain ends, the longest covers the most
+        while let Some(window) = latest
+            .keys()
+            .filter(|(from, _)| *from == live_from)
+            .max_by_key(|(_, to)| *to)
+            .copied()
+        {
+            let snapshot = latest.remove(&window).expect("window is a key");
+            live_from = snapshot
+                .delivery_to
+                .with_timezone(&delivery_from.timezone());
+            finalized.push(snapshot);
+        }
+
+        Self {
+            finalized,
+            live_from,
+        }
+    }
+
+    /// The report of the window from the finalized snapshots and the report of the live tail,
+    /// which has to cover `live_from` to the end of the window
+    pub fn stitch(self, live: Report) -> Result<StitchedReport> {
+        let timezone = live.delivery_from().timezone();
+        let mut report = live;
+        // Backwards from the live tail, so each snapshot touches the window merged so far
+        for snapshot in self.finalized.iter().rev() {
+            report = report.merge(Report::from_breakdown(
+                &snapshot.delivery_from.with_timezone(&timezone),
+                &snapshot.delivery_to.with_timezone(&timezone),
+                &snapshot.breakdown(),
+            )?)?;
+        }
+        Ok(StitchedReport {
+            report,
+            snapshots: self.finalized.len(),
+            preliminary_from: self.live_from,
+        })
+    }
+}
+
+/// A report of finalized snapshots up to `preliminary_from`, and of trades after it, which may
+/// still change. Snapshots don't keep the prices of single trades, so the price statistics only
+/// cover the preliminary part.
+pub struct StitchedReport {
+    pub report: Report,
+    /// How many snapshots the finalized part was stitched from
+    pub snapshots: usize,
+    pub preliminary_from: DateTime<Tz>,
+}
+
+/// The stitched report as exported by `StitchedReport::to_json`
+#[derive(Serialize)]
+struct StitchedReportJson<'a> {
+    snapshots: usize,
+    finalized_from: &'a DateTime<Tz>,
+    preliminary_from: &'a DateTime<Tz>,
+    preliminary_to: &'a DateTime<Tz>,
+    report: serde_json::Value,
+}
+
+impl StitchedReport {
+    /// Whether any of the window is aggregated from trades rather than finalized snapshots
+    pub fn is_preliminary(&self) -> bool {
+        self.preliminary_from < *self.report.delivery_to()
+    }
+
+    /// The report as exported by `Report::to_json`, along with which part of it is preliminary
+    pub fn to_json(&self) -> Result<String> {
+        Ok(serde_json::to_string(&StitchedReportJson {
+            snapshots: self.snapshots,
+            finalized_from: self.report.delivery_from(),
+            preliminary_from: &self.preliminary_from,
+            preliminary_to: self.report.delivery_to(),
+            report: serde_json::from_str(&self.report.to_json()?)?,
+        })?)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::report::Metric;
+    use crate::trade::{AreaSelection, MarketSelection, Trade};
+    use chrono::TimeZone;
+    use chrono_tz::Europe::Copenhagen;
+    use rust_decimal_macros::dec;
+
+    #[test]
+    fn finalized_days_are_stitched_to_the_live_tail() {
+        let day = |day: u32| Copenhagen.with_ymd_and_hms(2024, 5, day, 0, 0, 0).unwrap();
+        let snapshot = |from: u32, to: u32, taken: u32, trades: Vec<Trade>| {
+            let report = Report::new(&day(from), &day(to), trades).unwrap();
+            ReportSnapshot {
+                taken_at: day(taken).fixed_offset(),
+                ..ReportSnapshot::from_report(&report)
+            }
+        };
+        let snapshots = vec![
+            snapshot(1, 2, 4, vec![Trade::test().mwh(1).price(40).build()]),
+            // Corrected after the first one, which it supersedes
+            snapshot(1, 2, 6, vec![Trade::test().mwh(10).price(40).build()]),
+            snapshot(2, 3, 5, vec![]),
+            // Taken too soon after its window to be final
+            snapshot(3, 4, 4, vec![Trade::test().mwh(99).price(40).build()]),
+        ];
+
+        let plan = StitchPlan::new(snapshots, &day(1), &day(5), Duration::hours(48));
+        assert_eq!(plan.finalized.len(), 2);
+        assert_eq!(plan.live_from, day(3));
+
+        let live = Report::new(
+            &day(3),
+            &day(5),
+            vec![Trade::test().sell().mwh(4).price(50).build()],
+        )
+        .unwrap();
+        let stitched = plan.stitch(live).unwrap();
+        assert!(stitched.is_preliminary());
+        assert_eq!(*stitched.report.delivery_from(), day(1));
+        assert_eq!(
+            stitched.report.metric(
+                Metric::GrossProfit,
+                MarketSelection::All,
+                AreaSelection::All
+            ),
+            dec!(-200)
+        );
+    }
+}
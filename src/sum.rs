@@ -0,0 +1,185 @@
+use rust_decimal::Decimal;
+use serde::{Deserialize, Serialize};
+
+/// Running sum of decimals kept as an `i128` mantissa at the largest scale added so far, so
+/// adding a value is an integer addition instead of `Decimal` arithmetic. Sums and products
+/// are exact like with `Decimal`, and only converted to one by `to_decimal`. Values that don't
+/// fit an `i128` at the common scale spill over into a `Decimal`, so nothing overflows either.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Serialize, Deserialize)]
+#[serde(from = "Decimal", into = "Decimal")]
+pub struct DecimalSum {
+    mantissa: i128,
+    scale: u32,
+    spill: Decimal,
+}
+
+/// Powers of ten an `i128` can hold
+const POWERS_OF_TEN: [i128; 39] = {
+    let mut powers = [1; 39];
+    let mut index = 1;
+    while index < powers.len() {
+        powers[index] = powers[index - 1] * 10;
+        index += 1;
+    }
+    powers
+};
+
+fn rescale(mantissa: i128, from: u32, to: u32) -> Option<i128> {
+    POWERS_OF_TEN
+        .get((to - from) as usize)
+        .and_then(|power| mantissa.checked_mul(*power))
+}
+
+impl DecimalSum {
+    pub fn add(&mut self, value: Decimal) {
+        if !self.add_parts(value.mantissa(), value.scale()) {
+            self.spill += value;
+        }
+    }
+
+    /// Adds the product of the factors, multiplying their mantissas as integers
+    pub fn add_product(&mut self, factors: &[Decimal]) {
+        let product = factors
+            .iter()
+            .try_fold((1i128, 0u32), |(mantissa, scale), factor| {
+                Some((
+                    mantissa.checked_mul(factor.mantissa())?,
+                    scale + factor.scale(),
+                ))
+            });
+        let added = product.is_some_and(|(mantissa, scale)| self.add_parts(mantissa, scale));
+        if !added {
+            self.spill += factors.iter().product::<Decimal>();
+        }
+    }
+
+    /// Adds another sum, exactly like adding each of its values
+    pub fn merge(&mut self, other: DecimalSum) {
+        if !self.add_parts(other.mantissa, other.scale) {
+            self.spill += other.mantissa_decimal();
+        }
+        self.spill += other.spill;
+    }
+
+    /// Adds `mantissa * 10^-scale`, or returns false when it doesn't fit at the common scale
+    fn add_parts(&mut self, mantissa: i128, scale: u32) -> bool {
+        let common = self.scale.max(scale);
+        let (Some(sum), Some(value)) = (
+            rescale(self.mantissa, self.scale, common),
+            rescale(mantissa, scale, common),
+        ) else {
+            return false;
+        };
+        let Some(sum) = sum.checked_add(value) else {
+            return false;
+        };
+        self.mantissa = sum;
+        self.scale = common;
+        true
+    }
+
+    /// The mantissa as a decimal, dropping the digits beyond its precision rounded half to even
+    /// like `Decimal` arithmetic does
+    fn mantissa_decimal(&self) -> Decimal {
+        for dropped in 0..=self.scale {
+            let power = POWERS_OF_TEN[dropped as usize];
+            let (quotient, remainder) = (self.mantissa / power, self.mantissa % power);
+            let half = power / 2;
+            let round_away = dropped > 0
+                && (remainder.abs() > half || (remainder.abs() == half && quotient % 2 != 0));
+            let mantissa = quotient
+                + if round_away {
+                    self.mantissa.signum()
+                } else {
+                    0
+                };
+            if let Ok(value) = Decimal::try_from_i128_with_scale(mantissa, self.scale - dropped) {
+                return value;
+            }
+        }
+        // Beyond the range of `Decimal`, which would have overflowed as well
+        if self.mantissa < 0 {
+            Decimal::MIN
+        } else {
+            Decimal::MAX
+        }
+    }
+
+    pub fn to_decimal(self) -> Decimal {
+        self.mantissa_decimal() + self.spill
+    }
+}
+
+impl From<Decimal> for DecimalSum {
+    fn from(value: Decimal) -> Self {
+        let mut sum = DecimalSum::default();
+        sum.add(value);
+        sum
+    }
+}
+
+impl From<DecimalSum> for Decimal {
+    fn from(sum: DecimalSum) -> Self {
+        sum.to_decimal()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use rust_decimal_macros::dec;
+
+    #[test]
+    fn sums_and_products_equal_decimal_arithmetic() {
+        let quantities = [
+            dec!(0),
+            dec!(1),
+            dec!(0.1),
+            dec!(12.345),
+            dec!(2500),
+            dec!(0.001),
+        ];
+        let lengths = [dec!(0.25), dec!(0.5), dec!(1), dec!(1.5)];
+        let prices = [dec!(-500), dec!(-0.01), dec!(0), dec!(49.99), dec!(3000.5)];
+
+        let mut mw = DecimalSum::default();
+        let mut cash_flow = DecimalSum::default();
+        let (mut expected_mw, mut expected_cash_flow) = (Decimal::ZERO, Decimal::ZERO);
+        for quantity in quantities {
+            for length in lengths {
+                for price in prices {
+                    mw.add_product(&[quantity, length]);
+                    cash_flow.add_product(&[quantity, length, price]);
+                    expected_mw += quantity * length;
+                    expected_cash_flow += quantity * length * price;
+                    assert_eq!(mw.to_decimal(), expected_mw);
+                    assert_eq!(cash_flow.to_decimal(), expected_cash_flow);
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn values_beyond_an_i128_spill_over() {
+        let mut sum = DecimalSum::default();
+        sum.add(dec!(0.0000000000000000000000000001));
+        sum.add(Decimal::MAX / dec!(2));
+        sum.add_product(&[Decimal::MAX / dec!(4), dec!(1.5)]);
+        sum.add(-Decimal::MAX / dec!(2));
+
+        assert_eq!(
+            sum.to_decimal(),
+            dec!(0.0000000000000000000000000001) + Decimal::MAX / dec!(4) * dec!(1.5)
+        );
+    }
+
+    #[test]
+    fn digits_beyond_the_precision_of_decimal_are_rounded_half_to_even() {
+        let mut sum = DecimalSum::default();
+        // 25 * 10^-30, which has two digits too many for a `Decimal`
+        sum.add_product(&[dec!(0.000000000000005), dec!(0.000000000000005)]);
+        assert_eq!(sum.to_decimal(), dec!(0));
+        sum.add_product(&[dec!(0.000000000000005), dec!(0.000000000000015)]);
+        assert_eq!(sum.to_decimal(), dec!(0.0000000000000000000000000001));
+    }
+}
@@ -0,0 +1,125 @@
+use std::time::Duration;
+
+use serde::{Serialize, Serializer};
+
+use crate::monitoring;
+use crate::trade::TradeTable;
+
+/// Phases a report run is broken into. Durations are only comparable across runs for the
+/// same strategy, as e.g. the stream strategy fetches and aggregates in one interleaved phase.
+#[derive(Debug, Serialize, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "snake_case", tag = "phase", content = "table")]
+pub enum Phase {
+    /// Fetching and decoding all rows of a single table
+    Query(TradeTable),
+    /// Fetching, decoding and aggregating rows as they arrive
+    Stream,
+    /// Receiving decoded trades from other tasks
+    Collect,
+    Aggregation,
+    Export,
+}
+
+impl Phase {
+    pub fn name(&self) -> &'static str {
+        match self {
+            Phase::Query(_) => "query",
+            Phase::Stream => "stream",
+            Phase::Collect => "collect",
+            Phase::Aggregation => "aggregation",
+            Phase::Export => "export",
+        }
+    }
+}
+
+#[derive(Debug, Serialize)]
+pub struct PhaseTiming {
+    #[serde(flatten)]
+    pub phase: Phase,
+    #[serde(rename = "elapsed_ms", serialize_with = "serialize_millis")]
+    pub elapsed: Duration,
+}
+
+#[derive(Debug, Serialize)]
+pub struct StrategyTimings {
+    pub strategy: String,
+    pub phases: Vec<PhaseTiming>,
+    /// `monitoring::trades_fetched` when the strategy started
+    #[serde(skip)]
+    fetched_before: u64,
+}
+
+impl StrategyTimings {
+    pub fn new(strategy: impl Into<String>) -> Self {
+        Self {
+            strategy: strategy.into(),
+            phases: Vec::new(),
+            fetched_before: monitoring::trades_fetched(),
+        }
+    }
+
+    /// Records the phase and logs it as an event of the strategy
+    pub fn record(&mut self, phase: Phase, elapsed: Duration) {
+        let elapsed_ms = elapsed.as_secs_f64() * 1000.0;
+        match phase {
+            Phase::Query(table) => tracing::info!(
+                strategy = %self.strategy,
+                phase = phase.name(),
+                %table,
+                elapsed_ms,
+                "phase finished"
+            ),
+            _ => tracing::info!(
+                strategy = %self.strategy,
+                phase = phase.name(),
+                elapsed_ms,
+                "phase finished"
+            ),
+        }
+        self.phases.push(PhaseTiming { phase, elapsed });
+    }
+
+    pub fn total(&self) -> Duration {
+        self.phases.iter().map(|timing| timing.elapsed).sum()
+    }
+
+    /// Trades fetched from the database since the strategy started
+    pub fn trades_fetched(&self) -> u64 {
+        monitoring::trades_fetched() - self.fetched_before
+    }
+
+    /// Logs the total of the finished strategy and records its report build for monitoring
+    pub fn finish(&self) {
+        tracing::info!(
+            strategy = %self.strategy,
+            trades = self.trades_fetched(),
+            elapsed_ms = self.total().as_secs_f64() * 1000.0,
+            "strategy finished"
+        );
+        monitoring::record_report_build(self);
+    }
+}
+
+/// Timings for a whole run of the binary, one entry per strategy executed.
+#[derive(Debug, Default, Serialize)]
+pub struct RunMetadata {
+    #[serde(rename = "pool_init_ms", serialize_with = "serialize_millis")]
+    pub pool_init: Duration,
+    pub strategies: Vec<StrategyTimings>,
+}
+
+impl RunMetadata {
+    /// Adds the timings of a strategy once it's finished
+    pub fn add(&mut self, timings: StrategyTimings) {
+        timings.finish();
+        self.strategies.push(timings);
+    }
+
+    pub fn to_json(&self) -> serde_json::Result<String> {
+        serde_json::to_string_pretty(self)
+    }
+}
+
+fn serialize_millis<S: Serializer>(duration: &Duration, serializer: S) -> Result<S::Ok, S::Error> {
+    serializer.serialize_f64(duration.as_secs_f64() * 1000.0)
+}
@@ -1,13 +1,23 @@
+use std::borrow::Cow;
+use std::collections::BTreeSet;
+use std::fmt;
 use std::str::FromStr;
+use std::sync::{Arc, Mutex, PoisonError};
 
-use chrono::{DateTime, FixedOffset};
+use chrono::{DateTime, Duration, FixedOffset};
 use rust_decimal::Decimal;
-use serde::{Deserialize, Serialize};
-use strum_macros::{EnumIter, EnumString};
+use serde::{de, Deserialize, Deserializer, Serialize, Serializer};
+#[cfg(feature = "runtime")]
+use sqlx::error::BoxDynError;
+#[cfg(feature = "runtime")]
+use sqlx::postgres::{PgTypeInfo, PgValueRef};
+#[cfg(feature = "runtime")]
+use sqlx::{Decode, FromRow, Postgres, Type};
+use strum_macros::{Display, EnumIter, EnumString};
 
-#[derive(
-    Debug, Serialize, Deserialize, EnumString, Hash, PartialEq, PartialOrd, Eq, Clone, Copy,
-)]
+/// A market area. Areas we don't know yet, e.g. added upstream before we redeploy, decode as
+/// `Other` with their name when decoding is lenient, see `Decoding`.
+#[derive(Debug, EnumString, Hash, PartialEq, PartialOrd, Eq, Ord, Clone, Copy)]
 #[strum(serialize_all = "UPPERCASE")]
 pub enum Area {
     Amp,
@@ -19,41 +29,272 @@ pub enum Area {
     NO2,
     SE1,
     SE3,
+    /// An area under its name as stored, interned so areas stay `Copy`
+    #[strum(disabled)]
+    Other(&'static str),
+}
+
+impl Area {
+    pub const KNOWN: [Area; 9] = [
+        Area::Amp,
+        Area::DK1,
+        Area::DK2,
+        Area::FR,
+        Area::GB,
+        Area::NL,
+        Area::NO2,
+        Area::SE1,
+        Area::SE3,
+    ];
+
+    /// The name areas are serialized and stored with, the variant name for known areas
+    pub(crate) fn serde_name(&self) -> &'static str {
+        match self {
+            Area::Amp => "Amp",
+            Area::DK1 => "DK1",
+            Area::DK2 => "DK2",
+            Area::FR => "FR",
+            Area::GB => "GB",
+            Area::NL => "NL",
+            Area::NO2 => "NO2",
+            Area::SE1 => "SE1",
+            Area::SE3 => "SE3",
+            Area::Other(name) => name,
+        }
+    }
+}
+
+impl TryFrom<String> for Area {
+    type Error = UnknownValue;
+
+    fn try_from(item: String) -> Result<Self, Self::Error> {
+        Decoding::Strict.area(item)
+    }
+}
+
+impl fmt::Display for Area {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Area::Other(name) => f.write_str(name),
+            area => f.write_str(&area.serde_name().to_uppercase()),
+        }
+    }
+}
+
+impl Serialize for Area {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.serialize_str(self.serde_name())
+    }
+}
+
+/// Unknown names deserialize as `Area::Other`, as reports of trades decoded leniently are
+/// saved with them and have to be read back
+impl<'de> Deserialize<'de> for Area {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        let name = String::deserialize(deserializer)?;
+        match Area::KNOWN
+            .into_iter()
+            .find(|area| area.serde_name() == name)
+        {
+            Some(area) => Ok(area),
+            None => Decoding::Lenient.area(name).map_err(de::Error::custom),
+        }
+    }
+}
+
+/// Currencies trades are settled in, stored by their ISO code, e.g. `EUR`
+#[derive(
+    Debug,
+    Serialize,
+    Deserialize,
+    EnumString,
+    Display,
+    Hash,
+    PartialEq,
+    PartialOrd,
+    Eq,
+    Ord,
+    Clone,
+    Copy,
+)]
+#[serde(rename_all = "UPPERCASE")]
+#[strum(serialize_all = "UPPERCASE")]
+pub enum Currency {
+    Eur,
+    Gbp,
 }
 
-impl From<String> for Area {
-    fn from(item: String) -> Self {
-        Area::from_str(&item).unwrap_or_else(|_| panic!("Invalid area: {}", item))
+/// How values we don't know are decoded, configured as e.g.
+///
+/// ```toml
+/// decoding = "lenient"
+/// ```
+#[derive(Debug, Deserialize, Default, Clone, Copy, PartialEq)]
+#[serde(rename_all = "snake_case")]
+pub enum Decoding {
+    /// Unknown areas fail the query they're decoded in
+    #[default]
+    Strict,
+    /// Unknown areas are aggregated as `Area::Other` under their name
+    Lenient,
+}
+
+impl Decoding {
+    /// The area of the name, `Area::Other` if it's unknown and decoding is lenient
+    pub fn area(self, name: String) -> Result<Area, UnknownValue> {
+        match Area::from_str(&name) {
+            Ok(area) => Ok(area),
+            Err(_) if self == Decoding::Lenient => Ok(Area::Other(intern(name))),
+            Err(_) => Err(UnknownValue::new("area", name)),
+        }
+    }
+
+    /// Checks an area read from the trade tables, where rows decode without any context and
+    /// unknown areas come as `Area::Other`, failing it unless decoding is lenient
+    pub fn check(self, area: Area) -> Result<Area, UnknownValue> {
+        match area {
+            Area::Other(name) if self == Decoding::Strict => {
+                Err(UnknownValue::new("area", name.to_string()))
+            }
+            area => Ok(area),
+        }
+    }
+}
+
+/// A `'static` copy of the name, leaked once per distinct name. Only the few names of unknown
+/// areas are interned, so what's leaked stays small.
+fn intern(name: String) -> &'static str {
+    static NAMES: Mutex<BTreeSet<&'static str>> = Mutex::new(BTreeSet::new());
+    let mut names = NAMES.lock().unwrap_or_else(PoisonError::into_inner);
+    if let Some(interned) = names.get(name.as_str()) {
+        return interned;
     }
+    let interned: &'static str = Box::leak(name.into_boxed_str());
+    names.insert(interned);
+    interned
+}
+
+/// The values as a sorted slice without duplicates, shared by the clones of a selection
+pub(crate) fn sorted_set<T: Ord>(values: impl IntoIterator<Item = T>) -> Arc<[T]> {
+    values
+        .into_iter()
+        .collect::<BTreeSet<T>>()
+        .into_iter()
+        .collect()
 }
 
-#[derive(Debug, Serialize, Deserialize, PartialEq, Clone, Copy)]
+#[derive(Debug, Serialize, PartialEq, Eq, Hash, Clone)]
 pub enum AreaSelection {
     All,
     Specific(Area),
+    /// The areas under a node of the `hierarchy::AreaHierarchy`, e.g. a country
+    Group(Arc<[Area]>),
+    /// Any of several areas, e.g. DK1 and DK2, see `AreaSelection::multiple`
+    Multiple(Arc<[Area]>),
 }
 
-#[derive(Debug, Serialize, Deserialize, EnumString)]
-#[strum(serialize_all = "lowercase")]
-pub enum CounterPart {
-    Nordpool,
-    Epex,
-    Esett,
-    Elexon,
-    Rte,
-    Semo,
-    Tennet,
-    Amprion,
+impl AreaSelection {
+    /// Selects any of the areas, in any order and with duplicates
+    pub fn multiple(areas: impl IntoIterator<Item = Area>) -> Self {
+        AreaSelection::Multiple(sorted_set(areas))
+    }
+
+    pub fn contains(&self, area: Area) -> bool {
+        match self {
+            AreaSelection::All => true,
+            AreaSelection::Specific(selected) => *selected == area,
+            AreaSelection::Group(areas) | AreaSelection::Multiple(areas) => areas.contains(&area),
+        }
+    }
 }
 
+/// A counter part of trades, identified by its lowercase name, e.g. `nordpool`. The exchanges
+/// and TSOs we've always traded with are constants, and any other counter part, such as a
+/// broker, is decoded by name. Their metadata is in `counterparts::CounterPartRegistry`.
+#[derive(Debug, Serialize, Deserialize, PartialEq, PartialOrd, Eq, Ord, Hash, Clone)]
+#[serde(from = "String", into = "String")]
+pub struct CounterPart(Cow<'static, str>);
+
+impl CounterPart {
+    pub const NORDPOOL: Self = Self(Cow::Borrowed("nordpool"));
+    pub const EPEX: Self = Self(Cow::Borrowed("epex"));
+    pub const ESETT: Self = Self(Cow::Borrowed("esett"));
+    pub const ELEXON: Self = Self(Cow::Borrowed("elexon"));
+    pub const RTE: Self = Self(Cow::Borrowed("rte"));
+    pub const SEMO: Self = Self(Cow::Borrowed("semo"));
+    pub const TENNET: Self = Self(Cow::Borrowed("tennet"));
+    pub const AMPRION: Self = Self(Cow::Borrowed("amprion"));
+
+    /// The counter parts that were an enum before brokers, which are always known
+    pub const BUILT_IN: [Self; 8] = [
+        Self::NORDPOOL,
+        Self::EPEX,
+        Self::ESETT,
+        Self::ELEXON,
+        Self::RTE,
+        Self::SEMO,
+        Self::TENNET,
+        Self::AMPRION,
+    ];
+
+    pub fn name(&self) -> &str {
+        &self.0
+    }
+}
+
+impl fmt::Display for CounterPart {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(&self.0)
+    }
+}
+
+/// Parses a name given by a user, which has to be letters, digits, `_` or `-`
+impl FromStr for CounterPart {
+    type Err = anyhow::Error;
+
+    fn from_str(name: &str) -> Result<Self, Self::Err> {
+        let valid = |c: char| c.is_ascii_alphanumeric() || c == '_' || c == '-';
+        if name.trim().is_empty() || !name.trim().chars().all(valid) {
+            anyhow::bail!("Invalid counter part: {}", name);
+        }
+        Ok(Self::from(name.to_string()))
+    }
+}
+
+/// Decodes stored names as is, apart from case, so the variant names serialized before
+/// counter parts were open, e.g. `Nordpool`, still decode
 impl From<String> for CounterPart {
-    fn from(item: String) -> Self {
-        CounterPart::from_str(&item).unwrap_or_else(|_| panic!("Invalid counter part: {}", item))
+    fn from(name: String) -> Self {
+        let name = name.trim().to_lowercase();
+        match Self::BUILT_IN
+            .into_iter()
+            .find(|counter_part| counter_part.name() == name)
+        {
+            Some(built_in) => built_in,
+            None => Self(Cow::Owned(name)),
+        }
+    }
+}
+
+impl From<CounterPart> for String {
+    fn from(counter_part: CounterPart) -> Self {
+        counter_part.0.into_owned()
     }
 }
 
 #[derive(
-    Debug, Serialize, Deserialize, EnumString, Hash, PartialEq, PartialOrd, Eq, Copy, Clone,
+    Debug,
+    Serialize,
+    Deserialize,
+    EnumString,
+    Display,
+    Hash,
+    PartialEq,
+    PartialOrd,
+    Eq,
+    Ord,
+    Copy,
+    Clone,
 )]
 #[strum(serialize_all = "lowercase")]
 pub enum TradeSide {
@@ -61,13 +302,15 @@ pub enum TradeSide {
     Sell,
 }
 
-impl From<String> for TradeSide {
-    fn from(item: String) -> Self {
-        TradeSide::from_str(&item).unwrap_or_else(|_| panic!("Invalid trade side: {}", item))
+impl TryFrom<String> for TradeSide {
+    type Error = UnknownValue;
+
+    fn try_from(item: String) -> Result<Self, Self::Error> {
+        TradeSide::from_str(&item).map_err(|_| UnknownValue::new("trade side", item))
     }
 }
 
-#[derive(Debug, Serialize, Deserialize, EnumString, Clone, Copy)]
+#[derive(Debug, Serialize, Deserialize, EnumString, Display, PartialEq, Eq, Hash, Clone, Copy)]
 #[strum(serialize_all = "snake_case")]
 pub enum TradeType {
     Intraday,
@@ -88,10 +331,12 @@ pub enum TradeType {
     Deserialize,
     EnumString,
     EnumIter,
+    Display,
     Hash,
     PartialEq,
     PartialOrd,
     Eq,
+    Ord,
     Clone,
     Copy,
 )]
@@ -119,23 +364,226 @@ impl From<TradeType> for Market {
     }
 }
 
-#[derive(Debug, Serialize, Deserialize, PartialEq, Clone, Copy)]
+#[derive(Debug, Serialize, Deserialize, PartialEq, Eq, Hash, Clone)]
 pub enum MarketSelection {
     All,
     Specific(Market),
+    /// Any of several markets, see `MarketSelection::multiple`
+    #[serde(deserialize_with = "deserialize_markets")]
+    Multiple(Arc<[Market]>),
 }
 
-impl From<String> for TradeType {
-    fn from(item: String) -> Self {
-        TradeType::from_str(&item).unwrap_or_else(|_| panic!("Invalid trade type: {}", item))
+impl MarketSelection {
+    /// Selects any of the markets, in any order and with duplicates
+    pub fn multiple(markets: impl IntoIterator<Item = Market>) -> Self {
+        MarketSelection::Multiple(sorted_set(markets))
+    }
+
+    pub fn contains(&self, market: Market) -> bool {
+        match self {
+            MarketSelection::All => true,
+            MarketSelection::Specific(selected) => *selected == market,
+            MarketSelection::Multiple(markets) => markets.contains(&market),
+        }
     }
 }
 
-#[derive(Debug, Serialize, Deserialize)]
+fn deserialize_markets<'de, D: Deserializer<'de>>(
+    deserializer: D,
+) -> Result<Arc<[Market]>, D::Error> {
+    let markets = Vec::<Market>::deserialize(deserializer)?;
+    Ok(sorted_set(markets))
+}
+
+/// Counter parts whose trades are aggregated, see `ReportBuilder::counter_parts`. Unlike areas
+/// and markets, reports don't keep counter parts apart, so they're selected before aggregating.
+#[derive(Debug, Default, Serialize, PartialEq, Clone)]
+pub enum CounterPartSelection {
+    #[default]
+    All,
+    Specific(CounterPart),
+    /// Any of several counter parts, see `CounterPartSelection::multiple`
+    Multiple(Arc<[CounterPart]>),
+}
+
+impl CounterPartSelection {
+    /// Selects any of the counter parts, in any order and with duplicates
+    pub fn multiple(counter_parts: impl IntoIterator<Item = CounterPart>) -> Self {
+        CounterPartSelection::Multiple(sorted_set(counter_parts))
+    }
+
+    pub fn contains(&self, counter_part: &CounterPart) -> bool {
+        match self {
+            CounterPartSelection::All => true,
+            CounterPartSelection::Specific(selected) => selected == counter_part,
+            CounterPartSelection::Multiple(counter_parts) => counter_parts.contains(counter_part),
+        }
+    }
+}
+
+/// The table the trades of a market are stored in
+#[derive(Debug, Serialize, Display, Clone, Copy, PartialEq, Eq, Hash)]
+#[serde(rename_all = "lowercase")]
+#[strum(serialize_all = "lowercase")]
+pub enum TradeTable {
+    Intraday,
+    Auction,
+    Imbalance,
+}
+
+impl From<Market> for TradeTable {
+    fn from(market: Market) -> Self {
+        match market {
+            Market::Intraday => TradeTable::Intraday,
+            Market::Auction => TradeTable::Auction,
+            Market::Imbalance => TradeTable::Imbalance,
+        }
+    }
+}
+
+impl TradeTable {
+    pub const ALL: [TradeTable; 3] = [
+        TradeTable::Intraday,
+        TradeTable::Auction,
+        TradeTable::Imbalance,
+    ];
+
+    pub fn market(&self) -> Market {
+        match self {
+            TradeTable::Intraday => Market::Intraday,
+            TradeTable::Auction => Market::Auction,
+            TradeTable::Imbalance => Market::Imbalance,
+        }
+    }
+
+    pub fn table_name(&self) -> &'static str {
+        match self {
+            TradeTable::Intraday => "intraday_trades",
+            TradeTable::Auction => "auction_trades",
+            TradeTable::Imbalance => "imbalance_trades",
+        }
+    }
+}
+
+impl TradeType {
+    /// Length of the delivery period of auction products, which is fixed per product.
+    /// Intraday and imbalance trades can have any delivery period.
+    pub fn product_length(&self) -> Option<Duration> {
+        match self {
+            TradeType::Intraday | TradeType::Imbalance => None,
+            TradeType::AuctionGbDahHh | TradeType::AuctionGbId1Hh | TradeType::AuctionGbId2Hh => {
+                Some(Duration::minutes(30))
+            }
+            TradeType::AuctionGbDahH
+            | TradeType::AuctionEurDahH
+            | TradeType::AuctionEurId1H
+            | TradeType::AuctionEurId2H
+            | TradeType::AuctionEurId3H => Some(Duration::hours(1)),
+        }
+    }
+}
+
+impl TryFrom<String> for Currency {
+    type Error = UnknownValue;
+
+    fn try_from(item: String) -> Result<Self, Self::Error> {
+        Currency::from_str(&item).map_err(|_| UnknownValue::new("currency", item))
+    }
+}
+
+impl TryFrom<String> for TradeType {
+    type Error = UnknownValue;
+
+    fn try_from(item: String) -> Result<Self, Self::Error> {
+        TradeType::from_str(&item).map_err(|_| UnknownValue::new("trade type", item))
+    }
+}
+
+/// A value of a text column that doesn't decode into the type of its field, e.g. an area added
+/// to the tables before it's added here
+#[derive(Debug, Clone, PartialEq)]
+pub struct UnknownValue {
+    pub kind: &'static str,
+    pub value: String,
+}
+
+impl UnknownValue {
+    fn new(kind: &'static str, value: String) -> Self {
+        Self { kind, value }
+    }
+}
+
+impl fmt::Display for UnknownValue {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "Unknown {} {:?}", self.kind, self.value)
+    }
+}
+
+impl std::error::Error for UnknownValue {}
+
+/// Decodes the text columns of the trade tables into their types, failing the row with an
+/// `UnknownValue` rather than panicking, so queries can select e.g. `area AS "area: Area"`
+#[cfg(feature = "runtime")]
+macro_rules! decode_from_text {
+    ($($type:ty),*) => {
+        $(
+            impl Type<Postgres> for $type {
+                fn type_info() -> PgTypeInfo {
+                    <String as Type<Postgres>>::type_info()
+                }
+
+                fn compatible(ty: &PgTypeInfo) -> bool {
+                    <String as Type<Postgres>>::compatible(ty)
+                }
+            }
+
+            impl<'r> Decode<'r, Postgres> for $type {
+                fn decode(value: PgValueRef<'r>) -> Result<Self, BoxDynError> {
+                    Ok(Self::try_from(<String as Decode<Postgres>>::decode(value)?)?)
+                }
+            }
+        )*
+    };
+}
+
+#[cfg(feature = "runtime")]
+decode_from_text!(CounterPart, Currency, TradeSide, TradeType);
+
+/// Unknown areas decode as `Area::Other`, as rows decode without knowing how strictly. Where
+/// trades are fetched, `Decoding::check` then fails them unless decoding is lenient.
+#[cfg(feature = "runtime")]
+impl Type<Postgres> for Area {
+    fn type_info() -> PgTypeInfo {
+        <String as Type<Postgres>>::type_info()
+    }
+
+    fn compatible(ty: &PgTypeInfo) -> bool {
+        <String as Type<Postgres>>::compatible(ty)
+    }
+}
+
+#[cfg(feature = "runtime")]
+impl<'r> Decode<'r, Postgres> for Area {
+    fn decode(value: PgValueRef<'r>) -> Result<Self, BoxDynError> {
+        Ok(Decoding::Lenient.area(<String as Decode<Postgres>>::decode(value)?)?)
+    }
+}
+
+/// A trade as stored in the trade tables.
+///
+/// Despite its name, `quantity_mwh` is the contracted power in MW, delivered over the whole
+/// delivery period: a half-hourly GB auction trade of 10 delivers 10 MW for 30 minutes, i.e. 5 MWh.
+/// Energy is derived by multiplying with the contract length when aggregating. It's negative for
+/// sells once normalized, whichever `units::QuantityConvention` the table stores sides in, and
+/// aggregations take the side from the sign rather than from `trade_side`.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[cfg_attr(feature = "runtime", derive(FromRow))]
 pub struct Trade {
-    pub id: i32,
+    pub id: i64,
     pub area: Area,
     pub counter_part: CounterPart,
+    /// The currency of the price, and so of the cash flow
+    pub currency: Currency,
     pub delivery_end: DateTime<FixedOffset>,
     pub delivery_start: DateTime<FixedOffset>,
     pub price: Option<Decimal>,
@@ -147,9 +595,179 @@ pub struct Trade {
 #[derive(Debug, Serialize, Deserialize)]
 pub struct TradeForReport {
     pub area: Area,
+    pub currency: Currency,
     pub delivery_end: DateTime<FixedOffset>,
     pub delivery_start: DateTime<FixedOffset>,
     pub price: Option<Decimal>,
     pub quantity_mwh: Decimal,
+    /// As stored, only needed to sign the quantities of tables storing the side apart
+    pub trade_side: TradeSide,
     pub trade_type: TradeType,
 }
+
+/// Terse construction of trades in tests, e.g.
+///
+/// ```ignore
+/// Trade::test().area(Area::GB).sell().mwh(10).price(50).half_hour("2024-05-01T12:00+01:00")
+/// ```
+///
+/// Defaults to buying 1 MW at 50 EUR in the DK1 intraday hour from 2024-05-01T12:00+02:00.
+#[cfg(test)]
+pub struct TradeBuilder {
+    area: Area,
+    counter_part: CounterPart,
+    currency: Currency,
+    trade_type: TradeType,
+    side: TradeSide,
+    quantity: Decimal,
+    price: Option<Decimal>,
+    delivery_start: DateTime<FixedOffset>,
+    delivery_end: DateTime<FixedOffset>,
+}
+
+#[cfg(test)]
+impl Trade {
+    pub fn test() -> TradeBuilder {
+        TradeBuilder::default()
+    }
+}
+
+#[cfg(test)]
+impl Default for TradeBuilder {
+    fn default() -> Self {
+        Self {
+            area: Area::DK1,
+            counter_part: CounterPart::NORDPOOL,
+            currency: Currency::Eur,
+            trade_type: TradeType::Intraday,
+            side: TradeSide::Buy,
+            quantity: Decimal::ONE,
+            price: Some(Decimal::from(50)),
+            delivery_start: parse_test_time("2024-05-01T12:00+02:00"),
+            delivery_end: parse_test_time("2024-05-01T13:00+02:00"),
+        }
+    }
+}
+
+/// Parses RFC 3339 timestamps, with or without seconds
+#[cfg(test)]
+fn parse_test_time(value: &str) -> DateTime<FixedOffset> {
+    DateTime::parse_from_rfc3339(value)
+        .or_else(|_| DateTime::parse_from_str(value, "%Y-%m-%dT%H:%M%:z"))
+        .unwrap_or_else(|_| panic!("Invalid test timestamp: {}", value))
+}
+
+#[cfg(test)]
+impl TradeBuilder {
+    pub fn area(mut self, area: Area) -> Self {
+        self.area = area;
+        self
+    }
+
+    pub fn counter_part(mut self, counter_part: CounterPart) -> Self {
+        self.counter_part = counter_part;
+        self
+    }
+
+    pub fn currency(mut self, currency: Currency) -> Self {
+        self.currency = currency;
+        self
+    }
+
+    pub fn trade_type(mut self, trade_type: TradeType) -> Self {
+        self.trade_type = trade_type;
+        self
+    }
+
+    pub fn buy(mut self) -> Self {
+        self.side = TradeSide::Buy;
+        self
+    }
+
+    /// Sells the quantity, which makes `quantity_mwh` negative
+    pub fn sell(mut self) -> Self {
+        self.side = TradeSide::Sell;
+        self
+    }
+
+    /// The unsigned `quantity_mwh`, i.e. the power in MW, signed by the side when built
+    pub fn mwh(mut self, quantity: impl Into<Decimal>) -> Self {
+        self.quantity = quantity.into();
+        self
+    }
+
+    pub fn price(mut self, price: impl Into<Decimal>) -> Self {
+        self.price = Some(price.into());
+        self
+    }
+
+    pub fn no_price(mut self) -> Self {
+        self.price = None;
+        self
+    }
+
+    pub fn quarter_hour(self, start: &str) -> Self {
+        self.lasting(start, Duration::minutes(15))
+    }
+
+    pub fn half_hour(self, start: &str) -> Self {
+        self.lasting(start, Duration::minutes(30))
+    }
+
+    pub fn hour(self, start: &str) -> Self {
+        self.lasting(start, Duration::hours(1))
+    }
+
+    fn lasting(mut self, start: &str, length: Duration) -> Self {
+        self.delivery_start = parse_test_time(start);
+        self.delivery_end = self.delivery_start + length;
+        self
+    }
+
+    pub fn build(self) -> Trade {
+        let quantity_mwh = match self.side {
+            TradeSide::Buy => self.quantity.abs(),
+            TradeSide::Sell => -self.quantity.abs(),
+        };
+        Trade {
+            id: 1,
+            area: self.area,
+            counter_part: self.counter_part,
+            currency: self.currency,
+            delivery_end: self.delivery_end,
+            delivery_start: self.delivery_start,
+            price: self.price,
+            quantity_mwh,
+            trade_side: self.side,
+            trade_type: self.trade_type,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn unknown_values_are_errors_rather_than_panics() {
+        assert_eq!(Area::try_from("DK1".to_string()), Ok(Area::DK1));
+        assert_eq!(
+            Area::try_from("DK3".to_string()).unwrap_err().to_string(),
+            "Unknown area \"DK3\""
+        );
+        assert!(TradeSide::try_from("hold".to_string()).is_err());
+
+        let other = Decoding::Lenient.area("DK3".to_string()).unwrap();
+        assert_eq!(other, Area::Other("DK3"));
+        assert_eq!(Decoding::Lenient.check(other), Ok(other));
+        assert!(Decoding::Strict.check(other).is_err());
+        assert_eq!(other.to_string(), "DK3");
+        assert_eq!(serde_json::to_string(&other).unwrap(), "\"DK3\"");
+        assert_eq!(
+            serde_json::from_str::<Vec<Area>>("[\"Amp\", \"DK1\"]").unwrap(),
+            vec![Area::Amp, Area::DK1]
+        );
+        assert_eq!(serde_json::from_str::<Area>("\"DK3\"").unwrap(), other);
+        assert!(TradeType::try_from("auction_eur_dah_qh".to_string()).is_err());
+    }
+}
@@ -0,0 +1,189 @@
+use anyhow::{bail, Result};
+use chrono::{DateTime, FixedOffset};
+use rust_decimal::Decimal;
+use serde::Deserialize;
+
+use crate::report::contract_length;
+use crate::trade::{TradeSide, TradeTable};
+
+/// Unit a source delivers quantities in. Trades are normalized to `Mw`, the contracted power
+/// over the delivery period, which is what aggregations multiply with the contract length.
+#[derive(Debug, Deserialize, Default, Clone, Copy, PartialEq)]
+#[serde(rename_all = "lowercase")]
+pub enum QuantityUnit {
+    #[default]
+    Mw,
+    /// Energy delivered over the whole delivery period
+    Mwh,
+}
+
+/// How the tables store which side a trade is on. Trades are normalized to signed quantities,
+/// negative for sells, which is what every aggregation derives the side from, so reports agree
+/// whichever way the trades were fetched.
+#[derive(Debug, Deserialize, Default, Clone, Copy, PartialEq)]
+#[serde(rename_all = "snake_case")]
+pub enum QuantityConvention {
+    /// The sign of the quantity gives the side, and `trade_side` is expected to agree with it
+    #[default]
+    Signed,
+    /// `trade_side` gives the side, and quantities are magnitudes
+    SideColumn,
+}
+
+impl QuantityConvention {
+    /// The signed quantity of a trade stored with the side
+    pub fn signed(&self, quantity: Decimal, side: TradeSide) -> Decimal {
+        match (self, side) {
+            (QuantityConvention::Signed, _) => quantity,
+            (QuantityConvention::SideColumn, TradeSide::Buy) => quantity.abs(),
+            (QuantityConvention::SideColumn, TradeSide::Sell) => -quantity.abs(),
+        }
+    }
+
+    /// The quantity as stored, the inverse of `signed`
+    pub fn stored(&self, quantity: Decimal) -> Decimal {
+        match self {
+            QuantityConvention::Signed => quantity,
+            QuantityConvention::SideColumn => quantity.abs(),
+        }
+    }
+
+    /// Whether the stored quantity and side disagree, e.g. a buy with a negative quantity.
+    /// Negative sells are fine with `SideColumn`, as they're signed the same either way.
+    pub fn contradicts(&self, quantity: Decimal, side: TradeSide) -> bool {
+        match (self, side) {
+            (_, TradeSide::Buy) => quantity < Decimal::ZERO,
+            (QuantityConvention::Signed, TradeSide::Sell) => quantity > Decimal::ZERO,
+            (QuantityConvention::SideColumn, TradeSide::Sell) => false,
+        }
+    }
+}
+
+/// The quantity unit of each source, and how the sides are stored, configured as e.g.
+///
+/// ```toml
+/// [quantity_units]
+/// imbalance = "mwh"
+/// convention = "side_column"
+/// ```
+#[derive(Debug, Deserialize, Default, Clone)]
+pub struct QuantityUnits {
+    #[serde(default)]
+    intraday: QuantityUnit,
+    #[serde(default)]
+    auction: QuantityUnit,
+    #[serde(default)]
+    imbalance: QuantityUnit,
+    #[serde(default)]
+    convention: QuantityConvention,
+}
+
+impl QuantityUnits {
+    pub fn convention(&self) -> QuantityConvention {
+        self.convention
+    }
+
+    /// Whether the quantities of the table are stored as trades are normalized, in MW and
+    /// signed, so they needn't be converted
+    pub fn is_normalized(&self, table: TradeTable) -> bool {
+        self.unit(table) == QuantityUnit::Mw && self.convention == QuantityConvention::Signed
+    }
+
+    pub fn unit(&self, table: TradeTable) -> QuantityUnit {
+        match table {
+            TradeTable::Intraday => self.intraday,
+            TradeTable::Auction => self.auction,
+            TradeTable::Imbalance => self.imbalance,
+        }
+    }
+
+    /// Converts a quantity from the unit of the table into MW
+    pub fn normalize(
+        &self,
+        table: TradeTable,
+        quantity: Decimal,
+        delivery_start: &DateTime<FixedOffset>,
+        delivery_end: &DateTime<FixedOffset>,
+    ) -> Result<Decimal> {
+        match self.unit(table) {
+            QuantityUnit::Mw => Ok(quantity),
+            QuantityUnit::Mwh => {
+                let contract_length = contract_length(delivery_start, delivery_end)?;
+                if contract_length <= Decimal::ZERO {
+                    bail!(
+                        "Can't convert MWh to MW for delivery period {} - {}",
+                        delivery_start,
+                        delivery_end
+                    );
+                }
+                Ok(quantity / contract_length)
+            }
+        }
+    }
+
+    /// Converts a quantity in MW into the unit of the table, the inverse of `normalize`
+    pub fn denormalize(
+        &self,
+        table: TradeTable,
+        quantity: Decimal,
+        delivery_start: &DateTime<FixedOffset>,
+        delivery_end: &DateTime<FixedOffset>,
+    ) -> Result<Decimal> {
+        match self.unit(table) {
+            QuantityUnit::Mw => Ok(quantity),
+            QuantityUnit::Mwh => Ok(quantity * contract_length(delivery_start, delivery_end)?),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use rust_decimal_macros::dec;
+
+    #[test]
+    fn mwh_over_half_an_hour_is_normalized_to_double_the_mw() {
+        let units: QuantityUnits = toml::from_str(r#"auction = "mwh""#).unwrap();
+        let start = DateTime::parse_from_rfc3339("2024-05-01T12:00:00+01:00").unwrap();
+        let end = DateTime::parse_from_rfc3339("2024-05-01T12:30:00+01:00").unwrap();
+
+        assert_eq!(
+            units
+                .normalize(TradeTable::Auction, dec!(5), &start, &end)
+                .unwrap(),
+            dec!(10)
+        );
+        assert_eq!(
+            units
+                .normalize(TradeTable::Intraday, dec!(5), &start, &end)
+                .unwrap(),
+            dec!(5)
+        );
+        // Written back in the unit of the table
+        assert_eq!(
+            units
+                .denormalize(TradeTable::Auction, dec!(10), &start, &end)
+                .unwrap(),
+            dec!(5)
+        );
+    }
+
+    #[test]
+    fn sides_are_normalized_into_the_sign() {
+        let units: QuantityUnits = toml::from_str(r#"convention = "side_column""#).unwrap();
+        let convention = units.convention();
+        assert!(!units.is_normalized(TradeTable::Intraday));
+        assert_eq!(convention.signed(dec!(5), TradeSide::Sell), dec!(-5));
+        assert_eq!(convention.signed(dec!(-5), TradeSide::Sell), dec!(-5));
+        assert_eq!(convention.signed(dec!(5), TradeSide::Buy), dec!(5));
+        assert_eq!(convention.stored(dec!(-5)), dec!(5));
+        assert!(convention.contradicts(dec!(-5), TradeSide::Buy));
+        assert!(!convention.contradicts(dec!(-5), TradeSide::Sell));
+
+        // Signed quantities are taken as they are, whatever the side says
+        let signed = QuantityConvention::Signed;
+        assert_eq!(signed.signed(dec!(5), TradeSide::Sell), dec!(5));
+        assert!(signed.contradicts(dec!(5), TradeSide::Sell));
+        assert!(QuantityUnits::default().is_normalized(TradeTable::Intraday));
+    }
+}
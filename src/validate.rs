@@ -0,0 +1,243 @@
+use std::collections::{BTreeMap, HashSet};
+
+use anyhow::Result;
+use chrono::{DateTime, FixedOffset};
+use chrono_tz::Tz;
+use futures::TryStreamExt;
+use rust_decimal::Decimal;
+use serde::Serialize;
+use sqlx::PgPool;
+use strum_macros::Display;
+
+use crate::db::{get_table_trades_stream_ordered, TradeTable};
+use crate::trade::{Area, CounterPart, Decoding, Trade, TradeSide, TradeType};
+use crate::units::{QuantityConvention, QuantityUnits};
+
+/// Ids of trades listed per issue, enough to look into the data without flooding the summary
+const SAMPLE_IDS: usize = 10;
+
+/// Bad data in the trade tables that reports would count wrong or not at all
+#[derive(Debug, Serialize, Display, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+#[serde(rename_all = "snake_case")]
+#[strum(serialize_all = "snake_case")]
+pub enum Issue {
+    /// The trade equals an earlier one of the table in everything but its id, e.g. a file
+    /// inserted twice
+    Duplicate,
+    /// `delivery_end` is at or before `delivery_start`
+    EmptyDeliveryPeriod,
+    /// Reports skip trades without a price
+    NullPrice,
+    ZeroQuantity,
+    /// The quantity contradicts `trade_side` under the configured `QuantityConvention`, e.g. a
+    /// buy with a negative quantity
+    SideMismatch,
+}
+
+/// How many trades have an issue, with the ids of the first of them
+#[derive(Debug, Default, Serialize, PartialEq)]
+pub struct IssueCount {
+    pub trades: usize,
+    pub sample_ids: Vec<i64>,
+}
+
+/// The fields that make two trades the same, apart from `delivery_start` which duplicates are
+/// found by
+type TradeKey = (
+    Area,
+    CounterPart,
+    DateTime<FixedOffset>,
+    Option<Decimal>,
+    Decimal,
+    TradeSide,
+    TradeType,
+);
+
+/// The issues of the trades of a table. Trades have to be added ordered by `delivery_start`, as
+/// duplicates are only looked for among trades delivered at the same time, so the trades of a
+/// single delivery start are held at a time.
+#[derive(Debug, Serialize)]
+pub struct TableCheck {
+    pub table: TradeTable,
+    pub trades: usize,
+    pub issues: BTreeMap<Issue, IssueCount>,
+    #[serde(skip)]
+    convention: QuantityConvention,
+    #[serde(skip)]
+    delivery_start: Option<DateTime<FixedOffset>>,
+    #[serde(skip)]
+    seen: HashSet<TradeKey>,
+}
+
+impl TableCheck {
+    pub fn new(table: TradeTable, convention: QuantityConvention) -> Self {
+        Self {
+            table,
+            trades: 0,
+            issues: BTreeMap::new(),
+            convention,
+            delivery_start: None,
+            seen: HashSet::new(),
+        }
+    }
+
+    pub fn add_trade(&mut self, trade: &Trade) {
+        self.trades += 1;
+        if self.delivery_start != Some(trade.delivery_start) {
+            self.delivery_start = Some(trade.delivery_start);
+            self.seen.clear();
+        }
+        let key = (
+            trade.area,
+            trade.counter_part.clone(),
+            trade.delivery_end,
+            trade.price,
+            trade.quantity_mwh,
+            trade.trade_side,
+            trade.trade_type,
+        );
+        if !self.seen.insert(key) {
+            self.record(Issue::Duplicate, trade.id);
+        }
+        if trade.delivery_end <= trade.delivery_start {
+            self.record(Issue::EmptyDeliveryPeriod, trade.id);
+        }
+        if trade.price.is_none() {
+            self.record(Issue::NullPrice, trade.id);
+        }
+        if trade.quantity_mwh.is_zero() {
+            self.record(Issue::ZeroQuantity, trade.id);
+        } else if self
+            .convention
+            .contradicts(trade.quantity_mwh, trade.trade_side)
+        {
+            self.record(Issue::SideMismatch, trade.id);
+        }
+    }
+
+    fn record(&mut self, issue: Issue, id: i64) {
+        let count = self.issues.entry(issue).or_default();
+        count.trades += 1;
+        if count.sample_ids.len() < SAMPLE_IDS {
+            count.sample_ids.push(id);
+        }
+    }
+}
+
+/// The issues found in each trade table over a delivery window, printed as JSON for fixing the
+/// data before reports are run on it
+#[derive(Debug, Serialize)]
+pub struct DataQualitySummary {
+    pub delivery_from: DateTime<Tz>,
+    pub delivery_to: DateTime<Tz>,
+    pub tables: Vec<TableCheck>,
+}
+
+impl DataQualitySummary {
+    /// Trades with at least one issue are counted once per issue
+    pub fn issues(&self) -> usize {
+        self.tables
+            .iter()
+            .flat_map(|table| table.issues.values())
+            .map(|count| count.trades)
+            .sum()
+    }
+
+    pub fn is_clean(&self) -> bool {
+        self.issues() == 0
+    }
+}
+
+/// Scans the trade tables for the trades delivered in the window, one table at a time. Trades
+/// are checked as stored, so quantities aren't normalized into signed MW, which would fail for
+/// empty delivery periods and hide sides contradicting the quantities. Sides are checked against
+/// the convention of the units.
+pub async fn check_tables(
+    pool: &PgPool,
+    delivery_from: &DateTime<Tz>,
+    delivery_to: &DateTime<Tz>,
+    units: &QuantityUnits,
+    decoding: Decoding,
+) -> Result<DataQualitySummary> {
+    let stored = QuantityUnits::default();
+    let mut tables = Vec::new();
+    for table in TradeTable::ALL {
+        let mut check = TableCheck::new(table, units.convention());
+        let mut trades = get_table_trades_stream_ordered(
+            pool,
+            table,
+            delivery_from,
+            delivery_to,
+            &stored,
+            decoding,
+        );
+        while let Some(trade) = trades.try_next().await? {
+            check.add_trade(&trade);
+        }
+        tables.push(check);
+    }
+    Ok(DataQualitySummary {
+        delivery_from: *delivery_from,
+        delivery_to: *delivery_to,
+        tables,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn bad_trades_are_counted_per_issue() {
+        let mut check = TableCheck::new(TradeTable::Intraday, QuantityConvention::Signed);
+        let with_id = |id, trade: Trade| Trade { id, ..trade };
+        let trade = || Trade::test().mwh(10).price(50).build();
+        let mut empty = trade();
+        empty.delivery_end = empty.delivery_start;
+        let mut mismatched = trade();
+        mismatched.quantity_mwh = -mismatched.quantity_mwh;
+        let trades = [
+            with_id(1, trade()),
+            with_id(2, trade()),
+            with_id(3, Trade::test().no_price().mwh(0).build()),
+            with_id(4, empty),
+            with_id(5, mismatched),
+            // The same as the first, but delivered later
+            with_id(
+                6,
+                Trade::test()
+                    .mwh(10)
+                    .price(50)
+                    .hour("2024-05-01T13:00+02:00")
+                    .build(),
+            ),
+        ];
+        for trade in trades.iter() {
+            check.add_trade(trade);
+        }
+
+        let issues: Vec<(Issue, Vec<i64>)> = check
+            .issues
+            .iter()
+            .map(|(issue, count)| (*issue, count.sample_ids.clone()))
+            .collect();
+        assert_eq!(
+            issues,
+            vec![
+                (Issue::Duplicate, vec![2]),
+                (Issue::EmptyDeliveryPeriod, vec![4]),
+                (Issue::NullPrice, vec![3]),
+                (Issue::ZeroQuantity, vec![3]),
+                (Issue::SideMismatch, vec![5]),
+            ]
+        );
+        assert_eq!(check.trades, 6);
+
+        // Sells stored as magnitudes are fine when the side column gives the side
+        let mut check = TableCheck::new(TradeTable::Intraday, QuantityConvention::SideColumn);
+        let mut sell = Trade::test().sell().mwh(10).build();
+        sell.quantity_mwh = sell.quantity_mwh.abs();
+        check.add_trade(&sell);
+        assert!(check.issues.is_empty());
+    }
+}
@@ -0,0 +1,246 @@
+use std::collections::{BTreeMap, HashMap};
+
+use anyhow::{anyhow, Result};
+use rust_decimal::Decimal;
+use serde::Deserialize;
+use strum_macros::Display;
+
+use crate::counterparts::CounterPartRegistry;
+use crate::report::contract_length;
+use crate::trade::{Area, CounterPart, Trade, TradeSide};
+
+/// How VAT applies to the invoices of a counter part
+#[derive(Debug, Default, Deserialize, Display, Clone, Copy, PartialEq)]
+#[serde(rename_all = "snake_case")]
+#[strum(serialize_all = "snake_case")]
+pub enum VatTreatment {
+    /// VAT is invoiced on top of the cash flows
+    #[default]
+    Charged,
+    /// The buyer accounts for the VAT, so invoices are net and the VAT is only booked
+    ReverseCharge,
+    /// No VAT applies
+    Exempt,
+}
+
+/// VAT rates by area, applied to counter parts charging VAT without a rate of their own, e.g.
+///
+/// ```toml
+/// [vat.rates]
+/// DK1 = 0.25
+/// GB = 0.20
+/// ```
+#[derive(Debug, Default, Deserialize, Clone)]
+pub struct VatConfig {
+    #[serde(default)]
+    pub rates: HashMap<Area, Decimal>,
+}
+
+/// Cash flows and VAT invoiced with a counter part in an area for one side
+#[derive(Debug, PartialEq)]
+pub struct VatRow {
+    pub counter_part: CounterPart,
+    /// Settlement currency of the counter part, empty if it isn't known
+    pub currency: String,
+    pub area: Area,
+    pub side: TradeSide,
+    pub treatment: VatTreatment,
+    pub rate: Decimal,
+    pub net: Decimal,
+    /// VAT booked on the cash flows, including reverse charged VAT the buyer accounts for
+    pub vat: Decimal,
+    /// What the invoices amount to, net of reverse charged VAT
+    pub gross: Decimal,
+}
+
+impl VatRow {
+    pub fn record(&self) -> Vec<String> {
+        vec![
+            self.counter_part.to_string(),
+            self.currency.clone(),
+            self.area.to_string(),
+            self.side.to_string(),
+            self.treatment.to_string(),
+            self.rate.to_string(),
+            self.net.round_dp(2).to_string(),
+            self.vat.to_string(),
+            self.gross.to_string(),
+        ]
+    }
+}
+
+/// An accounting view of the cash flows per counter part, area and side with their VAT, for
+/// reconciling against invoices. VAT is computed on the invoiced total rather than per trade,
+/// and rounded to cents like an invoice. Cash flows are the energy times the price on both
+/// sides, and trades without a price are skipped like in `Report`.
+#[derive(Debug)]
+pub struct VatLedger<'a> {
+    counter_parts: &'a CounterPartRegistry,
+    config: &'a VatConfig,
+    cash_flows: BTreeMap<(CounterPart, Area, TradeSide), Decimal>,
+    skipped_trades: usize,
+}
+
+impl<'a> VatLedger<'a> {
+    pub fn new(counter_parts: &'a CounterPartRegistry, config: &'a VatConfig) -> Self {
+        Self {
+            counter_parts,
+            config,
+            cash_flows: BTreeMap::new(),
+            skipped_trades: 0,
+        }
+    }
+
+    pub fn add_trade(&mut self, trade: &Trade) -> Result<()> {
+        let Some(price) = trade.price else {
+            self.skipped_trades += 1;
+            return Ok(());
+        };
+        let side = if trade.quantity_mwh < Decimal::ZERO {
+            TradeSide::Sell
+        } else {
+            TradeSide::Buy
+        };
+        let mwh =
+            trade.quantity_mwh.abs() * contract_length(&trade.delivery_start, &trade.delivery_end)?;
+        *self
+            .cash_flows
+            .entry((trade.counter_part.clone(), trade.area, side))
+            .or_default() += mwh * price;
+        Ok(())
+    }
+
+    pub fn skipped_trades(&self) -> usize {
+        self.skipped_trades
+    }
+
+    /// The treatment and rate of the invoices of a counter part in an area. Counter parts that
+    /// aren't registered are taken to charge VAT at the rate of the area.
+    fn treatment(&self, counter_part: &CounterPart, area: Area) -> Result<(VatTreatment, Decimal)> {
+        let info = self.counter_parts.get(counter_part);
+        let treatment = info.map_or(VatTreatment::default(), |info| info.vat);
+        if treatment == VatTreatment::Exempt {
+            return Ok((treatment, Decimal::ZERO));
+        }
+        let rate = info
+            .and_then(|info| info.vat_rate)
+            .or_else(|| self.config.rates.get(&area).copied())
+            .ok_or(anyhow!(
+                "No VAT rate for {} in {}, configure one under vat.rates or for the counter part",
+                counter_part,
+                area
+            ))?;
+        Ok((treatment, rate))
+    }
+
+    /// A row per counter part, area and side, ordered by them
+    pub fn rows(&self) -> Result<Vec<VatRow>> {
+        self.cash_flows
+            .iter()
+            .map(|((counter_part, area, side), &net)| {
+                let (treatment, rate) = self.treatment(counter_part, *area)?;
+                let vat = (net.round_dp(2) * rate).round_dp(2);
+                let gross = match treatment {
+                    VatTreatment::Charged => net.round_dp(2) + vat,
+                    VatTreatment::ReverseCharge | VatTreatment::Exempt => net.round_dp(2),
+                };
+                Ok(VatRow {
+                    counter_part: counter_part.clone(),
+                    currency: self
+                        .counter_parts
+                        .get(counter_part)
+                        .map_or(String::new(), |info| info.currency.clone()),
+                    area: *area,
+                    side: *side,
+                    treatment,
+                    rate,
+                    net,
+                    vat,
+                    gross,
+                })
+            })
+            .collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::counterparts::CounterPartInfo;
+    use rust_decimal_macros::dec;
+
+    #[test]
+    fn vat_follows_the_treatment_of_the_counter_part() {
+        let configured: HashMap<String, CounterPartInfo> = toml::from_str(
+            r#"
+            [tradition]
+            kind = "broker"
+            currency = "EUR"
+            vat = "reverse_charge"
+
+            [icap]
+            kind = "broker"
+            currency = "GBP"
+            vat = "exempt"
+            "#,
+        )
+        .unwrap();
+        let registry = CounterPartRegistry::new(&configured);
+        let config: VatConfig = toml::from_str("rates = { DK1 = 0.25 }").unwrap();
+        let mut ledger = VatLedger::new(&registry, &config);
+        let trades = [
+            Trade::test()
+                .counter_part(CounterPart::NORDPOOL)
+                .mwh(10)
+                .price(dec!(40.333))
+                .build(),
+            Trade::test()
+                .counter_part(CounterPart::from("tradition".to_string()))
+                .sell()
+                .mwh(2)
+                .price(50)
+                .build(),
+            Trade::test()
+                .counter_part(CounterPart::from("icap".to_string()))
+                .mwh(1)
+                .price(30)
+                .build(),
+            Trade::test().no_price().build(),
+        ];
+        for trade in trades.iter() {
+            ledger.add_trade(trade).unwrap();
+        }
+        assert_eq!(ledger.skipped_trades(), 1);
+
+        let rows = ledger.rows().unwrap();
+        let summary: Vec<_> = rows
+            .iter()
+            .map(|row| (row.counter_part.to_string(), row.side, row.vat, row.gross))
+            .collect();
+        assert_eq!(
+            summary,
+            vec![
+                ("icap".to_string(), TradeSide::Buy, dec!(0), dec!(30)),
+                (
+                    "nordpool".to_string(),
+                    TradeSide::Buy,
+                    dec!(100.83),
+                    dec!(504.16)
+                ),
+                (
+                    "tradition".to_string(),
+                    TradeSide::Sell,
+                    dec!(25),
+                    dec!(100)
+                ),
+            ]
+        );
+
+        // Areas without a rate can't be invoiced
+        let mut ledger = VatLedger::new(&registry, &config);
+        ledger
+            .add_trade(&Trade::test().area(Area::GB).mwh(1).price(30).build())
+            .unwrap();
+        assert!(ledger.rows().is_err());
+    }
+}
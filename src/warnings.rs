@@ -0,0 +1,50 @@
+use std::collections::BTreeSet;
+use std::process;
+
+use anyhow::{bail, Result};
+
+/// Exit code of a command that succeeded, but whose output may be incomplete
+pub const EXIT_WITH_WARNINGS: i32 = 2;
+
+/// Conditions where a command succeeded, but its data smells off, e.g. trades that were skipped.
+/// Commands exit with 0 without warnings, `EXIT_WITH_WARNINGS` with warnings and 1 on failure,
+/// so orchestration can tell the cases apart. Identical warnings are only reported once.
+#[derive(Debug, Default)]
+pub struct Warnings {
+    messages: BTreeSet<String>,
+}
+
+impl Warnings {
+    pub fn add(&mut self, message: impl Into<String>) {
+        self.messages.insert(message.into());
+    }
+
+    /// Adds a warning about trades skipped by an aggregation, if there were any
+    pub fn skipped_trades(&mut self, skipped: usize, aggregation: &str) {
+        if skipped > 0 {
+            self.add(format!(
+                "{} trades without a price were skipped in {}",
+                skipped, aggregation
+            ));
+        }
+    }
+
+    pub fn print(&self) {
+        for message in self.messages.iter() {
+            eprintln!("Warning: {}", message);
+        }
+    }
+
+    /// Reports the warnings and exits with `EXIT_WITH_WARNINGS` if there were any.
+    /// In strict mode warnings are a failure instead.
+    pub fn finish(self, strict: bool) -> Result<()> {
+        if self.messages.is_empty() {
+            return Ok(());
+        }
+        self.print();
+        if strict {
+            bail!("{} warnings in strict mode", self.messages.len());
+        }
+        process::exit(EXIT_WITH_WARNINGS);
+    }
+}
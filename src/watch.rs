@@ -0,0 +1,202 @@
+use std::collections::{BTreeSet, HashMap};
+use std::time::Duration;
+
+use anyhow::{Context, Result};
+use chrono::{DateTime, Local};
+use chrono_tz::Tz;
+use sqlx::postgres::PgListener;
+use sqlx::PgPool;
+
+use crate::cancel::{or_cancelled, Cancelled};
+use crate::db::{get_trades_after_id, TradeSource, TradeTable};
+use crate::fanout::Aggregation;
+use crate::position::ForwardCurve;
+use crate::report::Report;
+use crate::trade::Decoding;
+use crate::units::QuantityUnits;
+
+/// Channel the trade tables notify on when new trades are inserted
+const NOTIFY_CHANNEL: &str = "new_trades";
+
+/// Ids below the watermark of a table fetched again on every sync, see `IncrementalSync`
+pub const WATERMARK_LOOKBACK_IDS: i64 = 10_000;
+
+/// Keeps an aggregation up to date by fetching the trades with ids above the highest id seen per
+/// table. As the watermarks are kept here and not derived from notifications, a sync after missed
+/// notifications (e.g. while the listener was reconnecting) still picks up every new trade.
+///
+/// Ids are assigned when trades are inserted but become visible when their transaction commits,
+/// so a trade can show up after one with a higher id has moved the watermark past it. Every sync
+/// therefore fetches the last `lookback` ids below the watermark again, and skips the trades it
+/// has already aggregated. Trades committed more than `lookback` ids late are still missed.
+#[derive(Debug)]
+pub struct IncrementalSync {
+    lookback: i64,
+    watermarks: HashMap<TradeTable, i64>,
+    /// Ids aggregated within the lookback of the watermark, per table
+    recent_ids: HashMap<TradeTable, BTreeSet<i64>>,
+}
+
+impl Default for IncrementalSync {
+    fn default() -> Self {
+        Self::with_lookback(WATERMARK_LOOKBACK_IDS)
+    }
+}
+
+impl IncrementalSync {
+    pub fn with_lookback(lookback: i64) -> Self {
+        Self {
+            lookback,
+            watermarks: HashMap::new(),
+            recent_ids: HashMap::new(),
+        }
+    }
+
+    pub fn watermark(&self, table: TradeTable) -> i64 {
+        *self.watermarks.get(&table).unwrap_or(&0)
+    }
+
+    /// Adds all trades in the delivery window newer than the watermarks to the aggregation,
+    /// returning how many were added
+    pub async fn sync<A: Aggregation>(
+        &mut self,
+        pool: &PgPool,
+        delivery_from: &DateTime<Tz>,
+        delivery_to: &DateTime<Tz>,
+        units: &QuantityUnits,
+        decoding: Decoding,
+        aggregation: &mut A,
+    ) -> Result<usize> {
+        let mut added = 0;
+
+        for table in TradeTable::ALL {
+            let trades = get_trades_after_id(
+                pool,
+                table,
+                self.watermark(table).saturating_sub(self.lookback),
+                delivery_from,
+                delivery_to,
+                units,
+                decoding,
+            )
+            .await?;
+
+            let watermark = self.watermark(table);
+            let recent_ids = self.recent_ids.entry(table).or_default();
+            for trade in trades.iter() {
+                if recent_ids.insert(trade.id) {
+                    aggregation.add_trade(trade)?;
+                    added += 1;
+                }
+            }
+            if let Some(last) = trades.last() {
+                let watermark = watermark.max(last.id);
+                self.watermarks.insert(table, watermark);
+                // Ids at or below the lookback aren't fetched again
+                *recent_ids = recent_ids.split_off(&(watermark.saturating_sub(self.lookback) + 1));
+            }
+        }
+
+        Ok(added)
+    }
+}
+
+/// Watches the trade tables, syncing the report whenever a notification arrives on
+/// `new_trades` and at least every `poll_interval` in case notifications were missed.
+/// `on_update` is called after every sync that added trades. A forward curve is synced
+/// alongside the report over its own window, and written after every sync so periods that
+/// have started drop out of it. Runs until the cancellation of the source, failing with
+/// `Cancelled`; a sync cancelled between two tables has only moved the watermarks past trades
+/// that were aggregated.
+pub async fn watch<F>(
+    source: &TradeSource,
+    delivery_from: &DateTime<Tz>,
+    delivery_to: &DateTime<Tz>,
+    poll_interval: Duration,
+    mut forward_curve: Option<ForwardCurve>,
+    mut on_update: F,
+) -> Result<()>
+where
+    F: FnMut(&Report, usize),
+{
+    let pool = source
+        .pool()
+        .context("Trades can only be watched in the tables of Postgres, which notify of them")?;
+    let (units, cancellation) = (source.units(), source.cancellation());
+    let decoding = source.decoding();
+    let mut report = Report::empty(delivery_from, delivery_to)?;
+    let mut sync = IncrementalSync::default();
+    let mut curve_sync = IncrementalSync::default();
+
+    let added = or_cancelled(
+        cancellation,
+        sync.sync(
+            pool,
+            delivery_from,
+            delivery_to,
+            units,
+            decoding,
+            &mut report,
+        ),
+    )
+    .await?;
+    on_update(&report, added);
+    if let Some(curve) = forward_curve.as_mut() {
+        or_cancelled(
+            cancellation,
+            sync_forward_curve(&mut curve_sync, pool, units, decoding, curve),
+        )
+        .await?;
+    }
+
+    let mut listener = PgListener::connect_with(pool).await?;
+    listener.listen(NOTIFY_CHANNEL).await?;
+    let mut interval = tokio::time::interval(poll_interval);
+
+    loop {
+        tokio::select! {
+            _ = cancellation.cancelled() => return Err(Cancelled.into()),
+            notification = listener.recv() => {
+                notification?;
+            }
+            _ = interval.tick() => {}
+        }
+
+        let added = or_cancelled(
+            cancellation,
+            sync.sync(
+                pool,
+                delivery_from,
+                delivery_to,
+                units,
+                decoding,
+                &mut report,
+            ),
+        )
+        .await?;
+        if added > 0 {
+            on_update(&report, added);
+        }
+        if let Some(curve) = forward_curve.as_mut() {
+            or_cancelled(
+                cancellation,
+                sync_forward_curve(&mut curve_sync, pool, units, decoding, curve),
+            )
+            .await?;
+        }
+    }
+}
+
+async fn sync_forward_curve(
+    sync: &mut IncrementalSync,
+    pool: &PgPool,
+    units: &QuantityUnits,
+    decoding: Decoding,
+    curve: &mut ForwardCurve,
+) -> Result<()> {
+    let delivery_from = *curve.delivery_from();
+    let delivery_to = *curve.delivery_to();
+    sync.sync(pool, &delivery_from, &delivery_to, units, decoding, curve)
+        .await?;
+    curve.write(Local::now().fixed_offset())
+}
@@ -0,0 +1,697 @@
+//! Guards the public API other teams embed the library through. The signatures services rely on
+//! are pinned, so changing one breaks this test at compile time rather than the services using
+//! it, and the whole API is compared to the snapshot in `public-api.txt`. A change here is a
+//! breaking change: deprecate the old item first, see "API stability" in the README, and only
+//! then update its line and the snapshot.
+use std::collections::{BTreeMap, BTreeSet};
+use std::path::Path;
+use std::process::Command;
+
+use anyhow::Result;
+use chrono::{DateTime, FixedOffset, TimeZone};
+use chrono_tz::{Europe::Copenhagen, Tz};
+use rust_decimal::Decimal;
+use serde_json::Value;
+use trading_results_rs::{
+    trade_stream, Area, AreaSelection, BreakdownRow, CounterPart, Currency, Market,
+    MarketSelection, Metric, QuantityUnits, Report, ReportBuilder, Trade, TradeSide, TradeStream,
+    TradeType,
+};
+
+type Accessor = fn(&Report, MarketSelection, AreaSelection) -> Decimal;
+type Window<'a> = (&'a DateTime<Tz>, &'a DateTime<Tz>);
+
+#[test]
+fn report_signatures_are_stable() {
+    let _: fn(&DateTime<Tz>, &DateTime<Tz>) -> ReportBuilder = ReportBuilder::new;
+    let _ = |(from, to): Window, trades: Vec<Trade>| -> Result<Report> {
+        Report::new(from, to, trades)
+    };
+    let _: fn(&DateTime<Tz>, &DateTime<Tz>) -> Result<Report> = Report::empty;
+    let _: fn(&Report, Metric, MarketSelection, AreaSelection) -> Result<Decimal> =
+        Report::try_metric;
+    let _: fn(&Report, Metric, MarketSelection, AreaSelection) -> BTreeMap<Currency, Decimal> =
+        Report::metric_by_currency;
+    let _: fn(&Report, MarketSelection, AreaSelection) -> Option<Decimal> = Report::vwap_buy;
+    let _: fn(&Report, MarketSelection, AreaSelection) -> Option<Decimal> = Report::vwap_sell;
+    let _: fn(&Report) -> Vec<BreakdownRow> = Report::breakdown;
+    let _: fn(&Report) -> usize = Report::skipped_trades;
+    let _: fn(&Report) -> Result<String> = Report::to_json;
+    let _: fn(&mut Report, &Trade) -> Result<()> = Report::add_trade;
+    let _: fn(Report, Report) -> Result<Report> = Report::merge;
+    let _: fn(Vec<Trade>) -> TradeStream<'static> = trade_stream;
+    let _: fn() -> QuantityUnits = QuantityUnits::default;
+
+    // Deprecated in favour of `Report::try_metric`, kept until the next breaking release
+    #[allow(deprecated)]
+    let _: [Accessor; 7] = [
+        Report::mw_bought,
+        Report::mw_sold,
+        Report::revenue,
+        Report::costs,
+        Report::gross_profit,
+        Report::net_position,
+        Report::net_cash_flow,
+    ];
+}
+
+#[tokio::test]
+async fn deprecated_accessors_match_their_metrics() -> Result<()> {
+    let from = Copenhagen.with_ymd_and_hms(2024, 5, 1, 0, 0, 0).unwrap();
+    let to = Copenhagen.with_ymd_and_hms(2024, 5, 2, 0, 0, 0).unwrap();
+    let hour = |time: &str| DateTime::<FixedOffset>::parse_from_rfc3339(time).unwrap();
+    let trade = |id, quantity_mwh: i64, trade_side| Trade {
+        id,
+        area: Area::DK1,
+        counter_part: CounterPart::NORDPOOL,
+        currency: Currency::Eur,
+        delivery_start: hour("2024-05-01T12:00:00+02:00"),
+        delivery_end: hour("2024-05-01T13:00:00+02:00"),
+        price: Some(Decimal::from(50)),
+        quantity_mwh: Decimal::from(quantity_mwh),
+        trade_side,
+        trade_type: TradeType::Intraday,
+    };
+    let trades = vec![trade(1, 10, TradeSide::Buy), trade(2, -4, TradeSide::Sell)];
+    let report = ReportBuilder::new(&from, &to)
+        .build_from_stream(trade_stream(trades))
+        .await
+        .unwrap();
+
+    let (market, area) = (
+        MarketSelection::Specific(Market::Intraday),
+        AreaSelection::All,
+    );
+    #[allow(deprecated)]
+    let accessors: [(Accessor, Metric); 7] = [
+        (Report::mw_bought, Metric::MwBought),
+        (Report::mw_sold, Metric::MwSold),
+        (Report::revenue, Metric::Revenue),
+        (Report::costs, Metric::Costs),
+        (Report::gross_profit, Metric::GrossProfit),
+        (Report::net_position, Metric::NetPosition),
+        (Report::net_cash_flow, Metric::NetCashFlow),
+    ];
+    for (accessor, metric) in accessors {
+        assert_eq!(
+            accessor(&report, market.clone(), area.clone()),
+            report.try_metric(metric, market.clone(), area.clone())?,
+            "{}",
+            metric
+        );
+    }
+    assert_eq!(
+        report.try_metric(Metric::NetPosition, market, area)?,
+        Decimal::from(6)
+    );
+    Ok(())
+}
+
+/// Renders the public API from the rustdoc JSON of the crate, one line per item, like the
+/// simplified output of cargo-public-api. Needs a nightly toolchain, as rustdoc only writes
+/// JSON on nightly. `UPDATE_PUBLIC_API=1` rewrites the snapshot instead of comparing to it.
+#[test]
+fn public_api_matches_the_snapshot() {
+    let root = Path::new(env!("CARGO_MANIFEST_DIR"));
+    // A target directory of its own, as the one of `cargo test` is locked while tests run
+    let target = root.join("target").join("public-api");
+    let status = Command::new("cargo")
+        .current_dir(root)
+        .args([
+            "+nightly",
+            "rustdoc",
+            "--lib",
+            "--all-features",
+            "--target-dir",
+        ])
+        .arg(&target)
+        .args(["--", "-Z", "unstable-options", "--output-format", "json"])
+        .status()
+        .expect("Could not run cargo");
+    assert!(
+        status.success(),
+        "rustdoc failed, is a nightly toolchain installed?"
+    );
+    let json = std::fs::read_to_string(target.join("doc").join("trading_results_rs.json")).unwrap();
+    let api = PublicApi::new(serde_json::from_str(&json).unwrap()).render();
+
+    let snapshot_path = root.join("public-api.txt");
+    if std::env::var("UPDATE_PUBLIC_API").is_ok_and(|update| update == "1") {
+        std::fs::write(&snapshot_path, api).unwrap();
+        return;
+    }
+    let snapshot = std::fs::read_to_string(&snapshot_path).unwrap_or_default();
+    let (old, new): (BTreeSet<&str>, BTreeSet<&str>) =
+        (snapshot.lines().collect(), api.lines().collect());
+    let changes: Vec<String> = old
+        .difference(&new)
+        .map(|line| format!("-{}", line))
+        .chain(new.difference(&old).map(|line| format!("+{}", line)))
+        .collect();
+    assert!(
+        changes.is_empty(),
+        "The public API changed since public-api.txt, update it with `task public-api-snapshot` \
+         if the change is meant to be released:\n{}",
+        changes.join("\n")
+    );
+}
+
+struct PublicApi {
+    doc: Value,
+}
+
+impl PublicApi {
+    fn new(doc: Value) -> Self {
+        Self { doc }
+    }
+
+    fn item(&self, id: &Value) -> Option<&Value> {
+        self.doc["index"].get(id.to_string())
+    }
+
+    fn render(&self) -> String {
+        let mut lines = BTreeSet::new();
+        let root = self.item(&self.doc["root"]).unwrap();
+        self.module(root, "trading_results_rs", &mut lines);
+        lines.into_iter().map(|line| line + "\n").collect()
+    }
+
+    fn module(&self, module: &Value, path: &str, lines: &mut BTreeSet<String>) {
+        for item in module["inner"]["module"]["items"].as_array().unwrap() {
+            let Some(item) = self.item(item) else {
+                continue;
+            };
+            if item["visibility"] != "public" || item["crate_id"] != 0 {
+                continue;
+            }
+            let (kind, inner) = kind(item);
+            // Re-exports are named by what they import
+            let name = item["name"].as_str().or(inner["name"].as_str()).unwrap();
+            let item_path = format!("{}::{}", path, name);
+            match kind {
+                "module" => {
+                    lines.insert(format!("pub mod {}", item_path));
+                    self.module(item, &item_path, lines);
+                }
+                "use" => {
+                    let source = inner["source"].as_str().unwrap();
+                    if inner["is_glob"] == true {
+                        lines.insert(format!("pub use {}::{}::*", path, source));
+                    } else {
+                        lines.insert(format!("pub use {} = {}", item_path, source));
+                    }
+                }
+                "function" => {
+                    lines.insert(format!("pub {}", self.function(&item_path, inner)));
+                }
+                "constant" => {
+                    lines.insert(format!(
+                        "pub const {}: {}",
+                        item_path,
+                        self.ty(&inner["type"])
+                    ));
+                }
+                "static" => {
+                    lines.insert(format!(
+                        "pub static {}: {}",
+                        item_path,
+                        self.ty(&inner["type"])
+                    ));
+                }
+                "type_alias" => {
+                    lines.insert(format!(
+                        "pub type {}{} = {}",
+                        item_path,
+                        self.generics(&inner["generics"]),
+                        self.ty(&inner["type"])
+                    ));
+                }
+                "struct" => {
+                    lines.insert(format!(
+                        "pub struct {}{}",
+                        item_path,
+                        self.generics(&inner["generics"])
+                    ));
+                    let fields = match &inner["kind"] {
+                        Value::Object(kind) if kind.contains_key("plain") => {
+                            kind["plain"]["fields"].clone()
+                        }
+                        Value::Object(kind) if kind.contains_key("tuple") => kind["tuple"].clone(),
+                        _ => Value::Null,
+                    };
+                    self.fields(&item_path, &fields, lines);
+                    self.impls(&item_path, &inner["impls"], lines);
+                }
+                "enum" => {
+                    lines.insert(format!(
+                        "pub enum {}{}",
+                        item_path,
+                        self.generics(&inner["generics"])
+                    ));
+                    for variant in inner["variants"].as_array().unwrap() {
+                        let variant = self.item(variant).unwrap();
+                        let variant_path =
+                            format!("{}::{}", item_path, variant["name"].as_str().unwrap());
+                        lines.insert(format!("pub {}", variant_path));
+                        let fields = match &variant["inner"]["variant"]["kind"] {
+                            Value::Object(kind) if kind.contains_key("tuple") => {
+                                kind["tuple"].clone()
+                            }
+                            Value::Object(kind) if kind.contains_key("struct") => {
+                                kind["struct"]["fields"].clone()
+                            }
+                            _ => Value::Null,
+                        };
+                        self.fields(&variant_path, &fields, lines);
+                    }
+                    self.impls(&item_path, &inner["impls"], lines);
+                }
+                "trait" => {
+                    let bounds = self.bounds(&inner["bounds"]);
+                    lines.insert(format!(
+                        "pub trait {}{}{}",
+                        item_path,
+                        self.generics(&inner["generics"]),
+                        if bounds.is_empty() {
+                            String::new()
+                        } else {
+                            format!(": {}", bounds)
+                        }
+                    ));
+                    self.associated(&item_path, &inner["items"], lines);
+                    self.impls(&item_path, &inner["implementations"], lines);
+                }
+                other => {
+                    lines.insert(format!("pub {} {}", other, item_path));
+                }
+            }
+        }
+    }
+
+    fn fields(&self, path: &str, fields: &Value, lines: &mut BTreeSet<String>) {
+        let Some(fields) = fields.as_array() else {
+            return;
+        };
+        for (index, field) in fields.iter().enumerate() {
+            let Some(field) = self.item(field) else {
+                continue;
+            };
+            if field["visibility"] != "public" {
+                continue;
+            }
+            let name = match field["name"].as_str() {
+                Some(name) if name.parse::<usize>().is_err() => name.to_string(),
+                _ => index.to_string(),
+            };
+            lines.insert(format!(
+                "pub {}::{}: {}",
+                path,
+                name,
+                self.ty(&field["inner"]["struct_field"])
+            ));
+        }
+    }
+
+    /// Inherent methods as items of the type, trait implementations by their header, leaving out
+    /// auto traits and blanket implementations, which follow from the rest
+    fn impls(&self, path: &str, impls: &Value, lines: &mut BTreeSet<String>) {
+        for id in impls.as_array().into_iter().flatten() {
+            let Some(item) = self.item(id) else {
+                continue;
+            };
+            let inner = &item["inner"]["impl"];
+            if inner["is_synthetic"] == true || !inner["blanket_impl"].is_null() {
+                continue;
+            }
+            if inner["trait"].is_null() {
+                let items: Vec<Value> = inner["items"]
+                    .as_array()
+                    .unwrap()
+                    .iter()
+                    .filter(|item| {
+                        self.item(item)
+                            .is_some_and(|item| item["visibility"] == "public")
+                    })
+                    .cloned()
+                    .collect();
+                self.associated(path, &Value::Array(items), lines);
+            } else {
+                lines.insert(format!(
+                    "impl{} {}{} for {}{}",
+                    self.generics(&inner["generics"]),
+                    if inner["is_negative"] == true {
+                        "!"
+                    } else {
+                        ""
+                    },
+                    self.path(&inner["trait"]),
+                    self.ty(&inner["for"]),
+                    self.where_clause(&inner["generics"])
+                ));
+            }
+        }
+    }
+
+    fn associated(&self, path: &str, items: &Value, lines: &mut BTreeSet<String>) {
+        for item in items.as_array().unwrap() {
+            let Some(item) = self.item(item) else {
+                continue;
+            };
+            let item_path = format!("{}::{}", path, item["name"].as_str().unwrap());
+            let (kind, inner) = kind(item);
+            let line = match kind {
+                "function" => self.function(&item_path, inner),
+                "assoc_const" => format!("const {}: {}", item_path, self.ty(&inner["type"])),
+                "assoc_type" => {
+                    let bounds = self.bounds(&inner["bounds"]);
+                    format!(
+                        "type {}{}{}",
+                        item_path,
+                        if bounds.is_empty() {
+                            String::new()
+                        } else {
+                            format!(": {}", bounds)
+                        },
+                        if inner["type"].is_null() {
+                            String::new()
+                        } else {
+                            format!(" = {}", self.ty(&inner["type"]))
+                        }
+                    )
+                }
+                other => format!("{} {}", other, item_path),
+            };
+            lines.insert(format!("pub {}", line));
+        }
+    }
+
+    fn function(&self, path: &str, function: &Value) -> String {
+        let header = &function["header"];
+        let qualifiers: String = [
+            ("const ", "is_const"),
+            ("async ", "is_async"),
+            ("unsafe ", "is_unsafe"),
+        ]
+        .into_iter()
+        .filter(|(_, flag)| header[flag] == true)
+        .map(|(qualifier, _)| qualifier)
+        .collect();
+        let inputs: Vec<String> = function["sig"]["inputs"]
+            .as_array()
+            .unwrap()
+            .iter()
+            .map(|input| {
+                let (name, ty) = (input[0].as_str().unwrap(), &input[1]);
+                match (name, self.ty(ty).as_str()) {
+                    ("self", "Self") => "self".to_string(),
+                    ("self", "&Self") => "&self".to_string(),
+                    ("self", "&mut Self") => "&mut self".to_string(),
+                    (name, ty) => format!("{}: {}", name, ty),
+                }
+            })
+            .collect();
+        let output = &function["sig"]["output"];
+        format!(
+            "{}fn {}{}({}){}{}",
+            qualifiers,
+            path,
+            self.generics(&function["generics"]),
+            inputs.join(", "),
+            if output.is_null() {
+                String::new()
+            } else {
+                format!(" -> {}", self.ty(output))
+            },
+            self.where_clause(&function["generics"])
+        )
+    }
+
+    fn generics(&self, generics: &Value) -> String {
+        let params: Vec<String> = generics["params"]
+            .as_array()
+            .unwrap()
+            .iter()
+            .filter_map(|param| {
+                let name = param["name"].as_str().unwrap();
+                let kind = &param["kind"];
+                if let Some(lifetime) = kind.get("lifetime") {
+                    let outlives: Vec<&str> = lifetime["outlives"]
+                        .as_array()
+                        .unwrap()
+                        .iter()
+                        .filter_map(Value::as_str)
+                        .collect();
+                    Some(if outlives.is_empty() {
+                        name.to_string()
+                    } else {
+                        format!("{}: {}", name, outlives.join(" + "))
+                    })
+                } else if let Some(ty) = kind.get("type") {
+                    // `impl Trait` arguments show up as synthetic parameters
+                    if ty["is_synthetic"] == true {
+                        return None;
+                    }
+                    let bounds = self.bounds(&ty["bounds"]);
+                    Some(if bounds.is_empty() {
+                        name.to_string()
+                    } else {
+                        format!("{}: {}", name, bounds)
+                    })
+                } else {
+                    Some(format!(
+                        "const {}: {}",
+                        name,
+                        self.ty(&kind["const"]["type"])
+                    ))
+                }
+            })
+            .collect();
+        if params.is_empty() {
+            String::new()
+        } else {
+            format!("<{}>", params.join(", "))
+        }
+    }
+
+    fn where_clause(&self, generics: &Value) -> String {
+        let predicates: Vec<String> = generics["where_predicates"]
+            .as_array()
+            .unwrap()
+            .iter()
+            .map(|predicate| {
+                if let Some(bound) = predicate.get("bound_predicate") {
+                    format!(
+                        "{}: {}",
+                        self.ty(&bound["type"]),
+                        self.bounds(&bound["bounds"])
+                    )
+                } else if let Some(lifetime) = predicate.get("lifetime_predicate") {
+                    format!("{}: {}", lifetime["lifetime"], lifetime["outlives"])
+                } else {
+                    let eq = &predicate["eq_predicate"];
+                    format!("{} = {}", self.ty(&eq["lhs"]), self.term(&eq["rhs"]))
+                }
+            })
+            .collect();
+        if predicates.is_empty() {
+            String::new()
+        } else {
+            format!(" where {}", predicates.join(", "))
+        }
+    }
+
+    fn bounds(&self, bounds: &Value) -> String {
+        let bounds: Vec<String> = bounds
+            .as_array()
+            .into_iter()
+            .flatten()
+            .map(|bound| {
+                if let Some(bound) = bound.get("trait_bound") {
+                    let modifier = match bound["modifier"].as_str() {
+                        Some("maybe") => "?",
+                        Some("maybe_const") => "~const ",
+                        _ => "",
+                    };
+                    format!("{}{}", modifier, self.path(&bound["trait"]))
+                } else if let Some(lifetime) = bound.get("outlives") {
+                    lifetime.as_str().unwrap().to_string()
+                } else {
+                    bound.to_string()
+                }
+            })
+            .collect();
+        bounds.join(" + ")
+    }
+
+    /// The full path of an item where rustdoc knows it, otherwise as written in the source
+    fn path(&self, path: &Value) -> String {
+        let name = self.doc["paths"]
+            .get(path["id"].to_string())
+            .map(|summary| {
+                summary["path"]
+                    .as_array()
+                    .unwrap()
+                    .iter()
+                    .map(|segment| segment.as_str().unwrap())
+                    .collect::<Vec<_>>()
+                    .join("::")
+            })
+            .unwrap_or_else(|| path["path"].as_str().unwrap().to_string());
+        format!("{}{}", name, self.args(&path["args"]))
+    }
+
+    fn args(&self, args: &Value) -> String {
+        if let Some(angle) = args.get("angle_bracketed") {
+            let args = angle["args"]
+                .as_array()
+                .unwrap()
+                .iter()
+                .map(|arg| {
+                    if let Some(ty) = arg.get("type") {
+                        self.ty(ty)
+                    } else if let Some(lifetime) = arg.get("lifetime") {
+                        lifetime.as_str().unwrap().to_string()
+                    } else if let Some(constant) = arg.get("const") {
+                        constant["expr"].as_str().unwrap_or_default().to_string()
+                    } else {
+                        "_".to_string()
+                    }
+                })
+                .chain(
+                    angle["constraints"]
+                        .as_array()
+                        .unwrap()
+                        .iter()
+                        .map(|constraint| {
+                            let name = constraint["name"].as_str().unwrap();
+                            let binding = &constraint["binding"];
+                            if let Some(term) = binding.get("equality") {
+                                format!("{} = {}", name, self.term(term))
+                            } else {
+                                format!("{}: {}", name, self.bounds(&binding["constraint"]))
+                            }
+                        }),
+                )
+                .collect::<Vec<_>>();
+            if args.is_empty() {
+                String::new()
+            } else {
+                format!("<{}>", args.join(", "))
+            }
+        } else if let Some(parenthesized) = args.get("parenthesized") {
+            let inputs: Vec<String> = parenthesized["inputs"]
+                .as_array()
+                .unwrap()
+                .iter()
+                .map(|input| self.ty(input))
+                .collect();
+            let output = &parenthesized["output"];
+            format!(
+                "({}){}",
+                inputs.join(", "),
+                if output.is_null() {
+                    String::new()
+                } else {
+                    format!(" -> {}", self.ty(output))
+                }
+            )
+        } else {
+            String::new()
+        }
+    }
+
+    fn term(&self, term: &Value) -> String {
+        match term.get("type") {
+            Some(ty) => self.ty(ty),
+            None => term["constant"]["expr"]
+                .as_str()
+                .unwrap_or_default()
+                .to_string(),
+        }
+    }
+
+    fn ty(&self, ty: &Value) -> String {
+        let Some((kind, inner)) = ty.as_object().and_then(|ty| ty.iter().next()) else {
+            return ty.as_str().unwrap_or_default().to_string();
+        };
+        match kind.as_str() {
+            "resolved_path" => self.path(inner),
+            "generic" | "primitive" => inner.as_str().unwrap().to_string(),
+            "infer" => "_".to_string(),
+            "tuple" => {
+                let types: Vec<String> = inner
+                    .as_array()
+                    .unwrap()
+                    .iter()
+                    .map(|ty| self.ty(ty))
+                    .collect();
+                format!("({})", types.join(", "))
+            }
+            "slice" => format!("[{}]", self.ty(inner)),
+            "array" => format!(
+                "[{}; {}]",
+                self.ty(&inner["type"]),
+                inner["len"].as_str().unwrap()
+            ),
+            "borrowed_ref" => format!(
+                "&{}{}{}",
+                inner["lifetime"]
+                    .as_str()
+                    .map(|lifetime| format!("{} ", lifetime))
+                    .unwrap_or_default(),
+                if inner["is_mutable"] == true {
+                    "mut "
+                } else {
+                    ""
+                },
+                self.ty(&inner["type"])
+            ),
+            "raw_pointer" => format!(
+                "*{} {}",
+                if inner["is_mutable"] == true {
+                    "mut"
+                } else {
+                    "const"
+                },
+                self.ty(&inner["type"])
+            ),
+            "impl_trait" => format!("impl {}", self.bounds(inner)),
+            "dyn_trait" => {
+                let traits: Vec<String> = inner["traits"]
+                    .as_array()
+                    .unwrap()
+                    .iter()
+                    .map(|bound| self.path(&bound["trait"]))
+                    .chain(inner["lifetime"].as_str().map(str::to_string))
+                    .collect();
+                format!("dyn {}", traits.join(" + "))
+            }
+            "qualified_path" => {
+                let self_type = self.ty(&inner["self_type"]);
+                let name = inner["name"].as_str().unwrap();
+                if inner["trait"].is_null() {
+                    format!("{}::{}", self_type, name)
+                } else {
+                    format!(
+                        "<{} as {}>::{}",
+                        self_type,
+                        self.path(&inner["trait"]),
+                        name
+                    )
+                }
+            }
+            _ => ty.to_string(),
+        }
+    }
+}
+
+fn kind(item: &Value) -> (&str, &Value) {
+    match &item["inner"] {
+        Value::Object(inner) => inner
+            .iter()
+            .next()
+            .map(|(kind, inner)| (kind.as_str(), inner))
+            .unwrap(),
+        inner => (inner.as_str().unwrap_or_default(), &Value::Null),
+    }
+}
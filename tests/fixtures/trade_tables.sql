@@ -0,0 +1,18 @@
+-- The trade tables as in production, with ids assigned by the database
+CREATE TABLE intraday_trades (
+    id serial PRIMARY KEY,
+    area varchar NOT NULL,
+    counter_part varchar NOT NULL,
+    currency varchar NOT NULL,
+    delivery_start timestamptz NOT NULL,
+    delivery_end timestamptz NOT NULL,
+    price numeric NOT NULL,
+    quantity_mwh numeric NOT NULL,
+    trade_side varchar NOT NULL,
+    trade_type varchar NOT NULL
+);
+
+CREATE TABLE auction_trades (LIKE intraday_trades INCLUDING ALL);
+CREATE TABLE imbalance_trades (LIKE intraday_trades INCLUDING ALL);
+-- Imbalance trades are only priced once settled
+ALTER TABLE imbalance_trades ALTER COLUMN price DROP NOT NULL;